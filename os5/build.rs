@@ -1,15 +1,69 @@
 //! Building applications linker
 
-use std::fs::{read_dir, File};
+use std::fs::{read_dir, read_to_string, File};
 use std::io::{Result, Write};
+use std::process::Command;
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-changed={}", CHECKSUMS_PATH);
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    // a commit moves the branch HEAD points to, not HEAD itself
+    if let Some(branch) = read_to_string("../.git/HEAD")
+        .ok()
+        .and_then(|head| head.strip_prefix("ref: ").map(|r| r.trim().to_string()))
+    {
+        println!("cargo:rerun-if-changed=../.git/{}", branch);
+    }
     insert_app_data().unwrap();
+    insert_build_info();
+}
+
+/// Pass the commit and the profile of the build on to `sys_sysinfo`
+fn insert_build_info() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+    println!(
+        "cargo:rustc-env=BUILD_PROFILE={}",
+        std::env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string())
+    );
 }
 
 static TARGET_PATH: &str = "../user/build/elf/";
+/// `name checksum` lines the user build writes, see user/checksums.py
+static CHECKSUMS_PATH: &str = "../user/build/checksums.txt";
+
+/// The FNV-1a 64 checksum of each app as the user build recorded it, 0 for
+/// an app it has none of, which the kernel does not check and warns about
+fn app_checksums(apps: &[String]) -> Vec<u64> {
+    let recorded = read_to_string(CHECKSUMS_PATH).unwrap_or_else(|err| {
+        println!(
+            "cargo:warning={}: {}, no app image will be checked, run make in user first",
+            CHECKSUMS_PATH, err
+        );
+        String::new()
+    });
+    apps.iter()
+        .map(|app| {
+            let sum = recorded
+                .lines()
+                .filter_map(|line| line.split_once(' '))
+                .find(|(name, _)| name == app)
+                .map_or(0, |(_, sum)| u64::from_str_radix(sum, 16).unwrap());
+            if sum == 0 && !recorded.is_empty() {
+                println!("cargo:warning={} has no checksum of {}", CHECKSUMS_PATH, app);
+            }
+            sum
+        })
+        .collect()
+}
 
 /// get app data and build linker
 /// while saving app names in order
@@ -42,6 +96,27 @@ _num_app:
     }
     writeln!(f, r#"    .quad app_{}_end"#, apps.len() - 1)?;
 
+    // an image is page aligned and padded, its length comes from its end
+    writeln!(
+        f,
+        r#"
+    .global _app_ends
+_app_ends:"#
+    )?;
+    for i in 0..apps.len() {
+        writeln!(f, r#"    .quad app_{}_end"#, i)?;
+    }
+
+    writeln!(
+        f,
+        r#"
+    .global _app_checksums
+_app_checksums:"#
+    )?;
+    for sum in app_checksums(&apps) {
+        writeln!(f, r#"    .quad {:#x}"#, sum)?;
+    }
+
     writeln!(
         f,
         r#"
@@ -60,12 +135,14 @@ _app_names:"#
     .section .data
     .global app_{0}_start
     .global app_{0}_end
-    .align 3
+    .align 12
 app_{0}_start:
     .incbin "{2}{1}.elf"
 app_{0}_end:"#,
             idx, app, TARGET_PATH
         )?;
     }
+    // so that no page of an image holds anything else, see map_self
+    writeln!(f, r#"    .align 12"#)?;
     Ok(())
 }
@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""Paste 2 KiB into the console of a running kernel, all in one write, and
+check ch5b_paste got every byte in order.
+
+Usage: paste_test.py BOOTLOADER KERNEL_BIN KERNEL_ENTRY_PA
+"""
+import subprocess
+import sys
+
+LINES = 32
+LINE_LEN = 64
+
+
+def paste():
+    lines = []
+    for i in range(LINES):
+        body = "".join(chr(ord("a") + (i + j) % 26) for j in range(LINE_LEN - 6))
+        lines.append("%04d %s\n" % (i, body))
+    return "".join(lines).encode() + b"end\nend\n"
+
+
+def wait_for(qemu, text):
+    seen = b""
+    while text not in seen:
+        byte = qemu.stdout.read(1)
+        if not byte:
+            sys.exit("qemu exited before printing %r" % text)
+        sys.stdout.buffer.write(byte)
+        sys.stdout.flush()
+        seen = seen[-256:] + byte
+    return seen
+
+
+def main():
+    bootloader, kernel, entry = sys.argv[1:4]
+    qemu = subprocess.Popen(
+        [
+            "qemu-system-riscv64",
+            "-machine", "virt",
+            "-nographic",
+            "-bios", bootloader,
+            "-device", "loader,file=%s,addr=%s" % (kernel, entry),
+        ],
+        stdin=subprocess.PIPE,
+        stdout=subprocess.PIPE,
+    )
+    try:
+        wait_for(qemu, b">> ")
+        qemu.stdin.write(b"ch5b_paste\n")
+        qemu.stdin.flush()
+        wait_for(qemu, b"lines now\n")
+        data = paste()
+        assert len(data) >= 2048
+        qemu.stdin.write(data)
+        qemu.stdin.flush()
+        line = b""
+        while b"paste OK!" not in line:
+            line = wait_for(qemu, b"\n")
+            if b"Panicked" in line or b">> " in line:
+                sys.exit("ch5b_paste failed")
+    finally:
+        qemu.kill()
+    print()
+
+
+if __name__ == "__main__":
+    main()
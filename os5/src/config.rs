@@ -1,14 +1,95 @@
 //! Constants used in rCore
 
+/// User stack of a program that does not ask for another size, and how much
+/// of a larger one is mapped up front
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Largest user stack a program may ask for, see
+/// [`crate::mm::MemorySet::from_elf`]
+pub const USER_STACK_MAX: usize = 8 * 1024 * 1024;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// The scratch stacks exec runs on, one for each hart
+pub const KERNEL_STACK_SIZE_LARGE: usize = 4096 * 8;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
 pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// Most tasks alive at once, zombies included: there are this many pids
+/// and kernel stack slots, see [`crate::task::pid_alloc`]
+pub const MAX_TASKS: usize = 1024;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// The read-only vDSO data page of every user space, see [`crate::mm::vdso`]
+pub const VDSO_DATA: usize = TRAP_CONTEXT - PAGE_SIZE;
+/// The read-only comm page of each process, see [`crate::mm::comm`]
+pub const COMM_PAGE: usize = VDSO_DATA - PAGE_SIZE;
+/// The code page every process shares next to it, where signal handlers
+/// return to
+pub const SIGRETURN_TRAMPOLINE: usize = COMM_PAGE - PAGE_SIZE;
+/// User mappings end at or below this, the top of the lower half of SV39,
+/// so the pages above from [`SIGRETURN_TRAMPOLINE`] up are out of their reach
+pub const USER_VA_MAX: usize = 1 << 38;
+/// mmap maps nothing below this, where the ELF image lives
+pub const MMAP_BASE: usize = 0x1000_0000;
+/// Top of the user stack
+pub const USER_STACK_TOP: usize = USER_VA_MAX;
 pub const CLOCK_FREQ: usize = 12500000;
+/// Device registers mapped into the kernel space, `(start, len)`
+#[cfg(all(feature = "board_qemu", not(feature = "trace_uart")))]
+pub const MMIO: &[(usize, usize)] = &[
+    (0x0010_0000, 0x00_2000), // sifive_test (shutdown / reboot), goldfish RTC
+    (0x0c00_0000, 0x40_0000), // PLIC
+    (0x1000_0000, 0x00_1000), // 16550 UART
+];
+#[cfg(feature = "trace_uart")]
+pub const MMIO: &[(usize, usize)] = &[
+    (0x0010_0000, 0x00_2000), // sifive_test (shutdown / reboot), goldfish RTC
+    (0x0300_0000, 0x01_0000), // PCI I/O ports
+    (0x0c00_0000, 0x40_0000), // PLIC
+    (0x1000_0000, 0x00_1000), // 16550 UART
+    (0x3000_0000, 0x10_0000), // PCI configuration space of bus 0
+];
+#[cfg(not(feature = "board_qemu"))]
+pub const MMIO: &[(usize, usize)] = &[];
+#[cfg(feature = "board_qemu")]
+pub const PLIC_BASE: usize = 0x0c00_0000;
+#[cfg(feature = "board_qemu")]
+pub const UART_BASE: usize = 0x1000_0000;
+/// PCI configuration space, ECAM, of QEMU virt
+#[cfg(feature = "trace_uart")]
+pub const PCI_ECAM_BASE: usize = 0x3000_0000;
+/// Where PCI I/O port 0 of QEMU virt is
+#[cfg(feature = "trace_uart")]
+pub const PCI_PIO_BASE: usize = 0x0300_0000;
+/// I/O port the kernel puts the trace UART at
+#[cfg(feature = "trace_uart")]
+pub const TRACE_UART_PORT: usize = 0x1000;
+/// PLIC source of the UART
+#[cfg(feature = "board_qemu")]
+pub const IRQ_UART: usize = 10;
+/// Bytes of console input the kernel holds for readers
+#[cfg(feature = "board_qemu")]
+pub const CONSOLE_RX_RING_SIZE: usize = 4096;
+/// Whether user pages may be writable and executable at once, see the
+/// `allow_wx` feature
+pub const ALLOW_WX: bool = cfg!(feature = "allow_wx");
+/// Longest path a syscall takes, without the NUL
+pub const PATH_MAX: usize = 4096;
+/// Bytes the strings and the argv array of exec may take together, so that
+/// they fit in the first page of the user stack
+pub const ARG_MAX: usize = PAGE_SIZE / 2;
+/// Size caps of ramfs files, each and all together
+pub const RAMFS_FILE_MAX: usize = 64 * 1024;
+pub const RAMFS_TOTAL_MAX: usize = 256 * 1024;
 pub const BIG_STRIDE: isize = i8::MAX as isize;
+/// Number of harts that have a per-CPU block, see [`crate::percpu`]
+pub const MAX_HARTS: usize = 4;
+/// Mutexes there may be at once, over all processes
+pub const MUTEX_MAX: usize = 256;
+/// How many times locking an adaptive mutex checks it again before the task
+/// blocks, while the holder runs on another hart
+pub const MUTEX_SPIN_LIMIT: usize = 1000;
+/// Adaptive mutexes only spin with at least this many harts online, with
+/// fewer the holder cannot be running while the locker is
+pub const MUTEX_SPIN_MIN_HARTS: usize = 2;
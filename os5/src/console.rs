@@ -1,11 +1,29 @@
-//! SBI console driver, for text output
+//! Console input and output
+//!
+//! With the `board_qemu` feature it goes through the UART driver, otherwise
+//! through SBI calls.
 
-use crate::sbi::console_putchar;
+#[cfg(feature = "board_qemu")]
+use crate::drivers::uart;
+#[cfg(not(feature = "board_qemu"))]
+use crate::sbi::{console_getchar, console_putchar};
+#[cfg(not(feature = "board_qemu"))]
+use crate::sync::UPSafeCell;
+#[cfg(not(feature = "board_qemu"))]
+use lazy_static::*;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
 impl Write for Stdout {
+    #[cfg(feature = "board_qemu")]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            uart::putchar(byte);
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "board_qemu"))]
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for c in s.chars() {
             console_putchar(c as usize);
@@ -14,10 +32,88 @@ impl Write for Stdout {
     }
 }
 
+/// Push out buffered output as far as the device accepts it right now
+pub fn poll_output() {
+    #[cfg(feature = "board_qemu")]
+    uart::poll();
+    #[cfg(feature = "trace_uart")]
+    crate::trace::poll();
+}
+
+/// Wait until all buffered output has been handed to the device
+pub fn flush() {
+    #[cfg(feature = "board_qemu")]
+    uart::flush();
+}
+
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
 
+/// Output `bytes` as they are, whether they are UTF-8 or not
+pub fn write_bytes(bytes: &[u8]) {
+    for &byte in bytes {
+        #[cfg(feature = "board_qemu")]
+        uart::putchar(byte);
+        #[cfg(not(feature = "board_qemu"))]
+        console_putchar(byte as usize);
+    }
+}
+
+/// The next byte of console input, if there is one
+#[cfg(feature = "board_qemu")]
+pub fn getchar() -> Option<u8> {
+    uart::getchar()
+}
+
+/// The next byte of console input, if there is one
+#[cfg(not(feature = "board_qemu"))]
+pub fn getchar() -> Option<u8> {
+    PEEKED.exclusive_access().take().or_else(sbi_getchar)
+}
+
+#[cfg(not(feature = "board_qemu"))]
+fn sbi_getchar() -> Option<u8> {
+    match console_getchar() {
+        0 => None,
+        c => Some(c as u8),
+    }
+}
+
+#[cfg(not(feature = "board_qemu"))]
+lazy_static! {
+    /// A byte [`input_pending`] took from SBI, which cannot peek
+    static ref PEEKED: UPSafeCell<Option<u8>> = unsafe { UPSafeCell::new(None) };
+}
+
+/// Whether there is console input for [`getchar`]
+#[cfg(feature = "board_qemu")]
+pub fn input_pending() -> bool {
+    uart::rx_pending()
+}
+
+/// Whether there is console input for [`getchar`]
+#[cfg(not(feature = "board_qemu"))]
+pub fn input_pending() -> bool {
+    let mut peeked = PEEKED.exclusive_access();
+    if peeked.is_none() {
+        *peeked = sbi_getchar();
+    }
+    peeked.is_some()
+}
+
+/// Console input bytes lost so far, SBI does not tell
+#[cfg(feature = "board_qemu")]
+pub fn input_dropped() -> usize {
+    uart::rx_dropped()
+}
+
+/// Console input bytes lost so far, SBI does not tell
+#[cfg(not(feature = "board_qemu"))]
+pub fn input_dropped() -> usize {
+    0
+}
+
 #[macro_export]
 /// print string macro
 macro_rules! print {
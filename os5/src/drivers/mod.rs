@@ -0,0 +1,23 @@
+//! Device drivers of QEMU virt
+//!
+//! Only built with the `board_qemu` feature, other boards keep using SBI for
+//! the console and get no device interrupts.
+
+pub mod plic;
+#[cfg(feature = "trace_uart")]
+pub mod trace_uart;
+pub mod uart;
+
+use crate::config::IRQ_UART;
+
+/// Set up the interrupt controller for the boot hart and the devices that
+/// raise interrupts
+pub fn init() {
+    plic::init_hart();
+    uart::init();
+    plic::register_irq(IRQ_UART, uart::handle_irq);
+    #[cfg(feature = "trace_uart")]
+    if !trace_uart::init() {
+        warn!("[kernel] no trace UART, start QEMU with -device pci-serial");
+    }
+}
@@ -0,0 +1,92 @@
+//! Driver of the platform-level interrupt controller
+//!
+//! Every device interrupt reaches a hart as a supervisor external interrupt
+//! through the PLIC. [`handle_irq`] claims the pending IRQ, runs the handler
+//! registered for it with [`register_irq`] and completes it.
+//!
+//! Handlers run inside the trap handler with interrupts disabled and must not
+//! block: they do not run on behalf of any task, and switching away would
+//! leave the IRQ claimed. Both are asserted.
+
+use crate::config::PLIC_BASE;
+use crate::percpu::{hart_id, this_hart};
+use crate::sync::UPSafeCell;
+use core::sync::atomic::Ordering;
+use lazy_static::*;
+use riscv::register::sstatus;
+
+/// Number of interrupt sources of the QEMU virt PLIC
+pub const MAX_IRQ: usize = 96;
+
+const PRIORITY: usize = 0x0;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+/// Handler of one IRQ line
+pub type IrqHandler = fn();
+
+lazy_static! {
+    static ref HANDLERS: UPSafeCell<[Option<IrqHandler>; MAX_IRQ]> =
+        unsafe { UPSafeCell::new([None; MAX_IRQ]) };
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    (PLIC_BASE + offset) as *mut u32
+}
+
+/// The PLIC context of S-mode on `hart`, M-mode takes the even ones
+fn s_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+fn context_reg(offset: usize) -> *mut u32 {
+    reg(CONTEXT + s_context(hart_id()) * CONTEXT_STRIDE + offset)
+}
+
+/// Let every IRQ with a non-zero priority through on the current hart
+pub fn init_hart() {
+    unsafe {
+        context_reg(THRESHOLD).write_volatile(0);
+    }
+}
+
+/// Run `handler` whenever `irq` fires, and enable it on the current hart
+pub fn register_irq(irq: usize, handler: IrqHandler) {
+    assert!(irq > 0 && irq < MAX_IRQ, "invalid IRQ {}", irq);
+    HANDLERS.exclusive_access()[irq] = Some(handler);
+    let enable = reg(ENABLE + s_context(hart_id()) * ENABLE_STRIDE + irq / 32 * 4);
+    unsafe {
+        reg(PRIORITY + irq * 4).write_volatile(1);
+        enable.write_volatile(enable.read_volatile() | 1 << (irq % 32));
+    }
+}
+
+/// Serve a supervisor external interrupt
+pub fn handle_irq() {
+    assert!(
+        !sstatus::read().sie(),
+        "IRQ handler entered with interrupts enabled"
+    );
+    let claim = context_reg(CLAIM);
+    let irq = unsafe { claim.read_volatile() } as usize;
+    if irq == 0 {
+        // another hart got it first
+        return;
+    }
+    let handler = HANDLERS.exclusive_access()[irq];
+    match handler {
+        Some(handler) => {
+            this_hart().in_irq.store(true, Ordering::Relaxed);
+            handler();
+            this_hart().in_irq.store(false, Ordering::Relaxed);
+        }
+        None => warn!("[kernel] IRQ {} has no handler", irq),
+    }
+    unsafe {
+        claim.write_volatile(irq as u32);
+    }
+}
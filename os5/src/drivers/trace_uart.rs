@@ -0,0 +1,87 @@
+//! Write-only driver of the second serial port, where [`crate::trace`]
+//! frames go
+//!
+//! QEMU virt has a single 16550 of its own, the second one is a PCI serial
+//! card (`-device pci-serial`). Nothing assigns PCI resources before the
+//! kernel, so [`init`] looks for the card on bus 0, puts its I/O BAR at
+//! [`TRACE_UART_PORT`] and turns on I/O decoding. Transmitting is polled and
+//! never waits: [`try_send`] takes what the FIFO has room for.
+
+use crate::config::{PCI_ECAM_BASE, PCI_PIO_BASE, TRACE_UART_PORT};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Red Hat, Inc., QEMU's PCI vendor id
+const VENDOR_QEMU: u16 = 0x1b36;
+const DEVICE_SERIAL: u16 = 0x0002;
+const PCI_COMMAND: usize = 0x04;
+const PCI_BAR0: usize = 0x10;
+const COMMAND_IO_SPACE: u16 = 1 << 0;
+const PCI_SLOTS: usize = 32;
+
+const THR: usize = 0;
+const IER: usize = 1;
+const FCR: usize = 2;
+const LCR: usize = 3;
+const LSR: usize = 5;
+const FCR_ENABLE_AND_CLEAR: u8 = 0b111;
+const LCR_8N1: u8 = 0b11;
+const LSR_TX_EMPTY: u8 = 1 << 5;
+const FIFO_DEPTH: usize = 16;
+
+/// The card was found and set up
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+/// Configuration space of function 0 of `slot` on bus 0
+fn config_base(slot: usize) -> usize {
+    PCI_ECAM_BASE + (slot << 15)
+}
+
+fn write_reg(offset: usize, value: u8) {
+    unsafe { ((PCI_PIO_BASE + TRACE_UART_PORT + offset) as *mut u8).write_volatile(value) }
+}
+
+fn read_reg(offset: usize) -> u8 {
+    unsafe { ((PCI_PIO_BASE + TRACE_UART_PORT + offset) as *const u8).read_volatile() }
+}
+
+/// Find and set up the card, false if QEMU was started without one
+pub fn init() -> bool {
+    let slot = (0..PCI_SLOTS).find(|&slot| {
+        let id = unsafe { (config_base(slot) as *const u32).read_volatile() };
+        id == (DEVICE_SERIAL as u32) << 16 | VENDOR_QEMU as u32
+    });
+    let base = match slot {
+        Some(slot) => config_base(slot),
+        None => return false,
+    };
+    unsafe {
+        // an I/O BAR, the low bits are read-only
+        ((base + PCI_BAR0) as *mut u32).write_volatile(TRACE_UART_PORT as u32);
+        let command = (base + PCI_COMMAND) as *mut u16;
+        command.write_volatile(command.read_volatile() | COMMAND_IO_SPACE);
+    }
+    // the baud rate means nothing to an emulated card, the default stays
+    write_reg(IER, 0);
+    write_reg(LCR, LCR_8N1);
+    write_reg(FCR, FCR_ENABLE_AND_CLEAR);
+    PRESENT.store(true, Ordering::Release);
+    true
+}
+
+/// Whether [`init`] found the card
+pub fn present() -> bool {
+    PRESENT.load(Ordering::Acquire)
+}
+
+/// Hand bytes from the front of `bytes` to the transmit FIFO if it is empty,
+/// return how many it took
+pub fn try_send(bytes: &[u8]) -> usize {
+    if !present() || read_reg(LSR) & LSR_TX_EMPTY == 0 {
+        return 0;
+    }
+    let n = bytes.len().min(FIFO_DEPTH);
+    for &byte in &bytes[..n] {
+        write_reg(THR, byte);
+    }
+    n
+}
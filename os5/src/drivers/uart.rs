@@ -0,0 +1,265 @@
+//! Driver of the 16550 UART of QEMU virt
+//!
+//! Transmitting is polled. Bytes are queued in a small ring and moved to the
+//! transmit FIFO whenever it has room: on every [`putchar`], on the UART
+//! interrupt (the transmitter-empty interrupt is on while bytes are queued)
+//! and from the idle loop. A writer only spins once the ring is full.
+//!
+//! Every received byte raises [`crate::config::IRQ_UART`], [`handle_irq`]
+//! moves it to the receive ring, where [`getchar`] picks it up. Once the ring
+//! is nearly full the receive interrupt is turned off and RTS dropped, so
+//! input waits in the device until [`getchar`] has drained the ring below
+//! its low-water mark.
+
+use crate::config::{CONSOLE_RX_RING_SIZE, UART_BASE};
+use crate::sync::UPSafeCell;
+use lazy_static::*;
+
+/// Receiver buffer / transmitter holding register, divisor latch low
+const RBR_THR_DLL: usize = 0;
+/// Interrupt enable register, divisor latch high
+const IER_DLM: usize = 1;
+/// FIFO control register
+const FCR: usize = 2;
+/// Line control register
+const LCR: usize = 3;
+/// Modem control register
+const MCR: usize = 4;
+/// Line status register
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+/// Enable the FIFOs and clear both, interrupt on every received byte
+const FCR_ENABLE_AND_CLEAR: u8 = 0b111;
+const LCR_8N1: u8 = 0b11;
+const LCR_DLAB: u8 = 1 << 7;
+/// DTR, RTS and OUT2, which gates the interrupt line
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+const MCR_RTS: u8 = 1 << 1;
+const LSR_DATA_READY: u8 = 1 << 0;
+/// The FIFO was full and at least one byte got lost
+const LSR_OVERRUN: u8 = 1 << 1;
+const LSR_TX_EMPTY: u8 = 1 << 5;
+
+const FIFO_DEPTH: usize = 16;
+const CLOCK: usize = 3_686_400;
+const BAUD_RATE: usize = 115_200;
+
+const TX_RING_SIZE: usize = 512;
+const RX_RING_SIZE: usize = CONSOLE_RX_RING_SIZE;
+/// Stop receiving with room for one more FIFO left, go on at a quarter full
+const RX_HIGH_WATER: usize = RX_RING_SIZE - FIFO_DEPTH;
+const RX_LOW_WATER: usize = RX_RING_SIZE / 4;
+
+/// Fixed-size FIFO of bytes
+struct ByteRing<const N: usize> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> ByteRing<N> {
+    const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    /// Return false if the ring is full
+    fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buf[(self.head + self.len) % N] = byte;
+        self.len += 1;
+        true
+    }
+    fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+struct Uart {
+    tx: ByteRing<TX_RING_SIZE>,
+    rx: ByteRing<RX_RING_SIZE>,
+    /// Last value written to IER
+    ier: u8,
+    /// Reception is off until the receive ring drains
+    throttled: bool,
+    /// Received bytes lost because the receive ring was full
+    dropped: usize,
+}
+
+lazy_static! {
+    static ref UART: UPSafeCell<Uart> = unsafe {
+        UPSafeCell::new(Uart {
+            tx: ByteRing::new(),
+            rx: ByteRing::new(),
+            ier: 0,
+            throttled: false,
+            dropped: 0,
+        })
+    };
+}
+
+fn read_reg(offset: usize) -> u8 {
+    unsafe { ((UART_BASE + offset) as *const u8).read_volatile() }
+}
+
+fn write_reg(offset: usize, value: u8) {
+    unsafe { ((UART_BASE + offset) as *mut u8).write_volatile(value) }
+}
+
+impl Uart {
+    fn set_ier(&mut self, ier: u8) {
+        if ier != self.ier {
+            self.ier = ier;
+            write_reg(IER_DLM, ier);
+        }
+    }
+    /// Move queued bytes to the transmit FIFO if it is empty
+    fn drain_tx(&mut self) {
+        if read_reg(LSR) & LSR_TX_EMPTY != 0 {
+            for _ in 0..FIFO_DEPTH {
+                match self.tx.pop() {
+                    Some(byte) => write_reg(RBR_THR_DLL, byte),
+                    None => break,
+                }
+            }
+        }
+        // wake us up when the FIFO is empty again if there is more to send
+        let ier = if self.tx.is_empty() {
+            self.ier & !IER_TX_EMPTY
+        } else {
+            self.ier | IER_TX_EMPTY
+        };
+        self.set_ier(ier);
+    }
+    /// Turn reception off above the high-water mark and back on below the
+    /// low-water mark
+    fn update_rx_flow(&mut self) {
+        let throttled = if self.throttled {
+            self.rx.len() > RX_LOW_WATER
+        } else {
+            self.rx.len() >= RX_HIGH_WATER
+        };
+        if throttled == self.throttled {
+            return;
+        }
+        self.throttled = throttled;
+        if throttled {
+            write_reg(MCR, MCR_DTR_RTS_OUT2 & !MCR_RTS);
+            self.set_ier(self.ier & !IER_RX_AVAILABLE);
+        } else {
+            write_reg(MCR, MCR_DTR_RTS_OUT2);
+            // raises the interrupt at once for whatever waited in the FIFO
+            self.set_ier(self.ier | IER_RX_AVAILABLE);
+        }
+    }
+}
+
+/// Set the line to 115200 8N1, enable the FIFOs and the receive interrupt
+pub fn init() {
+    let divisor = CLOCK / (16 * BAUD_RATE);
+    let mut uart = UART.exclusive_access();
+    // the transmit ring may already hold boot messages
+    while !uart.tx.is_empty() {
+        uart.drain_tx();
+    }
+    write_reg(IER_DLM, 0);
+    write_reg(LCR, LCR_DLAB);
+    write_reg(RBR_THR_DLL, divisor as u8);
+    write_reg(IER_DLM, (divisor >> 8) as u8);
+    write_reg(LCR, LCR_8N1);
+    write_reg(FCR, FCR_ENABLE_AND_CLEAR);
+    write_reg(MCR, MCR_DTR_RTS_OUT2);
+    uart.ier = IER_RX_AVAILABLE;
+    write_reg(IER_DLM, IER_RX_AVAILABLE);
+}
+
+/// Queue `byte` for transmission, spinning while the ring is full
+pub fn putchar(byte: u8) {
+    let mut uart = UART.exclusive_access();
+    while uart.tx.is_full() {
+        uart.drain_tx();
+    }
+    uart.tx.push(byte);
+    uart.drain_tx();
+}
+
+/// Send whatever the transmit FIFO has room for
+pub fn poll() {
+    UART.exclusive_access().drain_tx();
+}
+
+/// Wait until every queued byte is in the transmit FIFO
+pub fn flush() {
+    let mut uart = UART.exclusive_access();
+    while !uart.tx.is_empty() {
+        uart.drain_tx();
+    }
+}
+
+/// The next received byte, if there is one
+pub fn getchar() -> Option<u8> {
+    let mut uart = UART.exclusive_access();
+    let byte = uart.rx.pop();
+    uart.update_rx_flow();
+    byte
+}
+
+/// Whether the receive ring holds a byte
+pub fn rx_pending() -> bool {
+    !UART.exclusive_access().rx.is_empty()
+}
+
+/// Received bytes dropped so far
+pub fn rx_dropped() -> usize {
+    UART.exclusive_access().dropped
+}
+
+/// Drain the receiver into the receive ring and feed the transmitter
+pub fn handle_irq() {
+    let mut uart = UART.exclusive_access();
+    let mut overrun = false;
+    // throttled, bytes wait in the FIFO
+    while !uart.throttled {
+        let lsr = read_reg(LSR);
+        if lsr & LSR_OVERRUN != 0 {
+            uart.dropped += 1;
+            overrun = true;
+        }
+        if lsr & LSR_DATA_READY == 0 {
+            break;
+        }
+        let byte = read_reg(RBR_THR_DLL);
+        if !uart.rx.push(byte) {
+            uart.dropped += 1;
+            overrun = true;
+        }
+        uart.update_rx_flow();
+    }
+    uart.drain_tx();
+    // printing needs the UART itself
+    drop(uart);
+    if overrun {
+        warn!("[kernel] console input overrun, bytes dropped");
+    }
+}
@@ -1,6 +1,8 @@
     .section .text.entry
     .globl _start
 _start:
+    # a0 = hart id from SBI, kept in tp for the whole life of the kernel
+    mv tp, a0
     la sp, boot_stack_top
     call rust_main
 
@@ -9,4 +11,11 @@ _start:
 boot_stack:
     .space 4096 * 16
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
+    .section .text
+    .globl _secondary_start
+_secondary_start:
+    # a0 = hart id, a1 = top of the stack hotplug::cpu_up gave it
+    mv tp, a0
+    mv sp, a1
+    call rust_secondary_main
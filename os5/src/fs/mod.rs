@@ -0,0 +1,267 @@
+//! Files that tasks can hold in their fd table
+
+mod pipe;
+mod ramfs;
+mod stdio;
+
+use crate::mm::{register_shrinker, FrameTracker, Memory, UserBuffer};
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The common abstraction of all IO resources
+pub trait File: Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    fn kind(&self) -> FileKind;
+    /// The id both ends of a pipe share and whether the other end is still
+    /// open, `None` for any other file
+    fn pipe_ends(&self) -> Option<(usize, bool)> {
+        None
+    }
+    fn read(&self, buf: UserBuffer) -> usize;
+    fn write(&self, buf: UserBuffer) -> usize;
+    /// Read what there is now, `None` rather than waiting if that is
+    /// nothing. A file that is no stream never blocks
+    fn read_nonblock(&self, buf: UserBuffer) -> Option<usize> {
+        Some(self.read(buf))
+    }
+    /// Write what fits now, `None` rather than waiting if nothing does
+    fn write_nonblock(&self, buf: UserBuffer) -> Option<usize> {
+        Some(self.write(buf))
+    }
+    /// Current size, `None` for a stream that cannot seek
+    fn size(&self) -> Option<usize> {
+        None
+    }
+    /// Read from `offset`, a stream ignores it
+    fn read_at(&self, _offset: usize, buf: UserBuffer) -> usize {
+        self.read(buf)
+    }
+    /// Write at `offset`, a stream ignores it
+    fn write_at(&self, _offset: usize, buf: UserBuffer) -> usize {
+        self.write(buf)
+    }
+    /// Whether [`File::write_page`] would take a whole page now
+    fn accepts_page(&self) -> bool {
+        false
+    }
+    /// Write the page in `frame`, lent by the writer instead of copied, false
+    /// if nothing reads it any more. Only called after [`File::accepts_page`]
+    fn write_page(&self, _frame: Arc<FrameTracker>) -> bool {
+        unreachable!("file takes no pages");
+    }
+    /// The `POLL*` bits of what would not block now, a file that is no
+    /// stream never blocks
+    fn poll(&self) -> u16 {
+        let mut events = 0;
+        if self.readable() {
+            events |= POLLIN;
+        }
+        if self.writable() {
+            events |= POLLOUT;
+        }
+        events
+    }
+}
+
+/// Events of `sys_ppoll`, as in Linux: there is data to read
+pub const POLLIN: u16 = 0x1;
+/// A write would not block
+pub const POLLOUT: u16 = 0x4;
+/// The read end of the pipe written to is closed
+pub const POLLERR: u16 = 0x8;
+/// The write end of the pipe read from is closed
+pub const POLLHUP: u16 = 0x10;
+/// The fd is not open
+pub const POLLNVAL: u16 = 0x20;
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// What an fd refers to, as `sys_fd_info` reports it
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileKind {
+    Stdin = 0,
+    Stdout = 1,
+    PipeRead = 2,
+    PipeWrite = 3,
+    RamFile = 4,
+}
+
+/// What [`OpenFile::seek`] can fail with
+pub enum SeekError {
+    /// The file is a stream
+    NotSeekable,
+    /// Bad `whence`, or a result that is negative or overflows
+    Invalid,
+}
+
+/// An open file description: the file, the offset for reading and
+/// writing it and whether that may block. fds dup'd from one another share
+/// it.
+pub struct OpenFile {
+    file: Arc<dyn File>,
+    offset: UPSafeCell<usize>,
+    /// [`O_NONBLOCK`]
+    nonblock: AtomicBool,
+}
+
+impl OpenFile {
+    pub fn new(file: Arc<dyn File>) -> Self {
+        Self {
+            file,
+            offset: unsafe { UPSafeCell::new(0) },
+            nonblock: AtomicBool::new(false),
+        }
+    }
+    pub fn nonblock(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+    pub fn readable(&self) -> bool {
+        self.file.readable()
+    }
+    pub fn writable(&self) -> bool {
+        self.file.writable()
+    }
+    pub fn kind(&self) -> FileKind {
+        self.file.kind()
+    }
+    pub fn pipe_ends(&self) -> Option<(usize, bool)> {
+        self.file.pipe_ends()
+    }
+    pub fn poll(&self) -> u16 {
+        self.file.poll()
+    }
+    /// Whether the file is a stream, whose reads may block
+    pub fn is_stream(&self) -> bool {
+        self.file.size().is_none()
+    }
+    /// Read from the offset and move it past what was read, `None` if the
+    /// file is a stream with [`O_NONBLOCK`] that has nothing to read now
+    pub fn read(&self, buf: UserBuffer) -> Option<usize> {
+        if self.nonblock() && self.is_stream() {
+            return self.file.read_nonblock(buf);
+        }
+        // not borrowed across the read, a pipe read may block
+        let offset = *self.offset.exclusive_access();
+        let n = self.file.read_at(offset, buf);
+        *self.offset.exclusive_access() = offset + n;
+        Some(n)
+    }
+    /// Write at the offset and move it past what was written, `None` as
+    /// for [`Self::read`]
+    pub fn write(&self, buf: UserBuffer) -> Option<usize> {
+        if self.nonblock() && self.is_stream() {
+            return self.file.write_nonblock(buf);
+        }
+        let offset = *self.offset.exclusive_access();
+        let n = self.file.write_at(offset, buf);
+        *self.offset.exclusive_access() = offset + n;
+        Some(n)
+    }
+    /// Whether a page may be lent to the file, see [`File::accepts_page`].
+    /// Only streams that may block take pages, the offset is left alone
+    pub fn accepts_page(&self) -> bool {
+        self.is_stream() && !self.nonblock() && self.file.accepts_page()
+    }
+    pub fn write_page(&self, frame: Arc<FrameTracker>) -> bool {
+        self.file.write_page(frame)
+    }
+    /// Move the offset like `lseek` and return it
+    pub fn seek(&self, offset: isize, whence: usize) -> Result<usize, SeekError> {
+        let size = self.file.size().ok_or(SeekError::NotSeekable)?;
+        let mut current = self.offset.exclusive_access();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => *current,
+            SEEK_END => size,
+            _ => return Err(SeekError::Invalid),
+        };
+        let new = isize::try_from(base)
+            .ok()
+            .and_then(|base| base.checked_add(offset))
+            .filter(|&new| new >= 0)
+            .ok_or(SeekError::Invalid)?;
+        *current = new as usize;
+        Ok(*current)
+    }
+}
+
+/// Flag of `sys_pipe` and `sys_dup3` for fds closed on exec
+pub const O_CLOEXEC: u32 = 1 << 19;
+/// Flag of `sys_pipe` and `F_SETFL` for streams that fail with `-EAGAIN`
+/// rather than block
+pub const O_NONBLOCK: u32 = 1 << 11;
+/// fd flag closing the fd on exec, for `F_GETFD` / `F_SETFD`
+pub const FD_CLOEXEC: usize = 1;
+/// fds from here on cannot be opened with `sys_dup3`
+pub const MAX_FD: usize = 1024;
+
+bitflags! {
+    /// Flags for opening files
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+        const CLOEXEC = O_CLOEXEC;
+    }
+}
+
+impl OpenFlags {
+    /// Whether the file is opened for reading and for writing
+    pub fn read_write(&self) -> (bool, bool) {
+        let access = *self & (Self::WRONLY | Self::RDWR);
+        if access.is_empty() {
+            (true, false)
+        } else if access.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// An entry of the fd table
+#[derive(Clone)]
+pub struct FileDescriptor {
+    pub file: Arc<OpenFile>,
+    /// Dropped by exec
+    pub cloexec: bool,
+}
+
+impl FileDescriptor {
+    /// An fd for a new open file description of `file`
+    pub fn new(file: Arc<dyn File>, cloexec: bool) -> Self {
+        Self {
+            file: Arc::new(OpenFile::new(file)),
+            cloexec,
+        }
+    }
+    /// Another fd for the same open file description, sharing the offset
+    pub fn dup(&self, cloexec: bool) -> Self {
+        Self {
+            file: self.file.clone(),
+            cloexec,
+        }
+    }
+}
+
+pub use pipe::{lent_pages, make_pipe};
+pub use ramfs::{
+    normalize_path, open_ram_file, ram_dir_exists, read_ram_file, write_ram_file,
+};
+pub use stdio::{Stdin, Stdout};
+
+/// Let the heap have back what ramfs files do not use
+pub fn init() {
+    register_shrinker("ramfs slack", Memory::Heap, ramfs::shrink_ram_files);
+}
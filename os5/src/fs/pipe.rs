@@ -0,0 +1,361 @@
+use super::{File, FileKind, POLLERR, POLLHUP, POLLIN, POLLOUT};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use crate::mm::{FrameTracker, UserBuffer};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::task::{
+    mark_interrupted, set_block_reason, signal_pending, suspend_current_and_run_next, BlockReason,
+};
+
+/// One end of a pipe
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    buffer: Arc<UPSafeCell<PipeRingBuffer>>,
+}
+
+impl Pipe {
+    /// Create the read end of a pipe from a ring buffer
+    pub fn read_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+        Self {
+            readable: true,
+            writable: false,
+            buffer,
+        }
+    }
+    /// Create the write end of a pipe with a ring buffer
+    pub fn write_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+        Self {
+            readable: false,
+            writable: true,
+            buffer,
+        }
+    }
+    /// Whether the read end of the pipe of this write end is closed, the
+    /// buffer is then held by the write end only
+    fn reader_closed(&self) -> bool {
+        Arc::strong_count(&self.buffer) == 1
+    }
+}
+
+const RING_BUFFER_SIZE: usize = 32;
+/// Lent pages a pipe holds at most, the writer waits for the reader beyond
+const MAX_LENT_PAGES: usize = 16;
+
+/// Id of the next pipe
+static NEXT_PIPE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Pages all pipes took lent instead of copied
+static LENT_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn lent_pages() -> usize {
+    LENT_PAGES.load(Ordering::Relaxed)
+}
+
+/// A whole page of a writer, read from `start` on
+struct LentPage {
+    frame: Arc<FrameTracker>,
+    start: usize,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RingBufferStatus {
+    FULL,
+    EMPTY,
+    NORMAL,
+}
+
+/// The underlying ring buffer of a pipe
+///
+/// Whole pages lent by the writer queue up behind the bytes, they are only
+/// taken while the ring is empty and no byte is written while they wait.
+pub struct PipeRingBuffer {
+    arr: [u8; RING_BUFFER_SIZE],
+    head: usize,
+    tail: usize,
+    status: RingBufferStatus,
+    write_end: Option<Weak<Pipe>>,
+    pages: VecDeque<LentPage>,
+    /// A reader is blocked for data
+    reader_waiting: bool,
+    /// Reported for both ends by `sys_fd_info`
+    id: usize,
+}
+
+impl PipeRingBuffer {
+    pub fn new() -> Self {
+        Self {
+            arr: [0; RING_BUFFER_SIZE],
+            head: 0,
+            tail: 0,
+            status: RingBufferStatus::EMPTY,
+            write_end: None,
+            pages: VecDeque::new(),
+            reader_waiting: false,
+            id: NEXT_PIPE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+    /// Set the write end bound to this buffer
+    pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
+        self.write_end = Some(Arc::downgrade(write_end));
+    }
+    /// Write into the buffer
+    pub fn write_byte(&mut self, byte: u8) {
+        self.status = RingBufferStatus::NORMAL;
+        self.arr[self.tail] = byte;
+        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
+        if self.tail == self.head {
+            self.status = RingBufferStatus::FULL;
+        }
+    }
+    /// Read from the buffer
+    pub fn read_byte(&mut self) -> u8 {
+        self.status = RingBufferStatus::NORMAL;
+        let c = self.arr[self.head];
+        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        if self.head == self.tail {
+            self.status = RingBufferStatus::EMPTY;
+        }
+        c
+    }
+    /// Get the length of remaining data in the buffer
+    pub fn available_read(&self) -> usize {
+        if self.status == RingBufferStatus::EMPTY {
+            0
+        } else {
+            if self.tail > self.head {
+                self.tail - self.head
+            } else {
+                self.tail + RING_BUFFER_SIZE - self.head
+            }
+        }
+    }
+    /// Get the length of remaining space in the buffer, none while lent
+    /// pages wait
+    pub fn available_write(&self) -> usize {
+        if self.status == RingBufferStatus::FULL || !self.pages.is_empty() {
+            0
+        } else {
+            RING_BUFFER_SIZE - self.available_read()
+        }
+    }
+    /// Check if all write ends bounded to this buffer are closed
+    pub fn all_write_ends_closed(&self) -> bool {
+        self.write_end.as_ref().unwrap().upgrade().is_none()
+    }
+    /// Move bytes to `dst` from the ring or, once it is empty, from the first
+    /// lent page. Return how many
+    fn read_into(&mut self, dst: &mut [u8]) -> usize {
+        let in_ring = self.available_read();
+        if in_ring > 0 {
+            let n = in_ring.min(dst.len());
+            for byte in dst[..n].iter_mut() {
+                *byte = self.read_byte();
+            }
+            return n;
+        }
+        let page = match self.pages.front_mut() {
+            Some(page) => page,
+            None => return 0,
+        };
+        let src = &page.frame.ppn.get_bytes_array()[page.start..];
+        let n = src.len().min(dst.len());
+        dst[..n].copy_from_slice(&src[..n]);
+        page.start += n;
+        if page.start == PAGE_SIZE {
+            // the writer gets its frame back for free on its next write
+            self.pages.pop_front();
+        }
+        n
+    }
+}
+
+/// Create a pipe
+/// return (read_end, write_end)
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer = Arc::new(unsafe {
+        UPSafeCell::new(PipeRingBuffer::new())
+    });
+    let read_end = Arc::new(
+        Pipe::read_end_with_buffer(buffer.clone())
+    );
+    let write_end = Arc::new(
+        Pipe::write_end_with_buffer(buffer.clone())
+    );
+    buffer.exclusive_access().set_write_end(&write_end);
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool { self.readable }
+    fn writable(&self) -> bool { self.writable }
+    fn kind(&self) -> FileKind {
+        if self.readable {
+            FileKind::PipeRead
+        } else {
+            FileKind::PipeWrite
+        }
+    }
+    /// Each end holds the buffer once, however many fds refer to it
+    fn pipe_ends(&self) -> Option<(usize, bool)> {
+        let id = self.buffer.exclusive_access().id;
+        Some((id, Arc::strong_count(&self.buffer) > 1))
+    }
+    fn poll(&self) -> u16 {
+        let other_end_open = Arc::strong_count(&self.buffer) > 1;
+        let ring_buffer = self.buffer.exclusive_access();
+        let mut events = 0;
+        if self.readable {
+            if ring_buffer.available_read() > 0 || !ring_buffer.pages.is_empty() {
+                events |= POLLIN;
+            }
+            if !other_end_open {
+                events |= POLLHUP;
+            }
+        } else if !other_end_open {
+            events |= POLLERR;
+        } else if ring_buffer.available_write() > 0 {
+            events |= POLLOUT;
+        }
+        events
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        assert!(self.readable());
+        let mut buffers = buf.buffers.into_iter();
+        let mut dst: &'static mut [u8] = &mut [];
+        let mut read_size = 0usize;
+        loop {
+            if dst.is_empty() {
+                match buffers.next() {
+                    Some(next) => dst = next,
+                    None => return read_size,
+                }
+                continue;
+            }
+            let mut ring_buffer = self.buffer.exclusive_access();
+            let n = ring_buffer.read_into(dst);
+            if n == 0 {
+                if ring_buffer.all_write_ends_closed() {
+                    return read_size;
+                }
+                // whole pages may be lent to us meanwhile
+                ring_buffer.reader_waiting = true;
+                let id = ring_buffer.id;
+                drop(ring_buffer);
+                if signal_pending() {
+                    self.buffer.exclusive_access().reader_waiting = false;
+                    // what was read so far is kept, only an empty read fails
+                    if read_size == 0 {
+                        mark_interrupted();
+                    }
+                    return read_size;
+                }
+                set_block_reason(Some(BlockReason::PipeRead(id)));
+                suspend_current_and_run_next();
+                set_block_reason(None);
+                continue;
+            }
+            ring_buffer.reader_waiting = false;
+            dst = &mut core::mem::take(&mut dst)[n..];
+            read_size += n;
+        }
+    }
+    /// What is in the pipe now, `None` if that is nothing while it has a
+    /// writer
+    fn read_nonblock(&self, buf: UserBuffer) -> Option<usize> {
+        assert!(self.readable());
+        if buf.len() == 0 {
+            return Some(0);
+        }
+        let mut ring_buffer = self.buffer.exclusive_access();
+        let mut read_size = 0usize;
+        for dst in buf.buffers {
+            let mut dst = &mut dst[..];
+            while !dst.is_empty() {
+                let n = ring_buffer.read_into(dst);
+                if n == 0 {
+                    break;
+                }
+                dst = &mut dst[n..];
+                read_size += n;
+            }
+            if !dst.is_empty() {
+                break;
+            }
+        }
+        if read_size == 0 && !ring_buffer.all_write_ends_closed() {
+            return None;
+        }
+        Some(read_size)
+    }
+    /// Only to a blocked reader, or after pages it has not read yet
+    fn accepts_page(&self) -> bool {
+        let ring_buffer = self.buffer.exclusive_access();
+        self.writable
+            && ring_buffer.available_read() == 0
+            && (ring_buffer.reader_waiting || !ring_buffer.pages.is_empty())
+    }
+    fn write_page(&self, frame: Arc<FrameTracker>) -> bool {
+        loop {
+            if self.reader_closed() {
+                return false;
+            }
+            let mut ring_buffer = self.buffer.exclusive_access();
+            if ring_buffer.pages.len() < MAX_LENT_PAGES {
+                ring_buffer.pages.push_back(LentPage { frame, start: 0 });
+                LENT_PAGES.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+            drop(ring_buffer);
+            suspend_current_and_run_next();
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        assert!(self.writable());
+        let mut buf_iter = buf.into_iter();
+        let mut write_size = 0usize;
+        loop {
+            // what is written so far counts, the next write fails
+            if self.reader_closed() {
+                return write_size;
+            }
+            let mut ring_buffer = self.buffer.exclusive_access();
+            let loop_write = ring_buffer.available_write();
+            if loop_write == 0 {
+                drop(ring_buffer);
+                suspend_current_and_run_next();
+                continue;
+            }
+            // write at most loop_write bytes
+            for _ in 0..loop_write {
+                if let Some(byte_ref) = buf_iter.next() {
+                    ring_buffer.write_byte(unsafe { *byte_ref });
+                    write_size += 1;
+                } else {
+                    return write_size;
+                }
+            }
+        }
+    }
+    /// What fits in the ring now, `None` if no byte does. As for
+    /// [`Self::write`] nothing is written once the reader is gone
+    fn write_nonblock(&self, buf: UserBuffer) -> Option<usize> {
+        assert!(self.writable());
+        if self.reader_closed() || buf.len() == 0 {
+            return Some(0);
+        }
+        let mut ring_buffer = self.buffer.exclusive_access();
+        let mut write_size = 0usize;
+        for byte_ref in buf.into_iter().take(ring_buffer.available_write()) {
+            ring_buffer.write_byte(unsafe { *byte_ref });
+            write_size += 1;
+        }
+        if write_size == 0 {
+            return None;
+        }
+        Some(write_size)
+    }
+}
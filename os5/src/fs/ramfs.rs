@@ -0,0 +1,236 @@
+//! In-memory files that live until reboot
+//!
+//! There are no directories, a path is just the name of a file in one global
+//! registry. Names are absolute and normalized, and a directory is just a
+//! prefix of some names up to a `/`. Reads and writes go to the offset of the
+//! open file, a write past the end fills the gap with zeros. The size of every
+//! file and of all files together is capped so that a runaway writer cannot
+//! use up the kernel heap.
+
+use super::{File, FileKind, OpenFlags};
+use crate::config::{RAMFS_FILE_MAX, RAMFS_TOTAL_MAX};
+use crate::mm::{UserBuffer, SHRINK_HARD};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// The content of a named file
+pub struct RamInode {
+    data: UPSafeCell<Vec<u8>>,
+}
+
+struct RamFs {
+    files: BTreeMap<String, Arc<RamInode>>,
+    /// Bytes in all files
+    total: usize,
+}
+
+lazy_static! {
+    static ref RAMFS: UPSafeCell<RamFs> = unsafe {
+        UPSafeCell::new(RamFs {
+            files: BTreeMap::new(),
+            total: 0,
+        })
+    };
+}
+
+/// Room past its data a file keeps unless the heap is short enough for
+/// [`SHRINK_HARD`]
+const RAMFS_SLACK_KEEP: usize = 4096;
+
+/// Give back the heap the file buffers hold past their data, a shrinker
+/// of the heap. Buffers are copied rather than shrunk in place, which would
+/// panic if the heap has no room for the copy
+pub fn shrink_ram_files(level: usize) -> usize {
+    let keep = if level >= SHRINK_HARD { 0 } else { RAMFS_SLACK_KEEP };
+    let fs = match RAMFS.try_exclusive_access() {
+        Some(fs) => fs,
+        None => return 0,
+    };
+    let mut freed = 0;
+    for inode in fs.files.values() {
+        let mut data = match inode.data.try_exclusive_access() {
+            Some(data) => data,
+            None => continue,
+        };
+        if data.capacity() - data.len() <= keep {
+            continue;
+        }
+        let mut copy = Vec::new();
+        if copy.try_reserve_exact(data.len()).is_err() {
+            continue;
+        }
+        copy.extend_from_slice(&data);
+        freed += data.capacity() - copy.capacity();
+        *data = copy;
+    }
+    freed
+}
+
+/// A ramfs file opened for reading and/or writing
+pub struct RamFile {
+    readable: bool,
+    writable: bool,
+    inode: Arc<RamInode>,
+}
+
+/// The absolute form of `path` taken relative to `cwd`, with empty, `.`
+/// and `..` components resolved. `..` of the root is the root
+pub fn normalize_path(cwd: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    let start = if path.starts_with('/') { "" } else { cwd };
+    for part in start.split('/').chain(path.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    let mut normalized = String::new();
+    for part in parts {
+        normalized.push('/');
+        normalized.push_str(part);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// Whether the normalized `dir` is the root or a prefix of some file
+pub fn ram_dir_exists(dir: &str) -> bool {
+    if dir == "/" {
+        return true;
+    }
+    let fs = RAMFS.exclusive_access();
+    let found = fs.files.keys().any(|name| {
+        name.len() > dir.len() && name.starts_with(dir) && name.as_bytes()[dir.len()] == b'/'
+    });
+    found
+}
+
+/// Open the file `name`, creating it with [`OpenFlags::CREATE`]
+pub fn open_ram_file(name: &str, flags: OpenFlags) -> Option<Arc<RamFile>> {
+    let (readable, writable) = flags.read_write();
+    let mut fs = RAMFS.exclusive_access();
+    let inode = match fs.files.get(name) {
+        Some(inode) => inode.clone(),
+        None if flags.contains(OpenFlags::CREATE) => {
+            let inode = Arc::new(RamInode {
+                data: unsafe { UPSafeCell::new(Vec::new()) },
+            });
+            fs.files.insert(String::from(name), inode.clone());
+            inode
+        }
+        None => return None,
+    };
+    if flags.contains(OpenFlags::TRUNC) {
+        let mut data = inode.data.exclusive_access();
+        fs.total -= data.len();
+        *data = Vec::new();
+    }
+    Some(Arc::new(RamFile {
+        readable,
+        writable,
+        inode,
+    }))
+}
+
+/// Replace the content of `name` with `data`, creating it if need be.
+/// `false`, and no file is created or changed, if `data` does not fit the
+/// caps
+pub fn write_ram_file(name: &str, data: &[u8]) -> bool {
+    let mut fs = RAMFS.exclusive_access();
+    let old = fs.files.get(name).map_or(0, |inode| inode.data.exclusive_access().len());
+    if data.len() > RAMFS_FILE_MAX || fs.total - old + data.len() > RAMFS_TOTAL_MAX {
+        return false;
+    }
+    let inode = fs
+        .files
+        .entry(String::from(name))
+        .or_insert_with(|| {
+            Arc::new(RamInode {
+                data: unsafe { UPSafeCell::new(Vec::new()) },
+            })
+        })
+        .clone();
+    *inode.data.exclusive_access() = Vec::from(data);
+    fs.total = fs.total - old + data.len();
+    true
+}
+
+/// A copy of the content of `name`, `None` if there is no such file
+pub fn read_ram_file(name: &str) -> Option<Vec<u8>> {
+    let fs = RAMFS.exclusive_access();
+    let data = fs.files.get(name)?.data.exclusive_access().clone();
+    Some(data)
+}
+
+impl File for RamFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn kind(&self) -> FileKind {
+        FileKind::RamFile
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.read_at(0, buf)
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let end = self.inode.data.exclusive_access().len();
+        self.write_at(end, buf)
+    }
+    fn size(&self) -> Option<usize> {
+        Some(self.inode.data.exclusive_access().len())
+    }
+    /// Read up to the end of the file, 0 at or past it
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> usize {
+        let data = self.inode.data.exclusive_access();
+        let mut pos = offset;
+        for buffer in buf.buffers {
+            if pos >= data.len() {
+                break;
+            }
+            let n = buffer.len().min(data.len() - pos);
+            buffer[..n].copy_from_slice(&data[pos..pos + n]);
+            pos += n;
+            if n < buffer.len() {
+                break;
+            }
+        }
+        pos - offset
+    }
+    /// Write as much as the caps allow, 0 if the file is out of space. The
+    /// zeros filling a gap up to `offset` count against the caps too
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> usize {
+        let mut fs = RAMFS.exclusive_access();
+        let mut data = self.inode.data.exclusive_access();
+        let mut pos = offset;
+        for buffer in buf.buffers.iter() {
+            // the file may grow to `limit` bytes
+            let limit = RAMFS_FILE_MAX.min(data.len() + (RAMFS_TOTAL_MAX - fs.total));
+            if pos >= limit {
+                break;
+            }
+            let n = buffer.len().min(limit - pos);
+            if data.len() < pos + n {
+                fs.total += pos + n - data.len();
+                data.resize(pos + n, 0);
+            }
+            data[pos..pos + n].copy_from_slice(&buffer[..n]);
+            pos += n;
+            if n < buffer.len() {
+                break;
+            }
+        }
+        pos - offset
+    }
+}
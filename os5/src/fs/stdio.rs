@@ -0,0 +1,123 @@
+use super::{File, FileKind, POLLIN};
+use crate::console::{getchar, input_pending, write_bytes};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::{current_task, mark_interrupted, signal_pending, suspend_current_and_run_next};
+use alloc::collections::VecDeque;
+use lazy_static::*;
+
+lazy_static! {
+    /// Pids of the tasks reading stdin, served in the order they came
+    static ref READERS: UPSafeCell<VecDeque<usize>> =
+        unsafe { UPSafeCell::new(VecDeque::new()) };
+}
+
+/// The standard input
+pub struct Stdin;
+/// The standard output
+pub struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn kind(&self) -> FileKind {
+        FileKind::Stdin
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn poll(&self) -> u16 {
+        if input_pending() {
+            POLLIN
+        } else {
+            0
+        }
+    }
+    /// Read up to a newline or a full buffer, the next reader only gets its
+    /// turn after that so a line is never split between two readers
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        let pid = current_task().unwrap().pid.0;
+        READERS.exclusive_access().push_back(pid);
+        let mut bytes = user_buf.into_iter();
+        let mut next = bytes.next();
+        let mut read_size = 0usize;
+        while let Some(dst) = next {
+            let our_turn = READERS.exclusive_access().front() == Some(&pid);
+            if let Some(ch) = if our_turn { getchar() } else { None } {
+                unsafe {
+                    dst.write_volatile(ch);
+                }
+                read_size += 1;
+                if ch == b'\n' || ch == b'\r' {
+                    break;
+                }
+                next = bytes.next();
+                continue;
+            }
+            if signal_pending() {
+                // what was read so far is kept, only an empty read fails
+                if read_size == 0 {
+                    mark_interrupted();
+                }
+                break;
+            }
+            suspend_current_and_run_next();
+        }
+        READERS.exclusive_access().retain(|&reader| reader != pid);
+        read_size
+    }
+    /// What is typed so far, up to a newline, `None` if that is nothing or
+    /// a blocking reader is still served
+    fn read_nonblock(&self, user_buf: UserBuffer) -> Option<usize> {
+        if user_buf.len() == 0 {
+            return Some(0);
+        }
+        if !READERS.exclusive_access().is_empty() || !input_pending() {
+            return None;
+        }
+        let mut read_size = 0usize;
+        for dst in user_buf.into_iter() {
+            let ch = match getchar() {
+                Some(ch) => ch,
+                None => break,
+            };
+            unsafe {
+                dst.write_volatile(ch);
+            }
+            read_size += 1;
+            if ch == b'\n' || ch == b'\r' {
+                break;
+            }
+        }
+        if read_size == 0 {
+            return None;
+        }
+        Some(read_size)
+    }
+    fn write(&self, _user_buf: UserBuffer) -> usize {
+        panic!("Cannot write to stdin!");
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn kind(&self) -> FileKind {
+        FileKind::Stdout
+    }
+    fn read(&self, _user_buf: UserBuffer) -> usize {
+        panic!("Cannot read from stdout!");
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        // a character may be split across two buffers, or not be UTF-8 at all
+        for buffer in user_buf.buffers.iter() {
+            write_bytes(buffer);
+        }
+        user_buf.len()
+    }
+}
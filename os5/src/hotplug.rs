@@ -0,0 +1,113 @@
+//! Taking harts down and bringing them back up
+//!
+//! [`cpu_down`] makes a hart reschedule, which puts the task it runs back on
+//! the ready queue, and stop at its next pass through the scheduler through
+//! SBI `hart_stop`. It is offline from then on, so the task manager hands it
+//! no task and no IPI is sent to it. [`cpu_up`] starts it again through SBI
+//! `hart_start` at `_secondary_start`, on a stack of its own, and it joins
+//! [`crate::task::run_tasks`] like the boot hart. Harts other than the boot
+//! hart are only ever started this way.
+//!
+//! The boot hart takes the device interrupts and stays up.
+
+use crate::config::MAX_HARTS;
+use crate::percpu::{hart_id, hart_state, this_hart, IDLE_PASS};
+use crate::sbi::{hart_start, hart_status, hart_stop, send_ipi, HSM_STOPPED};
+use crate::timer::get_time_ms;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+/// Size of the stack a started hart schedules on, like the boot stack
+const HART_STACK_SIZE: usize = 4096 * 16;
+/// How long [`cpu_up`] and [`cpu_down`] wait for another hart
+const TIMEOUT_MS: usize = 100;
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct HartStack([u8; HART_STACK_SIZE]);
+
+/// Stacks of the started harts by hart id, reused each time one starts
+static mut HART_STACKS: [HartStack; MAX_HARTS] = [HartStack([0; HART_STACK_SIZE]); MAX_HARTS];
+
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// Take the current hart as the boot hart
+pub fn init() {
+    BOOT_HART.store(hart_id(), Ordering::Relaxed);
+}
+
+/// The hart that booted the kernel, which never goes down
+pub fn boot_hart() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+/// Wait up to [`TIMEOUT_MS`] for `flag` to become `value`, true if it did
+fn wait_for(flag: &AtomicBool, value: bool) -> bool {
+    let deadline = get_time_ms() + TIMEOUT_MS;
+    while flag.load(Ordering::Acquire) != value && get_time_ms() < deadline {
+        core::hint::spin_loop();
+    }
+    flag.load(Ordering::Acquire) == value
+}
+
+/// Take `hart` down, true once it has stopped
+///
+/// The current hart stops as the caller leaves it, this returns true right
+/// away then. False for the boot hart, one that is not online or already on
+/// its way down, or one that did not stop within [`TIMEOUT_MS`], which it
+/// still does later.
+pub fn cpu_down(hart: usize) -> bool {
+    if hart >= MAX_HARTS || hart == BOOT_HART.load(Ordering::Relaxed) {
+        return false;
+    }
+    let state = hart_state(hart);
+    if !state.online.load(Ordering::Acquire) || state.stopping.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    state.need_resched.store(true, Ordering::Release);
+    if hart == hart_id() {
+        return true;
+    }
+    send_ipi(1 << hart);
+    wait_for(&state.online, false)
+}
+
+/// Stop the current hart if [`cpu_down`] asked it to, called by the
+/// scheduler loop with no task on the hart
+pub fn stop_if_requested() {
+    let state = this_hart();
+    if !state.stopping.load(Ordering::Acquire) {
+        return;
+    }
+    unsafe {
+        sstatus::clear_sie();
+    }
+    state.running_pass.store(IDLE_PASS, Ordering::Relaxed);
+    state.need_resched.store(false, Ordering::Relaxed);
+    state.online.store(false, Ordering::Release);
+    state.stopping.store(false, Ordering::Release);
+    info!("[kernel] hart {} stopped", hart_id());
+    hart_stop()
+}
+
+/// Start `hart`, true once it schedules tasks. False if there is no such
+/// hart, it is not stopped yet, or it did not come up within [`TIMEOUT_MS`]
+pub fn cpu_up(hart: usize) -> bool {
+    extern "C" {
+        fn _secondary_start();
+    }
+    if hart >= MAX_HARTS {
+        return false;
+    }
+    let state = hart_state(hart);
+    if state.online.load(Ordering::Acquire)
+        || state.stopping.load(Ordering::Acquire)
+        || hart_status(hart) != Some(HSM_STOPPED)
+    {
+        return false;
+    }
+    // the kernel is identity mapped, the hart starts with paging off
+    let stack_top =
+        unsafe { core::ptr::addr_of!(HART_STACKS[hart]) as usize } + HART_STACK_SIZE;
+    hart_start(hart, _secondary_start as usize, stack_top) && wait_for(&state.online, true)
+}
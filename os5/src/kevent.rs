@@ -0,0 +1,136 @@
+//! Task lifecycle events as one-line `key=value` records
+//!
+//! `kevent!(TaskExit { pid, code, signal })` logs, at debug level, a line
+//!
+//! ```text
+//! [kevent] seq=42 time_us=1234567 hart=0 event=TaskExit pid=3 code=0 signal=0
+//! ```
+//!
+//! `seq` counts the events printed, so a gap shows that some were lost.
+//! Events are never rate limited, and each [`Kind`] can be switched off
+//! with `sys_kevent_filter`, e.g. by the `kevent_filter` builtin of the
+//! shell. The idle task shows as `pid=idle`. The tree of processes comes
+//! out of a captured log with e.g.
+//!
+//! ```text
+//! awk '$1 == "[kevent]" { delete e; for (i = 2; i <= NF; i++) { split($i, kv, "="); e[kv[1]] = kv[2] }
+//!      if (e["event"] ~ /Fork|Spawn/) print e["parent"], "->", e["child"] }' log.txt
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Log target of the events, the logger prints them as they are
+pub const TARGET: &str = "kevent";
+
+/// What an event is about, its bit in the filter is `1 << kind`
+#[derive(Copy, Clone)]
+pub enum Kind {
+    /// `from` was switched out, `to` in, on `hart`
+    TaskSwitch = 0,
+    /// `pid` gave up the hart and is ready, `voluntary` or preempted
+    TaskSuspend = 1,
+    /// `pid` exited with `code`, or was killed by `signal`, with the pages
+    /// it had `mapped`, `resident`, `shared` and at most resident (`peak`)
+    TaskExit = 2,
+    /// `parent` reaped `child`
+    TaskReap = 3,
+    /// `pid` has run `cost_ms` since `first_ms`, asked for by task_info
+    TaskTime = 4,
+    /// `pid` took a fault paging could not resolve, which a signal handler
+    /// gets if it has one
+    Fault = 5,
+    /// `parent` forked `child`
+    Fork = 6,
+    /// `parent` started `child` running `path` with `argc` arguments
+    Spawn = 7,
+    /// `pid` runs `path` now, with `argc` arguments
+    Exec = 8,
+}
+
+/// Every [`Kind`]
+pub const KEVENT_ALL: usize = (1 << 9) - 1;
+
+static FILTER: AtomicUsize = AtomicUsize::new(KEVENT_ALL);
+static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether events of `kind` are printed now
+pub fn enabled(kind: Kind) -> bool {
+    FILTER.load(Ordering::Relaxed) & 1 << kind as usize != 0 && log::Level::Debug <= log::max_level()
+}
+
+/// The number of the next event
+pub fn next_seq() -> usize {
+    SEQ.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Switch on the kinds in `enable`, then off those in `disable`, and return
+/// the kinds on afterwards
+pub fn set_filter(enable: usize, disable: usize) -> usize {
+    let mut filter = FILTER.load(Ordering::Relaxed);
+    loop {
+        let new = (filter | enable) & !disable & KEVENT_ALL;
+        match FILTER.compare_exchange_weak(filter, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return new,
+            Err(now) => filter = now,
+        }
+    }
+}
+
+/// Check [`set_filter`] on the filter of every kind, which it is left at
+pub fn filter_test() {
+    let bit = |kind: Kind| 1 << kind as usize;
+    let (switch, fork, exec) = (bit(Kind::TaskSwitch), bit(Kind::Fork), bit(Kind::Exec));
+    assert_eq!(set_filter(0, 0), KEVENT_ALL);
+    // off, then on again, bits of no kind are ignored
+    assert_eq!(set_filter(0, switch | fork), KEVENT_ALL & !(switch | fork));
+    assert_eq!(set_filter(fork, exec), KEVENT_ALL & !(switch | exec));
+    assert_eq!(set_filter(KEVENT_ALL | 1 << 40, 0), KEVENT_ALL);
+    info!("filter_test passed!");
+}
+
+/// A pid as events show it
+pub fn pid(pid: usize) -> PidField {
+    PidField(pid)
+}
+
+/// See [`pid`]
+pub struct PidField(usize);
+
+impl core::fmt::Display for PidField {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.0 == crate::task::IDLE_PID {
+            f.write_str("idle")
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+/// Log an event of [`Kind`] `$kind` with its fields, each `name` for a
+/// variable of that name or `name: value`
+#[macro_export]
+macro_rules! kevent {
+    ($kind:ident { $($field:ident $(: $value:expr)?),* $(,)? }) => {
+        if $crate::kevent::enabled($crate::kevent::Kind::$kind) {
+            debug!(
+                target: $crate::kevent::TARGET,
+                concat!("seq={} time_us={} hart={} event=", stringify!($kind) $(, " ", stringify!($field), "={}")*),
+                $crate::kevent::next_seq(),
+                $crate::timer::get_time_us(),
+                $crate::percpu::hart_id()
+                $(, $crate::kevent_value!($field $(: $value)?))*
+            );
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! kevent_value {
+    ($field:ident) => {
+        $field
+    };
+    ($field:ident : $value:expr) => {
+        $value
+    };
+}
@@ -25,5 +25,7 @@ fn panic(info: &PanicInfo) -> ! {
             info.message().unwrap()
         );
     }
-    shutdown()
+    crate::shutdown::panic_summary();
+    crate::console::flush();
+    shutdown(true)
 }
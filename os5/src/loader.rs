@@ -1,5 +1,8 @@
 //! Loading user applications into memory
 
+use crate::sync::UPSafeCell;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::*;
 
@@ -11,19 +14,22 @@ pub fn get_num_app() -> usize {
     unsafe { (_num_app as usize as *const usize).read_volatile() }
 }
 
-/// get applications data
+/// get applications data, which starts on a page and has the rest of its
+/// last page to itself
 pub fn get_app_data(app_id: usize) -> &'static [u8] {
     extern "C" {
         fn _num_app();
+        fn _app_ends();
     }
     let num_app_ptr = _num_app as usize as *const usize;
     let num_app = get_num_app();
-    let app_start = unsafe { core::slice::from_raw_parts(num_app_ptr.add(1), num_app + 1) };
+    let app_start = unsafe { core::slice::from_raw_parts(num_app_ptr.add(1), num_app) };
+    let app_end = unsafe { core::slice::from_raw_parts(_app_ends as usize as *const usize, num_app) };
     assert!(app_id < num_app);
     unsafe {
         core::slice::from_raw_parts(
             app_start[app_id] as *const u8,
-            app_start[app_id + 1] - app_start[app_id],
+            app_end[app_id] - app_start[app_id],
         )
     }
 }
@@ -53,19 +59,113 @@ lazy_static! {
     };
 }
 
-/// Get elf data by app name
-pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
+/// The checksum the user build recorded for app `app_id`, 0 if none
+fn get_app_checksum(app_id: usize) -> u64 {
+    extern "C" {
+        fn _app_checksums();
+    }
+    assert!(app_id < get_num_app());
+    unsafe { (_app_checksums as usize as *const u64).add(app_id).read_volatile() }
+}
+
+/// FNV-1a, 64 bits, what user/checksums.py records for each app
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100_0000_01b3);
+    }
+    hash
+}
+
+lazy_static! {
+    /// Whether the image of each app matched its checksum, `None` until it
+    /// is first looked up
+    static ref APP_INTACT: UPSafeCell<Vec<Option<bool>>> =
+        unsafe { UPSafeCell::new(vec![None; get_num_app()]) };
+}
+
+/// Check the image of app `app_id` the first time, then remember it
+fn app_intact(app_id: usize) -> bool {
+    if let Some(intact) = APP_INTACT.exclusive_access()[app_id] {
+        return intact;
+    }
+    let expected = get_app_checksum(app_id);
+    let actual = fnv1a64(get_app_data(app_id));
+    let intact = expected == 0 || expected == actual;
+    if !intact {
+        error!(
+            "[kernel] app {} is stale: checksum {:#018x}, the build recorded {:#018x}",
+            APP_NAMES[app_id], actual, expected
+        );
+    }
+    APP_INTACT.exclusive_access()[app_id] = Some(intact);
+    intact
+}
+
+/// Why the elf data of an app cannot be had
+#[derive(Debug)]
+pub enum AppError {
+    /// No app of that name
+    NotFound,
+    /// The image does not match the checksum the user build recorded
+    Stale,
+}
+
+/// Get elf data by app name, checked against its checksum
+pub fn get_app_data_by_name(name: &str) -> Result<&'static [u8], AppError> {
     let num_app = get_num_app();
-    (0..num_app)
+    let app_id = (0..num_app)
         .find(|&i| APP_NAMES[i] == name)
-        .map(get_app_data)
+        .ok_or(AppError::NotFound)?;
+    if !app_intact(app_id) {
+        return Err(AppError::Stale);
+    }
+    Ok(get_app_data(app_id))
 }
 
-/// Print all of app names during kernel initialization
+/// Print all of app names during kernel initialization, marking the ones
+/// found stale so far, and warn of the ones that are never checked
 pub fn list_apps() {
     println!("/**** APPS ****");
-    for app in APP_NAMES.iter() {
-        println!("{}", app);
+    for (app_id, app) in APP_NAMES.iter().enumerate() {
+        if APP_INTACT.exclusive_access()[app_id] == Some(false) {
+            println!("{} (stale)", app);
+        } else {
+            println!("{}", app);
+        }
     }
     println!("**************/");
+    let unchecked = (0..get_num_app())
+        .filter(|&app_id| get_app_checksum(app_id) == 0)
+        .count();
+    if unchecked > 0 {
+        warn!(
+            "[kernel] {} of {} apps have no checksum recorded, their images are not checked",
+            unchecked,
+            get_num_app()
+        );
+    }
+}
+
+/// One line for each app, its name and ` (stale)` if its image does not
+/// match its checksum, checking every app not looked up yet
+pub fn app_list() -> String {
+    let mut list = String::new();
+    for (app_id, app) in APP_NAMES.iter().enumerate() {
+        list.push_str(app);
+        if !app_intact(app_id) {
+            list.push_str(" (stale)");
+        }
+        list.push('\n');
+    }
+    list
+}
+
+/// Check [`fnv1a64`] against known values
+pub fn checksum_test() {
+    assert_eq!(fnv1a64(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(fnv1a64(b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(fnv1a64(b"foobar"), 0x8594_4171_f739_67e8);
+    info!("checksum_test passed!");
 }
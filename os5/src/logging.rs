@@ -1,7 +1,125 @@
 //! Global logger
+//!
+//! Every call site, a `file:line` of a log macro or of
+//! [`println_ratelimited!`], may print [`RATE_LIMIT_BURST`] messages a
+//! second. Further ones are dropped, and counted in a line printed once the
+//! next second starts, so that a task faulting in a loop cannot flood the
+//! console. Panics print directly and are never held back.
 
+use crate::sync::{InterruptGuard, UPSafeCell};
+use crate::timer::get_time_ms;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use lazy_static::*;
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
+/// Messages a call site may print per window
+const RATE_LIMIT_BURST: usize = 10;
+const RATE_LIMIT_WINDOW_MS: usize = 1000;
+/// Call sites tracked at once, the messages of further ones always print
+const RATE_LIMIT_SITES: usize = 32;
+
+struct Site {
+    file: String,
+    line: u32,
+    window_start: usize,
+    printed: usize,
+    /// Dropped in this window
+    suppressed: usize,
+    /// Dropped since boot
+    total_suppressed: usize,
+}
+
+lazy_static! {
+    static ref SITES: UPSafeCell<Vec<Site>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+/// Whether the call site `file:line` may print a message now
+pub fn rate_limit(file: &str, line: u32) -> bool {
+    // a device interrupt may log too
+    let _guard = InterruptGuard::new();
+    let now = get_time_ms();
+    let mut sites = SITES.exclusive_access();
+    let slot = match sites.iter().position(|site| site.line == line && site.file == file) {
+        Some(slot) => slot,
+        None if sites.len() < RATE_LIMIT_SITES => {
+            sites.push(Site {
+                file: String::from(file),
+                line,
+                window_start: now,
+                printed: 0,
+                suppressed: 0,
+                total_suppressed: 0,
+            });
+            sites.len() - 1
+        }
+        None => return true,
+    };
+    let site = &mut sites[slot];
+    if now >= site.window_start + RATE_LIMIT_WINDOW_MS {
+        if site.suppressed > 0 {
+            println!(
+                "[kernel] suppressed {} similar messages from {}:{}",
+                site.suppressed, file, line
+            );
+        }
+        site.window_start = now;
+        site.printed = 0;
+        site.suppressed = 0;
+    }
+    if site.printed < RATE_LIMIT_BURST {
+        site.printed += 1;
+        true
+    } else {
+        site.suppressed += 1;
+        site.total_suppressed += 1;
+        false
+    }
+}
+
+/// Messages suppressed since boot
+pub fn suppressed_messages() -> usize {
+    let _guard = InterruptGuard::new();
+    let sites = SITES.exclusive_access();
+    sites.iter().map(|site| site.total_suppressed).sum()
+}
+
+/// Messages suppressed since boot and the call sites they came from,
+/// `None` if the table is busy, e.g. in a panic inside the logger
+pub fn try_suppressed() -> Option<(usize, usize)> {
+    let sites = SITES.try_exclusive_access()?;
+    let suppressed = sites.iter().filter(|site| site.total_suppressed > 0);
+    Some((suppressed.clone().map(|site| site.total_suppressed).sum(), suppressed.count()))
+}
+
+/// `file:line:count` of every call site that had messages suppressed,
+/// separated by commas
+pub fn suppressed_sites() -> String {
+    let _guard = InterruptGuard::new();
+    let mut text = String::new();
+    for site in SITES.exclusive_access().iter() {
+        if site.total_suppressed > 0 {
+            if !text.is_empty() {
+                text.push(',');
+            }
+            write!(text, "{}:{}:{}", site.file, site.line, site.total_suppressed).unwrap();
+        }
+    }
+    text
+}
+
+/// [`println!`] for messages a task can cause at will, limited like log
+/// messages
+#[macro_export]
+macro_rules! println_ratelimited {
+    ($($arg: tt)+) => {
+        if $crate::logging::rate_limit(file!(), line!()) {
+            $crate::println!($($arg)+);
+        }
+    };
+}
+
 /// a simple logger
 struct SimpleLogger;
 
@@ -13,6 +131,16 @@ impl Log for SimpleLogger {
         if !self.enabled(record.metadata()) {
             return;
         }
+        // events are for tools to read, and losing some would mislead them
+        if record.target() == crate::kevent::TARGET {
+            println!("[kevent] {}", record.args());
+            return;
+        }
+        if let (Some(file), Some(line)) = (record.file(), record.line()) {
+            if !rate_limit(file, line) {
+                return;
+            }
+        }
         let color = match record.level() {
             Level::Error => 31, // Red
             Level::Warn => 93,  // BrightYellow
@@ -30,15 +30,28 @@ extern crate alloc;
 #[macro_use]
 mod console;
 mod config;
+#[cfg(feature = "board_qemu")]
+mod drivers;
+mod fs;
+mod hotplug;
+#[macro_use]
+mod kevent;
 mod lang_items;
 mod loader;
+#[macro_use]
 mod logging;
 mod mm;
+#[macro_use]
+mod percpu;
+mod reboot;
 mod sbi;
+mod shutdown;
 mod sync;
 mod syscall;
 mod task;
 mod timer;
+#[cfg(feature = "trace_uart")]
+mod trace;
 mod trap;
 
 core::arch::global_asm!(include_str!("entry.asm"));
@@ -62,14 +75,52 @@ pub fn rust_main() -> ! {
     clear_bss();
     logging::init();
     println!("[kernel] Hello, world!");
+    timer::init();
     mm::init();
-    mm::remap_test();
+    mm::sanity_check();
+    fs::init();
     task::add_initproc();
+    shutdown::record_baseline();
+    task::current_task_test();
+    task::status_test();
+    #[cfg(feature = "kernel_test")]
+    task::fairness_test();
+    #[cfg(feature = "kernel_test")]
+    mm::shrink_test();
+    #[cfg(feature = "kernel_test")]
+    reboot::soft_reboot_test();
+    loader::checksum_test();
+    kevent::filter_test();
     info!("after initproc!");
     trap::init();
+    percpu::init();
+    hotplug::init();
     trap::enable_timer_interrupt();
+    trap::enable_soft_interrupt();
+    timer::enable_user_time();
+    #[cfg(feature = "board_qemu")]
+    {
+        drivers::init();
+        trap::enable_external_interrupt();
+    }
     timer::set_next_trigger();
     loader::list_apps();
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+/// Where a hart started by [`hotplug::cpu_up`] enters the kernel, the boot
+/// hart has set up everything shared
+#[no_mangle]
+pub fn rust_secondary_main() -> ! {
+    mm::activate_kernel_space();
+    trap::init();
+    percpu::init();
+    trap::enable_timer_interrupt();
+    trap::enable_soft_interrupt();
+    timer::enable_user_time();
+    timer::set_next_trigger();
+    info!("[kernel] hart {} started", percpu::hart_id());
+    task::run_tasks();
+    panic!("Unreachable in rust_secondary_main!");
+}
@@ -0,0 +1,279 @@
+//! What backs the pages of a [`super::MapArea`]
+
+use super::{frame_alloc, FrameTracker};
+use crate::config::PAGE_SIZE;
+use crate::syscall::ENOMEM;
+use super::{PhysPageNum, VirtPageNum};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// What a child gets from an area of its parent on fork
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ForkBehavior {
+    /// Its own frames, filled with a copy of the resident pages
+    Copy,
+    /// The very same physical pages
+    Share,
+}
+
+/// Source of the physical pages of an area
+pub trait MappingBackend: Send + Sync {
+    /// Physical page backing `vpn`, a frame is allocated if the backend owns
+    /// frames and has none for `vpn` yet
+    fn fault_in(&mut self, vpn: VirtPageNum) -> PhysPageNum;
+    /// `vpn` is being unmapped, let go of its frame
+    fn on_unmap(&mut self, vpn: VirtPageNum);
+    /// Write the page of `vpn` back to where it came from, like `msync`.
+    /// Called before every unmap; only a backend with a backing store has
+    /// anything to do
+    fn flush(&mut self, _vpn: VirtPageNum) {}
+    fn fork_behavior(&self) -> ForkBehavior;
+    /// Backend for the same area in a forked child. With
+    /// [`ForkBehavior::Copy`] it holds no frames, the caller copies the data
+    fn fork(&self) -> Box<dyn MappingBackend>;
+    /// Take over the pages from `vpn` on, for splitting the area at `vpn`
+    fn split_off(&mut self, vpn: VirtPageNum) -> Box<dyn MappingBackend>;
+    /// Pages are frames of the frame allocator and count in
+    /// [`super::MemoryStats`]
+    fn owns_frames(&self) -> bool {
+        true
+    }
+    /// Pages are only mapped when first touched
+    fn is_lazy(&self) -> bool {
+        false
+    }
+    /// Zero-filled memory of this address space alone, whose content is
+    /// all there is to it
+    fn is_private_anon(&self) -> bool {
+        false
+    }
+    /// munmap may take the pages away, as for what mmap maps
+    fn munmappable(&self) -> bool {
+        self.is_lazy()
+    }
+    /// Frames may be dropped and come back as zero pages on the next touch
+    fn discardable(&self) -> bool {
+        false
+    }
+    /// Pages currently backed by a frame of the backend
+    fn resident(&self) -> Vec<VirtPageNum> {
+        Vec::new()
+    }
+    fn is_resident(&self, _vpn: VirtPageNum) -> bool {
+        false
+    }
+    /// How many resident pages share their frame with another area
+    fn shared(&self) -> usize {
+        0
+    }
+    /// Whether the frame of `vpn` is shared with another area or a borrower
+    fn is_shared(&self, _vpn: VirtPageNum) -> bool {
+        false
+    }
+    /// Hand out the frame of resident `vpn` for the kernel to read later,
+    /// e.g. from a pipe. The caller maps the page read-only, `None` if the
+    /// frame cannot be lent
+    fn lend(&mut self, _vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        None
+    }
+    /// Whether `vpn` is mapped read-only because of [`Self::lend`]
+    fn is_lent(&self, _vpn: VirtPageNum) -> bool {
+        false
+    }
+    /// Frame to map `vpn` writable again after [`Self::lend`]: its own one
+    /// if the borrower is done with it, else a copy. `Ok(None)` if not lent,
+    /// `-ENOMEM` with the page still lent if there is no frame for the copy
+    fn reclaim(&mut self, _vpn: VirtPageNum) -> Result<Option<PhysPageNum>, isize> {
+        Ok(None)
+    }
+}
+
+/// Anonymous memory private to the address space, zero-filled
+///
+/// A frame is only shared while it is lent, the page is read-only until the
+/// next write to it takes the frame back or copies it.
+pub struct AnonPrivate {
+    frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    lent: BTreeSet<VirtPageNum>,
+    lazy: bool,
+}
+
+impl AnonPrivate {
+    /// Every page gets its frame when the area is mapped
+    pub fn eager() -> Box<dyn MappingBackend> {
+        Box::new(Self {
+            frames: BTreeMap::new(),
+            lent: BTreeSet::new(),
+            lazy: false,
+        })
+    }
+    /// Pages get their frame on first touch
+    pub fn lazy() -> Box<dyn MappingBackend> {
+        Box::new(Self {
+            frames: BTreeMap::new(),
+            lent: BTreeSet::new(),
+            lazy: true,
+        })
+    }
+}
+
+impl MappingBackend for AnonPrivate {
+    fn fault_in(&mut self, vpn: VirtPageNum) -> PhysPageNum {
+        self.frames
+            .entry(vpn)
+            .or_insert_with(|| Arc::new(frame_alloc().unwrap()))
+            .ppn
+    }
+    fn on_unmap(&mut self, vpn: VirtPageNum) {
+        self.frames.remove(&vpn);
+        self.lent.remove(&vpn);
+    }
+    fn fork_behavior(&self) -> ForkBehavior {
+        ForkBehavior::Copy
+    }
+    fn fork(&self) -> Box<dyn MappingBackend> {
+        Box::new(Self {
+            frames: BTreeMap::new(),
+            lent: BTreeSet::new(),
+            lazy: self.lazy,
+        })
+    }
+    fn split_off(&mut self, vpn: VirtPageNum) -> Box<dyn MappingBackend> {
+        Box::new(Self {
+            frames: self.frames.split_off(&vpn),
+            lent: self.lent.split_off(&vpn),
+            lazy: self.lazy,
+        })
+    }
+    fn lend(&mut self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        let frame = self.frames.get(&vpn)?.clone();
+        self.lent.insert(vpn);
+        Some(frame)
+    }
+    fn is_lent(&self, vpn: VirtPageNum) -> bool {
+        self.lent.contains(&vpn)
+    }
+    fn reclaim(&mut self, vpn: VirtPageNum) -> Result<Option<PhysPageNum>, isize> {
+        if !self.lent.contains(&vpn) {
+            return Ok(None);
+        }
+        let frame = self.frames.get_mut(&vpn).unwrap();
+        if Arc::strong_count(frame) > 1 {
+            let copy = frame_alloc().ok_or(-ENOMEM)?;
+            copy.ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(copy);
+        }
+        self.lent.remove(&vpn);
+        Ok(Some(frame.ppn))
+    }
+    fn is_lazy(&self) -> bool {
+        self.lazy
+    }
+    fn is_private_anon(&self) -> bool {
+        true
+    }
+    fn discardable(&self) -> bool {
+        self.lazy
+    }
+    fn resident(&self) -> Vec<VirtPageNum> {
+        self.frames.keys().copied().collect()
+    }
+    fn is_resident(&self, vpn: VirtPageNum) -> bool {
+        self.frames.contains_key(&vpn)
+    }
+    fn is_shared(&self, vpn: VirtPageNum) -> bool {
+        self.frames
+            .get(&vpn)
+            .map_or(false, |frame| Arc::strong_count(frame) > 1)
+    }
+}
+
+/// A fixed range of physical pages such as device registers, starting at
+/// `ppn` for the first page of the area
+pub struct Mmio {
+    vpn: VirtPageNum,
+    ppn: PhysPageNum,
+}
+
+impl Mmio {
+    pub fn new(vpn: VirtPageNum, ppn: PhysPageNum) -> Box<dyn MappingBackend> {
+        Box::new(Self { vpn, ppn })
+    }
+    /// Physical pages at the same addresses as the virtual ones, as for the
+    /// kernel's own sections
+    pub fn identical(vpn: VirtPageNum) -> Box<dyn MappingBackend> {
+        Self::new(vpn, PhysPageNum(vpn.0))
+    }
+}
+
+impl MappingBackend for Mmio {
+    fn fault_in(&mut self, vpn: VirtPageNum) -> PhysPageNum {
+        PhysPageNum(self.ppn.0 + (vpn.0 - self.vpn.0))
+    }
+    fn on_unmap(&mut self, _vpn: VirtPageNum) {}
+    fn owns_frames(&self) -> bool {
+        false
+    }
+    fn fork_behavior(&self) -> ForkBehavior {
+        ForkBehavior::Share
+    }
+    fn fork(&self) -> Box<dyn MappingBackend> {
+        Mmio::new(self.vpn, self.ppn)
+    }
+    fn split_off(&mut self, vpn: VirtPageNum) -> Box<dyn MappingBackend> {
+        Mmio::new(vpn, PhysPageNum(self.ppn.0 + (vpn.0 - self.vpn.0)))
+    }
+}
+
+/// The ELF image of an app as embedded in the kernel, read-only, see
+/// [`super::MemorySet::map_self`]
+///
+/// The kernel's own pages of the image are mapped: they never change and
+/// stay resident, no frame is used. The build starts each image on a page
+/// boundary and pads it to a page, see `build.rs`.
+pub struct AppImage {
+    /// First page of the area, where `data` starts
+    vpn: VirtPageNum,
+    data: &'static [u8],
+}
+
+impl AppImage {
+    pub fn new(vpn: VirtPageNum, data: &'static [u8]) -> Box<dyn MappingBackend> {
+        let aligned = data.as_ptr() as usize % PAGE_SIZE == 0;
+        assert!(aligned, "app image not page aligned");
+        Box::new(Self { vpn, data })
+    }
+}
+
+impl MappingBackend for AppImage {
+    fn fault_in(&mut self, vpn: VirtPageNum) -> PhysPageNum {
+        // the kernel is identity mapped
+        PhysPageNum((self.data.as_ptr() as usize / PAGE_SIZE) + (vpn.0 - self.vpn.0))
+    }
+    fn on_unmap(&mut self, _vpn: VirtPageNum) {}
+    fn owns_frames(&self) -> bool {
+        false
+    }
+    fn munmappable(&self) -> bool {
+        true
+    }
+    fn fork_behavior(&self) -> ForkBehavior {
+        ForkBehavior::Share
+    }
+    fn fork(&self) -> Box<dyn MappingBackend> {
+        Box::new(Self {
+            vpn: self.vpn,
+            data: self.data,
+        })
+    }
+    fn split_off(&mut self, vpn: VirtPageNum) -> Box<dyn MappingBackend> {
+        let offset = ((vpn.0 - self.vpn.0) * PAGE_SIZE).min(self.data.len());
+        let (head, tail) = self.data.split_at(offset);
+        self.data = head;
+        Box::new(Self { vpn, data: tail })
+    }
+}
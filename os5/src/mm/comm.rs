@@ -0,0 +1,45 @@
+//! The comm page
+//!
+//! Two pages at fixed addresses of every user space, which the user library
+//! relies on:
+//!
+//! - [`COMM_PAGE`], read-only, a frame of each process holding its
+//!   [`CommPage`], filled in as exec and fork make the process
+//! - [`SIGRETURN_TRAMPOLINE`], read-only and executable, the kernel's own
+//!   code for a signal handler to return to, which makes the sigreturn
+//!   syscall; handlers are started with `ra` pointing there
+//!
+//! The permissions of a page are all or nothing, so the code has a page of
+//! its own.
+//!
+//! [`COMM_PAGE`]: crate::config::COMM_PAGE
+//! [`SIGRETURN_TRAMPOLINE`]: crate::config::SIGRETURN_TRAMPOLINE
+
+use super::{PhysAddr, PhysPageNum};
+use crate::config::SIGRETURN_TRAMPOLINE;
+
+/// Layout of the page, which the user library shares
+#[repr(C)]
+pub struct CommPage {
+    /// Pid of the process
+    pub pid: usize,
+    /// Where signal handlers return to
+    pub sigreturn: usize,
+}
+
+impl CommPage {
+    pub fn new(pid: usize) -> Self {
+        Self {
+            pid,
+            sigreturn: SIGRETURN_TRAMPOLINE,
+        }
+    }
+}
+
+/// The frame of the trampoline code, to map at [`SIGRETURN_TRAMPOLINE`]
+pub fn sigreturn_ppn() -> PhysPageNum {
+    extern "C" {
+        fn ssigreturn();
+    }
+    PhysAddr::from(ssigreturn as usize).into()
+}
@@ -1,6 +1,7 @@
 //! Implementation of [`FrameAllocator`] which
 //! controls all the frames in the operating system.
 
+use super::shrink::{reclaim, report_oom, Memory};
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
 use crate::sync::UPSafeCell;
@@ -44,35 +45,67 @@ trait FrameAllocator {
 
 /// an implementation for frame allocator
 pub struct StackFrameAllocator {
+    start: usize,
     current: usize,
     end: usize,
     recycled: Vec<usize>,
+    /// The most frames ever in use at once
+    peak: usize,
 }
 
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
         self.current = l.0;
         self.end = r.0;
         info!("last {} Physical Frames.", self.end - self.current);
     }
+    /// The whole range of frames handed to this allocator at init
+    pub fn range(&self) -> (PhysPageNum, PhysPageNum) {
+        (self.start.into(), self.end.into())
+    }
+    /// Frames that can still be allocated
+    pub fn free(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
+    /// Frames allocated and not freed yet
+    pub fn in_use(&self) -> usize {
+        self.current - self.start - self.recycled.len()
+    }
+    /// Take the freed frames right below `current` back, as if they were
+    /// never handed out, hand the others out lowest first and start the
+    /// peak over
+    pub fn compact(&mut self) {
+        self.recycled.sort_unstable();
+        while self.recycled.last() == Some(&(self.current - 1)) {
+            self.recycled.pop();
+            self.current -= 1;
+        }
+        self.recycled.reverse();
+        self.peak = self.in_use();
+    }
 }
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
         Self {
+            start: 0,
             current: 0,
             end: 0,
             recycled: Vec::new(),
+            peak: 0,
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
+        let ppn = if let Some(ppn) = self.recycled.pop() {
+            ppn
         } else if self.current == self.end {
-            None
+            return None;
         } else {
             self.current += 1;
-            Some((self.current - 1).into())
-        }
+            self.current - 1
+        };
+        self.peak = self.peak.max(self.in_use());
+        Some(ppn.into())
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
@@ -93,6 +126,7 @@ lazy_static! {
         unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
 }
 
+/// initiate the frame allocator using `ekernel` and `MEMORY_END`
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
@@ -103,12 +137,39 @@ pub fn init_frame_allocator() {
     );
 }
 
-/// initiate the frame allocator using `ekernel` and `MEMORY_END`
+/// allocate a frame, once more after the shrinkers ran if there is none
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+    let mut ppn = FRAME_ALLOCATOR.exclusive_access().alloc();
+    if ppn.is_none() && reclaim(Memory::Frames) > 0 {
+        ppn = FRAME_ALLOCATOR.exclusive_access().alloc();
+    }
+    if ppn.is_none() {
+        report_oom(Memory::Frames);
+    }
+    ppn.map(FrameTracker::new)
+}
+
+/// get the range of physical frames managed by the frame allocator
+pub fn frame_allocator_range() -> (PhysPageNum, PhysPageNum) {
+    FRAME_ALLOCATOR.exclusive_access().range()
+}
+
+/// Hand frames out as after boot as far as those in use allow, for a soft
+/// reboot
+pub fn frame_allocator_compact() {
+    FRAME_ALLOCATOR.exclusive_access().compact();
+}
+
+/// get the number of free physical frames
+pub fn frame_allocator_free() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free()
+}
+
+/// Frames in use now and at most so far, `None` if the allocator is busy,
+/// e.g. in a panic inside it
+pub fn try_frame_usage() -> Option<(usize, usize)> {
+    let allocator = FRAME_ALLOCATOR.try_exclusive_access()?;
+    Some((allocator.in_use(), allocator.peak))
 }
 
 /// deallocate a frame
@@ -1,11 +1,47 @@
 //! The global allocator
 
+use super::shrink::{reclaim, report_oom, Memory};
 use crate::config::KERNEL_HEAP_SIZE;
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// [`LockedHeap`] keeping count of the bytes handed out, without a lock of
+/// its own so the count can be read in a panic
+struct CountingHeap {
+    heap: LockedHeap,
+    in_use: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for CountingHeap {
+    /// Tried once more after the shrinkers ran if the heap is full
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut ptr = self.heap.alloc(layout);
+        if ptr.is_null() && reclaim(Memory::Heap) > 0 {
+            ptr = self.heap.alloc(layout);
+        }
+        if ptr.is_null() {
+            report_oom(Memory::Heap);
+        } else {
+            let in_use = self.in_use.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(in_use, Ordering::Relaxed);
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap.dealloc(ptr, layout);
+        self.in_use.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
 
 #[global_allocator]
 /// heap allocator instance
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: CountingHeap = CountingHeap {
+    heap: LockedHeap::empty(),
+    in_use: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
 
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
@@ -20,11 +56,32 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
+/// Bytes of the kernel heap in use now and at most so far
+pub fn heap_usage() -> (usize, usize) {
+    (
+        HEAP_ALLOCATOR.in_use.load(Ordering::Relaxed),
+        HEAP_ALLOCATOR.peak.load(Ordering::Relaxed),
+    )
+}
+
+/// Start the peak of [`heap_usage`] over from what is in use now
+pub fn reset_heap_peak() {
+    let in_use = HEAP_ALLOCATOR.in_use.load(Ordering::Relaxed);
+    HEAP_ALLOCATOR.peak.store(in_use, Ordering::Relaxed);
+}
+
+/// get the address range of the kernel heap
+pub fn heap_range() -> (usize, usize) {
+    let start = unsafe { HEAP_SPACE.as_ptr() as usize };
+    (start, start + KERNEL_HEAP_SIZE)
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
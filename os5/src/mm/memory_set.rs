@@ -1,18 +1,31 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
-use super::{frame_alloc, FrameTracker};
+use super::frame_allocator::frame_allocator_range;
+use super::heap_allocator::heap_range;
+use super::{AnonPrivate, AppImage, ForkBehavior, FrameTracker, MappingBackend, Mmio};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use super::comm::{sigreturn_ppn, CommPage};
+use super::vdso::vdso_ppn;
+use super::tlb::GenerationPtr;
+use crate::config::{
+    ALLOW_WX, COMM_PAGE, MEMORY_END, MMAP_BASE, MMIO, PAGE_SIZE, SIGRETURN_TRAMPOLINE, TRAMPOLINE,
+    TRAP_CONTEXT, USER_STACK_MAX, USER_STACK_SIZE, USER_STACK_TOP, USER_VA_MAX, VDSO_DATA,
+};
 use crate::sync::UPSafeCell;
-use alloc::collections::BTreeMap;
+use crate::syscall::{EEXIST, EFAULT, EINVAL, ENOMEM};
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt::Write;
 use lazy_static::*;
 use riscv::register::satp;
 
 extern "C" {
+    fn skernel();
     fn stext();
     fn etext();
     fn srodata();
@@ -20,6 +33,7 @@ extern "C" {
     fn sdata();
     fn edata();
     fn sbss_with_stack();
+    fn sbss();
     fn ebss();
     fn ekernel();
     fn strampoline();
@@ -35,18 +49,118 @@ lazy_static! {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// Pages in areas backed by frames
+    mapped_pages: usize,
+    /// Pages of those backed by a frame
+    resident_pages: usize,
+    /// Highest `resident_pages` so far
+    peak_resident_pages: usize,
+}
+
+/// Page counts of an address space, see [`MemorySet::stats`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStats {
+    pub mapped_pages: usize,
+    pub resident_pages: usize,
+    /// Resident pages whose frame is shared with another address space
+    pub shared_pages: usize,
+    pub peak_resident_pages: usize,
+}
+
+/// Bits of a [`MemorySet::pagemap`] entry: the page is mapped
+pub const PAGEMAP_PRESENT: u64 = 1 << 0;
+pub const PAGEMAP_WRITABLE: u64 = 1 << 1;
+pub const PAGEMAP_EXECUTABLE: u64 = 1 << 2;
+/// Reachable from user mode, not so after an mprotect to no access
+pub const PAGEMAP_USER: u64 = 1 << 3;
+/// Read-only until the next write copies it or takes it back, as a page
+/// lent to a pipe
+pub const PAGEMAP_COW: u64 = 1 << 4;
+/// The frame is shared with another address space or a borrower
+pub const PAGEMAP_SHARED: u64 = 1 << 5;
+/// Where the frame number starts, in debug builds only
+pub const PAGEMAP_PFN_SHIFT: u64 = 12;
+
+/// Why [`MemorySet::check_user_range`] refused a range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserRangeError {
+    /// It has no pages
+    Empty,
+    /// It reaches above [`USER_VA_MAX`]
+    OutOfRange,
+    /// Some of it is already mapped
+    Overlap,
+}
+
+impl UserRangeError {
+    /// What a syscall asking for the range fails with
+    pub fn errno(self) -> isize {
+        match self {
+            UserRangeError::Empty => EINVAL,
+            UserRangeError::OutOfRange => ENOMEM,
+            UserRangeError::Overlap => EEXIST,
+        }
+    }
+}
+
+/// A user area as a checkpoint keeps it, see [`MemorySet::area_images`]
+pub struct AreaImage<'a> {
+    pub start: VirtPageNum,
+    pub end: VirtPageNum,
+    pub perm: MapPermission,
+    pub kind: AreaKind,
+    /// Pages are mapped as they are touched, not up front
+    pub lazy: bool,
+    /// The resident pages that are not all zeros and their content, in
+    /// address order; the others are zeros
+    pub pages: Vec<(VirtPageNum, &'a [u8])>,
 }
 
 impl MemorySet {
     pub fn new_bare() -> Self {
+        Self::with_page_table(PageTable::new())
+    }
+    fn with_page_table(page_table: PageTable) -> Self {
         Self {
-            page_table: PageTable::new(),
+            page_table,
             areas: Vec::new(),
+            mapped_pages: 0,
+            resident_pages: 0,
+            peak_resident_pages: 0,
+        }
+    }
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            mapped_pages: self.mapped_pages,
+            resident_pages: self.resident_pages,
+            shared_pages: self.areas.iter().map(|area| area.backend.shared()).sum(),
+            peak_resident_pages: self.peak_resident_pages,
         }
     }
+    /// Account for `area` joining the address space
+    fn account(&mut self, area: &MapArea) {
+        if !area.backend.owns_frames() {
+            return;
+        }
+        self.mapped_pages += area.vpn_range.get_end().0 - area.vpn_range.get_start().0;
+        self.resident_pages += area.backend.resident().len();
+        self.peak_resident_pages = self.peak_resident_pages.max(self.resident_pages);
+    }
+    /// Account for `area` leaving the address space
+    fn unaccount(&mut self, area: &MapArea) {
+        if !area.backend.owns_frames() {
+            return;
+        }
+        self.mapped_pages -= area.vpn_range.get_end().0 - area.vpn_range.get_start().0;
+        self.resident_pages -= area.backend.resident().len();
+    }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
+    /// Of the page table, for the trap context next to [`Self::token`]
+    pub fn generation(&self) -> GenerationPtr {
+        self.page_table.generation()
+    }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
         &mut self,
@@ -55,34 +169,75 @@ impl MemorySet {
         permission: MapPermission,
     ) {
         self.push(
-            MapArea::new(start_va, end_va, MapType::Framed, permission),
+            MapArea::new(start_va, end_va, AnonPrivate::eager(), permission, AreaKind::Kernel),
             None,
         );
     }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
-        if let Some((idx, area)) = self
+        if let Some(idx) = self
             .areas
-            .iter_mut()
-            .enumerate()
-            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start_vpn)
         {
+            let mut area = self.areas.remove(idx);
+            self.unaccount(&area);
             area.unmap(&mut self.page_table);
-            self.areas.remove(idx);
         }
     }
+    /// Split the area that crosses `vpn`, if any, into two at `vpn`
+    fn split_at(&mut self, vpn: VirtPageNum) {
+        if let Some(idx) = self.areas.iter().position(|area| {
+            area.vpn_range.get_start() < vpn && vpn < area.vpn_range.get_end()
+        }) {
+            let tail = self.areas[idx].split_off(vpn);
+            self.areas.insert(idx + 1, tail);
+        }
+    }
+    /// Whether a new user area may take `[start_va, end_va)`: below
+    /// [`USER_VA_MAX`] and clear of every area. What is mapped above, the
+    /// trampoline, trap context and vDSO page, is out of reach that way
+    pub fn check_user_range(
+        &self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+    ) -> Result<(), UserRangeError> {
+        if start_va.0 >= end_va.0 {
+            return Err(UserRangeError::Empty);
+        }
+        if end_va.0 > USER_VA_MAX {
+            return Err(UserRangeError::OutOfRange);
+        }
+        let rg = VPNRange::new(start_va.floor(), end_va.ceil());
+        if self.areas.iter().any(|area| area.overlaps(rg)) {
+            return Err(UserRangeError::Overlap);
+        }
+        Ok(())
+    }
+    /// The pages of `[start, start + len)`, none if it wraps or reaches
+    /// above [`USER_VA_MAX`]
+    fn user_pages(start: usize, len: usize) -> Option<VPNRange> {
+        let end = start.checked_add(len).filter(|&end| end <= USER_VA_MAX)?;
+        Some(VPNRange::new(VirtAddr(start).floor(), VirtAddr(end).ceil()))
+    }
+    /// Whether every page of `rg` is in some area for which `f` holds
+    fn covered_by(&self, rg: VPNRange, f: impl Fn(&MapArea) -> bool) -> bool {
+        rg.into_iter()
+            .all(|vpn| self.areas.iter().any(|area| area.contains(vpn) && f(area)))
+    }
 
+    /// Map `[start, start + len)` lazily, frames are allocated by
+    /// [`MemorySet::fault_in`] on first touch. `-EINVAL` below
+    /// [`MMAP_BASE`], `-EEXIST` if some of it is mapped already
     pub fn mmap(&mut self, start: usize, len: usize, port: usize) -> isize {
-        let rg = VPNRange::new(VirtAddr(start).floor(), VirtAddr(start+len).ceil() );
-        for vpn in rg {
-            if let Some(pte) = self.page_table.find_pte(vpn) {
-                let a = pte as *const PageTableEntry as usize;
-                if pte.is_valid() {
-                    info!("valid fault: {:?}", a);
-                    return -1;
-                }else {
-                    info!("invalid fault: {:?}", a);
-                }
-            }
+        if start < MMAP_BASE {
+            return -EINVAL;
+        }
+        let end = match start.checked_add(len) {
+            Some(end) => end,
+            None => return -ENOMEM,
+        };
+        if let Err(err) = self.check_user_range(VirtAddr(start), VirtAddr(end)) {
+            return -err.errno();
         }
         let mut perm = MapPermission::U;
         if port & 0x01 != 0 {
@@ -95,40 +250,341 @@ impl MemorySet {
             perm |= MapPermission::X;
         }
 
-        self.insert_framed_area(VirtAddr(start), VirtAddr(start+len), perm);
+        self.push(
+            MapArea::new(VirtAddr(start), VirtAddr(end), AnonPrivate::lazy(), perm, AreaKind::Mmap),
+            None,
+        );
         0
-
     }
 
+    /// Unmap `[start, start + len)`, which must be all mmapped or
+    /// [`Self::map_self`] mapped
     pub fn munmap(&mut self, start: usize, len: usize) -> isize {
-        let rg = VPNRange::new(VirtAddr(start).floor(), VirtAddr(start+len).ceil() );
-        for vpn in rg {
-            if let Some(pte) = self.page_table.find_pte(vpn) {
-                if ! pte.is_valid(){
-                    return -1;
-                }
-            }else {
-                return -1;
+        let rg = match Self::user_pages(start, len) {
+            Some(rg) => rg,
+            None => return -EINVAL,
+        };
+        if !self.covered_by(rg, |area| area.backend.munmappable()) {
+            return -EINVAL;
+        }
+        self.split_at(rg.get_start());
+        self.split_at(rg.get_end());
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            let area_rg = self.areas[idx].vpn_range;
+            if area_rg.get_start() >= rg.get_start() && area_rg.get_end() <= rg.get_end() {
+                let mut area = self.areas.remove(idx);
+                self.unaccount(&area);
+                area.unmap(&mut self.page_table);
+            } else {
+                idx += 1;
             }
         }
+        0
 
-        for area in &mut self.areas {
-            for vpn in rg {
-                if vpn < area.vpn_range.get_end() && vpn >= area.vpn_range.get_start() {
-                    area.unmap_one(&mut self.page_table, vpn);
+    }
+    /// Map `data`, the ELF image of the app, read-only at `hint` or else the
+    /// lowest free address from [`MMAP_BASE`] on, and return where.
+    /// `-ENOMEM` if there is no room
+    pub fn map_self(&mut self, data: &'static [u8], hint: usize) -> isize {
+        let len = data.len();
+        let fits = |start: usize| {
+            start >= MMAP_BASE
+                && start % PAGE_SIZE == 0
+                && start
+                    .checked_add(len)
+                    .map_or(false, |end| self.check_user_range(VirtAddr(start), VirtAddr(end)).is_ok())
+        };
+        let start = if fits(hint) {
+            hint
+        } else {
+            match self.free_range(len) {
+                Some(start) => start,
+                None => return -ENOMEM,
+            }
+        };
+        let vpn = VirtAddr(start).floor();
+        self.push(
+            MapArea::new(
+                VirtAddr(start),
+                VirtAddr(start + len),
+                AppImage::new(vpn, data),
+                MapPermission::R | MapPermission::U,
+                AreaKind::Mmap,
+            ),
+            None,
+        );
+        start as isize
+    }
+    /// Lowest address from [`MMAP_BASE`] on with `len` bytes free
+    fn free_range(&self, len: usize) -> Option<usize> {
+        let mut start = MMAP_BASE;
+        loop {
+            let end = start.checked_add(len)?;
+            let rg = VPNRange::new(VirtAddr(start).floor(), VirtAddr(end).ceil());
+            match self.areas.iter().find(|area| area.overlaps(rg)) {
+                Some(area) => start = VirtAddr::from(area.vpn_range.get_end()).0,
+                None if end <= USER_VA_MAX => return Some(start),
+                None => return None,
+            }
+        }
+    }
+    /// Drop the frames of the pages in `[start, start + len)`, which must be
+    /// all mmapped; the range stays mapped and reads back as zeros
+    pub fn discard(&mut self, start: usize, len: usize) -> isize {
+        let rg = match Self::user_pages(start, len) {
+            Some(rg) => rg,
+            None => return -EINVAL,
+        };
+        if !self.covered_by(rg, |area| area.backend.discardable()) {
+            return -EINVAL;
+        }
+        for area in self.areas.iter_mut() {
+            if !area.backend.discardable() || !area.overlaps(rg) {
+                continue;
+            }
+            let resident: Vec<VirtPageNum> = area
+                .backend
+                .resident()
+                .into_iter()
+                .filter(|vpn| rg.get_start() <= *vpn && *vpn < rg.get_end())
+                .collect();
+            for vpn in resident {
+                area.unmap_one(&mut self.page_table, vpn);
+                self.resident_pages -= 1;
+            }
+        }
+        0
+    }
+    /// What the page of `vpn` is like, as [`PAGEMAP_PRESENT`] and the other
+    /// bits, 0 if it is not mapped
+    pub fn pagemap(&self, vpn: VirtPageNum) -> u64 {
+        let pte = match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => pte,
+            _ => return 0,
+        };
+        let mut entry = PAGEMAP_PRESENT;
+        if pte.writable() {
+            entry |= PAGEMAP_WRITABLE;
+        }
+        if pte.executable() {
+            entry |= PAGEMAP_EXECUTABLE;
+        }
+        if pte.flags().contains(PTEFlags::U) {
+            entry |= PAGEMAP_USER;
+        }
+        if let Some(area) = self.areas.iter().find(|area| area.contains(vpn)) {
+            if area.backend.is_lent(vpn) {
+                entry |= PAGEMAP_COW;
+            }
+            if area.backend.is_shared(vpn) {
+                entry |= PAGEMAP_SHARED;
+            }
+        }
+        // the physical layout is nobody's business in a release build
+        if cfg!(debug_assertions) {
+            entry |= (pte.ppn().0 as u64) << PAGEMAP_PFN_SHIFT;
+        }
+        entry
+    }
+    /// One line per area, in address order:
+    ///
+    /// ```text
+    /// 0000000000010000-0000000000012000 r-xp elf 2/2
+    /// ```
+    ///
+    /// that is the range, `rwx` and `s` for frames shared on fork or `p`,
+    /// the [`AreaKind`], and how many of the pages are mapped now out of all
+    pub fn vma_list(&self) -> String {
+        let mut areas: Vec<&MapArea> = self.areas.iter().collect();
+        areas.sort_by_key(|area| area.vpn_range.get_start());
+        let mut text = String::new();
+        for area in areas {
+            let (start, end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            let resident = area
+                .vpn_range
+                .into_iter()
+                .filter(|&vpn| self.page_table.translate(vpn).map_or(false, |pte| pte.is_valid()))
+                .count();
+            let perm = |flag, c| if area.map_perm.contains(flag) { c } else { '-' };
+            let shared = match area.backend.fork_behavior() {
+                ForkBehavior::Share => 's',
+                ForkBehavior::Copy => 'p',
+            };
+            let _ = writeln!(
+                text,
+                "{:016x}-{:016x} {}{}{}{} {} {}/{}",
+                VirtAddr::from(start).0,
+                VirtAddr::from(end).0,
+                perm(MapPermission::R, 'r'),
+                perm(MapPermission::W, 'w'),
+                perm(MapPermission::X, 'x'),
+                shared,
+                area.kind.name(),
+                resident,
+                end.0 - start.0
+            );
+        }
+        text
+    }
+    /// The areas of the program, its stack and what it mapped, the ones
+    /// [`Self::vma_list`] shows but the comm page and the trap context, in
+    /// address order. `None` if one of them is not private anonymous
+    /// memory, e.g. [`Self::map_self`] mapped it
+    pub fn area_images(&self) -> Option<Vec<AreaImage<'_>>> {
+        let mut images = Vec::new();
+        for area in self.areas.iter() {
+            if !matches!(area.kind, AreaKind::Elf | AreaKind::Stack | AreaKind::Mmap) {
+                continue;
+            }
+            if !area.backend.is_private_anon() {
+                return None;
+            }
+            let pages = area
+                .backend
+                .resident()
+                .into_iter()
+                .map(|vpn| {
+                    let bytes: &[u8] = self.translate(vpn).unwrap().ppn().get_bytes_array();
+                    (vpn, bytes)
+                })
+                .filter(|(_, bytes)| bytes.iter().any(|&byte| byte != 0))
+                .collect();
+            images.push(AreaImage {
+                start: area.vpn_range.get_start(),
+                end: area.vpn_range.get_end(),
+                perm: area.map_perm,
+                kind: area.kind,
+                lazy: area.backend.is_lazy(),
+                pages,
+            });
+        }
+        images.sort_by_key(|image| image.start);
+        Some(images)
+    }
+    /// Lend the frame of the resident user page `vpn`, which stays mapped
+    /// read-only until the next write to it, see [`MappingBackend::lend`]
+    pub fn lend_page(&mut self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        let area = self.areas.iter_mut().find(|area| {
+            area.contains(vpn) && area.map_perm.contains(MapPermission::U | MapPermission::R)
+        })?;
+        let frame = area.backend.lend(vpn)?;
+        self.page_table.set_flags(vpn, area.page_flags(vpn));
+        Some(frame)
+    }
+    /// Map the page of `vpn` writable again if it was lent, `Ok(false)` if
+    /// it was not, see [`MappingBackend::reclaim`]
+    fn reclaim(&mut self, vpn: VirtPageNum) -> Result<bool, isize> {
+        let area = match self
+            .areas
+            .iter_mut()
+            .find(|area| area.contains(vpn) && area.map_perm.contains(MapPermission::W))
+        {
+            Some(area) => area,
+            None => return Ok(false),
+        };
+        let ppn = match area.backend.reclaim(vpn)? {
+            Some(ppn) => ppn,
+            None => return Ok(false),
+        };
+        self.page_table.unmap(vpn);
+        self.page_table.map(vpn, ppn, area.pte_flags());
+        Ok(true)
+    }
+    /// Map the page of `vpn` through the backend of its area if the area is
+    /// lazy and allows `access`, or writable again if it was lent. Return
+    /// false if the fault is a real one, as is a write to a lent page that
+    /// finds no frame for its copy
+    pub fn fault_in(&mut self, vpn: VirtPageNum, access: MapPermission) -> bool {
+        if access.contains(MapPermission::W) && self.reclaim(vpn) == Ok(true) {
+            return true;
+        }
+        let area = match self
+            .areas
+            .iter_mut()
+            .find(|area| area.backend.is_lazy() && area.contains(vpn))
+        {
+            Some(area) => area,
+            None => return false,
+        };
+        if !area.map_perm.contains(access) || area.backend.is_resident(vpn) {
+            return false;
+        }
+        area.map_one(&mut self.page_table, vpn);
+        self.resident_pages += 1;
+        self.peak_resident_pages = self.peak_resident_pages.max(self.resident_pages);
+        true
+    }
+    /// Fault in the lazy pages of `[start, start + len)` on behalf of the
+    /// kernel, which is about to access them with `access`. `-EFAULT` if
+    /// some page is no user page allowing `access`, the kernel must keep off
+    /// the range then, `-ENOMEM` if a lent page finds no frame for its copy
+    pub fn populate(
+        &mut self,
+        start: usize,
+        len: usize,
+        access: MapPermission,
+    ) -> Result<(), isize> {
+        let end = start.checked_add(len).ok_or(-EFAULT)?;
+        let rg = VPNRange::new(VirtAddr(start).floor(), VirtAddr(end).ceil());
+        for vpn in rg {
+            if access.contains(MapPermission::W) {
+                self.reclaim(vpn)?;
+            }
+            self.fault_in(vpn, access);
+            let ok = self.page_table.translate(vpn).map_or(false, |pte| {
+                pte.is_valid()
+                    && pte.flags().contains(PTEFlags::U)
+                    && (!access.contains(MapPermission::R) || pte.readable())
+                    && (!access.contains(MapPermission::W) || pte.writable())
+            });
+            if !ok {
+                return Err(-EFAULT);
+            }
+        }
+        Ok(())
+    }
+    /// Change the permission of the user pages in `[start, start + len)`.
+    ///
+    /// With `port` 0 the pages stay mapped (the U bit is cleared instead of
+    /// V, since a valid PTE without R/W/X would be a page table pointer), so
+    /// that the frames are kept and a later mprotect can bring them back.
+    pub fn mprotect(&mut self, start: usize, len: usize, port: usize) -> isize {
+        // not the comm page either
+        let rg = match Self::user_pages(start, len) {
+            Some(rg) => rg,
+            None => return -ENOMEM,
+        };
+        if !self.covered_by(rg, |area| {
+            area.backend.owns_frames() && area.map_perm.contains(MapPermission::U)
+        }) {
+            return -EINVAL;
+        }
+        let mut perm = MapPermission::U | MapPermission::from_bits((port << 1) as u8).unwrap();
+        // W without R is reserved in RISC-V
+        if perm.contains(MapPermission::W) {
+            perm |= MapPermission::R;
+        }
+        self.split_at(rg.get_start());
+        self.split_at(rg.get_end());
+        for area in self.areas.iter_mut() {
+            if area.vpn_range.get_start() >= rg.get_start()
+                && area.vpn_range.get_end() <= rg.get_end()
+            {
+                area.map_perm = perm;
+                for vpn in area.backend.resident() {
+                    self.page_table.set_flags(vpn, area.page_flags(vpn));
                 }
             }
         }
-
-
         0
-
     }
     fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
         map_area.map(&mut self.page_table);
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
+        self.account(&map_area);
         self.areas.push(map_area);
     }
     /// Mention that trampoline is not collected by areas.
@@ -139,9 +595,70 @@ impl MemorySet {
             PTEFlags::R | PTEFlags::X,
         );
     }
+    /// The vDSO data page is not collected by areas either, it is the same
+    /// frame in every user space
+    fn map_vdso(&mut self) {
+        self.page_table.map(
+            VirtAddr::from(VDSO_DATA).into(),
+            vdso_ppn(),
+            PTEFlags::R | PTEFlags::U,
+        );
+    }
+    /// Nor is the signal return trampoline next to the comm page
+    fn map_sigreturn(&mut self) {
+        self.page_table.map(
+            VirtAddr::from(SIGRETURN_TRAMPOLINE).into(),
+            sigreturn_ppn(),
+            PTEFlags::R | PTEFlags::X | PTEFlags::U,
+        );
+    }
+    /// The comm page, read-only for the process, see [`super::comm`]
+    fn map_comm_page(&mut self) {
+        self.push(
+            MapArea::new(
+                COMM_PAGE.into(),
+                (COMM_PAGE + PAGE_SIZE).into(),
+                AnonPrivate::eager(),
+                MapPermission::R | MapPermission::U,
+                AreaKind::Comm,
+            ),
+            None,
+        );
+    }
+    /// The trap context, without `U`: trap.S saves to it on the trap
+    /// entry mapping, and a task that could write it could point the trap
+    /// return at anything, kernel_satp and trap_handler included
+    fn map_trap_context(&mut self) {
+        self.push(
+            MapArea::new(
+                TRAP_CONTEXT.into(),
+                TRAMPOLINE.into(),
+                AnonPrivate::eager(),
+                MapPermission::R | MapPermission::W,
+                AreaKind::Trap,
+            ),
+            None,
+        );
+    }
+    /// The frame of the trap context, which must be closed to user mode
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let pte = self
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .expect("trap context not mapped");
+        assert!(
+            !pte.flags().contains(PTEFlags::U),
+            "trap context open to user mode"
+        );
+        pte.ppn()
+    }
+    /// Fill in the comm page for process `pid`, see [`super::comm`]
+    pub fn init_comm_page(&self, pid: usize) {
+        let ppn = self.translate(VirtAddr::from(COMM_PAGE).into()).unwrap().ppn();
+        *ppn.get_mut::<CommPage>() = CommPage::new(pid);
+    }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        let mut memory_set = Self::with_page_table(PageTable::new_kernel());
         // map trampoline
         memory_set.map_trampoline();
         // map kernel sections
@@ -154,74 +671,89 @@ impl MemorySet {
         );
         info!("mapping .text section");
         memory_set.push(
-            MapArea::new(
+            MapArea::identical(
                 (stext as usize).into(),
                 (etext as usize).into(),
-                MapType::Identical,
                 MapPermission::R | MapPermission::X,
             ),
             None,
         );
         info!("mapping .rodata section");
         memory_set.push(
-            MapArea::new(
+            MapArea::identical(
                 (srodata as usize).into(),
                 (erodata as usize).into(),
-                MapType::Identical,
                 MapPermission::R,
             ),
             None,
         );
         info!("mapping .data section");
         memory_set.push(
-            MapArea::new(
+            MapArea::identical(
                 (sdata as usize).into(),
                 (edata as usize).into(),
-                MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
         info!("mapping .bss section");
         memory_set.push(
-            MapArea::new(
+            MapArea::identical(
                 (sbss_with_stack as usize).into(),
                 (ebss as usize).into(),
-                MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
         info!("mapping physical memory");
         memory_set.push(
-            MapArea::new(
+            MapArea::identical(
                 (ekernel as usize).into(),
                 MEMORY_END.into(),
-                MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
+        info!("mapping memory-mapped registers");
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::identical(
+                    start.into(),
+                    (start + len).into(),
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
     /// also returns user_sp and entry point.
+    ///
+    /// The user stack is as large as the program asks for with a stack size
+    /// note, see [`user_stack_size`]. [`USER_STACK_SIZE`] of it is mapped up
+    /// front, the pages below fault in as the stack grows into them.
     pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
+        memory_set.map_vdso();
         // map program headers of elf, with U flag
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
         let ph_count = elf_header.pt2.ph_count();
-        let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
                 let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
                 let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                // elf_frames turned away images reaching above MMAP_BASE
+                assert!(end_va.0 <= MMAP_BASE, "elf segment above MMAP_BASE");
+                memory_set
+                    .check_user_range(start_va, end_va)
+                    .expect("elf segment out of the user range");
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
                 if ph_flags.is_read() {
@@ -233,56 +765,217 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                if !ALLOW_WX && map_perm.contains(MapPermission::W | MapPermission::X) {
+                    // a PTE cannot be writable without being readable
+                    warn!(
+                        "[kernel] ELF segment at {:#x} is writable and executable, mapping it read-write",
+                        start_va.0
+                    );
+                    map_perm = (map_perm - MapPermission::X) | MapPermission::R;
+                }
+                // the pages with file data are copied, the BSS beyond them is
+                // faulted in
+                let data_end = elf_data_end(&ph);
+                if data_end > start_va.floor() {
+                    let data_end_va = data_end.min(end_va.ceil()).into();
+                    let map_area = MapArea::new(
+                        start_va,
+                        data_end_va,
+                        AnonPrivate::eager(),
+                        map_perm,
+                        AreaKind::Elf,
+                    );
+                    memory_set.push(
+                        map_area,
+                        Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
+                    );
+                }
+                if end_va.ceil() > data_end {
+                    let bss = MapArea::new(
+                        data_end.into(),
+                        end_va,
+                        AnonPrivate::lazy(),
+                        map_perm,
+                        AreaKind::Elf,
+                    );
+                    memory_set.push(bss, None);
+                }
             }
         }
         // map user stack with U flags
-        let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        let user_stack_top = USER_STACK_TOP;
+        let stack_size = user_stack_size(&elf);
+        let user_stack_bottom = user_stack_top - stack_size;
+        memory_set
+            .check_user_range(user_stack_bottom.into(), user_stack_top.into())
+            .expect("user stack out of the user range");
+        let eager_bottom = user_stack_top - stack_size.min(USER_STACK_SIZE);
+        if user_stack_bottom < eager_bottom {
+            memory_set.push(
+                MapArea::new(
+                    user_stack_bottom.into(),
+                    eager_bottom.into(),
+                    AnonPrivate::lazy(),
+                    MapPermission::R | MapPermission::W | MapPermission::U,
+                    AreaKind::Stack,
+                ),
+                None,
+            );
+        }
         memory_set.push(
             MapArea::new(
-                user_stack_bottom.into(),
+                eager_bottom.into(),
                 user_stack_top.into(),
-                MapType::Framed,
+                AnonPrivate::eager(),
                 MapPermission::R | MapPermission::W | MapPermission::U,
+                AreaKind::Stack,
             ),
             None,
         );
-        // map TrapContext
-        memory_set.push(
-            MapArea::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set.map_comm_page();
+        memory_set.map_sigreturn();
+        memory_set.map_trap_context();
         (
             memory_set,
             user_stack_top,
             elf.header.pt2.entry_point() as usize,
         )
     }
+    /// Frames [`MemorySet::from_elf`] takes for `elf_data`, with a bound for
+    /// the page tables; `None` if it is not an ELF that can be loaded, e.g.
+    /// one reaching above [`MMAP_BASE`]
+    pub fn elf_frames(elf_data: &[u8]) -> Option<usize> {
+        let elf = xmas_elf::ElfFile::new(elf_data).ok()?;
+        if elf.header.pt1.magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return None;
+        }
+        // the user stack, in up to two areas, the comm page and the trap
+        // context
+        let mut pages = user_stack_size(&elf).min(USER_STACK_SIZE) / PAGE_SIZE + 2;
+        let mut areas = 4;
+        for i in 0..elf.header.pt2.ph_count() {
+            let ph = elf.program_header(i).ok()?;
+            if ph.get_type().ok()? == xmas_elf::program::Type::Load {
+                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
+                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                if end_va.0 > MMAP_BASE {
+                    return None;
+                }
+                // a BSS area of its own takes no frame up front
+                let data_end = elf_data_end(&ph).min(end_va.ceil());
+                pages += data_end.0.saturating_sub(start_va.floor().0);
+                areas += 2;
+            }
+        }
+        // the root, and for each area and the trampoline at most a new table
+        // on each of the two lower levels, plus one per 512 pages
+        Some(pages + 1 + 2 * (areas + 1) + pages / 512)
+    }
+    /// A user space with the areas of `images` and their saved pages, like
+    /// one [`Self::from_elf`] built otherwise. `None` if an area is out of
+    /// the user range, overlaps another one or is not what
+    /// [`Self::area_images`] gives, e.g. writable and executable where that
+    /// is not allowed, or a page is out of its area
+    pub fn from_images(images: &[AreaImage]) -> Option<Self> {
+        let mut memory_set = Self::new_bare();
+        memory_set.map_trampoline();
+        memory_set.map_vdso();
+        for image in images {
+            let perm = image.perm;
+            let bad_perm = !perm.contains(MapPermission::U)
+                || (perm.contains(MapPermission::W) && !perm.contains(MapPermission::R))
+                || (!ALLOW_WX && perm.contains(MapPermission::W | MapPermission::X));
+            let in_area = |vpn: VirtPageNum| image.start <= vpn && vpn < image.end;
+            let pages_ok = image
+                .pages
+                .iter()
+                .all(|&(vpn, bytes)| in_area(vpn) && bytes.len() == PAGE_SIZE)
+                && image.pages.windows(2).all(|pair| pair[0].0 < pair[1].0);
+            if bad_perm
+                || !pages_ok
+                || !matches!(image.kind, AreaKind::Elf | AreaKind::Stack | AreaKind::Mmap)
+                || memory_set
+                    .check_user_range(image.start.into(), image.end.into())
+                    .is_err()
+            {
+                return None;
+            }
+            let backend = if image.lazy {
+                AnonPrivate::lazy()
+            } else {
+                AnonPrivate::eager()
+            };
+            let mut area =
+                MapArea::new(image.start.into(), image.end.into(), backend, perm, image.kind);
+            if image.lazy {
+                for &(vpn, _) in image.pages.iter() {
+                    area.map_one(&mut memory_set.page_table, vpn);
+                }
+            }
+            memory_set.push(area, None);
+            for &(vpn, bytes) in image.pages.iter() {
+                let ppn = memory_set.translate(vpn).unwrap().ppn();
+                ppn.get_bytes_array().copy_from_slice(bytes);
+            }
+        }
+        memory_set.map_comm_page();
+        memory_set.map_sigreturn();
+        memory_set.map_trap_context();
+        Some(memory_set)
+    }
+    /// Frames [`MemorySet::from_images`] takes for `images`, with a bound
+    /// for the page tables as in [`Self::elf_frames`]
+    pub fn image_frames(images: &[AreaImage]) -> usize {
+        // the comm page and the trap context
+        let mut pages = 2;
+        for image in images {
+            pages += if image.lazy {
+                image.pages.len()
+            } else {
+                image.end.0.saturating_sub(image.start.0)
+            };
+        }
+        let areas = images.len() + 2;
+        pages + 1 + 2 * (areas + 1) + pages / 512
+    }
+    /// Unmap every area but the trap context, so that exec can free the old
+    /// image before building the new one and the task can still be killed
+    pub fn release_user_areas(&mut self) {
+        let trap_cx_vpn: VirtPageNum = VirtAddr::from(TRAP_CONTEXT).into();
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            if self.areas[idx].vpn_range.get_start() == trap_cx_vpn {
+                idx += 1;
+                continue;
+            }
+            let mut area = self.areas.remove(idx);
+            self.unaccount(&area);
+            area.unmap(&mut self.page_table);
+        }
+    }
     /// Copy an identical user_space
     pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
+        memory_set.map_vdso();
+        memory_set.map_sigreturn();
+        // copy data sections/trap_context/user_stack/comm page
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
+            let mut new_area = MapArea::from_another(area);
+            if area.backend.is_lazy() {
+                // only what the parent touched, the other pages fault in as
+                // zeros on either side
+                for vpn in area.backend.resident() {
+                    new_area.map_one(&mut memory_set.page_table, vpn);
+                }
+            }
             memory_set.push(new_area, None);
+            if area.backend.fork_behavior() == ForkBehavior::Share {
+                continue;
+            }
             // copy data from another space
-            for vpn in area.vpn_range {
+            for vpn in area.backend.resident() {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn
@@ -305,73 +998,203 @@ impl MemorySet {
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
         self.areas.clear();
+        self.mapped_pages = 0;
+        self.resident_pages = 0;
+    }
+    /// Free the page tables once [`Self::recycle_data_pages`] emptied the
+    /// set, and return how many frames they took
+    pub fn release_page_tables(&mut self) -> usize {
+        if !self.areas.is_empty() {
+            return 0;
+        }
+        self.page_table.release_tables()
+    }
+}
+
+/// Section of the note in which a program asks for a user stack size, see
+/// `stack_size!` in the user library
+const STACK_SIZE_NOTE: &str = ".note.rcore.stacksize";
+/// Owner and type of that note, its descriptor is the size as a `u64`
+const STACK_SIZE_NOTE_NAME: &[u8] = b"rcore\0";
+const NT_RCORE_STACK_SIZE: u32 = 1;
+
+/// The user stack size `elf` asks for, rounded up to pages and capped at
+/// [`USER_STACK_MAX`], or [`USER_STACK_SIZE`] if it has no note for it
+fn user_stack_size(elf: &xmas_elf::ElfFile) -> usize {
+    let size = match elf
+        .find_section_by_name(STACK_SIZE_NOTE)
+        .and_then(|section| parse_stack_size_note(section.raw_data(elf)))
+    {
+        Some(size) => size,
+        None => return USER_STACK_SIZE,
+    };
+    if size > USER_STACK_MAX {
+        warn!(
+            "[kernel] user stack of {:#x} bytes asked for, capped at {:#x}",
+            size, USER_STACK_MAX
+        );
+    }
+    let size = size.clamp(PAGE_SIZE, USER_STACK_MAX);
+    (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+/// The size in a stack size note, `None` if `note` is not one
+fn parse_stack_size_note(note: &[u8]) -> Option<usize> {
+    let word = |at: usize| Some(u32::from_le_bytes(note.get(at..at + 4)?.try_into().ok()?));
+    let (name_size, desc_size, kind) = (word(0)? as usize, word(4)? as usize, word(8)?);
+    // the name is padded to 4 bytes
+    let desc = 12 + (name_size + 3) / 4 * 4;
+    if note.get(12..12 + name_size)? != STACK_SIZE_NOTE_NAME
+        || desc_size != 8
+        || kind != NT_RCORE_STACK_SIZE
+    {
+        return None;
     }
+    Some(u64::from_le_bytes(note.get(desc..desc + 8)?.try_into().ok()?) as usize)
+}
+
+/// The page after the last one with file data of `ph`, where its BSS pages
+/// start
+fn elf_data_end(ph: &xmas_elf::program::ProgramHeader) -> VirtPageNum {
+    VirtAddr::from((ph.virtual_addr() + ph.file_size()) as usize).ceil()
 }
 
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
-    map_type: MapType,
+    backend: Box<dyn MappingBackend>,
     map_perm: MapPermission,
+    kind: AreaKind,
+}
+
+/// What an area is for, as [`MemorySet::vma_list`] shows it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AreaKind {
+    /// A segment of the program, or its BSS
+    Elf,
+    Stack,
+    /// What mmap or map_self mapped
+    Mmap,
+    Comm,
+    Trap,
+    /// Any area of the kernel space
+    Kernel,
+}
+
+impl AreaKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            AreaKind::Elf => "elf",
+            AreaKind::Stack => "stack",
+            AreaKind::Mmap => "mmap",
+            AreaKind::Comm => "comm",
+            AreaKind::Trap => "trap",
+            AreaKind::Kernel => "kernel",
+        }
+    }
 }
 
 impl MapArea {
     pub fn new(
         start_va: VirtAddr,
         end_va: VirtAddr,
-        map_type: MapType,
+        backend: Box<dyn MappingBackend>,
         map_perm: MapPermission,
+        kind: AreaKind,
     ) -> Self {
         let start_vpn: VirtPageNum = start_va.floor();
         let end_vpn: VirtPageNum = end_va.ceil();
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
-            data_frames: BTreeMap::new(),
-            map_type,
+            backend,
             map_perm,
+            kind,
         }
     }
+    /// Area mapping the physical pages at the same addresses
+    pub fn identical(start_va: VirtAddr, end_va: VirtAddr, map_perm: MapPermission) -> Self {
+        Self::new(
+            start_va,
+            end_va,
+            Mmio::identical(start_va.floor()),
+            map_perm,
+            AreaKind::Kernel,
+        )
+    }
     pub fn from_another(another: &MapArea) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
-            data_frames: BTreeMap::new(),
-            map_type: another.map_type,
+            backend: another.backend.fork(),
             map_perm: another.map_perm,
+            kind: another.kind,
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
-        match self.map_type {
-            MapType::Identical => {
-                ppn = PhysPageNum(vpn.0);
-            }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
-            }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    pub fn overlaps(&self, rg: VPNRange) -> bool {
+        self.vpn_range.get_start() < rg.get_end() && rg.get_start() < self.vpn_range.get_end()
+    }
+    /// Cut the area at `vpn`, keeping `[start, vpn)` and returning
+    /// `[vpn, end)` together with its frames
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> MapArea {
+        assert!(self.contains(vpn) && vpn != self.vpn_range.get_start());
+        let tail = MapArea {
+            vpn_range: VPNRange::new(vpn, self.vpn_range.get_end()),
+            backend: self.backend.split_off(vpn),
+            map_perm: self.map_perm,
+            kind: self.kind,
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        tail
+    }
+    /// PTE flags of the pages, an area without any access (mprotect to
+    /// PROT_NONE) keeps its frames mapped for the kernel only, as a valid PTE
+    /// without R/W/X would be a page table pointer
+    fn pte_flags(&self) -> PTEFlags {
+        if self.map_perm & (MapPermission::R | MapPermission::W | MapPermission::X)
+            == MapPermission::empty()
+        {
+            PTEFlags::R
+        } else {
+            PTEFlags::from_bits(self.map_perm.bits).unwrap()
+        }
+    }
+    /// PTE flags of the resident page `vpn`, read-only while it is lent
+    fn page_flags(&self, vpn: VirtPageNum) -> PTEFlags {
+        if self.backend.is_lent(vpn) {
+            self.pte_flags() - PTEFlags::W
+        } else {
+            self.pte_flags()
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+    }
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let ppn = self.backend.fault_in(vpn);
+        page_table.map(vpn, ppn, self.pte_flags());
     }
 
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        #[allow(clippy::single_match)]
-        match self.map_type {
-            MapType::Framed => {
-                self.data_frames.remove(&vpn);
-            }
-            _ => {}
-        }
+        self.backend.flush(vpn);
+        self.backend.on_unmap(vpn);
         page_table.unmap(vpn);
     }
+    /// Map every page, except for lazy areas which are mapped page by page
+    /// as they are touched
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.backend.is_lazy() {
+            return;
+        }
         for vpn in self.vpn_range {
             self.map_one(page_table, vpn);
         }
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.backend.is_lazy() {
+            for vpn in self.backend.resident() {
+                self.unmap_one(page_table, vpn);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
@@ -379,7 +1202,6 @@ impl MapArea {
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
-        assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
         let mut current_vpn = self.vpn_range.get_start();
         let len = data.len();
@@ -400,13 +1222,6 @@ impl MapArea {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-/// map type for memory set: identical or framed
-pub enum MapType {
-    Identical,
-    Framed,
-}
-
 bitflags! {
     /// map permission corresponding to that in pte: `R W X U`
     pub struct MapPermission: u8 {
@@ -423,20 +1238,193 @@ pub fn remap_test() {
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
     let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
-    assert!(!kernel_space
-        .page_table
-        .translate(mid_text.floor())
-        .unwrap()
-        .writable());
-    assert!(!kernel_space
-        .page_table
-        .translate(mid_rodata.floor())
-        .unwrap()
-        .writable());
-    assert!(!kernel_space
-        .page_table
-        .translate(mid_data.floor())
-        .unwrap()
-        .executable());
+    let mid_bss: VirtAddr = ((sbss_with_stack as usize + ebss as usize) / 2).into();
+    let mid_phys: VirtAddr = ((ekernel as usize + MEMORY_END) / 2).into();
+    // (name, representative page, readable, writable, executable)
+    let checks = [
+        (".text", mid_text, true, false, true),
+        (".rodata", mid_rodata, true, false, false),
+        (".data", mid_data, true, true, false),
+        (".bss", mid_bss, true, true, false),
+        ("physical memory", mid_phys, true, true, false),
+    ];
+    for (name, va, r, w, x) in checks {
+        let pte = kernel_space
+            .page_table
+            .translate(va.floor())
+            .unwrap_or_else(|| panic!("remap_test: {} page {:?} is not mapped", name, va));
+        assert!(pte.is_valid(), "remap_test: {} page {:?} is invalid", name, va);
+        assert_eq!(pte.readable(), r, "remap_test: {} readable bit is wrong", name);
+        assert_eq!(pte.writable(), w, "remap_test: {} writable bit is wrong", name);
+        assert_eq!(pte.executable(), x, "remap_test: {} executable bit is wrong", name);
+        assert_eq!(
+            pte.ppn().0,
+            va.floor().0,
+            "remap_test: {} is not identically mapped",
+            name
+        );
+    }
     info!("remap_test passed!");
 }
+
+/// Map one page below, at and above each boundary of user space
+pub fn user_range_test() {
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.map_trampoline();
+    memory_set.map_vdso();
+    memory_set.map_trap_context();
+    let stack_bottom = USER_STACK_TOP - USER_STACK_SIZE;
+    memory_set.push(
+        MapArea::new(
+            stack_bottom.into(),
+            USER_STACK_TOP.into(),
+            AnonPrivate::eager(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+            AreaKind::Stack,
+        ),
+        None,
+    );
+    // up to the last byte of the page, which does not wrap for the trampoline
+    let check = |va: usize| memory_set.check_user_range(va.into(), (va + PAGE_SIZE - 1).into());
+    use UserRangeError::*;
+    let checks = [
+        ("stack bottom", stack_bottom, [Ok(()), Err(Overlap), Err(Overlap)]),
+        ("USER_VA_MAX", USER_VA_MAX, [Err(Overlap), Err(OutOfRange), Err(OutOfRange)]),
+        ("vDSO page", VDSO_DATA, [Err(OutOfRange); 3]),
+        ("trap context", TRAP_CONTEXT, [Err(OutOfRange); 3]),
+    ];
+    for (name, boundary, expected) in checks {
+        let got = [
+            check(boundary - PAGE_SIZE),
+            check(boundary),
+            check(boundary + PAGE_SIZE),
+        ];
+        assert_eq!(got, expected, "user_range_test: wrong around {}", name);
+    }
+    assert_eq!(
+        memory_set.check_user_range(MMAP_BASE.into(), MMAP_BASE.into()),
+        Err(Empty),
+        "user_range_test: empty range"
+    );
+    // mmap also keeps off the image below MMAP_BASE, and off the top of
+    // the address space when the length wraps around
+    assert_eq!(memory_set.mmap(MMAP_BASE - PAGE_SIZE, PAGE_SIZE, 0b011), -EINVAL);
+    assert_eq!(memory_set.mmap(MMAP_BASE, PAGE_SIZE, 0b011), 0);
+    assert_eq!(memory_set.mmap(MMAP_BASE + PAGE_SIZE, PAGE_SIZE, 0b011), 0);
+    assert_eq!(memory_set.mmap(MMAP_BASE + 2 * PAGE_SIZE, usize::MAX, 0b011), -ENOMEM);
+    assert_eq!(memory_set.mmap(TRAP_CONTEXT, PAGE_SIZE, 0b011), -ENOMEM);
+    assert_eq!(memory_set.mmap(MMAP_BASE, 2 * PAGE_SIZE, 0b011), -EEXIST);
+    info!("user_range_test passed!");
+}
+
+/// Check the kernel memory layout right after the kernel space is activated.
+///
+/// A broken linker script or config (overlapping sections, `ekernel` beyond
+/// `MEMORY_END`, frames overlapping the heap) otherwise shows up as random
+/// faults much later, so every violated invariant panics here by name.
+pub fn sanity_check() {
+    let sections = [
+        (".text", stext as usize, etext as usize),
+        (".rodata", srodata as usize, erodata as usize),
+        (".data", sdata as usize, edata as usize),
+        (".bss.stack", sbss_with_stack as usize, sbss as usize),
+        (".bss", sbss as usize, ebss as usize),
+    ];
+    // sections are ordered, non-overlapping and within the kernel image
+    assert_eq!(
+        skernel as usize, stext as usize,
+        "sanity_check: .text does not start at skernel"
+    );
+    let mut last_end = skernel as usize;
+    for (name, start, end) in sections {
+        assert!(start <= end, "sanity_check: {} starts after it ends", name);
+        assert!(
+            start >= last_end,
+            "sanity_check: {} overlaps the previous section",
+            name
+        );
+        last_end = end;
+    }
+    assert!(
+        last_end <= ekernel as usize,
+        "sanity_check: kernel sections extend beyond ekernel"
+    );
+    // page aligned boundaries, since permissions are set per page
+    for (name, addr) in [
+        ("skernel", skernel as usize),
+        ("strampoline", strampoline as usize),
+        ("etext", etext as usize),
+        ("erodata", erodata as usize),
+        ("edata", edata as usize),
+        ("ebss", ebss as usize),
+        ("ekernel", ekernel as usize),
+    ] {
+        assert!(
+            VirtAddr::from(addr).aligned(),
+            "sanity_check: {} = {:#x} is not page aligned",
+            name,
+            addr
+        );
+    }
+    assert!(
+        ekernel as usize <= MEMORY_END,
+        "sanity_check: ekernel {:#x} is beyond MEMORY_END {:#x}",
+        ekernel as usize,
+        MEMORY_END
+    );
+    // frames must not overlap the kernel image or the heap
+    let (frame_start, frame_end) = frame_allocator_range();
+    let frame_start: usize = PhysAddr::from(frame_start).into();
+    let frame_end: usize = PhysAddr::from(frame_end).into();
+    let (heap_start, heap_end) = heap_range();
+    assert!(
+        frame_start >= ekernel as usize && frame_end <= MEMORY_END,
+        "sanity_check: frame allocator range overlaps the kernel image"
+    );
+    assert!(
+        heap_end <= frame_start || heap_start >= frame_end,
+        "sanity_check: frame allocator range overlaps the kernel heap"
+    );
+    assert!(
+        heap_start >= sbss as usize && heap_end <= ebss as usize,
+        "sanity_check: kernel heap is not inside .bss"
+    );
+    // trampoline maps to strampoline with R|X only
+    let kernel_space = KERNEL_SPACE.exclusive_access();
+    let pte = kernel_space
+        .translate(VirtAddr::from(TRAMPOLINE).into())
+        .expect("sanity_check: trampoline is not mapped");
+    assert_eq!(
+        PhysAddr::from(pte.ppn()).0,
+        strampoline as usize,
+        "sanity_check: trampoline does not map to strampoline"
+    );
+    assert!(
+        pte.readable() && pte.executable() && !pte.writable(),
+        "sanity_check: trampoline permission is not R|X"
+    );
+    drop(kernel_space);
+    remap_test();
+    user_range_test();
+    // one-screen memory map
+    info!("kernel memory map:");
+    let print_range = |name: &str, start: usize, end: usize| {
+        info!(
+            "  {:<12} [{:#x}, {:#x}) {:>8} KiB",
+            name,
+            start,
+            end,
+            (end - start) / 1024
+        );
+    };
+    for (name, start, end) in sections {
+        print_range(name, start, end);
+    }
+    print_range("heap", heap_start, heap_end);
+    print_range("frames", frame_start, frame_end);
+    info!(
+        "  {:<12} {:#x} -> {:#x}",
+        "trampoline", TRAMPOLINE, strampoline as usize
+    );
+    info!("sanity_check passed!");
+}
@@ -8,22 +8,47 @@
 
 
 mod address;
+mod backend;
+pub mod comm;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shrink;
+pub mod tlb;
+pub mod vdso;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
-pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry};
+pub use backend::{AnonPrivate, AppImage, ForkBehavior, MappingBackend, Mmio};
+pub use frame_allocator::{
+    frame_alloc, frame_allocator_compact, frame_allocator_free, try_frame_usage, FrameTracker,
+};
+pub use heap_allocator::{heap_usage, reset_heap_peak};
+pub use shrink::{reclaim, register_shrinker, Memory, SHRINK_HARD, SHRINK_LIGHT};
+#[cfg(feature = "kernel_test")]
+pub use shrink::shrink_test;
+pub use memory_set::{remap_test, sanity_check, user_range_test};
+pub use memory_set::{
+    AreaImage, AreaKind, MapPermission, MemorySet, MemoryStats, UserRangeError, KERNEL_SPACE,
+};
+pub use page_table::{
+    copy_from_user, copy_to_user, translated_byte_buffer, user_chunks, PageTableEntry, UserBuffer,
+};
+#[cfg(debug_assertions)]
+pub use page_table::assert_sum_clear;
 pub use page_table::{PTEFlags, PageTable};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
+    vdso::init();
+    activate_kernel_space();
+    tlb::init();
+}
+
+/// Switch the current hart to the kernel address space
+pub fn activate_kernel_space() {
     KERNEL_SPACE.exclusive_access().activate();
 }
@@ -1,10 +1,20 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
+//!
+//! The kernel runs on its own page table, where no page has U, and reaches
+//! user memory by walking the page table of the user space here and going
+//! through the identity map of the frames. Only [`copy_to_user`] and
+//! [`copy_from_user`] dereference a user pointer, when the user space is the
+//! one `satp` holds, and set `sstatus.SUM` just around that copy; it stays
+//! clear otherwise, which trap_return and the scheduler check in debug
+//! builds.
 
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-use alloc::string::String;
+use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+use super::{tlb, MapPermission, VPNRange};
+use crate::config::PAGE_SIZE;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
+use riscv::register::{satp, sstatus};
 
 bitflags! {
     /// page table entry flags
@@ -57,9 +67,18 @@ impl PageTableEntry {
 }
 
 /// page table structure
+///
+/// Every change goes through [`tlb::page_table_changed`], which is how the
+/// trap paths know that the TLB needs a flush, see [`super::tlb`]. A user
+/// table keeps count of its own changes.
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
+    /// The table of the kernel space, which has ASID 0
+    kernel: bool,
+    /// Of a user table, none for the kernel's and one from a token, which
+    /// count as the kernel's, see [`tlb::page_table_changed`]
+    generation: Option<tlb::Generation>,
 }
 
 /// Assume that it won't oom when creating/mapping.
@@ -69,6 +88,16 @@ impl PageTable {
         PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
+            kernel: false,
+            generation: Some(tlb::Generation::new()),
+        }
+    }
+    /// The table of the kernel space
+    pub fn new_kernel() -> Self {
+        Self {
+            kernel: true,
+            generation: None,
+            ..Self::new()
         }
     }
     /// Temporarily used to get arguments from user space.
@@ -76,8 +105,18 @@ impl PageTable {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
+            kernel: false,
+            generation: None,
         }
     }
+    fn changed(&self) {
+        tlb::page_table_changed(self.generation.as_ref());
+    }
+    /// Where the trap context of a user space finds its generation
+    pub fn generation(&self) -> tlb::GenerationPtr {
+        let generation = self.generation.as_ref().expect("not a user table");
+        generation.as_ptr()
+    }
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let mut idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
@@ -119,12 +158,36 @@ impl PageTable {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.changed();
     }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
+        self.changed();
+    }
+    /// Free the frames of the table but the root, which is cleared, and
+    /// return how many. Only for a table nothing is mapped through any more
+    pub fn release_tables(&mut self) -> usize {
+        if self.frames.is_empty() {
+            // borrowed by `from_token`
+            return 0;
+        }
+        for pte in self.root_ppn.get_pte_array() {
+            *pte = PageTableEntry::empty();
+        }
+        let freed = self.frames.len() - 1;
+        self.frames.truncate(1);
+        self.changed();
+        freed
+    }
+    /// Replace the flags of a mapped page, keeping its frame
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before changing flags", vpn);
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V);
+        self.changed();
     }
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).copied()
@@ -140,59 +203,221 @@ impl PageTable {
         })
     }
     pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+        let asid = if self.kernel { 0 } else { tlb::user_asid_bits() };
+        8usize << 60 | asid | self.root_ppn.0
     }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// Panic if `sstatus.SUM` is set, which nothing outside a [`SumGuard`] needs
+#[cfg(debug_assertions)]
+#[track_caller]
+pub fn assert_sum_clear() {
+    assert!(!sstatus::read().sum(), "sstatus.SUM left set");
+}
+
+/// `sstatus.SUM` set while it lives, so the kernel may touch user pages
+struct SumGuard;
+
+impl SumGuard {
+    fn new() -> Self {
+        unsafe { sstatus::set_sum() };
+        Self
+    }
+}
+
+impl Drop for SumGuard {
+    fn drop(&mut self) {
+        unsafe { sstatus::clear_sum() };
+    }
+}
+
+/// Whether the kernel can reach all of `[va, va + len)` in the address space
+/// of `token` through the user pointer itself: that space is the current
+/// one and each page is mapped for user mode, writable if `write`
+fn directly_accessible(token: usize, va: usize, len: usize, write: bool) -> bool {
+    if satp::read().bits() != token {
+        return false;
+    }
     let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+    let rg = VPNRange::new(VirtAddr::from(va).floor(), VirtAddr::from(va + len).ceil());
+    let access = if write { PTEFlags::W } else { PTEFlags::R };
+    let needed = PTEFlags::U | access;
+    for vpn in rg {
+        match page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() && pte.flags().contains(needed) => {}
+            _ => return false,
         }
-        start = end_va.into();
     }
-    v
+    true
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
+/// The pieces of `[start, end)` in a user space, one for each run of
+/// physically contiguous frames
+pub struct UserChunks {
+    page_table: PageTable,
+    start: usize,
+    end: usize,
+}
+
+/// The bytes of `[ptr, ptr + len)` in the address space of `token` through
+/// the identity map of the kernel, up to the first page that is not mapped
+/// for user mode
+pub fn user_chunks(token: usize, ptr: *const u8, len: usize) -> UserChunks {
+    UserChunks {
+        page_table: PageTable::from_token(token),
+        start: ptr as usize,
+        end: ptr as usize + len,
+    }
+}
+
+impl UserChunks {
+    /// The frame of the user page holding `va`, `None` if it is not mapped
+    /// for user mode
+    fn user_frame(&self, va: usize) -> Option<PhysPageNum> {
+        let pte = self.page_table.translate(VirtAddr::from(va).floor())?;
+        if pte.is_valid() && pte.flags().contains(PTEFlags::U) {
+            Some(pte.ppn())
         } else {
-            string.push(ch as char);
-            va += 1;
+            None
+        }
+    }
+}
+
+impl Iterator for UserChunks {
+    type Item = &'static mut [u8];
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+            return None;
+        }
+        let pa = match self.user_frame(self.start) {
+            Some(ppn) => PhysAddr::from(ppn).0 + self.start % PAGE_SIZE,
+            None => {
+                self.start = self.end;
+                return None;
+            }
+        };
+        let mut len = (PAGE_SIZE - self.start % PAGE_SIZE).min(self.end - self.start);
+        // take in the following pages as long as their frames follow
+        while self.start + len < self.end {
+            match self.user_frame(self.start + len) {
+                Some(ppn) if PhysAddr::from(ppn).0 == pa + len => {}
+                _ => break,
+            }
+            len = (len + PAGE_SIZE).min(self.end - self.start);
         }
+        self.start += len;
+        Some(unsafe { core::slice::from_raw_parts_mut(pa as *mut u8, len) })
+    }
+}
+
+/// translate a pointer to a mutable u8 Vec through page table
+pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+    user_chunks(token, ptr, len).collect()
+}
+
+/// Copy `values` to `ptr` in the address space of `token`, where they may
+/// cross page boundaries. False if some of it is not mapped for user mode,
+/// only what comes before is written then
+pub fn copy_to_user<T: Copy>(token: usize, ptr: *mut T, values: &[T]) -> bool {
+    let size = core::mem::size_of_val(values);
+    let bytes = unsafe { core::slice::from_raw_parts(values.as_ptr() as *const u8, size) };
+    reclaim_lent(token, ptr as usize, size);
+    if directly_accessible(token, ptr as usize, size, true) {
+        let _sum = SumGuard::new();
+        unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr as *mut u8, size) };
+        return true;
     }
-    string
+    let mut copied = 0;
+    for chunk in user_chunks(token, ptr as *const u8, size) {
+        chunk.copy_from_slice(&bytes[copied..copied + chunk.len()]);
+        copied += chunk.len();
+    }
+    copied == size
+}
+
+/// Copy `values` from `ptr` in the address space of `token`, where they may
+/// cross page boundaries. False if some of it is not mapped for user mode,
+/// only what comes before is read then
+pub fn copy_from_user<T: Copy>(token: usize, ptr: *const T, values: &mut [T]) -> bool {
+    let size = core::mem::size_of_val(values);
+    let bytes = unsafe { core::slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, size) };
+    if directly_accessible(token, ptr as usize, size, false) {
+        let _sum = SumGuard::new();
+        unsafe { core::ptr::copy_nonoverlapping(ptr as *const u8, bytes.as_mut_ptr(), size) };
+        return true;
+    }
+    let mut copied = 0;
+    for chunk in user_chunks(token, ptr as *const u8, size) {
+        bytes[copied..copied + chunk.len()].copy_from_slice(chunk);
+        copied += chunk.len();
+    }
+    copied == size
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    //println!("into translated_refmut!");
+
+/// Take back the lent pages of `[va, va + len)` in the current address space
+/// before the kernel writes there, as a write from user mode would
+fn reclaim_lent(token: usize, va: usize, len: usize) {
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    let rg = VPNRange::new(VirtAddr::from(va).floor(), VirtAddr::from(va + len).ceil());
+    for vpn in rg {
+        let read_only = page_table
+            .translate(vpn)
+            .map_or(false, |pte| pte.is_valid() && !pte.writable());
+        if read_only && crate::task::current_user_token() == Some(token) {
+            crate::task::fault_in(VirtAddr::from(vpn).into(), MapPermission::W);
+        }
+    }
+}
+
+/// An array of user-space buffers, as returned by [`translated_byte_buffer`]
+pub struct UserBuffer {
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self { buffers }
+    }
+    /// Total length of the buffers
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|b| b.len()).sum()
+    }
+}
+
+impl IntoIterator for UserBuffer {
+    type Item = *mut u8;
+    type IntoIter = UserBufferIterator;
+    fn into_iter(self) -> Self::IntoIter {
+        UserBufferIterator {
+            buffers: self.buffers,
+            current_buffer: 0,
+            current_idx: 0,
+        }
+    }
+}
+
+/// Byte by byte iterator over a [`UserBuffer`]
+pub struct UserBufferIterator {
+    buffers: Vec<&'static mut [u8]>,
+    current_buffer: usize,
+    current_idx: usize,
+}
+
+impl Iterator for UserBufferIterator {
+    type Item = *mut u8;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_buffer >= self.buffers.len() {
+            None
+        } else {
+            let r = &mut self.buffers[self.current_buffer][self.current_idx] as *mut _;
+            if self.current_idx + 1 == self.buffers[self.current_buffer].len() {
+                self.current_idx = 0;
+                self.current_buffer += 1;
+            } else {
+                self.current_idx += 1;
+            }
+            Some(r)
+        }
+    }
 }
@@ -0,0 +1,176 @@
+//! Shrinkers, giving back memory kept only for later before an allocation
+//! fails
+//!
+//! A cache registers a shrinker with [`register_shrinker`], saying which
+//! [`Memory`] it gives back. When [`super::frame_alloc`] finds no frame, or
+//! the kernel heap cannot take an allocation, [`reclaim`] calls the
+//! shrinkers of that memory at [`SHRINK_LIGHT`] and, if they give back
+//! nothing, at [`SHRINK_HARD`], and the allocation is tried once more. If it
+//! fails again, [`report_oom`] prints what each shrinker returned.
+//!
+//! A shrinker runs in whatever context the allocation came from, with locks
+//! held that it cannot know about, so it only takes its own locks with
+//! `try_exclusive_access` and skips what it cannot lock. An allocation of a
+//! shrinker gets no reclaim of its own, it fails right away if the memory is
+//! still short.
+
+use crate::sync::{InterruptGuard, UPSafeCell};
+use lazy_static::*;
+
+/// What a shrinker gives back
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Memory {
+    /// Physical frames, counted in frames
+    Frames,
+    /// The kernel heap, counted in bytes
+    Heap,
+}
+
+/// Give back only what is cheap to have again
+pub const SHRINK_LIGHT: usize = 0;
+/// Give back everything that can go
+pub const SHRINK_HARD: usize = 1;
+
+const MAX_SHRINKERS: usize = 8;
+
+#[derive(Copy, Clone)]
+struct Shrinker {
+    name: &'static str,
+    memory: Memory,
+    shrink: fn(usize) -> usize,
+}
+
+/// Kept in place so that a reclaim inside the heap allocator never needs
+/// the heap
+struct Shrinkers {
+    slots: [Option<Shrinker>; MAX_SHRINKERS],
+    /// What each shrinker returned the last time it ran
+    returned: [usize; MAX_SHRINKERS],
+    /// The level each memory was last reclaimed at
+    level: [usize; 2],
+}
+
+lazy_static! {
+    static ref SHRINKERS: UPSafeCell<Shrinkers> = unsafe {
+        UPSafeCell::new(Shrinkers {
+            slots: [None; MAX_SHRINKERS],
+            returned: [0; MAX_SHRINKERS],
+            level: [SHRINK_LIGHT; 2],
+        })
+    };
+}
+
+/// Have `shrink` called with a level when `memory` runs out, it returns how
+/// much it gave back in the unit of `memory`
+pub fn register_shrinker(name: &'static str, memory: Memory, shrink: fn(usize) -> usize) {
+    let mut shrinkers = SHRINKERS.exclusive_access();
+    let slot = shrinkers
+        .slots
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .expect("too many shrinkers");
+    *slot = Some(Shrinker { name, memory, shrink });
+}
+
+/// Call the shrinkers of `memory`, harder if a light pass gives back
+/// nothing, and return how much they gave back
+///
+/// 0 without calling any while another reclaim runs, e.g. for an allocation
+/// of a shrinker.
+pub fn reclaim(memory: Memory) -> usize {
+    let _guard = InterruptGuard::new();
+    let mut shrinkers = match SHRINKERS.try_exclusive_access() {
+        Some(shrinkers) => shrinkers,
+        None => return 0,
+    };
+    let shrinkers = &mut *shrinkers;
+    for level in [SHRINK_LIGHT, SHRINK_HARD] {
+        shrinkers.level[memory as usize] = level;
+        let mut freed = 0;
+        for (slot, returned) in shrinkers.slots.iter().zip(shrinkers.returned.iter_mut()) {
+            match slot {
+                Some(shrinker) if shrinker.memory == memory => {
+                    *returned = (shrinker.shrink)(level);
+                    freed += *returned;
+                }
+                _ => {}
+            }
+        }
+        if freed > 0 {
+            return freed;
+        }
+    }
+    0
+}
+
+/// Print that an allocation of `memory` failed after [`reclaim`], with what
+/// each shrinker returned then. Nothing while a reclaim runs, a shrinker
+/// may fail an allocation on purpose
+pub fn report_oom(memory: Memory) {
+    let _guard = InterruptGuard::new();
+    let shrinkers = match SHRINKERS.try_exclusive_access() {
+        Some(shrinkers) => shrinkers,
+        None => return,
+    };
+    println!(
+        "[kernel] out of {:?} after reclaim at level {}",
+        memory, shrinkers.level[memory as usize]
+    );
+    for (slot, returned) in shrinkers.slots.iter().zip(shrinkers.returned.iter()) {
+        match slot {
+            Some(shrinker) if shrinker.memory == memory => {
+                println!("[kernel]   shrinker {} gave back {}", shrinker.name, returned);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "kernel_test")]
+lazy_static! {
+    /// Frames [`shrink_test`] holds as if cached
+    static ref STASH: UPSafeCell<alloc::vec::Vec<super::FrameTracker>> =
+        unsafe { UPSafeCell::new(alloc::vec::Vec::new()) };
+}
+
+#[cfg(feature = "kernel_test")]
+fn give_back_stash(level: usize) -> usize {
+    if level < SHRINK_HARD {
+        return 0;
+    }
+    match STASH.try_exclusive_access() {
+        Some(mut stash) => {
+            let freed = stash.len();
+            stash.clear();
+            freed
+        }
+        None => 0,
+    }
+}
+
+/// Take every frame, stash two with a shrinker that gives them back only
+/// when pressed hard, and check that allocations get them back before
+/// failing. The failure at the end prints an OOM report on purpose
+#[cfg(feature = "kernel_test")]
+pub fn shrink_test() {
+    use super::frame_alloc;
+    use alloc::vec::Vec;
+    register_shrinker("shrink_test stash", Memory::Frames, give_back_stash);
+    let mut taken = Vec::new();
+    while let Some(frame) = frame_alloc() {
+        taken.push(frame);
+    }
+    info!("shrink_test: took all {} frames", taken.len());
+    {
+        let mut stash = STASH.exclusive_access();
+        stash.push(taken.pop().unwrap());
+        stash.push(taken.pop().unwrap());
+    }
+    taken.push(frame_alloc().expect("shrink_test: stash not reclaimed"));
+    assert!(STASH.exclusive_access().is_empty());
+    taken.push(frame_alloc().expect("shrink_test: reclaimed frame lost"));
+    assert!(frame_alloc().is_none());
+    drop(taken);
+    assert!(frame_alloc().is_some());
+    info!("shrink_test passed!");
+}
@@ -0,0 +1,189 @@
+//! Flushing the TLB only when a page table changed
+//!
+//! User page tables run with ASID [`USER_ASID`] and the kernel's with ASID
+//! 0, so switching between the kernel and a user space keeps the entries of
+//! both apart and needs no flush. Every user [`super::PageTable`] has a
+//! [`Generation`] that each change of it moves on, and the trap context of
+//! the task reaches it next to `user_satp`. Each hart records the user
+//! `satp` and its generation as of its last flush. A flush is due:
+//!
+//! - at trap return, see [`user_return_fence`], to another user space than
+//!   the recorded one, which is the only one with entries in the TLB, or to
+//!   the same one if its generation moved on since,
+//! - at trap entry and before switching tasks, see [`sync`], if the kernel
+//!   table, which has a generation of its own, changed on another hart. A
+//!   change on this hart flushes at once.
+//!
+//! A change in one space thus flushes no hart running another one.
+//!
+//! Without ASIDs, as on the K210, user and kernel entries would mix, and
+//! every trap flushes at entry and return as before.
+
+use crate::percpu::this_cpu;
+use crate::sync::InterruptGuard;
+use alloc::boxed::Box;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Where the ASID sits in `satp`
+const SATP_ASID_SHIFT: usize = 44;
+const SATP_ASID_MASK: usize = 0xffff;
+/// ASID of every user page table, the TLB never holds two of them
+const USER_ASID: usize = 1;
+
+/// ASID bits the harts implement, 0 until [`init`]
+static ASID_BITS: AtomicUsize = AtomicUsize::new(0);
+/// Moves on with every change of the kernel page table
+static KERNEL_GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// User page tables made so far, each starts its generation at a count of
+/// its own, see [`Generation::new`]
+static SPACES: AtomicUsize = AtomicUsize::new(0);
+static FLUSHES: AtomicUsize = AtomicUsize::new(0);
+static FLUSHES_SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Moves on with every change of one user page table, which owns it
+pub struct Generation(Box<AtomicUsize>);
+
+impl Generation {
+    /// Start where no other table has been, so that a table with the root
+    /// frame of a freed one is not taken for it
+    pub fn new() -> Self {
+        let space = SPACES.fetch_add(1, Ordering::Relaxed) + 1;
+        Self(Box::new(AtomicUsize::new(space << 32)))
+    }
+    fn changed(&self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+    /// Where the trap context of the space finds it
+    pub fn as_ptr(&self) -> GenerationPtr {
+        GenerationPtr(&*self.0)
+    }
+}
+
+/// The [`Generation`] of a user space as its trap context holds it, so that
+/// trap_return reads it without the lock of the memory set
+#[derive(Clone, Copy)]
+pub struct GenerationPtr(*const AtomicUsize);
+
+/// It points to an atomic, which lives as long as the page table, and the
+/// trap context holding it is replaced along with the page table
+unsafe impl Send for GenerationPtr {}
+unsafe impl Sync for GenerationPtr {}
+
+impl GenerationPtr {
+    fn load(self) -> usize {
+        unsafe { (*self.0).load(Ordering::Acquire) }
+    }
+}
+
+/// What the TLB of a hart was last flushed against
+pub struct TlbState {
+    /// `satp` of the user space returned to after the flush, if any, and
+    /// the generation of that space
+    user_satp: Cell<Option<usize>>,
+    user_generation: Cell<usize>,
+    kernel_generation: Cell<usize>,
+}
+
+impl TlbState {
+    pub const fn new() -> Self {
+        Self {
+            user_satp: Cell::new(None),
+            user_generation: Cell::new(0),
+            // no generation yet, so the first check flushes
+            kernel_generation: Cell::new(usize::MAX),
+        }
+    }
+}
+
+/// Find out how many ASID bits the hart implements, with the kernel space
+/// active. The other harts are taken to have as many
+pub fn init() {
+    // the K210 implements an older privileged spec without ASIDs
+    if cfg!(feature = "board_k210") || cfg!(feature = "no_asid") {
+        return;
+    }
+    let satp = riscv::register::satp::read().bits();
+    let probed = unsafe {
+        core::arch::asm!("csrw satp, {}", in(reg) satp | SATP_ASID_MASK << SATP_ASID_SHIFT);
+        let probed = riscv::register::satp::read().bits();
+        core::arch::asm!("csrw satp, {}", "sfence.vma", in(reg) satp);
+        probed
+    };
+    let bits = (probed >> SATP_ASID_SHIFT & SATP_ASID_MASK).count_ones() as usize;
+    ASID_BITS.store(bits, Ordering::Relaxed);
+    info!("[kernel] {} ASID bits", bits);
+}
+
+pub fn asid_bits() -> usize {
+    ASID_BITS.load(Ordering::Relaxed)
+}
+
+/// The ASID bits of the `satp` of a user page table
+pub fn user_asid_bits() -> usize {
+    if asid_bits() == 0 {
+        0
+    } else {
+        USER_ASID << SATP_ASID_SHIFT
+    }
+}
+
+/// A page table changed, a user space's if it has `generation`. Any other
+/// counts as the kernel's, which every hart has to see
+pub fn page_table_changed(generation: Option<&Generation>) {
+    if let Some(generation) = generation {
+        generation.changed();
+        return;
+    }
+    KERNEL_GENERATION.fetch_add(1, Ordering::Release);
+    // the change may be used before the next trap, e.g. a new kernel stack
+    // written right away
+    unsafe {
+        core::arch::asm!("sfence.vma");
+    }
+}
+
+/// `(flushes, skipped)` of the flushes [`user_return_fence`] and [`sync`]
+/// decide on
+pub fn flush_counts() -> (usize, usize) {
+    (FLUSHES.load(Ordering::Relaxed), FLUSHES_SKIPPED.load(Ordering::Relaxed))
+}
+
+/// Whether the return to the user space of `satp` and `generation` has to
+/// flush the TLB once it switched to it, which the caller must then do
+pub fn user_return_fence(satp: usize, generation: GenerationPtr) -> bool {
+    let guard = InterruptGuard::new();
+    let state = &this_cpu(&guard).tlb;
+    let generation = generation.load();
+    let tagged = satp >> SATP_ASID_SHIFT & SATP_ASID_MASK != 0;
+    if tagged && state.user_satp.get() == Some(satp) && state.user_generation.get() == generation {
+        FLUSHES_SKIPPED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    FLUSHES.fetch_add(1, Ordering::Relaxed);
+    // an untagged space shares ASID 0 with the kernel, whose entries
+    // `__alltraps` flushes on the way back
+    state.user_satp.set(Some(satp).filter(|_| tagged));
+    state.user_generation.set(generation);
+    // the flush takes the kernel entries too
+    let kernel_generation = KERNEL_GENERATION.load(Ordering::Acquire);
+    state.kernel_generation.set(kernel_generation);
+    true
+}
+
+/// Flush the TLB if the kernel table changed since this hart last did,
+/// keeping what it records about the user space
+pub fn sync() {
+    let guard = InterruptGuard::new();
+    let state = &this_cpu(&guard).tlb;
+    let generation = KERNEL_GENERATION.load(Ordering::Acquire);
+    if state.kernel_generation.get() == generation {
+        FLUSHES_SKIPPED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    FLUSHES.fetch_add(1, Ordering::Relaxed);
+    unsafe {
+        core::arch::asm!("sfence.vma");
+    }
+    state.kernel_generation.set(generation);
+}
@@ -0,0 +1,38 @@
+//! The vDSO data page
+//!
+//! One frame of kernel data is mapped read-only at [`VDSO_DATA`] in every
+//! user space, so that the user library can tell the time by reading `time`
+//! itself instead of trapping into [`crate::syscall`].
+//!
+//! [`VDSO_DATA`]: crate::config::VDSO_DATA
+
+use super::{frame_alloc, FrameTracker, PhysPageNum};
+use crate::config::CLOCK_FREQ;
+use crate::timer::boot_ticks;
+use lazy_static::*;
+
+/// Layout of the page, which the user library shares
+#[repr(C)]
+pub struct VdsoData {
+    /// Ticks of `time` per second
+    pub clock_freq: usize,
+    /// `time` at boot, where the time of `sys_get_time` counts from
+    pub boot_ticks: usize,
+}
+
+lazy_static! {
+    static ref VDSO_FRAME: FrameTracker = frame_alloc().unwrap();
+}
+
+/// Fill in the page, once the timer is initialized
+pub fn init() {
+    *VDSO_FRAME.ppn.get_mut::<VdsoData>() = VdsoData {
+        clock_freq: CLOCK_FREQ,
+        boot_ticks: boot_ticks(),
+    };
+}
+
+/// The frame to map at [`crate::config::VDSO_DATA`]
+pub fn vdso_ppn() -> PhysPageNum {
+    VDSO_FRAME.ppn
+}
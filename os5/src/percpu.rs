@@ -0,0 +1,140 @@
+//! Hart-local storage
+//!
+//! Every hart owns one [`PerCpu`] block in a static array indexed by its hart
+//! id, which `entry.asm` puts in `tp` at boot and the trap entry keeps there
+//! while running in the kernel.
+//!
+//! A block may only be touched by its own hart with interrupts disabled, so
+//! [`per_cpu!`] takes an [`InterruptGuard`] and the reference it returns does
+//! not outlive the guard. No lock is needed on top of that.
+//!
+//! What other harts need to see lives in [`HartState`] instead, which only
+//! holds atomics and can be read from anywhere.
+
+use crate::config::MAX_HARTS;
+use crate::mm::tlb::TlbState;
+use crate::sync::InterruptGuard;
+use crate::syscall::SyscallLatency;
+use crate::task::Processor;
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+/// Data private to one hart
+pub struct PerCpu {
+    /// The task running on this hart, its idle task and scheduling loop
+    pub processor: Processor,
+    /// What the TLB was last flushed against
+    pub tlb: TlbState,
+    /// Time spent in the syscalls this hart ran
+    pub syscall_latency: SyscallLatency,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            processor: Processor::new(),
+            tlb: TlbState::new(),
+            syscall_latency: SyscallLatency::new(),
+        }
+    }
+}
+
+/// Only ever accessed by the owning hart with interrupts off
+unsafe impl Sync for PerCpu {}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const PER_CPU_INIT: PerCpu = PerCpu::new();
+static PER_CPU: [PerCpu; MAX_HARTS] = [PER_CPU_INIT; MAX_HARTS];
+
+/// `running_pass` of a hart that has nothing to run
+pub const IDLE_PASS: isize = isize::MAX;
+
+/// Data of one hart shared with the other harts
+pub struct HartState {
+    /// The hart has booted and schedules tasks
+    pub online: AtomicBool,
+    /// The hart should stop at its next pass through the scheduler, see
+    /// [`crate::hotplug`]
+    pub stopping: AtomicBool,
+    /// The current task should give up the hart at the next trap return
+    pub need_resched: AtomicBool,
+    /// Stride pass of the task running on the hart, [`IDLE_PASS`] if none
+    pub running_pass: AtomicIsize,
+    /// The hart runs an IRQ handler, which must not schedule
+    pub in_irq: AtomicBool,
+    /// Time spent in the idle task, in microseconds
+    pub idle_us: AtomicUsize,
+}
+
+impl HartState {
+    const fn new() -> Self {
+        Self {
+            online: AtomicBool::new(false),
+            stopping: AtomicBool::new(false),
+            need_resched: AtomicBool::new(false),
+            running_pass: AtomicIsize::new(IDLE_PASS),
+            in_irq: AtomicBool::new(false),
+            idle_us: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const HART_STATE_INIT: HartState = HartState::new();
+static HART_STATE: [HartState; MAX_HARTS] = [HART_STATE_INIT; MAX_HARTS];
+
+/// The shared state of hart `id`
+pub fn hart_state(id: usize) -> &'static HartState {
+    &HART_STATE[id]
+}
+
+/// The shared state of the current hart
+pub fn this_hart() -> &'static HartState {
+    hart_state(hart_id())
+}
+
+/// Bit `i` set if hart `i` is online
+pub fn online_mask() -> usize {
+    (0..MAX_HARTS)
+        .filter(|&id| HART_STATE[id].online.load(Ordering::Acquire))
+        .fold(0, |mask, id| mask | 1 << id)
+}
+
+/// How many harts are online
+pub fn online_harts() -> usize {
+    online_mask().count_ones() as usize
+}
+
+/// Mark the current hart as able to run tasks
+pub fn init() {
+    this_hart().online.store(true, Ordering::Release);
+}
+
+/// The id of the hart we are running on
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+/// The block of the current hart, valid as long as `_guard` is held
+pub fn this_cpu(_guard: &InterruptGuard) -> &PerCpu {
+    let id = hart_id();
+    assert!(id < MAX_HARTS, "hart {} has no per-cpu block", id);
+    &PER_CPU[id]
+}
+
+/// The syscall latencies of hart `id`, atomics any hart may read
+pub fn syscall_latency(id: usize) -> &'static SyscallLatency {
+    &PER_CPU[id].syscall_latency
+}
+
+/// Access the [`PerCpu`] block of the current hart, e.g.
+/// `per_cpu!(&guard).processor`
+#[macro_export]
+macro_rules! per_cpu {
+    ($guard:expr) => {
+        $crate::percpu::this_cpu($guard)
+    };
+}
@@ -0,0 +1,118 @@
+//! Rebooting, through SBI or in software
+//!
+//! [`reboot`] winds down like [`crate::shutdown::shutdown`] and asks SBI
+//! for a cold reset, or powers off with a warning where SBI cannot reset.
+//!
+//! [`soft_reboot`] resets no hardware. With the other harts down it drops
+//! every task but initproc, which is loaded again in place, and pids, kernel
+//! stacks and frames are handed out as after boot. The heap cannot be set up
+//! again under the data that lives in it, it only starts its peak over.
+//! Whatever is in use afterwards and was not at boot was not released by a
+//! teardown path, which is what a soft reboot is good for finding.
+
+use crate::config::MAX_HARTS;
+use crate::hotplug::{boot_hart, cpu_down, cpu_up};
+use crate::mm::{frame_allocator_compact, reset_heap_peak};
+use crate::percpu::hart_id;
+use crate::shutdown::{usage_since_boot, wind_down};
+use crate::sync::InterruptGuard;
+use crate::task::{current_task, respawn_initproc, suspend_current_and_run_next};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// What `sys_reboot` takes as its first argument, so that a stray call does
+/// not reboot
+pub const REBOOT_MAGIC: usize = 0xfee1_dead;
+/// Reset the machine through SBI
+pub const REBOOT_COLD: usize = 1;
+/// Drop every task and start initproc again, see [`soft_reboot`]
+pub const REBOOT_SOFT: usize = 2;
+
+/// Soft reboots so far
+static SOFT_REBOOTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Reset the machine in order, or power off if SBI cannot reset it
+pub fn reboot() -> ! {
+    let _guard = wind_down(format_args!("Rebooting"), 0);
+    crate::sbi::reboot();
+    println!("[kernel] warning: SBI has no system reset, powering off instead");
+    crate::console::flush();
+    crate::sbi::shutdown(false)
+}
+
+/// Drop every task but initproc and load it again, with initproc as the
+/// current task
+///
+/// It first moves to the boot hart, which cannot go down, and takes the
+/// other harts down until it is done.
+pub fn soft_reboot() {
+    let task = current_task().unwrap();
+    let boot = boot_hart();
+    if hart_id() != boot {
+        task.inner_exclusive_access().cpu_mask = 1 << boot;
+        while hart_id() != boot {
+            suspend_current_and_run_next();
+        }
+    }
+    drop(task);
+    let down = (0..MAX_HARTS)
+        .filter(|&hart| hart != boot && cpu_down(hart))
+        .fold(0, |mask, hart| mask | 1 << hart);
+    let dropped = {
+        let _guard = InterruptGuard::new();
+        reset_tasks_and_memory()
+    };
+    let ((frames, frames_delta), (heap, heap_delta)) = usage_since_boot();
+    println!(
+        "[kernel] soft reboot {}: {} tasks dropped, frames in use {} ({:+} since boot), heap in use {} bytes ({:+} since boot)",
+        SOFT_REBOOTS.fetch_add(1, Ordering::Relaxed) + 1,
+        dropped,
+        frames,
+        frames_delta,
+        heap,
+        heap_delta
+    );
+    for hart in (0..MAX_HARTS).filter(|&hart| down & 1 << hart != 0) {
+        if !cpu_up(hart) {
+            println!("[kernel] hart {} did not come back after the soft reboot", hart);
+        }
+    }
+}
+
+/// The part of [`soft_reboot`] that needs no current task, return how many
+/// tasks but initproc were dropped
+fn reset_tasks_and_memory() -> usize {
+    let dropped = respawn_initproc();
+    frame_allocator_compact();
+    reset_heap_peak();
+    dropped
+}
+
+/// Soft-reboot twice with a few forked tasks to drop each time, and check
+/// that the frames and heap in use come out the same both times
+///
+/// The first time may leave more in use than boot did, e.g. page tables of
+/// kernel stacks, a queue that grew or a `lazy_static` set up by then, but
+/// it must not grow from there.
+#[cfg(feature = "kernel_test")]
+pub fn soft_reboot_test() {
+    use crate::mm::{heap_usage, try_frame_usage};
+    use crate::task::{add_task, register_task, INITPROC};
+    const TASKS: usize = 4;
+    let mut usage = [(0, 0); 2];
+    for (cycle, usage) in usage.iter_mut().enumerate() {
+        for _ in 0..TASKS {
+            let child = INITPROC.fork().expect("soft_reboot_test: fork failed");
+            register_task(child.clone());
+            add_task(child);
+        }
+        let dropped = reset_tasks_and_memory();
+        assert_eq!(dropped, TASKS, "soft_reboot_test: tasks dropped in cycle {}", cycle);
+        *usage = (try_frame_usage().unwrap().0, heap_usage().0);
+        info!(
+            "soft_reboot_test: cycle {}: {} frames, {} heap bytes in use",
+            cycle, usage.0, usage.1
+        );
+    }
+    assert_eq!(usage[0], usage[1], "soft_reboot_test: memory in use grew between cycles");
+    info!("soft_reboot_test passed!");
+}
@@ -6,22 +6,40 @@ const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 const SBI_SHUTDOWN: usize = 8;
+const SBI_EXT_IPI: usize = 0x735049;
+const SBI_EXT_SRST: usize = 0x53525354;
+const SBI_EXT_HSM: usize = 0x48534D;
+const HSM_HART_START: usize = 0;
+const HSM_HART_STOP: usize = 1;
+const HSM_HART_GET_STATUS: usize = 2;
+/// What [`hart_status`] returns for a hart that can be started
+pub const HSM_STOPPED: usize = 1;
+const SRST_TYPE_SHUTDOWN: usize = 0;
+const SRST_TYPE_COLD_REBOOT: usize = 1;
+const SRST_REASON_NONE: usize = 0;
+const SRST_REASON_FAILURE: usize = 1;
 
 #[inline(always)]
 /// general sbi call
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
-    let mut ret;
+    sbi_call_fid(which, 0, arg0, arg1, arg2).0
+}
+
+#[inline(always)]
+/// sbi call of function `fid` of extension `eid`, returning `(error, value)`
+fn sbi_call_fid(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> (usize, usize) {
+    let (error, value);
     unsafe {
         core::arch::asm!(
-            "li x16, 0",
             "ecall",
-            inlateout("x10") arg0 => ret,
-            in("x11") arg1,
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => value,
             in("x12") arg2,
-            in("x17") which,
+            in("x16") fid,
+            in("x17") eid,
         );
     }
-    ret
+    (error, value)
 }
 
 /// use sbi call to set timer
@@ -39,8 +57,46 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
-/// use sbi call to shutdown the kernel
-pub fn shutdown() -> ! {
+/// use sbi IPI extension to raise a supervisor software interrupt on every
+/// hart in `hart_mask`
+pub fn send_ipi(hart_mask: usize) {
+    sbi_call(SBI_EXT_IPI, hart_mask, 0, 0);
+}
+
+/// use sbi HSM extension to start `hart` at physical address `start` with
+/// `opaque` in a1, true if it is starting
+pub fn hart_start(hart: usize, start: usize, opaque: usize) -> bool {
+    sbi_call_fid(SBI_EXT_HSM, HSM_HART_START, hart, start, opaque).0 == 0
+}
+
+/// use sbi HSM extension to stop the current hart, until another one starts
+/// it again
+pub fn hart_stop() -> ! {
+    sbi_call_fid(SBI_EXT_HSM, HSM_HART_STOP, 0, 0, 0);
+    panic!("hart_stop returned!");
+}
+
+/// use sbi HSM extension to get the state of `hart`, `None` if there is no
+/// such hart
+pub fn hart_status(hart: usize) -> Option<usize> {
+    match sbi_call_fid(SBI_EXT_HSM, HSM_HART_GET_STATUS, hart, 0, 0) {
+        (0, status) => Some(status),
+        _ => None,
+    }
+}
+
+/// use sbi SRST extension to reset the machine, returns only if there is
+/// no such extension
+pub fn reboot() {
+    sbi_call(SBI_EXT_SRST, SRST_TYPE_COLD_REBOOT, SRST_REASON_NONE, 0);
+}
+
+/// use sbi call to shutdown the kernel, telling the platform whether it
+/// was because of a failure
+pub fn shutdown(failure: bool) -> ! {
+    let reason = if failure { SRST_REASON_FAILURE } else { SRST_REASON_NONE };
+    sbi_call(SBI_EXT_SRST, SRST_TYPE_SHUTDOWN, reason, 0);
+    // no SRST extension, the legacy call cannot tell a failure
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
     panic!("It should shutdown!");
 }
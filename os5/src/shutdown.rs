@@ -0,0 +1,251 @@
+//! Orderly shutdown
+//!
+//! [`shutdown()`] parks the other harts at their next pass through the
+//! scheduler, drops the tasks, drains the console and prints a summary of
+//! the run before asking SBI to power off. [`crate::reboot::reboot`] does
+//! the same before asking SBI to reset. The summary is only formatted
+//! into the console buffer, nothing on the way allocates, so it can report
+//! on the heap whatever state that is in.
+
+use crate::config::MAX_HARTS;
+use crate::console::flush;
+use crate::logging::try_suppressed;
+use crate::mm::{heap_usage, try_frame_usage};
+use crate::percpu::{hart_id, hart_state, this_hart};
+use crate::sbi::send_ipi;
+use crate::sync::InterruptGuard;
+use crate::syscall::{syscall_counts, syscall_name};
+use crate::task::{context_switches, current_task, reap_all_tasks};
+use crate::timer::get_time_ms;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+/// Syscalls called fewer times than this are left out of the summary
+const SYSCALL_REPORT_THRESHOLD: usize = 100;
+/// How long the other harts get to park
+const PARK_TIMEOUT_MS: usize = 100;
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+/// Set by the first panic, a panic in the summary does not print it again
+static PANICKED: AtomicBool = AtomicBool::new(false);
+/// Frames and heap bytes in use once initproc is loaded
+static BASELINE_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static BASELINE_HEAP: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a shutdown has started, no new task is created then
+pub fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Acquire)
+}
+
+/// Take what is in use now as the baseline the summary compares against
+pub fn record_baseline() {
+    let (frames, _) = try_frame_usage().unwrap();
+    BASELINE_FRAMES.store(frames, Ordering::Relaxed);
+    BASELINE_HEAP.store(heap_usage().0, Ordering::Relaxed);
+}
+
+/// Stop the current hart for good if a shutdown has started, called by the
+/// scheduler loop with no task on the hart
+pub fn park_if_shutting_down() {
+    if shutting_down() {
+        park();
+    }
+}
+
+fn park() -> ! {
+    unsafe {
+        sstatus::clear_sie();
+    }
+    this_hart().online.store(false, Ordering::Release);
+    loop {
+        // with interrupts off this wakes up without trapping
+        unsafe { riscv::asm::wfi() };
+    }
+}
+
+/// Make the other harts reschedule so they park, and return the mask of
+/// those still online after [`PARK_TIMEOUT_MS`]
+fn park_other_harts() -> usize {
+    let me = hart_id();
+    let online = || {
+        (0..MAX_HARTS)
+            .filter(|&id| id != me && hart_state(id).online.load(Ordering::Acquire))
+            .fold(0, |mask, id| mask | 1 << id)
+    };
+    for id in 0..MAX_HARTS {
+        if online() & 1 << id != 0 {
+            hart_state(id).need_resched.store(true, Ordering::Release);
+            send_ipi(1 << id);
+        }
+    }
+    let deadline = get_time_ms() + PARK_TIMEOUT_MS;
+    while online() != 0 && get_time_ms() < deadline {
+        core::hint::spin_loop();
+    }
+    online()
+}
+
+/// Difference of `now` from `baseline`, as a signed number
+fn delta(now: usize, baseline: &AtomicUsize) -> isize {
+    now as isize - baseline.load(Ordering::Relaxed) as isize
+}
+
+/// Frames and heap bytes in use now, each as a difference from the
+/// baseline too
+pub fn usage_since_boot() -> ((usize, isize), (usize, isize)) {
+    let (frames, _) = try_frame_usage().unwrap_or((0, 0));
+    let (heap, _) = heap_usage();
+    (
+        (frames, delta(frames, &BASELINE_FRAMES)),
+        (heap, delta(heap, &BASELINE_HEAP)),
+    )
+}
+
+/// Shut the machine down in order, a nonzero `exit_code` is reported to the
+/// platform as a failure
+///
+/// A second hart getting here while a shutdown runs parks instead.
+pub fn shutdown(exit_code: i32) -> ! {
+    let _guard = wind_down(format_args!("Shutting down, exit code {}", exit_code), exit_code);
+    crate::sbi::shutdown(exit_code != 0)
+}
+
+/// What [`shutdown`] does before SBI: park the other harts, drop the tasks
+/// and print the summary under `banner`. Interrupts stay off until the
+/// guard is dropped
+#[cfg_attr(not(feature = "trace_uart"), allow(unused_variables))]
+pub fn wind_down(banner: fmt::Arguments, exit_code: i32) -> InterruptGuard {
+    if SHUTTING_DOWN.swap(true, Ordering::AcqRel) {
+        park();
+    }
+    let guard = InterruptGuard::new();
+    let stuck = park_other_harts();
+    let reaped = reap_all_tasks();
+    flush();
+    println!("[kernel] {}", banner);
+    if stuck != 0 {
+        println!("[kernel] harts {:#x} did not park", stuck);
+    }
+    println!("[kernel] tasks reaped: {}", reaped);
+    println!("[kernel] context switches: {}", context_switches());
+    if let Some((frames, peak)) = try_frame_usage() {
+        println!(
+            "[kernel] frames: peak {}, in use {} ({:+} since boot)",
+            peak,
+            frames,
+            delta(frames, &BASELINE_FRAMES)
+        );
+    }
+    let (heap, peak) = heap_usage();
+    println!(
+        "[kernel] heap: peak {} bytes, in use {} bytes ({:+} since boot)",
+        peak,
+        heap,
+        delta(heap, &BASELINE_HEAP)
+    );
+    for count in syscall_counts() {
+        if count.calls < SYSCALL_REPORT_THRESHOLD {
+            continue;
+        }
+        if count.err == 0 {
+            print!(
+                "[kernel] syscall {} ({}): {} calls, {} ok",
+                count.name, count.id, count.calls, count.ok
+            );
+        } else {
+            print!(
+                "[kernel] syscall {} ({}): {} calls, {} ok, {} failed, last errno {}",
+                count.name, count.id, count.calls, count.ok, count.err, count.last_errno
+            );
+        }
+        if count.max_us == 0 {
+            println!(", {} us", count.time_us);
+        } else {
+            println!(", {} us, longest {} us", count.time_us, count.max_us);
+        }
+    }
+    if let Some((messages, sites)) = try_suppressed() {
+        if messages > 0 {
+            println!("[kernel] log messages suppressed: {} from {} sites", messages, sites);
+        }
+    }
+    #[cfg(feature = "trace_uart")]
+    trace_summary(exit_code, reaped);
+    flush();
+    guard
+}
+
+/// The summary again as frames on the trace UART, the syscalls first so
+/// that the frames dropped meanwhile are counted
+#[cfg(feature = "trace_uart")]
+fn trace_summary(exit_code: i32, reaped: usize) {
+    use crate::trace::{self, Stream};
+    for count in syscall_counts() {
+        if count.calls > 0 {
+            let words = [count.id, count.calls, count.ok, count.err, count.last_errno];
+            trace::emit(Stream::Syscall, &words.map(|word| word as u64));
+        }
+    }
+    let (frames, frames_peak) = try_frame_usage().unwrap_or((0, 0));
+    let (heap, heap_peak) = heap_usage();
+    let words = [
+        exit_code as isize as usize,
+        reaped,
+        context_switches(),
+        frames_peak,
+        frames,
+        heap_peak,
+        heap,
+        trace::dropped(),
+    ];
+    trace::emit(Stream::Summary, &words.map(|word| word as u64));
+    trace::flush();
+}
+
+/// What the task on the panicking hart was doing, if there is one
+fn current_task_summary() {
+    let task = match current_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let inner = match task.try_inner_exclusive_access() {
+        Some(inner) => inner,
+        None => {
+            println!("[kernel] current task: pid {}, busy", task.getpid());
+            return;
+        }
+    };
+    print!("[kernel] current task: pid {} ({})", task.getpid(), inner.name);
+    if let Some((id, ms)) = inner.last_syscall {
+        print!(", last syscall {} ({}) at {} ms", syscall_name(id), id, ms);
+    }
+    if let Some(reason) = inner.block_reason {
+        print!(", waiting for {}", reason);
+    }
+    println!("");
+}
+
+/// The part of the summary that is safe to print from the panic handler,
+/// skipping what is held by the code that panicked
+pub fn panic_summary() {
+    if PANICKED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    // the other harts park when they can, without waiting for them
+    SHUTTING_DOWN.store(true, Ordering::Release);
+    current_task_summary();
+    println!("[kernel] context switches: {}", context_switches());
+    if let Some((frames, peak)) = try_frame_usage() {
+        println!("[kernel] frames: peak {}, in use {}", peak, frames);
+    } else {
+        println!("[kernel] frames: allocator busy");
+    }
+    let (heap, peak) = heap_usage();
+    println!("[kernel] heap: peak {} bytes, in use {} bytes", peak, heap);
+    if let Some((messages, _)) = try_suppressed() {
+        if messages > 0 {
+            println!("[kernel] log messages suppressed: {}", messages);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Tracking of the guards a hart holds, in debug builds only
+//!
+//! Every [`super::UPSafeCell`] borrow and [`super::InterruptGuard`] counts
+//! as held by its hart until it is dropped. Switching tasks with one held
+//! leaves a cell borrowed, or interrupts off, for whatever runs next, which
+//! then fails far away from the cause. [`assert_none_held`] in
+//! [`crate::task::schedule`] catches it where it happens, with the place the
+//! outermost guard was taken.
+
+use crate::config::MAX_HARTS;
+use crate::percpu::hart_id;
+use core::panic::Location;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const HELD_INIT: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const SITE_INIT: AtomicPtr<Location<'static>> = AtomicPtr::new(null_mut());
+/// Guards each hart holds
+static HELD: [AtomicUsize; MAX_HARTS] = [HELD_INIT; MAX_HARTS];
+/// Where the outermost of them was taken
+static OUTERMOST: [AtomicPtr<Location<'static>>; MAX_HARTS] = [SITE_INIT; MAX_HARTS];
+
+/// One guard, counted as held until dropped
+pub struct Held {
+    hart: usize,
+}
+
+impl Held {
+    /// Count a guard, taken where the caller was called from
+    #[track_caller]
+    pub fn new() -> Self {
+        let hart = hart_id();
+        if HELD[hart].fetch_add(1, Ordering::Relaxed) == 0 {
+            let site: &'static Location<'static> = Location::caller();
+            OUTERMOST[hart].store(site as *const _ as *mut _, Ordering::Relaxed);
+        }
+        Self { hart }
+    }
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        HELD[self.hart].fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Panic if the current hart holds any guard
+pub fn assert_none_held() {
+    let hart = hart_id();
+    let held = HELD[hart].load(Ordering::Relaxed);
+    if held != 0 {
+        // only ever set to a `&'static Location`
+        let site = unsafe { &*OUTERMOST[hart].load(Ordering::Relaxed) };
+        panic!("scheduling with {} guard(s) held, the outermost taken at {}", held, site);
+    }
+}
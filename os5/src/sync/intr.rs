@@ -0,0 +1,46 @@
+//! Interrupt disabling guard
+
+#[cfg(debug_assertions)]
+use super::held::Held;
+use core::marker::PhantomData;
+use riscv::register::sstatus;
+
+/// Supervisor interrupts stay disabled on this hart while the guard lives.
+///
+/// Holding one is the token that allows access to hart-local data, see
+/// [`crate::percpu`]. The guard is neither `Send` nor `Sync`, so it cannot
+/// leave the hart it was created on.
+pub struct InterruptGuard {
+    /// Whether interrupts were enabled before the guard was created
+    was_enabled: bool,
+    #[cfg(debug_assertions)]
+    _held: Held,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl InterruptGuard {
+    /// Disable interrupts, they are restored when the guard is dropped
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn new() -> Self {
+        let was_enabled = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        Self {
+            was_enabled,
+            #[cfg(debug_assertions)]
+            _held: Held::new(),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.was_enabled {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
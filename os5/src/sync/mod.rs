@@ -1,5 +1,21 @@
 //! Synchronization and interior mutability primitives
 
+#[cfg(debug_assertions)]
+mod held;
+mod intr;
+mod mutex;
+mod semaphore;
 mod up;
 
-pub use up::UPSafeCell;
+pub use intr::InterruptGuard;
+pub use mutex::{
+    mutex_cancel_wait, mutex_close, mutex_create, mutex_dup, mutex_lock, mutex_unlock,
+    release_mutexes, LockError,
+};
+pub use semaphore::{
+    semaphore_cancel_wait, semaphore_close, semaphore_create, semaphore_down, semaphore_dup,
+    semaphore_open, semaphore_unlink, semaphore_up, SemError, SEM_NAME_MAX,
+};
+pub use up::{UPRefMut, UPSafeCell};
+#[cfg(debug_assertions)]
+pub use held::assert_none_held;
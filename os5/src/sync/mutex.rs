@@ -0,0 +1,245 @@
+//! Blocking mutexes for user space, with priority inheritance
+//!
+//! Mutexes live in one global table of at most [`MUTEX_MAX`] like the
+//! semaphores, a task reaches them through handles in its own mutex table
+//! and shares them with the children it forks. A mutex goes away once no
+//! handle refers to it and nobody holds it. A task blocked on a mutex lends its
+//! effective priority to the holder, and on to whatever the holder is blocked
+//! on in turn, so that a low priority holder is not starved by tasks of middle
+//! priority while a high priority task waits for it.
+//!
+//! An adaptive mutex spins for a while before blocking the locker, as long
+//! as the holder runs on another hart and may let go soon. Unlocking hands
+//! the mutex straight to the waiter of the highest effective priority, the
+//! one that has waited longest of equal ones, so a spinner never takes it
+//! from under a blocked waiter.
+
+use crate::config::{MUTEX_MAX, MUTEX_SPIN_LIMIT, MUTEX_SPIN_MIN_HARTS};
+use crate::percpu::online_harts;
+use crate::sync::UPSafeCell;
+use crate::task::{
+    block_current_and_run_next, take_interrupted, wakeup_task, BlockReason, TaskControlBlock,
+    TaskStatus, Wait,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+struct Mutex {
+    owner: Option<Arc<TaskControlBlock>>,
+    /// Oldest first
+    waiters: Vec<Arc<TaskControlBlock>>,
+    /// Spin before blocking, see [`MUTEX_SPIN_LIMIT`]
+    adaptive: bool,
+    /// Handles referring to it, over all tasks
+    holders: usize,
+}
+
+struct Mutexes {
+    /// By global id, `None` for a free slot
+    table: Vec<Option<Mutex>>,
+}
+
+lazy_static! {
+    static ref MUTEXES: UPSafeCell<Mutexes> =
+        unsafe { UPSafeCell::new(Mutexes { table: Vec::new() }) };
+}
+
+impl Mutexes {
+    fn get(&mut self, id: usize) -> Option<&mut Mutex> {
+        self.table.get_mut(id)?.as_mut()
+    }
+    /// Mutex `id`, which some task holds or waits for
+    fn mutex(&self, id: usize) -> &Mutex {
+        self.table[id].as_ref().unwrap()
+    }
+    /// Free mutex `id` if nothing refers to it any more
+    fn check_unused(&mut self, id: usize) {
+        let mutex = self.mutex(id);
+        if mutex.holders == 0 && mutex.owner.is_none() {
+            self.table[id] = None;
+        }
+    }
+}
+
+/// Create an unlocked mutex held once and return its id, an `adaptive` one
+/// spins for a while before blocking. `None` if there are [`MUTEX_MAX`]
+/// already
+pub fn mutex_create(adaptive: bool) -> Option<usize> {
+    let mut mutexes = MUTEXES.exclusive_access();
+    let mutex = Mutex {
+        owner: None,
+        waiters: Vec::new(),
+        adaptive,
+        holders: 1,
+    };
+    match mutexes.table.iter().position(|slot| slot.is_none()) {
+        Some(id) => {
+            mutexes.table[id] = Some(mutex);
+            Some(id)
+        }
+        None if mutexes.table.len() < MUTEX_MAX => {
+            mutexes.table.push(Some(mutex));
+            Some(mutexes.table.len() - 1)
+        }
+        None => None,
+    }
+}
+
+/// Count one more handle to each mutex of `ids`, as a fork copies them
+pub fn mutex_dup(ids: &[Option<usize>]) {
+    let mut mutexes = MUTEXES.exclusive_access();
+    for &id in ids.iter().flatten() {
+        mutexes.get(id).unwrap().holders += 1;
+    }
+}
+
+/// Drop one handle to mutex `id`, one still locked goes away as it is
+/// unlocked
+pub fn mutex_close(id: usize) {
+    let mut mutexes = MUTEXES.exclusive_access();
+    mutexes.get(id).unwrap().holders -= 1;
+    mutexes.check_unused(id);
+}
+
+/// Why [`mutex_lock`] failed
+pub enum LockError {
+    /// There is no such mutex
+    Invalid,
+    /// The task already holds it
+    Deadlock,
+    /// A signal woke the task up before the mutex was handed over
+    Interrupted,
+}
+
+/// Lock mutex `id` for `task`, which must be the current task, blocking
+/// until it is handed over or a signal interrupts the wait
+pub fn mutex_lock(id: usize, task: &Arc<TaskControlBlock>) -> Result<(), LockError> {
+    let mut spins = if online_harts() >= MUTEX_SPIN_MIN_HARTS { MUTEX_SPIN_LIMIT } else { 0 };
+    let mut mutexes = loop {
+        let mut mutexes = MUTEXES.exclusive_access();
+        let mutex = match mutexes.get(id) {
+            Some(mutex) => mutex,
+            None => return Err(LockError::Invalid),
+        };
+        let spin = match &mutex.owner {
+            None => {
+                mutex.owner = Some(task.clone());
+                task.inner_exclusive_access().held_mutexes.push(id);
+                return Ok(());
+            }
+            Some(owner) if Arc::ptr_eq(owner, task) => return Err(LockError::Deadlock),
+            // the current task is not the owner, so a running owner is on
+            // another hart
+            Some(owner) => {
+                mutex.adaptive
+                    && spins > 0
+                    && owner.inner_exclusive_access().task_status == TaskStatus::Running
+            }
+        };
+        if !spin {
+            break mutexes;
+        }
+        drop(mutexes);
+        spins -= 1;
+        core::hint::spin_loop();
+    };
+    let mutex = mutexes.get(id).unwrap();
+    mutex.waiters.push(task.clone());
+    let mut inner = task.inner_exclusive_access();
+    inner.blocked_on = Some(id);
+    inner.wait = Some(Wait::Mutex(id));
+    inner.block_reason = Some(BlockReason::Mutex(id));
+    let prio = inner.effective_prio();
+    drop(inner);
+    boost(&mutexes, id, prio);
+    drop(mutexes);
+    block_current_and_run_next();
+    if take_interrupted() {
+        return Err(LockError::Interrupted);
+    }
+    // mutex_unlock made us the owner before waking us up
+    Ok(())
+}
+
+/// Take `task` off the waiters of mutex `id`, as a signal interrupts its
+/// wait, and give back the priority it lent to the holder
+pub fn mutex_cancel_wait(id: usize, task: &Arc<TaskControlBlock>) {
+    let mut mutexes = MUTEXES.exclusive_access();
+    let mutex = mutexes.get(id).unwrap();
+    mutex.waiters.retain(|waiter| !Arc::ptr_eq(waiter, task));
+    task.inner_exclusive_access().blocked_on = None;
+    if let Some(owner) = mutex.owner.clone() {
+        update_inherited_prio(&mutexes, &owner);
+    }
+}
+
+/// Unlock mutex `id` held by `task`, handing it to the waiter of the highest
+/// effective priority, of those the one that has waited longest. False if
+/// there is no such mutex or `task` does not hold it
+pub fn mutex_unlock(id: usize, task: &Arc<TaskControlBlock>) -> bool {
+    let mut mutexes = MUTEXES.exclusive_access();
+    let mutex = match mutexes.get(id) {
+        Some(mutex) if mutex.owner.as_ref().map_or(false, |o| Arc::ptr_eq(o, task)) => mutex,
+        _ => return false,
+    };
+    // max_by_key takes the last of equal ones, the oldest counting backwards
+    mutex.owner = (0..mutex.waiters.len())
+        .rev()
+        .max_by_key(|&i| mutex.waiters[i].inner_exclusive_access().effective_prio())
+        .map(|i| mutex.waiters.remove(i));
+    let next = mutex.owner.clone();
+    task.inner_exclusive_access().held_mutexes.retain(|&held| held != id);
+    update_inherited_prio(&mutexes, task);
+    if let Some(next) = next {
+        let mut inner = next.inner_exclusive_access();
+        inner.blocked_on = None;
+        inner.held_mutexes.push(id);
+        drop(inner);
+        update_inherited_prio(&mutexes, &next);
+        drop(mutexes);
+        wakeup_task(next);
+    } else {
+        mutexes.check_unused(id);
+    }
+    true
+}
+
+/// Unlock every mutex `task` still holds, as it exits
+pub fn release_mutexes(task: &Arc<TaskControlBlock>) {
+    loop {
+        let held = task.inner_exclusive_access().held_mutexes.last().copied();
+        match held {
+            Some(id) => mutex_unlock(id, task),
+            None => break,
+        };
+    }
+}
+
+/// Raise the holder of mutex `id` to `prio`, following the chain of
+/// mutexes the holders are blocked on
+fn boost(mutexes: &Mutexes, mut id: usize, prio: isize) {
+    while let Some(owner) = &mutexes.mutex(id).owner {
+        let mut inner = owner.inner_exclusive_access();
+        if inner.effective_prio() >= prio {
+            break;
+        }
+        inner.inherited_prio = prio;
+        match inner.blocked_on {
+            Some(next) => id = next,
+            None => break,
+        }
+    }
+}
+
+/// Recompute what `task` inherits from the waiters of the mutexes it holds
+fn update_inherited_prio(mutexes: &Mutexes, task: &Arc<TaskControlBlock>) {
+    let mut inner = task.inner_exclusive_access();
+    inner.inherited_prio = inner
+        .held_mutexes
+        .iter()
+        .flat_map(|&id| mutexes.mutex(id).waiters.iter())
+        .map(|waiter| waiter.inner_exclusive_access().effective_prio())
+        .max()
+        .unwrap_or(0);
+}
@@ -0,0 +1,231 @@
+//! Counting semaphores, anonymous or found by name
+//!
+//! Semaphores live in one global table like the mutexes, a task reaches them
+//! through handles in its own semaphore table. An anonymous one is shared by
+//! forking, a named one by opening the same name from any process. A
+//! semaphore goes away once no handle refers to it and it has no name.
+//!
+//! When a task stops holding a semaphore and every task still holding it is
+//! blocked on it, e.g. as the last poster of a pair of ping-pong tasks is
+//! killed, nobody is left to raise it: it is marked dead, its name dropped
+//! and the waiters woken with an error. Without a name nobody can open it
+//! either, and a task that would block on it as the last holder not
+//! waiting fails at once.
+
+use crate::sync::UPSafeCell;
+use crate::task::{
+    block_current_and_run_next, take_interrupted, wakeup_task, BlockReason, TaskControlBlock, Wait,
+};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Longest name of a named semaphore, in bytes
+pub const SEM_NAME_MAX: usize = 32;
+
+struct Semaphore {
+    count: usize,
+    /// Oldest first
+    waiters: VecDeque<Arc<TaskControlBlock>>,
+    /// Handles referring to it, over all tasks
+    holders: usize,
+    name: Option<String>,
+    /// Nobody is left to raise it
+    dead: bool,
+    /// Waiters [`Semaphores::kill`] woke, which fail unlike those
+    /// [`semaphore_up`] handed it to before it died
+    orphaned: Vec<Arc<TaskControlBlock>>,
+}
+
+struct Semaphores {
+    /// By global id, `None` for a free slot
+    table: Vec<Option<Semaphore>>,
+    names: BTreeMap<String, usize>,
+}
+
+lazy_static! {
+    static ref SEMAPHORES: UPSafeCell<Semaphores> = unsafe {
+        UPSafeCell::new(Semaphores {
+            table: Vec::new(),
+            names: BTreeMap::new(),
+        })
+    };
+}
+
+/// Why a semaphore operation failed
+pub enum SemError {
+    /// There is no such semaphore
+    Invalid,
+    /// A signal woke the task up before it got the semaphore
+    Interrupted,
+    /// Every holder is blocked on it, see the module documentation
+    Dead,
+}
+
+impl Semaphores {
+    fn get(&mut self, id: usize) -> Result<&mut Semaphore, SemError> {
+        self.table
+            .get_mut(id)
+            .and_then(|slot| slot.as_mut())
+            .ok_or(SemError::Invalid)
+    }
+    /// Put a semaphore with one holder in a free slot and return its id
+    fn insert(&mut self, count: usize, name: Option<String>) -> usize {
+        let sem = Semaphore {
+            count,
+            waiters: VecDeque::new(),
+            holders: 1,
+            name,
+            dead: false,
+            orphaned: Vec::new(),
+        };
+        match self.table.iter().position(|slot| slot.is_none()) {
+            Some(id) => {
+                self.table[id] = Some(sem);
+                id
+            }
+            None => {
+                self.table.push(Some(sem));
+                self.table.len() - 1
+            }
+        }
+    }
+    /// Free semaphore `id` if nothing refers to it, or wake its waiters with
+    /// an error if they are all that holds it
+    fn check_orphaned(&mut self, id: usize) {
+        let sem = self.table[id].as_mut().unwrap();
+        if sem.holders == 0 && sem.name.is_none() {
+            self.table[id] = None;
+        } else if !sem.waiters.is_empty() && sem.holders <= sem.waiters.len() {
+            self.kill(id);
+        }
+    }
+    /// Mark semaphore `id` as dead and wake its waiters
+    fn kill(&mut self, id: usize) {
+        let sem = self.table[id].as_mut().unwrap();
+        sem.dead = true;
+        if let Some(name) = sem.name.take() {
+            self.names.remove(&name);
+        }
+        while let Some(waiter) = sem.waiters.pop_front() {
+            sem.orphaned.push(waiter.clone());
+            wakeup_task(waiter);
+        }
+    }
+}
+
+/// Create an anonymous semaphore with `count` and return its id, held once
+pub fn semaphore_create(count: usize) -> usize {
+    SEMAPHORES.exclusive_access().insert(count, None)
+}
+
+/// The id of the semaphore `name`, created with `count` if there is none,
+/// held once more
+pub fn semaphore_open(name: &str, count: usize) -> usize {
+    let mut sems = SEMAPHORES.exclusive_access();
+    if let Some(&id) = sems.names.get(name) {
+        sems.table[id].as_mut().unwrap().holders += 1;
+        return id;
+    }
+    let id = sems.insert(count, Some(String::from(name)));
+    sems.names.insert(String::from(name), id);
+    id
+}
+
+/// Drop the name `name`, the semaphore stays as long as it is held. False
+/// if there is no such name
+pub fn semaphore_unlink(name: &str) -> bool {
+    let mut sems = SEMAPHORES.exclusive_access();
+    let id = match sems.names.remove(name) {
+        Some(id) => id,
+        None => return false,
+    };
+    sems.table[id].as_mut().unwrap().name = None;
+    sems.check_orphaned(id);
+    true
+}
+
+/// Count one more handle to each semaphore of `ids`, as a fork copies them
+pub fn semaphore_dup(ids: &[Option<usize>]) {
+    let mut sems = SEMAPHORES.exclusive_access();
+    for &id in ids.iter().flatten() {
+        sems.table[id].as_mut().unwrap().holders += 1;
+    }
+}
+
+/// Drop one handle to semaphore `id`
+pub fn semaphore_close(id: usize) {
+    let mut sems = SEMAPHORES.exclusive_access();
+    sems.table[id].as_mut().unwrap().holders -= 1;
+    sems.check_orphaned(id);
+}
+
+/// Raise semaphore `id`, handing it straight to the task that has waited
+/// longest if there is one
+pub fn semaphore_up(id: usize) -> Result<(), SemError> {
+    let mut sems = SEMAPHORES.exclusive_access();
+    let sem = sems.get(id)?;
+    if sem.dead {
+        return Err(SemError::Dead);
+    }
+    match sem.waiters.pop_front() {
+        Some(waiter) => {
+            drop(sems);
+            wakeup_task(waiter);
+        }
+        None => sem.count += 1,
+    }
+    Ok(())
+}
+
+/// Take semaphore `id` for `task`, which must be the current task, blocking
+/// until it is handed over, a signal interrupts the wait or nobody is left
+/// to raise it
+pub fn semaphore_down(id: usize, task: &Arc<TaskControlBlock>) -> Result<(), SemError> {
+    let mut sems = SEMAPHORES.exclusive_access();
+    let sem = sems.get(id)?;
+    if sem.dead {
+        return Err(SemError::Dead);
+    }
+    if sem.count > 0 {
+        sem.count -= 1;
+        return Ok(());
+    }
+    if sem.name.is_none() && sem.holders <= sem.waiters.len() + 1 {
+        // the last holder not waiting, nobody could raise it
+        sems.kill(id);
+        return Err(SemError::Dead);
+    }
+    sem.waiters.push_back(task.clone());
+    let mut inner = task.inner_exclusive_access();
+    inner.wait = Some(Wait::Semaphore(id));
+    inner.block_reason = Some(BlockReason::Semaphore(id));
+    drop(inner);
+    drop(sems);
+    block_current_and_run_next();
+    if take_interrupted() {
+        return Err(SemError::Interrupted);
+    }
+    // semaphore_up handed it over, even if it died since, unless its death
+    // woke us
+    let mut sems = SEMAPHORES.exclusive_access();
+    let orphaned = &mut sems.get(id)?.orphaned;
+    match orphaned.iter().position(|waiter| Arc::ptr_eq(waiter, task)) {
+        Some(i) => {
+            orphaned.swap_remove(i);
+            Err(SemError::Dead)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Take `task` off the waiters of semaphore `id`, as a signal interrupts
+/// its wait
+pub fn semaphore_cancel_wait(id: usize, task: &Arc<TaskControlBlock>) {
+    let mut sems = SEMAPHORES.exclusive_access();
+    if let Ok(sem) = sems.get(id) {
+        sem.waiters.retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+}
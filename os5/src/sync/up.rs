@@ -1,31 +1,98 @@
-//! Uniprocessor interior mutability primitives
+//! Interior mutability primitives
+//!
+//! [`UPSafeCell`] is named for the uniprocessor kernel it started in. Since
+//! [`crate::hotplug`] brings up more harts it is a spinlock, which still
+//! panics like the `RefCell` it was rather than deadlock when the hart that
+//! holds it takes it again.
 
-use core::cell::{RefCell, RefMut};
+use crate::percpu::hart_id;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, MutexGuard};
+
+#[cfg(debug_assertions)]
+use super::held::Held;
+
+/// [`UPSafeCell::owner`] while no hart holds the cell
+const NO_HART: usize = usize::MAX;
 
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
 ///
-/// We should only use it in uniprocessor.
-///
 /// In order to get mutable reference of inner data, call
-/// `exclusive_access`.
+/// `exclusive_access`, which spins while another hart holds it.
 pub struct UPSafeCell<T> {
     /// inner data
-    inner: RefCell<T>,
+    inner: Mutex<T>,
+    /// Hart holding the lock, [`NO_HART`] if none
+    owner: AtomicUsize,
 }
 
 unsafe impl<T> Sync for UPSafeCell<T> {}
 
+/// Exclusive access to the data of a [`UPSafeCell`], counted as a guard the
+/// hart holds in debug builds, see [`super::held`]
+pub struct UPRefMut<'a, T> {
+    inner: MutexGuard<'a, T>,
+    owner: &'a AtomicUsize,
+    #[cfg(debug_assertions)]
+    _held: Held,
+}
+
+impl<T> Deref for UPRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for UPRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T> Drop for UPRefMut<'_, T> {
+    /// Before the lock is released with `inner`
+    fn drop(&mut self) {
+        self.owner.store(NO_HART, Ordering::Release);
+    }
+}
+
 impl<T> UPSafeCell<T> {
-    /// User is responsible to guarantee that inner struct is only used in
-    /// uniprocessor.
+    /// User is responsible to guarantee that the inner struct is only
+    /// borrowed once at a time by a hart.
     pub unsafe fn new(value: T) -> Self {
         Self {
-            inner: RefCell::new(value),
+            inner: Mutex::new(value),
+            owner: AtomicUsize::new(NO_HART),
+        }
+    }
+    #[cfg_attr(debug_assertions, track_caller)]
+    fn guard<'a>(&'a self, inner: MutexGuard<'a, T>, hart: usize) -> UPRefMut<'a, T> {
+        self.owner.store(hart, Ordering::Release);
+        UPRefMut {
+            inner,
+            owner: &self.owner,
+            #[cfg(debug_assertions)]
+            _held: Held::new(),
+        }
+    }
+    /// Panic if the data has been borrowed by this hart, wait for the hart
+    /// that borrowed it otherwise.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn exclusive_access(&self) -> UPRefMut<'_, T> {
+        let hart = hart_id();
+        if self.owner.load(Ordering::Acquire) == hart {
+            panic!("already borrowed on hart {}", hart);
         }
+        self.guard(self.inner.lock(), hart)
     }
-    /// Panic if the data has been borrowed.
-    pub fn exclusive_access(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+    /// `None` if the data has been borrowed, e.g. by the code that panicked,
+    /// or is by another hart right now.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_exclusive_access(&self) -> Option<UPRefMut<'_, T>> {
+        let inner = self.inner.try_lock()?;
+        Some(self.guard(inner, hart_id()))
     }
 }
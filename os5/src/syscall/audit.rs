@@ -0,0 +1,87 @@
+//! Auditing the buffers passed to syscalls, see [`crate::task::audit_begin`]
+
+use super::nr::*;
+use super::process::self_or_child;
+use super::{EBUSY, EFAULT, EINVAL, ENOMEM};
+use crate::mm::{copy_to_user, MapPermission};
+use crate::task::{
+    audit_start, audit_stop, current_user_task, populate_user_buffer, AuditBuffer, AuditError,
+    AuditRecord,
+};
+use alloc::vec::Vec;
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    AUDIT [NO_BATCH] => |args| sys_audit(args[0], args[1]),
+    AUDIT_READ [NO_BATCH] => |args| sys_audit_read(args[0], args[1] as *mut AuditRecord, args[2]),
+};
+
+/// What syscall `id` is passed that an audit keeps, `None` for one it does
+/// not record
+pub fn audited_buffer(id: usize, args: &[usize; 6]) -> Option<AuditBuffer> {
+    match id {
+        WRITE => Some(AuditBuffer::Bytes(args[1], args[2])),
+        MAIL_WRITE => Some(AuditBuffer::Bytes(args[1], args[2])),
+        OPENAT | UNLINKAT | LINKAT => Some(AuditBuffer::Str(args[1])),
+        EXEC | SPAWN | SPAWNV | CHDIR | CHECKPOINT | RESTORE => Some(AuditBuffer::Str(args[0])),
+        _ => None,
+    }
+}
+
+/// Record the syscalls of task `pid`, the current task or a child of it,
+/// that take a buffer or a path, keeping up to `records`, at most
+/// [`crate::task::AUDIT_MAX_RECORDS`], for [`sys_audit_read`]. The ring is
+/// charged to the caller, `-ENOMEM` if that takes it over
+/// [`crate::task::AUDIT_MAX_BYTES`]; `-EBUSY` if the task is being audited
+/// already. `records` 0 stops recording and keeps the ring
+pub fn sys_audit(pid: usize, records: usize) -> isize {
+    let caller = current_or_esrch!(current_user_task());
+    let task = match self_or_child(&caller, pid) {
+        Ok(task) => task,
+        Err(err) => return err,
+    };
+    let charge = caller.inner_exclusive_access().audit_charge.clone();
+    let mut inner = task.inner_exclusive_access();
+    if records == 0 {
+        return match inner.audit.as_ref() {
+            Some(audit) if audit.owner == caller.getpid() => {
+                audit_stop(&mut inner);
+                0
+            }
+            _ => -EINVAL,
+        };
+    }
+    if inner.is_zombie() {
+        return -EINVAL;
+    }
+    match audit_start(&mut inner, caller.getpid(), &charge, records) {
+        Ok(()) => 0,
+        Err(AuditError::Busy) => -EBUSY,
+        Err(AuditError::NoMemory) => -ENOMEM,
+    }
+}
+
+/// Move the oldest records of the audit of task `pid` the caller started
+/// to `buf`, as many as fit in `len` bytes, and return how many. A child
+/// can be read after it exited, until it is waited for. `-EINVAL` if the
+/// caller did not start an audit of it
+pub fn sys_audit_read(pid: usize, buf: *mut AuditRecord, len: usize) -> isize {
+    let caller = current_or_esrch!(current_user_task());
+    let task = match self_or_child(&caller, pid) {
+        Ok(task) => task,
+        Err(err) => return err,
+    };
+    let max = len / core::mem::size_of::<AuditRecord>();
+    // checked before records are taken, so that none is lost to a bad buffer
+    if !populate_user_buffer(buf as usize, max * core::mem::size_of::<AuditRecord>(), MapPermission::W) {
+        return -EFAULT;
+    }
+    let records: Vec<AuditRecord> = {
+        let mut inner = task.inner_exclusive_access();
+        match inner.audit.as_mut() {
+            Some(audit) if audit.owner == caller.getpid() => audit.drain(max).into(),
+            _ => return -EINVAL,
+        }
+    };
+    copy_to_user(caller.get_user_token(), buf, &records);
+    records.len() as isize
+}
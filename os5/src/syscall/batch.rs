@@ -0,0 +1,89 @@
+//! Running several simple syscalls for the price of one trap
+
+use super::{dispatch_syscall, nr, syscall_desc, SyscallFlags, EFAULT, EINVAL, EPERM};
+use crate::mm::{copy_from_user, copy_to_user, MapPermission};
+use crate::task::{current_user_task, current_user_token, populate_user_buffer};
+use alloc::vec;
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    // the entries run through the dispatcher again, without a trap of their
+    // own
+    BATCH [NO_BATCH] => |args| sys_batch(args[0] as *mut BatchEntry, args[1], args[2]),
+};
+
+/// Entries one batch may hold
+const MAX_BATCH: usize = 256;
+/// Flag of [`sys_batch`] to stop at the first entry that fails
+const BATCH_STOP_ON_ERROR: usize = 1;
+
+/// One syscall of a batch, `ret` is filled in once it has run
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct BatchEntry {
+    pub id: usize,
+    pub args: [usize; 3],
+    pub ret: isize,
+}
+
+/// Whether `entry` may run inside a batch: a syscall that is not marked
+/// [`SyscallFlags::NO_BATCH`], nor [`SyscallFlags::BLOCKS`] unless it is a
+/// read from a file that never blocks: no stream, or one with `O_NONBLOCK`
+fn allowed(entry: &BatchEntry) -> bool {
+    let flags = match syscall_desc(entry.id) {
+        Some(desc) => desc.flags,
+        None => return false,
+    };
+    if flags.contains(SyscallFlags::NO_BATCH) {
+        return false;
+    }
+    if !flags.contains(SyscallFlags::BLOCKS) {
+        return true;
+    }
+    match entry.id {
+        nr::READ => current_user_task().map_or(false, |task| {
+            let inner = task.inner_exclusive_access();
+            // a bad fd just fails
+            let blocks = inner
+                .file(entry.args[0])
+                .map_or(false, |fd| fd.file.is_stream() && !fd.file.nonblock());
+            !blocks
+        }),
+        _ => false,
+    }
+}
+
+/// Run the `n` entries at `entries` in order, writing back the result of
+/// each, and return how many ran. Nothing runs, and `-EPERM` is returned, if
+/// any entry is not allowed in a batch. With [`BATCH_STOP_ON_ERROR`] in `flags`
+/// the batch ends after the first negative result
+pub fn sys_batch(entries: *mut BatchEntry, n: usize, flags: usize) -> isize {
+    if n > MAX_BATCH || flags & !BATCH_STOP_ON_ERROR != 0 {
+        return -EINVAL;
+    }
+    let token = current_or_esrch!(current_user_token());
+    let size = n * core::mem::size_of::<BatchEntry>();
+    if !populate_user_buffer(entries as usize, size, MapPermission::R | MapPermission::W) {
+        return -EFAULT;
+    }
+    let empty = BatchEntry {
+        id: 0,
+        args: [0; 3],
+        ret: 0,
+    };
+    let mut batch = vec![empty; n];
+    copy_from_user(token, entries, &mut batch);
+    if !batch.iter().all(allowed) {
+        return -EPERM;
+    }
+    let mut ran = 0;
+    for entry in batch.iter_mut() {
+        let [a0, a1, a2] = entry.args;
+        entry.ret = dispatch_syscall(entry.id, [a0, a1, a2, 0, 0, 0]);
+        ran += 1;
+        if entry.ret < 0 && flags & BATCH_STOP_ON_ERROR != 0 {
+            break;
+        }
+    }
+    copy_to_user(token, entries, &batch[..ran]);
+    ran as isize
+}
@@ -0,0 +1,67 @@
+//! Checkpoint and restore of a process, see [`crate::task::checkpoint`]
+
+use super::{EAGAIN, EINVAL, ENOENT, ENOMEM, ENOSPC};
+use crate::fs::normalize_path;
+use crate::config::PATH_MAX;
+use crate::kevent;
+use crate::shutdown::shutting_down;
+use crate::task::{
+    add_task, checkpoint, current_user_task, read_user_str, register_task, restore, CheckpointError,
+};
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    CHECKPOINT [NO_BATCH] => |args| sys_checkpoint(args[0] as *const u8),
+    RESTORE [NO_BATCH] => |args| sys_restore(args[0] as *const u8),
+};
+
+fn checkpoint_errno(err: CheckpointError) -> isize {
+    match err {
+        CheckpointError::Busy | CheckpointError::BadImage => -EINVAL,
+        CheckpointError::TooLarge => -ENOSPC,
+        CheckpointError::NotFound => -ENOENT,
+        CheckpointError::NoMemory => -ENOMEM,
+        CheckpointError::NoPid => -EAGAIN,
+    }
+}
+
+/// Save the current process to the ramfs file `path` and return 0; the
+/// process [`sys_restore`] starts from it returns 1 here instead. Only
+/// stdio may be open and no semaphore or mutex handle, `-EINVAL` otherwise,
+/// as in a signal handler or with a mapping that is not private; `-ENOSPC`
+/// if the image does not fit in the ramfs
+pub fn sys_checkpoint(path: *const u8) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let path = match read_user_str(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let path = normalize_path(&task.inner_exclusive_access().cwd, &path);
+    match checkpoint(&task, &path) {
+        Ok(()) => 0,
+        Err(err) => checkpoint_errno(err),
+    }
+}
+
+/// Start the process saved to the ramfs file `path` by [`sys_checkpoint`]
+/// as a child with fresh stdio, and return its pid. `-ENOENT` if there is
+/// no such file, `-EINVAL` if it holds no image of this kernel
+pub fn sys_restore(path: *const u8) -> isize {
+    if shutting_down() {
+        return -EAGAIN;
+    }
+    let task = current_or_esrch!(current_user_task());
+    let path = match read_user_str(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let path = normalize_path(&task.inner_exclusive_access().cwd, &path);
+    let child = match restore(&task, &path) {
+        Ok(child) => child,
+        Err(err) => return checkpoint_errno(err),
+    };
+    let pid = child.getpid();
+    register_task(child.clone());
+    add_task(child);
+    kevent!(Spawn { parent: task.pid.0, child: pid, path, argc: 0 });
+    pid as isize
+}
@@ -0,0 +1,60 @@
+//! Error numbers syscalls fail with, as their negation
+//!
+//! The numbers are the ones Linux uses, so that they mean what a reader
+//! expects. The user library includes this file for the same constants, and
+//! turns a negative return into -1 and keeps the number for `errno()`.
+
+#![allow(dead_code)]
+
+/// Not allowed, e.g. a mapping both writable and executable
+pub const EPERM: isize = 1;
+/// No such file or directory
+pub const ENOENT: isize = 2;
+/// No such process, what a syscall gets if it finds no current task
+pub const ESRCH: isize = 3;
+/// Interrupted by a signal
+pub const EINTR: isize = 4;
+/// The arguments of exec take more than `ARG_MAX` bytes
+pub const E2BIG: isize = 7;
+/// The image of the app does not match its checksum, or is no ELF
+pub const ENOEXEC: isize = 8;
+/// No such open file, or not open for that
+pub const EBADF: isize = 9;
+/// No such child to wait for
+pub const ECHILD: isize = 10;
+/// Out of some resource for now, e.g. pids
+pub const EAGAIN: isize = 11;
+/// Out of memory, or out of the address space
+pub const ENOMEM: isize = 12;
+/// A user buffer that is not mapped for the access
+pub const EFAULT: isize = 14;
+/// Taken already, e.g. a mutex the caller holds
+pub const EBUSY: isize = 16;
+/// Something is in the way, e.g. a mapping at the address asked for
+pub const EEXIST: isize = 17;
+/// Not a directory
+pub const ENOTDIR: isize = 20;
+/// An argument out of its range
+pub const EINVAL: isize = 22;
+/// No fd left
+pub const EMFILE: isize = 24;
+/// No space left on device
+pub const ENOSPC: isize = 28;
+/// Illegal seek
+pub const ESPIPE: isize = 29;
+/// A write to a pipe with no reader left, which raises SIGPIPE too
+pub const EPIPE: isize = 32;
+/// Result too large, for a buffer that cannot take it
+pub const ERANGE: isize = 34;
+/// Waiting would never end
+pub const EDEADLK: isize = 35;
+/// A name longer than allowed
+pub const ENAMETOOLONG: isize = 36;
+/// No such syscall
+pub const ENOSYS: isize = 38;
+/// The object went away, e.g. a dead semaphore
+pub const EIDRM: isize = 43;
+/// What an interrupted blocking syscall returns, never seen by user space:
+/// the syscall is restarted if the handler of the signal has `SA_RESTART`,
+/// else it fails with `-EINTR`
+pub const ERESTARTSYS: isize = 512;
@@ -0,0 +1,57 @@
+//! Restricting the syscalls of a process, see [`crate::task::SyscallFilter`]
+
+use super::{EFAULT, EINVAL, EPERM};
+use crate::config::MAX_SYSCALL_NUM;
+use crate::mm::{copy_from_user, MapPermission};
+use crate::task::{current_user_task, populate_user_buffer, set_syscall_filter, SyscallFilter};
+use alloc::vec;
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    SET_SYSCALL_FILTER [NO_BATCH] => |args| {
+        sys_set_syscall_filter(args[0] as *const usize, args[1], args[2])
+    },
+};
+
+/// A filter as [`super::sys_spawnv`] is passed it, the arguments of
+/// [`sys_set_syscall_filter`]
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct SpawnFilter {
+    pub ids: usize,
+    pub count: usize,
+    pub action: usize,
+}
+
+/// The filter allowing the `count` syscall numbers at `ids` in the space of
+/// `token`, `-EINVAL` for a number out of range or an unknown `action`
+pub(super) fn read_filter(token: usize, ids: *const usize, count: usize, action: usize) -> Result<SyscallFilter, isize> {
+    // a number more than once is no use
+    if count > MAX_SYSCALL_NUM {
+        return Err(-EINVAL);
+    }
+    if !populate_user_buffer(ids as usize, count * core::mem::size_of::<usize>(), MapPermission::R) {
+        return Err(-EFAULT);
+    }
+    let mut buf = vec![0; count];
+    copy_from_user(token, ids, &mut buf);
+    SyscallFilter::new(&buf, action).ok_or(-EINVAL)
+}
+
+/// Restrict the current task, and the tasks it starts from now on, to the
+/// `count` syscalls at `ids`, any other failing with `-EPERM` or killing it
+/// with `SIGSYS` as `action` says, see [`crate::task::FILTER_KILL`]. Exit
+/// and sigreturn are always allowed. A task with a filter may only narrow
+/// it, `-EPERM` for anything it would allow that the old one did not, or a
+/// kill turning into an error
+pub fn sys_set_syscall_filter(ids: *const usize, count: usize, action: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let filter = match read_filter(task.get_user_token(), ids, count, action) {
+        Ok(filter) => filter,
+        Err(err) => return err,
+    };
+    let mut inner = task.inner_exclusive_access();
+    if !set_syscall_filter(&mut inner, filter) {
+        return -EPERM;
+    }
+    0
+}
@@ -1,50 +1,487 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::translated_byte_buffer;
-use crate::sbi::console_getchar;
-use crate::task::{current_user_token, suspend_current_and_run_next};
+use crate::fs::{
+    make_pipe, normalize_path, open_ram_file, ram_dir_exists, FileDescriptor, FileKind, OpenFlags,
+    SeekError, FD_CLOEXEC, MAX_FD, O_CLOEXEC, O_NONBLOCK, POLLERR, POLLHUP, POLLNVAL,
+};
+use crate::mm::{copy_to_user, translated_byte_buffer, MapPermission, UserBuffer};
+use crate::config::{PAGE_SIZE, PATH_MAX};
+use crate::task::{
+    current_user_task, current_user_token, is_privileged, lend_user_page, pid2task,
+    populate_user_buffer,
+    read_user, read_user_str, send_signal, signal_pending, suspend_current_and_run_next,
+    take_interrupted, try_populate_user_buffer, write_user, SignalFlags, TaskControlBlock,
+};
+use crate::timer::get_time_ms;
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+use alloc::vec::Vec;
+use super::{
+    EAGAIN, EBADF, EFAULT, EINTR, EINVAL, EMFILE, ENOENT, ENOSPC, EPERM, EPIPE, ERANGE, ERESTARTSYS,
+    ESPIPE, ESRCH,
+};
 
-const FD_STDIN: usize = 0;
-const FD_STDOUT: usize = 1;
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    GETCWD [NO_BATCH] => |args| sys_getcwd(args[0] as *mut u8, args[1]),
+    DUP [NO_BATCH] => |args| sys_dup(args[0]),
+    DUP3 [NO_BATCH] => |args| sys_dup3(args[0], args[1], args[2] as u32),
+    FCNTL [NO_BATCH] => |args| sys_fcntl(args[0], args[1], args[2]),
+    CHDIR [NO_BATCH] => |args| sys_chdir(args[0] as *const u8),
+    // openat(dirfd, path, flags), relative paths are always taken from the
+    // working directory
+    OPENAT [NO_BATCH] => |args| sys_open(args[1] as *const u8, args[2] as u32),
+    CLOSE => |args| sys_close(args[0]),
+    PIPE [NO_BATCH] => |args| sys_pipe(args[0] as *mut usize, args[1] as u32),
+    LSEEK [NO_BATCH] => |args| sys_lseek(args[0], args[1] as isize, args[2]),
+    // only on a stream, a batch looks at the file
+    READ [BLOCKS] => |args| sys_read(args[0], args[1] as *const u8, args[2]),
+    // waits only while a pipe is full, which batches have always let through
+    WRITE => |args| sys_write(args[0], args[1] as *const u8, args[2]),
+    PPOLL [BLOCKS] => |args| {
+        sys_ppoll(args[0] as *mut PollFd, args[1], args[2] as isize, args[3] as *const u32)
+    },
+    FD_INFO [NO_BATCH] => |args| sys_fd_info(args[0], args[1] as *mut FdInfo, args[2]),
+};
+
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
-        }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+    let task = current_or_esrch!(current_user_task());
+    let inner = task.inner_exclusive_access();
+    let file = match inner.file(fd) {
+        Some(fd) if fd.file.writable() => fd.file.clone(),
+        _ => return -EBADF,
+    };
+    // release current task TCB manually to avoid multi-borrow
+    drop(inner);
+    // whole pages go to a pipe without a copy, as long as it takes them
+    let mut lent = 0;
+    while len - lent >= PAGE_SIZE
+        && (buf as usize + lent) % PAGE_SIZE == 0
+        && file.accepts_page()
+    {
+        let taken = match lend_user_page(buf as usize + lent) {
+            Some(frame) => file.write_page(frame),
+            None => false,
+        };
+        if !taken {
+            break;
         }
+        lent += PAGE_SIZE;
+    }
+    if lent == len && len > 0 {
+        return len as isize;
+    }
+    let (buf, len) = (buf as usize + lent, len - lent);
+    if !populate_user_buffer(buf, len, MapPermission::R) {
+        return if lent > 0 { lent as isize } else { -EFAULT };
     }
+    let token = current_or_esrch!(current_user_token());
+    let buffers = translated_byte_buffer(token, buf as *const u8, len);
+    let written = match file.write(UserBuffer::new(buffers)) {
+        // a ramfs file that takes no byte at all is full
+        Some(0) if len > 0 && lent == 0 && file.kind() == FileKind::RamFile => return -ENOSPC,
+        Some(n) => lent + n,
+        None => return -EAGAIN,
+    };
+    // a pipe that nothing reads any more
+    if written == 0 && len > 0 && matches!(file.pipe_ends(), Some((_, false))) {
+        send_signal(&task, SignalFlags::SIGPIPE);
+        return -EPIPE;
+    }
+    written as isize
 }
 
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            let mut c: usize;
-            loop {
-                c = console_getchar();
-                if c == 0 {
-                    suspend_current_and_run_next();
-                    continue;
-                } else {
-                    break;
-                }
+    let task = current_or_esrch!(current_user_task());
+    let inner = task.inner_exclusive_access();
+    let file = match inner.file(fd) {
+        Some(fd) if fd.file.readable() => fd.file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    if let Err(err) = try_populate_user_buffer(buf as usize, len, MapPermission::W) {
+        return err;
+    }
+    let buffers = translated_byte_buffer(current_or_esrch!(current_user_token()), buf, len);
+    let read = file.read(UserBuffer::new(buffers));
+    if take_interrupted() {
+        return -ERESTARTSYS;
+    }
+    match read {
+        Some(read) => read as isize,
+        None => -EAGAIN,
+    }
+}
+
+/// One fd of `sys_ppoll`, as in Linux
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct PollFd {
+    /// Skipped if negative
+    pub fd: i32,
+    /// The `POLL*` bits asked for, `POLLERR` and `POLLHUP` always count
+    pub events: u16,
+    /// The ones that are true, filled in by `sys_ppoll`
+    pub revents: u16,
+}
+
+/// Fill in `revents` of each of `polls` for the fds of `task`, return how
+/// many have any
+fn poll_fds(task: &Arc<TaskControlBlock>, polls: &mut [PollFd]) -> usize {
+    let inner = task.inner_exclusive_access();
+    for poll in polls.iter_mut() {
+        poll.revents = match usize::try_from(poll.fd) {
+            Err(_) => 0,
+            Ok(fd) => match inner.file(fd) {
+                Some(fd) => fd.file.poll() & (poll.events | POLLERR | POLLHUP),
+                None => POLLNVAL,
+            },
+        };
+    }
+    polls.iter().filter(|poll| poll.revents != 0).count()
+}
+
+/// Wait until one of the `nfds` fds at `fds` has one of its events, fill in
+/// `revents` of each and return how many have any
+///
+/// Unlike Linux the timeout is `timeout_ms` milliseconds, negative to wait
+/// for ever. Unless `sigmask` is null the signals it points to are blocked
+/// instead while ppoll waits, the old mask is back once it returns or, if
+/// a signal interrupts it, after that signal's handler. `-EINTR` then,
+/// `-EINVAL` for more than [`MAX_FD`] fds
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout_ms: isize, sigmask: *const u32) -> isize {
+    if nfds > MAX_FD {
+        return -EINVAL;
+    }
+    let mut polls = Vec::with_capacity(nfds);
+    for i in 0..nfds {
+        match read_user(fds.wrapping_add(i)) {
+            Ok(poll) => polls.push(poll),
+            Err(err) => return err,
+        }
+    }
+    let mask = if sigmask.is_null() {
+        None
+    } else {
+        match read_user(sigmask) {
+            Ok(mask) => Some(SignalFlags::from_bits_truncate(mask)),
+            Err(err) => return err,
+        }
+    };
+    let task = current_or_esrch!(current_user_task());
+    if let Some(mask) = mask {
+        let mut inner = task.inner_exclusive_access();
+        inner.saved_signal_mask = Some(inner.signal_mask);
+        inner.signal_mask = mask - SignalFlags::unblockable();
+    }
+    let deadline = usize::try_from(timeout_ms).ok().map(|ms| get_time_ms() + ms);
+    let ready = loop {
+        let ready = poll_fds(&task, &mut polls);
+        if ready > 0 || deadline.map_or(false, |deadline| get_time_ms() >= deadline) {
+            break ready;
+        }
+        if signal_pending() {
+            // handle_signals puts the old mask back
+            return -EINTR;
+        }
+        suspend_current_and_run_next();
+    };
+    let mut inner = task.inner_exclusive_access();
+    if let Some(old) = inner.saved_signal_mask.take() {
+        inner.signal_mask = old;
+    }
+    drop(inner);
+    for (i, poll) in polls.iter().enumerate() {
+        if let Err(err) = write_user(fds.wrapping_add(i), *poll) {
+            return err;
+        }
+    }
+    ready as isize
+}
+
+/// Open the ramfs file `path`, relative to the working directory unless it
+/// starts with `/`, see [`OpenFlags`] for `flags`
+pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let task = current_or_esrch!(current_user_task());
+    let path = match read_user_str(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let path = normalize_path(&task.inner_exclusive_access().cwd, &path);
+    let file = match open_ram_file(path.as_str(), flags) {
+        Some(file) => file,
+        None => return -ENOENT,
+    };
+    let mut inner = task.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[fd] = Some(FileDescriptor::new(
+        file,
+        flags.contains(OpenFlags::CLOEXEC),
+    ));
+    fd as isize
+}
+
+/// Change the working directory to `path`, which must be the root or a
+/// prefix of some ramfs file
+pub fn sys_chdir(path: *const u8) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let path = match read_user_str(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let mut inner = task.inner_exclusive_access();
+    let cwd = normalize_path(&inner.cwd, &path);
+    if !ram_dir_exists(&cwd) {
+        return -ENOENT;
+    }
+    inner.cwd = cwd;
+    0
+}
+
+/// Copy the working directory and a NUL to `buf` and return the length
+/// copied, or `-ERANGE` and copy nothing if `len` cannot take it all
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut cwd = task.inner_exclusive_access().cwd.clone();
+    cwd.push('\0');
+    if cwd.len() > len {
+        return -ERANGE;
+    }
+    if !populate_user_buffer(buf as usize, cwd.len(), MapPermission::W) {
+        return -EFAULT;
+    }
+    copy_to_user(task.get_user_token(), buf, cwd.as_bytes());
+    cwd.len() as isize
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    match inner.fd_table.get_mut(fd) {
+        Some(file) if file.is_some() => {
+            // the last write end of a pipe gives its readers EOF once dropped
+            file.take();
+            0
+        }
+        _ => -EBADF,
+    }
+}
+
+/// Move the offset of `fd` to `offset` from the start (`SEEK_SET`), the
+/// current offset (`SEEK_CUR`) or the end (`SEEK_END`) and return it.
+/// Pipes and the console cannot seek
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let inner = task.inner_exclusive_access();
+    let file = match inner.file(fd) {
+        Some(fd) => fd.file.clone(),
+        None => return -EBADF,
+    };
+    drop(inner);
+    match file.seek(offset, whence) {
+        Ok(offset) => offset as isize,
+        Err(SeekError::NotSeekable) => -ESPIPE,
+        Err(SeekError::Invalid) => -EINVAL,
+    }
+}
+
+/// Create a pipe, writing the read end and then the write end to `pipe`.
+/// `flags` may only hold `O_CLOEXEC` and `O_NONBLOCK`
+pub fn sys_pipe(pipe: *mut usize, flags: u32) -> isize {
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+        return -EINVAL;
+    }
+    let cloexec = flags & O_CLOEXEC != 0;
+    if !populate_user_buffer(pipe as usize, 2 * core::mem::size_of::<usize>(), MapPermission::W) {
+        return -EFAULT;
+    }
+    let task = current_or_esrch!(current_user_task());
+    let token = current_or_esrch!(current_user_token());
+    let mut inner = task.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let (read_end, write_end) = (
+        FileDescriptor::new(pipe_read, cloexec),
+        FileDescriptor::new(pipe_write, cloexec),
+    );
+    read_end.file.set_nonblock(flags & O_NONBLOCK != 0);
+    write_end.file.set_nonblock(flags & O_NONBLOCK != 0);
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[read_fd] = Some(read_end);
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            inner.fd_table[read_fd] = None;
+            return -EMFILE;
+        }
+    };
+    inner.fd_table[write_fd] = Some(write_end);
+    copy_to_user(token, pipe, &[read_fd, write_fd]);
+    0
+}
+
+/// Duplicate `fd` to the lowest free fd, the copy is kept across exec
+pub fn sys_dup(fd: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let file = match inner.file(fd) {
+        Some(fd) => fd.dup(false),
+        None => return -EBADF,
+    };
+    let new_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+
+/// Duplicate `old_fd` to `new_fd`, closing what `new_fd` was first.
+/// `flags` may only hold `O_CLOEXEC`
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    if flags & !O_CLOEXEC != 0 || old_fd == new_fd {
+        return -EINVAL;
+    }
+    if new_fd >= MAX_FD {
+        return -EBADF;
+    }
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let file = match inner.file(old_fd) {
+        Some(fd) => fd.dup(flags & O_CLOEXEC != 0),
+        None => return -EBADF,
+    };
+    if inner.fd_table.len() <= new_fd {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+
+/// Get (`F_GETFD`) or set (`F_SETFD`) the fd flags of `fd`, which are only
+/// `FD_CLOEXEC`, or the file status flags of its open file description
+/// (`F_GETFL` / `F_SETFL`), which are only `O_NONBLOCK`
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get_mut(fd) {
+        Some(Some(file)) => file,
+        _ => return -EBADF,
+    };
+    match cmd {
+        F_GETFD => {
+            if file.cloexec {
+                FD_CLOEXEC as isize
+            } else {
+                0
             }
-            let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
+        }
+        F_SETFD => {
+            file.cloexec = arg & FD_CLOEXEC != 0;
+            0
+        }
+        F_GETFL => {
+            if file.file.nonblock() {
+                O_NONBLOCK as isize
+            } else {
+                0
             }
-            1
         }
-        _ => {
-            panic!("Unsupported fd in sys_read!");
+        F_SETFL => {
+            file.file.set_nonblock(arg as u32 & O_NONBLOCK != 0);
+            0
         }
+        _ => -EINVAL,
+    }
+}
+
+/// `FdInfo::flags` of an fd closed on exec
+pub const FD_INFO_CLOEXEC: u32 = 1;
+/// `FdInfo::flags` of a pipe end whose other end is closed
+pub const FD_INFO_PEER_CLOSED: u32 = 2;
+/// `FdInfo::flags` of an fd with `O_NONBLOCK`
+pub const FD_INFO_NONBLOCK: u32 = 4;
+
+/// One open fd of a task, as `sys_fd_info` reports it
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FdInfo {
+    pub fd: usize,
+    /// A `FileKind`
+    pub kind: u32,
+    pub flags: u32,
+    /// The same for both ends of a pipe, 0 for any other file
+    pub pipe_id: usize,
+}
+
+/// Describe up to `len` open fds of task `pid` in `buf`, lowest first, and
+/// return how many it has open
+///
+/// Only the task itself, its parent, initproc and the shell may ask, see
+/// [`is_privileged`], anyone else gets `-EPERM`. `-ESRCH` if there is no
+/// task `pid`.
+pub fn sys_fd_info(pid: usize, buf: *mut FdInfo, len: usize) -> isize {
+    let caller = current_or_esrch!(current_user_task());
+    let task = match pid2task(pid) {
+        Some(task) => task,
+        None => return -ESRCH,
+    };
+    let parent = task
+        .inner_exclusive_access()
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map(|parent| parent.getpid());
+    if caller.getpid() != pid && parent != Some(caller.getpid()) && !is_privileged(&caller) {
+        return -EPERM;
+    }
+    // a snapshot, the table is not borrowed across the copy
+    let fds: Vec<FdInfo> = task
+        .inner_exclusive_access()
+        .fd_table
+        .iter()
+        .enumerate()
+        .filter_map(|(fd, entry)| entry.as_ref().map(|entry| (fd, entry)))
+        .map(|(fd, entry)| {
+            let mut flags = if entry.cloexec { FD_INFO_CLOEXEC } else { 0 };
+            if entry.file.nonblock() {
+                flags |= FD_INFO_NONBLOCK;
+            }
+            let pipe_id = match entry.file.pipe_ends() {
+                Some((id, peer_open)) => {
+                    if !peer_open {
+                        flags |= FD_INFO_PEER_CLOSED;
+                    }
+                    id
+                }
+                None => 0,
+            };
+            FdInfo {
+                fd,
+                kind: entry.file.kind() as u32,
+                flags,
+                pipe_id,
+            }
+        })
+        .collect();
+    let token = current_or_esrch!(current_user_token());
+    let n = fds.len().min(len);
+    if !populate_user_buffer(buf as usize, n * core::mem::size_of::<FdInfo>(), MapPermission::W) {
+        return -EFAULT;
     }
+    copy_to_user(token, buf, &fds[..n]);
+    fds.len() as isize
 }
@@ -9,45 +9,417 @@
 //! For clarity, each single syscall is implemented as its own function, named
 //! `sys_` then the name of the syscall. You can find functions like this in
 //! submodules, and you should also implement syscalls this way.
+//!
+//! Numbers come from the table in `table.rs`, which the user library is
+//! generated from too. Each submodule lists the syscalls it handles in its
+//! own `HANDLERS`, which [`SYSCALL_DESCS`] is built from: a new syscall is an
+//! entry in the table and one there, next to its `sys_` function.
+//! [`dispatch_syscall`] runs the same hooks around every one of them.
+
+#[macro_use]
+mod table;
 
-const SYSCALL_READ: usize = 63;
-const SYSCALL_WRITE: usize = 64;
-const SYSCALL_EXIT: usize = 93;
-const SYSCALL_YIELD: usize = 124;
-const SYSCALL_GET_TIME: usize = 169;
-const SYSCALL_GETPID: usize = 172;
-const SYSCALL_FORK: usize = 220;
-const SYSCALL_EXEC: usize = 221;
-const SYSCALL_WAITPID: usize = 260;
-const SYSCALL_SPAWN: usize = 400;
-const SYSCALL_MUNMAP: usize = 215;
-const SYSCALL_MMAP: usize = 222;
-const SYSCALL_SET_PRIORITY: usize = 140;
-const SYSCALL_TASK_INFO: usize = 410;
+/// Define [`nr`] and [`counter_index`] from the table
+macro_rules! define_numbers {
+    ($($name:ident = $id:literal, $args:literal;)*) => {
+        /// Syscall numbers, by their name in the table
+        #[allow(dead_code)]
+        pub mod nr {
+            $(pub const $name: usize = $id;)*
+        }
+        /// `(number, name, argument count)` of every syscall
+        pub const SYSCALLS: &[(usize, &str, usize)] = &[$(($id, stringify!($name), $args)),*];
+        /// Where in `syscall_times` syscall `id` is counted, `None` for an
+        /// unknown one
+        pub fn counter_index(id: usize) -> Option<usize> {
+            match id {
+                $($id)|* => Some(id),
+                _ => None,
+            }
+        }
+    };
+}
 
+syscall_table!(define_numbers);
+
+// every number is unique, and a valid index of `syscall_times`
+const _: () = {
+    let mut i = 0;
+    while i < SYSCALLS.len() {
+        assert!(SYSCALLS[i].0 < MAX_SYSCALL_NUM, "syscall number not below MAX_SYSCALL_NUM");
+        let mut j = i + 1;
+        while j < SYSCALLS.len() {
+            assert!(SYSCALLS[i].0 != SYSCALLS[j].0, "syscall number used twice");
+            j += 1;
+        }
+        i += 1;
+    }
+};
+
+/// Unwrap what a current-task helper returned, failing the syscall with
+/// `-ESRCH` if there is no current task
+macro_rules! current_or_esrch {
+    ($e:expr) => {
+        match $e {
+            Some(value) => value,
+            None => return -$crate::syscall::ESRCH,
+        }
+    };
+}
+
+/// The [`SyscallHandler`]s of a module, each `NAME => handler` or
+/// `NAME [FLAG | ...] => handler` for the syscall of that name in the table,
+/// see [`SyscallFlags`]. A handler is a function of the raw arguments,
+/// usually a closure converting them for the `sys_` function
+macro_rules! handlers {
+    ($($name:ident $([$($flag:ident)|+])? => $handler:expr,)*) => {
+        &[$(super::SyscallHandler {
+            id: super::nr::$name,
+            handler: $handler,
+            flags: super::SyscallFlags::from_bits_truncate(
+                0 $($(| super::SyscallFlags::$flag.bits())+)?
+            ),
+        },)*]
+    };
+}
+
+mod audit;
+mod batch;
+mod checkpoint;
+mod errno;
+mod filter;
 mod fs;
 mod process;
+mod sync;
+mod sysinfo;
 
-use fs::*;
+use crate::config::MAX_HARTS;
+use crate::config::MAX_SYSCALL_NUM;
+use crate::percpu::syscall_latency;
+use crate::sync::InterruptGuard;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::task::{
+    audit_begin, audit_end, auditing, check_syscall_filter, count_syscall_outcome, current_task,
+    kill_current_and_run_next, AuditRecord, FilterVerdict, SignalFlags, IDLE_PID,
+};
+use crate::timer::get_time_us;
+use audit::audited_buffer;
+pub use errno::*;
 pub use process::*;
 
+bitflags! {
+    /// What the dispatcher and a batch need to know of a handler
+    pub struct SyscallFlags: u8 {
+        /// May sleep until another task or the clock wakes the caller
+        const BLOCKS = 1 << 0;
+        /// Must not run inside a batch, see [`batch::sys_batch`]
+        const NO_BATCH = 1 << 1;
+    }
+}
+
+/// The handler of syscall `id`, as a module lists it
+pub struct SyscallHandler {
+    pub id: usize,
+    pub handler: fn(&[usize; 6]) -> isize,
+    pub flags: SyscallFlags,
+}
+
+/// What the dispatcher knows of a syscall the kernel implements
+#[derive(Copy, Clone)]
+pub struct SyscallDesc {
+    pub id: usize,
+    pub name: &'static str,
+    pub nargs: usize,
+    pub handler: fn(&[usize; 6]) -> isize,
+    pub flags: SyscallFlags,
+}
+
+/// The handlers of every module
+const HANDLERS: &[&[SyscallHandler]] = &[
+    fs::HANDLERS,
+    process::HANDLERS,
+    sync::HANDLERS,
+    sysinfo::HANDLERS,
+    audit::HANDLERS,
+    filter::HANDLERS,
+    checkpoint::HANDLERS,
+    batch::HANDLERS,
+];
+
+/// [`SyscallDesc`] of each implemented syscall by its number, its name and
+/// argument count taken from the table
+static SYSCALL_DESCS: [Option<SyscallDesc>; MAX_SYSCALL_NUM] = {
+    let mut descs: [Option<SyscallDesc>; MAX_SYSCALL_NUM] = [None; MAX_SYSCALL_NUM];
+    let mut module = 0;
+    while module < HANDLERS.len() {
+        let mut i = 0;
+        while i < HANDLERS[module].len() {
+            let handler = &HANDLERS[module][i];
+            assert!(descs[handler.id].is_none(), "syscall handled twice");
+            let mut entry = 0;
+            while SYSCALLS[entry].0 != handler.id {
+                entry += 1;
+            }
+            descs[handler.id] = Some(SyscallDesc {
+                id: handler.id,
+                name: SYSCALLS[entry].1,
+                nargs: SYSCALLS[entry].2,
+                handler: handler.handler,
+                flags: handler.flags,
+            });
+            i += 1;
+        }
+        module += 1;
+    }
+    descs
+};
+
+/// What the kernel knows of syscall `id`, `None` if it does not implement it
+pub fn syscall_desc(id: usize) -> Option<&'static SyscallDesc> {
+    SYSCALL_DESCS.get(id)?.as_ref()
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const COUNT_INIT: AtomicUsize = AtomicUsize::new(0);
+/// Calls of each syscall by all tasks, indexed like `syscall_times`
+static SYSCALL_COUNTS: [AtomicUsize; MAX_SYSCALL_NUM] = [COUNT_INIT; MAX_SYSCALL_NUM];
+/// Of those, the ones that returned 0 or more and less than 0, and the
+/// errno of the last that failed
+static SYSCALL_OK: [AtomicUsize; MAX_SYSCALL_NUM] = [COUNT_INIT; MAX_SYSCALL_NUM];
+static SYSCALL_ERR: [AtomicUsize; MAX_SYSCALL_NUM] = [COUNT_INIT; MAX_SYSCALL_NUM];
+static SYSCALL_ERRNO: [AtomicUsize; MAX_SYSCALL_NUM] = [COUNT_INIT; MAX_SYSCALL_NUM];
+
+/// The latencies of the syscalls one hart ran, kept in its
+/// [`crate::percpu::PerCpu`] block. Only that hart writes them, any hart
+/// may read them
+pub struct SyscallLatency {
+    /// Microseconds spent in the calls that returned, and the longest of
+    /// those that do not block
+    time_us: [AtomicUsize; MAX_SYSCALL_NUM],
+    max_us: [AtomicUsize; MAX_SYSCALL_NUM],
+}
+
+impl SyscallLatency {
+    pub const fn new() -> Self {
+        Self {
+            time_us: [COUNT_INIT; MAX_SYSCALL_NUM],
+            max_us: [COUNT_INIT; MAX_SYSCALL_NUM],
+        }
+    }
+}
+
+/// The counts of one syscall, for all tasks since boot
+pub struct SyscallCount {
+    pub id: usize,
+    pub name: &'static str,
+    /// Calls made, counted as the dispatcher is entered
+    pub calls: usize,
+    /// Calls that returned, counted as the dispatcher returns
+    pub ok: usize,
+    pub err: usize,
+    /// errno of the last call that failed, 0 if none did
+    pub last_errno: usize,
+    /// Time the calls that returned took in all, waits included
+    pub time_us: usize,
+    /// Longest of them, 0 for a syscall that may block
+    pub max_us: usize,
+}
+
+/// The counts of every syscall in the table, the latencies summed over the
+/// harts
+pub fn syscall_counts() -> impl Iterator<Item = SyscallCount> {
+    SYSCALLS.iter().map(|&(id, name, _)| {
+        let harts = (0..MAX_HARTS).map(syscall_latency);
+        let (time_us, max_us) = harts.fold((0, 0), |(time_us, max_us), latency| {
+            (
+                time_us + latency.time_us[id].load(Ordering::Relaxed),
+                max_us.max(latency.max_us[id].load(Ordering::Relaxed)),
+            )
+        });
+        SyscallCount {
+            id,
+            name,
+            calls: SYSCALL_COUNTS[id].load(Ordering::Relaxed),
+            ok: SYSCALL_OK[id].load(Ordering::Relaxed),
+            err: SYSCALL_ERR[id].load(Ordering::Relaxed),
+            last_errno: SYSCALL_ERRNO[id].load(Ordering::Relaxed),
+            time_us,
+            max_us,
+        }
+    })
+}
+
+/// One syscall on its way through [`dispatch_syscall`]
+struct Call {
+    id: usize,
+    args: [usize; 6],
+    desc: Option<&'static SyscallDesc>,
+    start_us: usize,
+    /// Record of it an audit is taking, see [`begin_audit`]
+    audit: Option<AuditRecord>,
+}
+
+/// Run before the handler, in order. One that fails the call with an errno
+/// ends it there, the handler and the pre-hooks after it do not run
+type PreHook = fn(&mut Call) -> Result<(), isize>;
+/// Run after the handler, in order, with what the call returned
+type PostHook = fn(&mut Call, isize);
+
+const PRE_HOOKS: &[PreHook] = &[count_call, trace_call, filter_call, begin_audit];
+const POST_HOOKS: &[PostHook] = &[record_latency, record_syscall_outcome, end_audit];
+
+/// Count the call as it enters the dispatcher
+fn count_call(call: &mut Call) -> Result<(), isize> {
+    if let Some(index) = counter_index(call.id) {
+        SYSCALL_COUNTS[index].fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Log the call with the arguments it takes, at trace level
+fn trace_call(call: &mut Call) -> Result<(), isize> {
+    if log::Level::Trace <= log::max_level() {
+        let pid = current_task().map_or(IDLE_PID, |task| task.getpid());
+        let nargs = call.desc.map_or(call.args.len(), |desc| desc.nargs);
+        trace!("[kernel] pid {} syscall {}{:x?}", pid, syscall_name(call.id), &call.args[..nargs]);
+    }
+    Ok(())
+}
+
+/// Refuse a call the syscall filter of the task does not allow, before
+/// anything it was passed is looked at
+fn filter_call(call: &mut Call) -> Result<(), isize> {
+    match check_syscall_filter(call.id) {
+        FilterVerdict::Allow => Ok(()),
+        FilterVerdict::Deny => Err(EPERM),
+        FilterVerdict::Kill => {
+            kill_current_and_run_next(SignalFlags::SIGSYS);
+            panic!("Unreachable in filter_call!");
+        }
+    }
+}
+
+/// Snapshot what the call is passed if its task is being audited, before
+/// the handler, which may change it
+fn begin_audit(call: &mut Call) -> Result<(), isize> {
+    if auditing() {
+        call.audit = audited_buffer(call.id, &call.args)
+            .and_then(|buffer| audit_begin(call.id, call.args, buffer));
+    }
+    Ok(())
+}
+
+fn end_audit(call: &mut Call, result: isize) {
+    if let Some(record) = call.audit.take() {
+        audit_end(record, result);
+    }
+}
+
+/// Add the time the call took, keeping the longest unless it may block,
+/// when that is mostly the wait
+fn record_latency(call: &mut Call, _result: isize) {
+    let index = match counter_index(call.id) {
+        Some(index) => index,
+        None => return,
+    };
+    let us = get_time_us() - call.start_us;
+    let guard = InterruptGuard::new();
+    let latency = &per_cpu!(&guard).syscall_latency;
+    // no other hart writes them, and no interrupt comes in between
+    let time_us = &latency.time_us[index];
+    time_us.store(time_us.load(Ordering::Relaxed) + us, Ordering::Relaxed);
+    if !call.desc.map_or(false, |desc| desc.flags.contains(SyscallFlags::BLOCKS)) {
+        let max_us = &latency.max_us[index];
+        max_us.store(max_us.load(Ordering::Relaxed).max(us), Ordering::Relaxed);
+    }
+}
+
+/// Count how the call ended with `result`, for all tasks and for the
+/// current one
+///
+/// This is the one place outcomes are counted, exactly once per call that
+/// gets back out of [`dispatch_syscall`]: a call failing on a bad argument
+/// counts as an error, one that never returns, as exit, is in neither
+/// count. An interrupted call counts as failing with `EINTR`, a restart of
+/// it is a call of its own.
+fn record_syscall_outcome(call: &mut Call, result: isize) {
+    let index = match counter_index(call.id) {
+        Some(index) => index,
+        None => return,
+    };
+    let errno = match result {
+        result if result == -ERESTARTSYS => Some(EINTR),
+        result if result < 0 => Some(-result),
+        _ => None,
+    };
+    match errno {
+        Some(errno) => {
+            SYSCALL_ERR[index].fetch_add(1, Ordering::Relaxed);
+            SYSCALL_ERRNO[index].store(errno as usize, Ordering::Relaxed);
+        }
+        None => {
+            SYSCALL_OK[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    count_syscall_outcome(index, errno.map(|errno| errno as u16));
+}
+
+/// Name of syscall `id` in the table, "unknown" if it is not there
+pub fn syscall_name(id: usize) -> &'static str {
+    SYSCALLS
+        .iter()
+        .find(|&&(number, _, _)| number == id)
+        .map_or("unknown", |&(_, name, _)| name)
+}
+
+/// What becomes of a syscall once its handler returns
+pub enum SyscallOutcome {
+    /// Done, back after the ecall with this return value
+    Return(isize),
+    /// Interrupted by a signal, the handler of the signal decides whether it
+    /// runs again or fails with `-EINTR`, see [`crate::task::handle_signals`]
+    Restart,
+}
+
 /// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
-    match syscall_id {
-        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_EXIT => sys_exit(args[0] as i32),
-        SYSCALL_YIELD => sys_yield(),
-        SYSCALL_GETPID => sys_getpid(),
-        SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
-        SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
-        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
-        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
-        SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
-        SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
-        SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallOutcome {
+    match dispatch_syscall(syscall_id, args) {
+        result if result == -ERESTARTSYS => SyscallOutcome::Restart,
+        result => SyscallOutcome::Return(result),
+    }
+}
+
+/// Run the handler of syscall `syscall_id` between the hooks, which returns
+/// `-ERESTARTSYS` if a signal interrupted it
+fn dispatch_syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    let mut call = Call {
+        id: syscall_id,
+        args,
+        desc: syscall_desc(syscall_id),
+        start_us: get_time_us(),
+        audit: None,
+    };
+    let result = match PRE_HOOKS.iter().try_for_each(|hook| hook(&mut call)) {
+        Ok(()) => match call.desc {
+            Some(desc) => (desc.handler)(&call.args),
+            None => unknown_syscall(syscall_id),
+        },
+        Err(errno) => -errno,
+    };
+    for hook in POST_HOOKS {
+        hook(&mut call, result);
     }
+    result
+}
+
+/// Fail syscall `id`, which this kernel does not implement, with a warning
+/// the logger rate-limits
+fn unknown_syscall(id: usize) -> isize {
+    let pid = current_task().map_or(IDLE_PID, |task| task.getpid());
+    let name = syscall_name(id);
+    warn!(
+        "[kernel] pid {} called syscall {} ({}), which is not implemented",
+        pid, id, name
+    );
+    -ENOSYS
 }
@@ -1,23 +1,105 @@
 //! Process management syscalls
 
-use crate::loader::get_app_data_by_name;
-use crate::mm::{translated_refmut, translated_str};
+use crate::loader::{get_app_data_by_name, AppError};
+use crate::mm::{copy_from_user, copy_to_user, MapPermission, VirtAddr, VirtPageNum};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, TaskStatus,  get_task_info_inner, 
-    sys_mmap_inner, sys_munmap_inner, set_priority_inner 
+    add_task, current_user_task, current_user_token, exit_current_and_run_next,
+    suspend_current_and_run_next, TaskStatus, task_info_v1, task_info_v2,
+    sys_mmap_inner, sys_munmap_inner, set_priority_inner, register_task,
+    get_process_info_inner, get_load_average, block_current_and_run_next,
+    set_affinity_inner, get_affinity_inner, sys_mprotect_inner, sys_madvise_inner, pid2task,
+    SignalAction, SignalFlags, MAX_SIG, drain_sched_trace, SchedEvent, populate_user_buffer,
+    kill_current_and_run_next, ExecError, TaskControlBlock, profile_start, profile_stop, ProfileError,
+    Rusage, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, send_signal, wait_for_signal, Wait,
+    take_interrupted, signal_pending, current_trap_cx, set_block_reason, BlockReason, INITPROC,
+    may_set_syscall_filter, set_syscall_filter, read_user, read_user_str, write_user, is_privileged,
 };
-use crate::timer::get_time_us;
+use crate::task::SHELL_PID;
+use crate::percpu::{hart_id, hart_state};
+use crate::fs::{FileDescriptor, MAX_FD};
+use crate::hotplug::{cpu_down, cpu_up};
+use crate::kevent;
+use crate::reboot::{reboot, soft_reboot, REBOOT_COLD, REBOOT_MAGIC, REBOOT_SOFT};
+use crate::shutdown::{shutdown, shutting_down};
+use crate::sync::release_mutexes;
+use crate::timer::{add_timer, get_time_ms, get_time_us};
+use alloc::alloc::{alloc_zeroed, handle_alloc_error, Layout};
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::sync::Arc;
-use crate::config::MAX_SYSCALL_NUM;
+use alloc::vec::Vec;
+use crate::config::{ARG_MAX, MAX_HARTS, MAX_SYSCALL_NUM, PAGE_SIZE, PATH_MAX};
+use core::sync::atomic::Ordering;
+use super::{
+    E2BIG, EAGAIN, EBADF, EBUSY, ECHILD, EFAULT, EINTR, EINVAL, ENAMETOOLONG, ENOENT, ENOEXEC,
+    ENOMEM, EPERM, ERESTARTSYS, ESRCH,
+};
+use super::filter::{read_filter, SpawnFilter};
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    EXIT [NO_BATCH] => |args| sys_exit(args[0] as i32),
+    SLEEP [BLOCKS] => |args| sys_sleep(args[0], args[1] as *mut usize, args[2]),
+    SCHED_SETAFFINITY [NO_BATCH] => |args| sys_sched_setaffinity(args[0], args[1]),
+    SCHED_GETAFFINITY [NO_BATCH] => |args| sys_sched_getaffinity(args[0], args[1] as *mut usize),
+    YIELD => |_| sys_yield(),
+    GETCPU [NO_BATCH] => |_| sys_getcpu(),
+    CPU_UP [NO_BATCH] => |args| sys_cpu_up(args[0]),
+    CPU_DOWN [NO_BATCH] => |args| sys_cpu_down(args[0]),
+    KILL [NO_BATCH] => |args| sys_kill(args[0], args[1]),
+    SIGSUSPEND [BLOCKS] => |args| sys_sigsuspend(args[0] as *const u32),
+    SIGACTION [NO_BATCH] => |args| {
+        sys_sigaction(args[0], args[1] as *const SignalAction, args[2] as *mut SignalAction)
+    },
+    SIGPROCMASK [NO_BATCH] => |args| {
+        sys_sigprocmask(args[0], args[1] as *const u32, args[2] as *mut u32)
+    },
+    SIGRETURN [NO_BATCH] => |_| sys_sigreturn(),
+    GETPID [NO_BATCH] => |_| sys_getpid(),
+    GETPPID [NO_BATCH] => |_| sys_getppid(),
+    GETTID [NO_BATCH] => |_| sys_gettid(),
+    PRCTL [NO_BATCH] => |args| sys_prctl(args[0], args[1]),
+    FORK [NO_BATCH] => |_| sys_fork(),
+    EXEC [NO_BATCH] => |args| sys_exec(args[0] as *const u8, args[1] as *const usize),
+    WAIT4 [BLOCKS] => |args| {
+        sys_wait4(args[0] as isize, args[1] as *mut i32, args[2], args[3] as *mut Rusage)
+    },
+    GETTIMEOFDAY => |args| sys_get_time(args[0] as *mut TimeVal, args[1]),
+    MMAP [NO_BATCH] => |args| sys_mmap(args[0], args[1], args[2]),
+    MUNMAP [NO_BATCH] => |args| sys_munmap(args[0], args[1]),
+    MPROTECT [NO_BATCH] => |args| sys_mprotect(args[0], args[1], args[2]),
+    MADVISE [NO_BATCH] => |args| sys_madvise(args[0], args[1], args[2]),
+    MAP_SELF [NO_BATCH] => |args| sys_map_self(args[0]),
+    PROFILE_START [NO_BATCH] => |args| sys_profile_start(args[0], args[1] as *mut usize, args[2]),
+    PROFILE_STOP [NO_BATCH] => |args| sys_profile_stop(args[0], args[1] as *mut usize),
+    PAGEMAP [NO_BATCH] => |args| sys_pagemap(args[0], args[1] as *mut u64, args[2]),
+    VMA_LIST [NO_BATCH] => |args| sys_vma_list(args[0], args[1] as *mut u8, args[2]),
+    SET_PRIORITY [NO_BATCH] => |args| sys_set_priority(args[0] as isize),
+    TASK_INFO [NO_BATCH] => |args| sys_task_info(args[0], args[1] as *mut u8),
+    SPAWN [NO_BATCH] => |args| sys_spawn(args[0] as *const u8),
+    SPAWNV [NO_BATCH] => |args| {
+        let actions = args[2] as *const SpawnAction;
+        let filter = args[4] as *const SpawnFilter;
+        sys_spawnv(args[0] as *const u8, args[1] as *const usize, actions, args[3], filter)
+    },
+    PROCESS_INFO [NO_BATCH] => |args| sys_process_info(args[0], args[1] as *mut ProcessInfo),
+    LOADAVG [NO_BATCH] => |args| sys_loadavg(args[0] as *mut LoadAvg),
+    SCHED_TRACE [NO_BATCH] => |args| {
+        sys_sched_trace(args[0] as *mut SchedEvent, args[1], args[2] as *mut usize)
+    },
+    PAUSE [BLOCKS] => |_| sys_pause(),
+    SHUTDOWN [NO_BATCH] => |args| sys_shutdown(args[0] as i32),
+    REBOOT [NO_BATCH] => |args| sys_reboot(args[0], args[1]),
+    KEVENT_FILTER [NO_BATCH] => |args| sys_kevent_filter(args[0], args[1]),
+};
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
 
+#[repr(C)]
 #[derive(Clone, Copy)]
 pub struct TaskInfo {
     pub status: TaskStatus,
@@ -25,6 +107,96 @@ pub struct TaskInfo {
     pub time: usize,
 }
 
+/// [`TaskInfo`] as version 1 of [`sys_task_info`] writes it
+pub const TASK_INFO_V1: usize = 1;
+/// [`TaskInfoV2`]
+pub const TASK_INFO_V2: usize = 2;
+/// Bytes of [`TaskInfoV2::name`]
+pub const TASK_NAME_LEN: usize = 32;
+
+/// Version 2 of the task info, which may grow at the end: `struct_size`
+/// tells how much of it each side knows
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TaskInfoV2 {
+    /// Size of the struct, set by the caller to the one it has room for and
+    /// by the kernel to the one it knows
+    pub struct_size: usize,
+    pub status: TaskStatus,
+    /// Milliseconds since the task first ran
+    pub time: usize,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub utime_us: usize,
+    pub stime_us: usize,
+    /// Voluntary and involuntary context switches
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+    pub mapped_pages: usize,
+    pub resident_pages: usize,
+    pub shared_pages: usize,
+    pub peak_resident_pages: usize,
+    /// Name of the app the task runs, NUL-terminated
+    pub name: [u8; TASK_NAME_LEN],
+    /// Calls of each syscall that returned 0 or more and less than 0, by
+    /// number like `syscall_times`. Unlike that they are counted as the
+    /// syscall returns, so neither has the call in flight, nor exit
+    pub syscall_ok: [u32; MAX_SYSCALL_NUM],
+    pub syscall_err: [u32; MAX_SYSCALL_NUM],
+    /// errno of the last call of each syscall that failed, 0 if none did
+    pub syscall_errno: [u16; MAX_SYSCALL_NUM],
+}
+
+impl TaskInfoV2 {
+    /// One of all zeros, built on the heap: it is too large for a kernel
+    /// stack
+    pub fn boxed() -> Box<Self> {
+        let layout = Layout::new::<Self>();
+        // all zeros is valid, with status UnInit
+        unsafe {
+            let info = alloc_zeroed(layout) as *mut Self;
+            if info.is_null() {
+                handle_alloc_error(layout);
+            }
+            Box::from_raw(info)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ProcessInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub status: TaskStatus,
+    pub prio: usize,
+    /// Total CPU time used, in milliseconds
+    pub cpu_time_ms: usize,
+    /// Recent CPU usage, in units of 1 / USAGE_SCALE
+    pub cpu_usage: usize,
+    pub mapped_pages: usize,
+    pub resident_pages: usize,
+    pub shared_pages: usize,
+    pub peak_resident_pages: usize,
+    /// Frames free in the whole system
+    pub free_frames: usize,
+    /// What the task waits for, see [`crate::task::BlockReason::encode`]
+    pub block_kind: u32,
+    pub block_arg: isize,
+    /// Number of the last syscall the task made, -1 if none, and when it
+    /// entered it, in milliseconds
+    pub last_syscall: isize,
+    pub last_syscall_ms: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LoadAvg {
+    /// Load average over 1, 5 and 15 seconds, in hundredths
+    pub load: [usize; 3],
+    /// Time each hart has spent idle, in milliseconds
+    pub idle_ms: [usize; MAX_HARTS],
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     debug!("[kernel] Application exited with code {}", exit_code);
     exit_current_and_run_next(exit_code);
@@ -38,42 +210,245 @@ pub fn sys_yield() -> isize {
 }
 
 pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+    current_or_esrch!(current_user_task()).pid.0 as isize
+}
+
+/// The id of the calling thread, which is the pid as a process has only the
+/// one
+pub fn sys_gettid() -> isize {
+    sys_getpid()
+}
+
+/// The pid of the parent, which changes when the parent exits, 0 for
+/// initproc
+pub fn sys_getppid() -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let inner = task.inner_exclusive_access();
+    inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid() as isize)
 }
 
-/// Syscall Fork which returns 0 for child process and child_pid for parent process
+/// `option` of `sys_prctl`: make the caller a subreaper if `arg` is not 0,
+/// so that its orphaned descendants come to it instead of initproc
+pub const PR_SET_CHILD_SUBREAPER: usize = 36;
+/// Write whether the caller is a subreaper to the `i32` at `arg`
+pub const PR_GET_CHILD_SUBREAPER: usize = 37;
+
+/// Set or get an attribute of the calling process, `-EINVAL` for an unknown
+/// `option`
+pub fn sys_prctl(option: usize, arg: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    match option {
+        PR_SET_CHILD_SUBREAPER => {
+            task.inner_exclusive_access().child_subreaper = arg != 0;
+            0
+        }
+        PR_GET_CHILD_SUBREAPER => {
+            if !populate_user_buffer(arg, core::mem::size_of::<i32>(), MapPermission::W) {
+                return -EFAULT;
+            }
+            let subreaper = task.inner_exclusive_access().child_subreaper as i32;
+            copy_to_user(task.get_user_token(), arg as *mut i32, &[subreaper]);
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// Syscall Fork which returns 0 for child process and child_pid for parent
+/// process, `-EAGAIN` if there are [`crate::config::MAX_TASKS`] tasks already
+/// or the kernel is shutting down
 pub fn sys_fork() -> isize {
-    let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    if shutting_down() {
+        return -EAGAIN;
+    }
+    let current_task = current_or_esrch!(current_user_task());
+    let new_task = match current_task.fork() {
+        Some(task) => task,
+        None => return -EAGAIN,
+    };
     let new_pid = new_task.pid.0;
+    kevent!(Fork { parent: current_task.pid.0, child: new_pid });
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
-    // we do not have to move to next instruction since we have done it before
-    // for child process, fork returns 0
-    trap_cx.x[10] = 0;
+    // the copy is in the middle of this fork, which returns 0 to the child
+    trap_cx.complete_syscall(0);
+    register_task(new_task.clone());
     // add new task to scheduler
     add_task(new_task);
     new_pid as isize
 }
 
+/// What exec or spawn of an app that cannot be loaded returns
+fn app_error(err: AppError) -> isize {
+    match err {
+        AppError::NotFound => -ENOENT,
+        AppError::Stale => -ENOEXEC,
+    }
+}
+
+/// Power off after the orderly shutdown, with `exit_code` telling the
+/// platform whether the run failed. Only for initproc and the shell, see
+/// [`is_privileged`]
+pub fn sys_shutdown(exit_code: i32) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    if !is_privileged(&task) {
+        return -EPERM;
+    }
+    drop(task);
+    shutdown(exit_code)
+}
+
+/// Reboot as `cmd` says, [`REBOOT_COLD`] or [`REBOOT_SOFT`], only for
+/// initproc and with [`REBOOT_MAGIC`]. A soft reboot returns to initproc
+/// loaded afresh, with no arguments
+pub fn sys_reboot(magic: usize, cmd: usize) -> isize {
+    if magic != REBOOT_MAGIC {
+        return -EINVAL;
+    }
+    let task = current_or_esrch!(current_user_task());
+    if !Arc::ptr_eq(&task, &INITPROC) {
+        return -EPERM;
+    }
+    drop(task);
+    match cmd {
+        REBOOT_COLD => reboot(),
+        REBOOT_SOFT => {
+            soft_reboot();
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// Switch the lifecycle events of the kinds in the bit mask `enable` on,
+/// then those in `disable` off, and return the kinds on afterwards.
+///
+/// Anyone may read the filter with both masks 0, only initproc and the
+/// shell may change it, see [`is_privileged`].
+pub fn sys_kevent_filter(enable: usize, disable: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    if enable | disable != 0 && !is_privileged(&task) {
+        return -EPERM;
+    }
+    kevent::set_filter(enable, disable) as isize
+}
+
+/// The strings of the null-terminated array `args`, none if it is null.
+/// `-E2BIG` if they take more than [`ARG_MAX`] bytes along with the argv
+/// array the new image gets
+fn read_user_args(mut args: *const usize) -> Result<Vec<String>, isize> {
+    let mut args_vec: Vec<String> = Vec::new();
+    // the NULL that ends argv
+    let mut size = core::mem::size_of::<usize>();
+    while !args.is_null() {
+        let arg_str_ptr = read_user(args)?;
+        if arg_str_ptr == 0 {
+            break;
+        }
+        let left = ARG_MAX.saturating_sub(size + core::mem::size_of::<usize>() + 1);
+        let arg = match read_user_str(arg_str_ptr as *const u8, left) {
+            Err(err) if err == -ENAMETOOLONG => return Err(-E2BIG),
+            arg => arg?,
+        };
+        size += core::mem::size_of::<usize>() + arg.len() + 1;
+        args_vec.push(arg);
+        args = args.wrapping_add(1);
+    }
+    Ok(args_vec)
+}
+
 /// Syscall Exec which accepts the elf path
-pub fn sys_exec(path: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(data) = get_app_data_by_name(path.as_str()) {
-        let task = current_task().unwrap();
-        task.exec(data);
-        info!("exec path {:?} as pid: {:?}", path, task.pid.0);
-        0
-    } else {
-        -1
+/// Run the app `path` with the null-terminated array of argument strings
+/// `args`, which may be null for no arguments. The new image starts with
+/// argc in a0 and in a1 argv, which points to copies of the strings on its
+/// stack, see [`crate::task::TaskControlBlock::exec`]
+pub fn sys_exec(path: *const u8, args: *const usize) -> isize {
+    let path = match read_user_str(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let args_vec = match read_user_args(args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    let data = match get_app_data_by_name(path.as_str()) {
+        Ok(data) => data,
+        Err(err) => return app_error(err),
+    };
+    let task = current_or_esrch!(current_user_task());
+    let argc = args_vec.len();
+    match task.exec(&path, data, args_vec) {
+        Ok(()) => {}
+        Err(ExecError::ImageLost) => {
+            println!("[kernel] Out of memory in exec of {}, killed.", path);
+            drop(path);
+            drop(task);
+            kill_current_and_run_next(SignalFlags::SIGKILL);
+            panic!("Unreachable in sys_exec!");
+        }
+        Err(ExecError::BadElf) => return -ENOEXEC,
+        Err(ExecError::NoMemory) => return -ENOMEM,
     }
+    // the handles went with the old image, and the mutexes still held go
+    // as they are unlocked
+    release_mutexes(&task);
+    kevent!(Exec { pid: task.pid.0, path, argc });
+    // the return value lands in a0, which is argc for the new image
+    argc as isize
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let task = current_task().unwrap();
+/// The only option of `wait4`: return 0 instead of waiting
+const WNOHANG: usize = 1;
+
+/// Reap a zombie child `pid` (any child if -1), storing its exit code to
+/// `exit_code_ptr` and, if `rusage` is not null, what it and its reaped
+/// children used.
+/// If there is not a child process whose pid is same as given, return
+/// `-ECHILD`. Else if there is a child process but it is still running, wait
+/// for it, or return 0 with `WNOHANG`. A signal interrupts the wait.
+pub fn sys_wait4(
+    pid: isize,
+    exit_code_ptr: *mut i32,
+    options: usize,
+    rusage: *mut Rusage,
+) -> isize {
+    if options & !WNOHANG != 0 {
+        return -EINVAL;
+    }
+    loop {
+        let task = current_or_esrch!(current_user_task());
+        match reap_child(&task, pid, exit_code_ptr, rusage) {
+            Some(ret) => return ret,
+            None if options & WNOHANG != 0 => return 0,
+            None => {}
+        }
+        drop(task);
+        if signal_pending() {
+            return -ERESTARTSYS;
+        }
+        set_block_reason(Some(BlockReason::WaitChild(pid)));
+        suspend_current_and_run_next();
+        set_block_reason(None);
+    }
+}
+
+/// One try of `sys_wait4`, None if the child is still running
+fn reap_child(
+    task: &Arc<TaskControlBlock>,
+    pid: isize,
+    exit_code_ptr: *mut i32,
+    rusage: *mut Rusage,
+) -> Option<isize> {
+    // checked before a child is reaped, so that none is lost to a bad buffer
+    let rusage_ok = rusage.is_null()
+        || populate_user_buffer(rusage as usize, core::mem::size_of::<Rusage>(), MapPermission::W);
+    if !rusage_ok || !populate_user_buffer(exit_code_ptr as usize, core::mem::size_of::<i32>(), MapPermission::W) {
+        return Some(-EFAULT);
+    }
     // find a child process
 
     // ---- access current TCB exclusively
@@ -83,7 +458,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        return -1;
+        return Some(-ECHILD);
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
@@ -96,47 +471,85 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // confirm that child will be deallocated after removing from children list
         assert_eq!(Arc::strong_count(&child), 1);
         let found_pid = child.getpid();
+        kevent!(TaskReap { parent: task.getpid(), child: found_pid });
         // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
+        let mut usage = child.rusage();
+        let child_inner = child.inner_exclusive_access();
+        let exit_code = child_inner.exit_code;
+        usage.add(&child_inner.children_usage);
+        drop(child_inner);
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        inner.children_usage.add(&usage);
+        drop(inner);
+        let token = task.get_user_token();
+        copy_to_user(token, exit_code_ptr, &[exit_code]);
+        if !rusage.is_null() {
+            copy_to_user(token, rusage, &[usage]);
+        }
+        Some(found_pid as isize)
     } else {
-        -2
+        None
     }
     // ---- release current PCB lock automatically
 }
 
 // YOUR JOB: 引入虚地址后重写 sys_get_time
 pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
-    let ptr = translated_refmut(current_user_token(), _ts);
-    
     let us = get_time_us();
     let ts = TimeVal {
         sec: us / 1_000_000,
         usec: us % 1_000_000,
     };
-        
-    *ptr = ts;
+    if let Err(err) = write_user(_ts, ts) {
+        return err;
+    }
     0
     // -1
 }
 
 // YOUR JOB: 引入虚地址后重写 sys_task_info
-pub fn sys_task_info(ti: *mut TaskInfo) -> isize { 
-    
-    
-    let ptr = translated_refmut(current_user_token(), ti) as *mut TaskInfo;
-    get_task_info_inner(ptr);    
-
-    0
-    
+/// Write the task info of the caller to `ti` in the layout of `version`
+///
+/// Version 2 fills in as much of [`TaskInfoV2`] as the caller has room for
+/// by the `struct_size` it set, less than its first word is an error.
+pub fn sys_task_info(version: usize, ti: *mut u8) -> isize {
+    let token = current_or_esrch!(current_user_token());
+    match version {
+        TASK_INFO_V1 => {
+            let info = current_or_esrch!(task_info_v1());
+            if !populate_user_buffer(ti as usize, core::mem::size_of::<TaskInfo>(), MapPermission::W) {
+                return -EFAULT;
+            }
+            copy_to_user(token, ti as *mut TaskInfo, &[info]);
+            0
+        }
+        TASK_INFO_V2 => {
+            let word = core::mem::size_of::<usize>();
+            if !populate_user_buffer(ti as usize, word, MapPermission::R | MapPermission::W) {
+                return -EFAULT;
+            }
+            let mut user_size = [0usize];
+            copy_from_user(token, ti as *const usize, &mut user_size);
+            if user_size[0] < word {
+                return -EINVAL;
+            }
+            let info = current_or_esrch!(task_info_v2());
+            let len = user_size[0].min(core::mem::size_of::<TaskInfoV2>());
+            let bytes = unsafe { core::slice::from_raw_parts(&*info as *const _ as *const u8, len) };
+            if !populate_user_buffer(ti as usize, len, MapPermission::W) {
+                return -EFAULT;
+            }
+            copy_to_user(token, ti, bytes);
+            0
+        }
+        _ => -EINVAL,
+    }
 }
 
 // YOUR JOB: 实现sys_set_priority，为任务添加优先级
 pub fn sys_set_priority(_prio: isize) -> isize {
     if _prio < 2 {
-        -1
+        -EINVAL
     }else {
         return set_priority_inner(_prio);
     }
@@ -150,31 +563,627 @@ pub fn sys_mmap(_start: usize, _len: usize, _port: usize) -> isize {
     
 }   
 
+pub fn sys_mprotect(start: usize, len: usize, port: usize) -> isize {
+    sys_mprotect_inner(start, len, port)
+}
+
+pub fn sys_madvise(start: usize, len: usize, advice: usize) -> isize {
+    sys_madvise_inner(start, len, advice)
+}
+
 pub fn sys_munmap(_start: usize, _len: usize) -> isize {
     sys_munmap_inner(_start, _len)
     
 }
+
+/// Entries [`sys_pagemap`] gathers at a time
+const PAGEMAP_BATCH: usize = 512;
+
+/// Describe `npages` pages of the current task from the one holding `addr`
+/// on, one [`crate::mm::MemorySet::pagemap`] entry each in `buf`. `-EINVAL`
+/// if the range wraps around
+pub fn sys_pagemap(addr: usize, buf: *mut u64, npages: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let start = VirtAddr::from(addr).floor();
+    if start.0.checked_add(npages).map_or(true, |end| end > usize::MAX / PAGE_SIZE) {
+        return -EINVAL;
+    }
+    if !populate_user_buffer(buf as usize, npages * core::mem::size_of::<u64>(), MapPermission::W) {
+        return -EFAULT;
+    }
+    let token = task.get_user_token();
+    let mut done = 0;
+    while done < npages {
+        let n = (npages - done).min(PAGEMAP_BATCH);
+        // a snapshot, the copy may fault pages in
+        let entries: Vec<u64> = {
+            let memory_set = task.memory_set.exclusive_access();
+            (start.0 + done..start.0 + done + n)
+                .map(|vpn| memory_set.pagemap(VirtPageNum(vpn)))
+                .collect()
+        };
+        copy_to_user(token, unsafe { buf.add(done) }, &entries);
+        done += n;
+    }
+    0
+}
+
+/// Copy the [`crate::mm::MemorySet::vma_list`] of task `pid` to `buf` and
+/// return its full length, of which only the lines that fit whole in `len`
+/// are copied. `pid` must be the caller, its parent or [`SHELL_PID`],
+/// `-EPERM` for another task
+pub fn sys_vma_list(pid: usize, buf: *mut u8, len: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let parent = task.inner_exclusive_access().parent.clone();
+    let parent = parent.and_then(|parent| parent.upgrade());
+    let target = if pid == task.getpid() {
+        Some(task.clone())
+    } else if pid == SHELL_PID {
+        pid2task(pid)
+    } else {
+        parent.filter(|parent| parent.getpid() == pid)
+    };
+    let target = match target {
+        Some(target) => target,
+        None if pid2task(pid).is_some() => return -EPERM,
+        None => return -ESRCH,
+    };
+    // a snapshot, the copy may fault pages in
+    let text = target.memory_set.exclusive_access().vma_list();
+    drop(target);
+    let fits = if text.len() <= len {
+        text.len()
+    } else {
+        text[..len].rfind('\n').map_or(0, |end| end + 1)
+    };
+    if !populate_user_buffer(buf as usize, fits, MapPermission::W) {
+        return -EFAULT;
+    }
+    copy_to_user(task.get_user_token(), buf, &text.as_bytes()[..fits]);
+    text.len() as isize
+}
+
+/// Map the ELF image the current task was started from read-only at `hint`,
+/// or wherever there is room from `MMAP_BASE` on if it is not a free page
+/// boundary, and return where. munmap takes it away again
+pub fn sys_map_self(hint: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let name = task.inner_exclusive_access().name.clone();
+    let data = match get_app_data_by_name(name.as_str()) {
+        Ok(data) => data,
+        Err(err) => return app_error(err),
+    };
+    let start = task.memory_set.exclusive_access().map_self(data, hint);
+    start
+}
     
 
 //
 // YOUR JOB: 实现 sys_spawn 系统调用
 // ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC 
 pub fn sys_spawn(_path: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, _path);
-    if let Some(data) = get_app_data_by_name(path.as_str()) {
-        let task = current_task().unwrap();
-        let new_task =  task.spawn(data);
-        let new_pid = new_task.pid.0;
-        let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
-
-        trap_cx.x[10] = 0;
-        add_task(new_task);
-        
-        let a = new_pid as isize;
-        info!("spawn task: {:?} as pid: {:?}",path, a);
-        a
+    let path = match read_user_str(_path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    if shutting_down() {
+        return -EAGAIN;
+    }
+    let data = match get_app_data_by_name(path.as_str()) {
+        Ok(data) => data,
+        Err(err) => return app_error(err),
+    };
+    let task = current_or_esrch!(current_user_task());
+    let fd_table = task.inner_exclusive_access().fd_table.clone();
+    let new_task = match task.spawn(&path, data, Vec::new(), fd_table) {
+        Some(task) => task,
+        None => return -EAGAIN,
+    };
+    let new_pid = new_task.pid.0;
+    register_task(new_task.clone());
+    add_task(new_task);
+    
+    kevent!(Spawn { parent: task.pid.0, child: new_pid, path, argc: 0 });
+    new_pid as isize
+}
+
+/// Most [`SpawnAction`]s one [`sys_spawnv`] takes
+const MAX_SPAWN_ACTIONS: usize = 16;
+/// [`SpawnAction::op`]: make `new_fd` another fd for `fd`, without cloexec
+const SPAWN_DUP2: usize = 0;
+/// [`SpawnAction::op`]: close `fd`
+const SPAWN_CLOSE: usize = 1;
+
+/// One change to the fds a [`sys_spawnv`] child starts with
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SpawnAction {
+    pub op: usize,
+    pub fd: usize,
+    pub new_fd: usize,
+}
+
+/// Apply `actions` in order to `fd_table`, as a forked child would before
+/// exec. `-EBADF` for an fd that is not open or a `new_fd` out of range,
+/// `-EINVAL` for an unknown op
+fn apply_spawn_actions(
+    fd_table: &mut Vec<Option<FileDescriptor>>,
+    actions: &[SpawnAction],
+) -> Result<(), isize> {
+    for action in actions {
+        if action.op != SPAWN_DUP2 && action.op != SPAWN_CLOSE {
+            return Err(-EINVAL);
+        }
+        let file = match fd_table.get(action.fd) {
+            Some(Some(file)) => file.dup(false),
+            _ => return Err(-EBADF),
+        };
+        if action.op == SPAWN_CLOSE {
+            fd_table[action.fd] = None;
+            continue;
+        }
+        if action.new_fd >= MAX_FD {
+            return Err(-EBADF);
+        }
+        if fd_table.len() <= action.new_fd {
+            fd_table.resize(action.new_fd + 1, None);
+        }
+        fd_table[action.new_fd] = Some(file);
+    }
+    Ok(())
+}
+
+/// Start `path` as a child with the arguments at `args`, taken as `exec`
+/// takes them, and the fds of the caller changed by the `n` [`SpawnAction`]s
+/// at `actions`, with those with cloexec closed afterwards as exec would.
+/// Unlike fork and exec the address space of the caller is never copied.
+/// Unless `filter` is null the child starts with it as its syscall filter,
+/// as if it had called [`super::filter::sys_set_syscall_filter`] first.
+/// Return the pid of the child
+pub fn sys_spawnv(
+    path: *const u8,
+    args: *const usize,
+    actions: *const SpawnAction,
+    n: usize,
+    filter: *const SpawnFilter,
+) -> isize {
+    if n > MAX_SPAWN_ACTIONS {
+        return -EINVAL;
+    }
+    let token = current_or_esrch!(current_user_token());
+    let path = match read_user_str(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let args = match read_user_args(args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    let size = n * core::mem::size_of::<SpawnAction>();
+    if !populate_user_buffer(actions as usize, size, MapPermission::R) {
+        return -EFAULT;
+    }
+    let empty = SpawnAction {
+        op: 0,
+        fd: 0,
+        new_fd: 0,
+    };
+    let mut spawn_actions = [empty; MAX_SPAWN_ACTIONS];
+    copy_from_user(token, actions, &mut spawn_actions[..n]);
+    let filter = if filter.is_null() {
+        None
     } else {
-        -1
+        if !populate_user_buffer(filter as usize, core::mem::size_of::<SpawnFilter>(), MapPermission::R) {
+            return -EFAULT;
+        }
+        let mut spec = [SpawnFilter::default()];
+        copy_from_user(token, filter, &mut spec);
+        let [filter] = spec;
+        match read_filter(token, filter.ids as *const usize, filter.count, filter.action) {
+            Ok(filter) => Some(filter),
+            Err(err) => return err,
+        }
+    };
+    if shutting_down() {
+        return -EAGAIN;
+    }
+    let data = match get_app_data_by_name(path.as_str()) {
+        Ok(data) => data,
+        Err(err) => return app_error(err),
+    };
+    let task = current_or_esrch!(current_user_task());
+    let mut fd_table = task.inner_exclusive_access().fd_table.clone();
+    if let Err(err) = apply_spawn_actions(&mut fd_table, &spawn_actions[..n]) {
+        return err;
+    }
+    // the child starts with the filter of the caller
+    if let Some(filter) = filter.as_ref() {
+        if !may_set_syscall_filter(&task.inner_exclusive_access(), filter) {
+            return -EPERM;
+        }
+    }
+    let argc = args.len();
+    let new_task = match task.spawn(&path, data, args, fd_table) {
+        Some(task) => task,
+        None => return -EAGAIN,
+    };
+    if let Some(filter) = filter {
+        // before it runs its first instruction
+        set_syscall_filter(&mut new_task.inner_exclusive_access(), filter);
+    }
+    let new_pid = new_task.pid.0;
+    register_task(new_task.clone());
+    add_task(new_task);
+    kevent!(Spawn { parent: task.pid.0, child: new_pid, path, argc });
+    new_pid as isize
+}
+
+/// The task `pid` if it is the current task or one of its children, which
+/// may have exited but not been waited for yet
+pub(super) fn self_or_child(caller: &Arc<TaskControlBlock>, pid: usize) -> Result<Arc<TaskControlBlock>, isize> {
+    if caller.getpid() == pid {
+        return Ok(caller.clone());
+    }
+    let child = caller
+        .inner_exclusive_access()
+        .children
+        .iter()
+        .find(|child| child.getpid() == pid)
+        .cloned();
+    match child {
+        Some(child) => Ok(child),
+        None if pid2task(pid).is_some() => Err(-EPERM),
+        None => Err(-ESRCH),
+    }
+}
+
+/// Sample where task `pid`, the current task or a child of it, runs in user
+/// mode at each timer interrupt, keeping up to `max_samples`, at most
+/// [`crate::task::PROFILE_MAX_SAMPLES`], of them for [`sys_profile_stop`] to copy to
+/// `buf`. `-EBUSY` if it is being profiled already, `-ENOMEM` if the other
+/// profiles leave too little of [`crate::task::PROFILE_TOTAL_SAMPLES`]
+pub fn sys_profile_start(pid: usize, buf: *mut usize, max_samples: usize) -> isize {
+    let caller = current_or_esrch!(current_user_task());
+    let task = match self_or_child(&caller, pid) {
+        Ok(task) => task,
+        Err(err) => return err,
+    };
+    if max_samples == 0 {
+        return -EINVAL;
+    }
+    match profile_start(&task, caller.getpid(), buf as usize, max_samples) {
+        Ok(()) => 0,
+        Err(ProfileError::Busy) => -EBUSY,
+        Err(ProfileError::NoRoom) => -ENOMEM,
+    }
+}
+
+/// Stop the profile of task `pid` the current task started, copy its samples
+/// to the buffer given to [`sys_profile_start`] and return how many there
+/// are; the samples there was no room for go to `dropped` unless it is null.
+/// A child can be stopped after it exited, until it is waited for. `-EINVAL`
+/// if the caller did not start a profile of it
+pub fn sys_profile_stop(pid: usize, dropped: *mut usize) -> isize {
+    let caller = current_or_esrch!(current_user_task());
+    let task = match self_or_child(&caller, pid) {
+        Ok(task) => task,
+        Err(err) => return err,
+    };
+    // checked before the profile is stopped, so that it is not lost
+    if !dropped.is_null()
+        && !populate_user_buffer(dropped as usize, core::mem::size_of::<usize>(), MapPermission::W)
+    {
+        return -EFAULT;
+    }
+    let profile = match profile_stop(&task, caller.getpid()) {
+        Some(profile) => profile,
+        None => return -EINVAL,
+    };
+    let token = caller.get_user_token();
+    let samples = &profile.samples;
+    let size = samples.len() * core::mem::size_of::<usize>();
+    if !populate_user_buffer(profile.buf, size, MapPermission::W) {
+        return -EFAULT;
+    }
+    copy_to_user(token, profile.buf as *mut usize, samples);
+    if !dropped.is_null() {
+        copy_to_user(token, dropped, &[profile.dropped]);
+    }
+    samples.len() as isize
+}
+
+/// Block the current task for at least `ms` milliseconds, and at most
+/// `slack_ms` more if that lets it wake up along with other sleepers
+///
+/// If a signal interrupts it, the milliseconds left go to `rem` unless it is
+/// null, and a restart only sleeps for those.
+pub fn sys_sleep(ms: usize, rem: *mut usize, slack_ms: usize) -> isize {
+    let expire_ms = get_time_ms() + ms;
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    inner.wait = Some(Wait::Sleep);
+    inner.block_reason = Some(BlockReason::SleepUntil(expire_ms));
+    drop(inner);
+    add_timer(expire_ms, slack_ms, task.clone());
+    block_current_and_run_next();
+    if !take_interrupted() {
+        return 0;
+    }
+    let left = expire_ms.saturating_sub(get_time_ms());
+    if !rem.is_null() {
+        if let Err(err) = write_user(rem, left) {
+            return err;
+        }
+    }
+    // a restart sleeps for what is left
+    current_or_esrch!(current_trap_cx()).orig_a0 = left;
+    -ERESTARTSYS
+}
+
+/// Get the scheduling and CPU usage information of task `pid`, `-EFAULT`
+/// if `info` is not writable
+pub fn sys_process_info(pid: usize, info: *mut ProcessInfo) -> isize {
+    let mut kinfo = ProcessInfo {
+        pid: 0,
+        ppid: 0,
+        status: TaskStatus::Ready,
+        prio: 0,
+        cpu_time_ms: 0,
+        cpu_usage: 0,
+        mapped_pages: 0,
+        resident_pages: 0,
+        shared_pages: 0,
+        peak_resident_pages: 0,
+        free_frames: 0,
+        block_kind: 0,
+        block_arg: 0,
+        last_syscall: -1,
+        last_syscall_ms: 0,
+    };
+    if !get_process_info_inner(pid, &mut kinfo) {
+        return -ESRCH;
+    }
+    if !populate_user_buffer(info as usize, core::mem::size_of::<ProcessInfo>(), MapPermission::W) {
+        return -EFAULT;
+    }
+    copy_to_user(current_or_esrch!(current_user_token()), info, &[kinfo]);
+    0
+}
+
+pub fn sys_loadavg(avg: *mut LoadAvg) -> isize {
+    let mut idle_ms = [0; MAX_HARTS];
+    for (hart, idle) in idle_ms.iter_mut().enumerate() {
+        *idle = hart_state(hart).idle_us.load(Ordering::Relaxed) / 1000;
+    }
+    let kavg = LoadAvg {
+        load: get_load_average(),
+        idle_ms,
+    };
+    if !populate_user_buffer(avg as usize, core::mem::size_of::<LoadAvg>(), MapPermission::W) {
+        return -EFAULT;
+    }
+    copy_to_user(current_or_esrch!(current_user_token()), avg, &[kavg]);
+    0
+}
+
+/// Move up to `len` scheduler events to `buf` and the number of events lost
+/// to overflow since the last call to `dropped`, return the number of events
+/// moved
+///
+/// Only initproc and the shell may read the log, see [`is_privileged`].
+pub fn sys_sched_trace(buf: *mut SchedEvent, len: usize, dropped: *mut usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    if !is_privileged(&task) {
+        return -EPERM;
+    }
+    let token = current_or_esrch!(current_user_token());
+    let size = len * core::mem::size_of::<SchedEvent>();
+    // both checked before the log is drained, so that no event is lost
+    if !populate_user_buffer(buf as usize, size, MapPermission::W)
+        || !populate_user_buffer(dropped as usize, core::mem::size_of::<usize>(), MapPermission::W)
+    {
+        return -EFAULT;
     }
+    let (events, lost) = drain_sched_trace(len);
+    copy_to_user(token, buf, &events);
+    copy_to_user(token, dropped, &[lost]);
+    events.len() as isize
+}
+
+/// Restrict task `pid` (0 for the caller) to the harts in `mask`
+pub fn sys_sched_setaffinity(pid: usize, mask: usize) -> isize {
+    set_affinity_inner(pid, mask)
+}
+
+pub fn sys_sched_getaffinity(pid: usize, mask: *mut usize) -> isize {
+    match get_affinity_inner(pid) {
+        Some(cpu_mask) => match write_user(mask, cpu_mask) {
+            Ok(()) => 0,
+            Err(err) => err,
+        },
+        None => -ESRCH,
+    }
+}
+
+/// The hart the caller is running on
+pub fn sys_getcpu() -> isize {
+    hart_id() as isize
+}
+
+/// Start `hart`, which then takes tasks from the ready queue. 0 once it has
+/// come up, `-EINVAL` if it cannot be started
+///
+/// Only initproc and the shell may do that, like [`sys_cpu_down`], see
+/// [`is_privileged`].
+pub fn sys_cpu_up(hart: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    if !is_privileged(&task) {
+        return -EPERM;
+    }
+    if cpu_up(hart) {
+        0
+    } else {
+        -EINVAL
+    }
+}
+
+/// Take `hart` down, its tasks move to the other harts. 0 once it has
+/// stopped, or right away for the hart of the caller, which stops as the
+/// caller leaves it. `-EINVAL` for the boot hart or one that is not up
+pub fn sys_cpu_down(hart: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    if !is_privileged(&task) {
+        return -EPERM;
+    }
+    if cpu_down(hart) {
+        0
+    } else {
+        -EINVAL
+    }
+}
+
+/// Whether `ancestor` is `task` or one of its ancestors
+fn descends_from(task: &Arc<TaskControlBlock>, ancestor: &Arc<TaskControlBlock>) -> bool {
+    let mut task = task.clone();
+    loop {
+        if Arc::ptr_eq(&task, ancestor) {
+            return true;
+        }
+        let parent = task.inner_exclusive_access().parent.clone();
+        match parent.and_then(|parent| parent.upgrade()) {
+            Some(parent) => task = parent,
+            None => return false,
+        }
+    }
+}
+
+/// Send signal `signum` to task `pid`, which must be the caller or one of
+/// its descendants unless the caller is privileged, see [`is_privileged`].
+/// `-EPERM` for initproc, which nothing may signal
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => signal,
+        None => return -EINVAL,
+    };
+    let caller = current_or_esrch!(current_user_task());
+    let target = match pid2task(pid) {
+        Some(target) => target,
+        None => return -ESRCH,
+    };
+    if Arc::ptr_eq(&target, &INITPROC) {
+        return -EPERM;
+    }
+    if !is_privileged(&caller) && !descends_from(&target, &caller) {
+        return -EPERM;
+    }
+    send_signal(&target, signal);
+    0
+}
+
+/// Set the action for `signum`, the old one goes to `old_action` unless it is
+/// null
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    if signum == 0 || signum > MAX_SIG || action.is_null() {
+        return -EINVAL;
+    }
+    let signal = SignalFlags::from_signum(signum).unwrap();
+    if signal == SignalFlags::SIGKILL || signal == SignalFlags::SIGSTOP {
+        return -EINVAL;
+    }
+    let new_action = match read_user(action) {
+        Ok(action) => action,
+        Err(err) => return err,
+    };
+    let task = current_or_esrch!(current_user_task());
+    let old = task.inner_exclusive_access().signal_actions.table[signum];
+    if !old_action.is_null() {
+        if let Err(err) = write_user(old_action, old) {
+            return err;
+        }
+    }
+    task.inner_exclusive_access().signal_actions.table[signum] = new_action;
+    0
+}
+
+/// Change the blocked signals as `how` says with `set`, unless it is null,
+/// storing the old mask to `old_set` unless it is null. SIGKILL and SIGSTOP
+/// are left out of the mask. Signals it unblocks are handled on the way
+/// back to user mode, before this returns
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    let set = if set.is_null() {
+        None
+    } else {
+        match read_user(set) {
+            Ok(set) => Some(SignalFlags::from_bits_truncate(set)),
+            Err(err) => return err,
+        }
+    };
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let old = inner.signal_mask;
+    if let Some(set) = set {
+        inner.signal_mask = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old - set,
+            SIG_SETMASK => set,
+            _ => return -EINVAL,
+        } - SignalFlags::unblockable();
+    }
+    drop(inner);
+    if !old_set.is_null() {
+        if let Err(err) = write_user(old_set, old.bits()) {
+            return err;
+        }
+    }
+    0
+}
+
+/// Block until a signal is delivered, return `-EINTR` once its handler has
+/// run
+pub fn sys_pause() -> isize {
+    current_or_esrch!(current_user_task());
+    wait_for_signal();
+    -EINTR
+}
+
+/// Block with the signal mask replaced by `mask` until a signal is
+/// delivered, return `-EINTR` once its handler has run with the old mask back
+///
+/// Unlike sigprocmask followed by pause, a signal unblocked here that is
+/// already pending or sent in between is not lost.
+pub fn sys_sigsuspend(mask: *const u32) -> isize {
+    let mask = match read_user(mask) {
+        Ok(mask) => SignalFlags::from_bits_truncate(mask),
+        Err(err) => return err,
+    };
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    inner.saved_signal_mask = Some(inner.signal_mask);
+    inner.signal_mask = mask - SignalFlags::unblockable();
+    drop(inner);
+    drop(task);
+    wait_for_signal();
+    -EINTR
+}
+
+/// Return from a signal handler to the context it interrupted
+pub fn sys_sigreturn() -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let backup = match inner.trap_ctx_backup.take() {
+        Some(backup) => backup,
+        None => return -EINVAL,
+    };
+    inner.handling_sig = -1;
+    inner.signal_mask = inner.signal_mask_backup;
+    // if the handler did not fix the fault, the next trap will tell
+    inner.fault_retry = inner.fault_site.take();
+    let trap_cx = inner.get_trap_cx();
+    *trap_cx = backup;
+    // keep a0 of the interrupted context, the return value goes there
+    trap_cx.x[10] as isize
 }
@@ -0,0 +1,153 @@
+//! Mutex and semaphore syscalls
+
+use super::{EAGAIN, EBUSY, EDEADLK, EFAULT, EIDRM, EINVAL, ENOENT, EPERM, ERESTARTSYS};
+use crate::sync::{
+    mutex_close, mutex_create, mutex_lock, mutex_unlock, semaphore_close, semaphore_create,
+    semaphore_down, semaphore_open, semaphore_unlink, semaphore_up, LockError, SemError,
+    SEM_NAME_MAX,
+};
+use crate::task::{current_user_task, read_user_str};
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    MUTEX_CREATE [NO_BATCH] => |args| sys_mutex_create(args[0] != 0),
+    MUTEX_LOCK [BLOCKS] => |args| sys_mutex_lock(args[0]),
+    MUTEX_UNLOCK [NO_BATCH] => |args| sys_mutex_unlock(args[0]),
+    MUTEX_CLOSE [NO_BATCH] => |args| sys_mutex_close(args[0]),
+    SEMAPHORE_CREATE [NO_BATCH] => |args| sys_semaphore_create(args[0]),
+    SEMAPHORE_UP [NO_BATCH] => |args| sys_semaphore_up(args[0]),
+    SEMAPHORE_DOWN [BLOCKS] => |args| sys_semaphore_down(args[0]),
+    SEM_OPEN [NO_BATCH] => |args| sys_sem_open(args[0] as *const u8, args[1]),
+    SEM_UNLINK [NO_BATCH] => |args| sys_sem_unlink(args[0] as *const u8),
+    SEM_CLOSE [NO_BATCH] => |args| sys_sem_close(args[0]),
+};
+
+/// Create a mutex and return a handle to it, which the children forked from
+/// now on share. A `blocking` one always blocks a locker that finds it held,
+/// any other kind spins for a while first. `-EAGAIN` if there are
+/// [`crate::config::MUTEX_MAX`] mutexes already
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let id = match mutex_create(!blocking) {
+        Some(id) => id,
+        None => return -EAGAIN,
+    };
+    let handle = task.inner_exclusive_access().alloc_mutex(id);
+    handle as isize
+}
+
+/// Lock the mutex behind `handle`, blocking while another task holds it.
+/// `-EINVAL` if there is none, `-EDEADLK` if the caller holds it already
+pub fn sys_mutex_lock(handle: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let id = task.inner_exclusive_access().mutex(handle);
+    match id.ok_or(LockError::Invalid).and_then(|id| mutex_lock(id, &task)) {
+        Ok(()) => 0,
+        Err(LockError::Invalid) => -EINVAL,
+        Err(LockError::Deadlock) => -EDEADLK,
+        Err(LockError::Interrupted) => -ERESTARTSYS,
+    }
+}
+
+/// Unlock the mutex behind `handle`. `-EPERM` if the caller does not hold it
+pub fn sys_mutex_unlock(handle: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let id = task.inner_exclusive_access().mutex(handle);
+    match id {
+        Some(id) if mutex_unlock(id, &task) => 0,
+        _ => -EPERM,
+    }
+}
+
+/// Close `handle`, the mutex goes away with its last handle. `-EINVAL` if
+/// it is not open, `-EBUSY` if the caller holds the mutex
+pub fn sys_mutex_close(handle: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let id = match inner.mutex(handle) {
+        Some(id) if inner.held_mutexes.contains(&id) => return -EBUSY,
+        Some(id) => id,
+        None => return -EINVAL,
+    };
+    inner.mutexes[handle] = None;
+    drop(inner);
+    mutex_close(id);
+    0
+}
+
+/// Create an anonymous semaphore with `count` and return a handle to it,
+/// which the children forked from now on share
+pub fn sys_semaphore_create(count: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let id = semaphore_create(count);
+    let handle = task.inner_exclusive_access().alloc_semaphore(id);
+    handle as isize
+}
+
+/// Map a failed semaphore operation to its return value
+fn sem_error(err: SemError) -> isize {
+    match err {
+        SemError::Invalid => -EINVAL,
+        SemError::Interrupted => -ERESTARTSYS,
+        SemError::Dead => -EIDRM,
+    }
+}
+
+/// Raise the semaphore behind `handle`. `-EINVAL` if there is none, `-EIDRM` if it
+/// is dead
+pub fn sys_semaphore_up(handle: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let id = task.inner_exclusive_access().semaphore(handle);
+    match id.ok_or(SemError::Invalid).and_then(semaphore_up) {
+        Ok(()) => 0,
+        Err(err) => sem_error(err),
+    }
+}
+
+/// Take the semaphore behind `handle`, blocking until it is raised.
+/// `-EINVAL` if there is none, `-EIDRM` if nobody is left to raise it
+pub fn sys_semaphore_down(handle: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let id = task.inner_exclusive_access().semaphore(handle);
+    match id.ok_or(SemError::Invalid).and_then(|id| semaphore_down(id, &task)) {
+        Ok(()) => 0,
+        Err(err) => sem_error(err),
+    }
+}
+
+/// Open the semaphore called `name`, created with `count` if there is none,
+/// and return a handle to it. `-ENAMETOOLONG` if `name` is longer than
+/// [`SEM_NAME_MAX`], `-EINVAL` if it is empty
+pub fn sys_sem_open(name: *const u8, count: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let name = match read_user_str(name, SEM_NAME_MAX) {
+        Ok(name) if !name.is_empty() => name,
+        Ok(_) => return -EINVAL,
+        Err(err) => return err,
+    };
+    let id = semaphore_open(&name, count);
+    let handle = task.inner_exclusive_access().alloc_semaphore(id);
+    handle as isize
+}
+
+/// Remove the name `name`, the semaphore goes away with its last handle.
+/// `-ENOENT` if there is no such name
+pub fn sys_sem_unlink(name: *const u8) -> isize {
+    match read_user_str(name, SEM_NAME_MAX) {
+        Ok(name) if semaphore_unlink(&name) => 0,
+        Err(err) if err == -EFAULT => err,
+        _ => -ENOENT,
+    }
+}
+
+/// Close `handle`. `-EINVAL` if it is not open
+pub fn sys_sem_close(handle: usize) -> isize {
+    let task = current_or_esrch!(current_user_task());
+    let mut inner = task.inner_exclusive_access();
+    let id = match inner.semaphores.get_mut(handle).and_then(|slot| slot.take()) {
+        Some(id) => id,
+        None => return -EINVAL,
+    };
+    drop(inner);
+    semaphore_close(id);
+    0
+}
@@ -0,0 +1,137 @@
+//! What kernel build is running, and the apps built into it
+
+use super::EFAULT;
+use crate::config::{
+    CLOCK_FREQ, KERNEL_HEAP_SIZE, KERNEL_STACK_SIZE, KERNEL_STACK_SIZE_LARGE, MAX_HARTS, MAX_TASKS,
+    MEMORY_END,
+};
+use crate::console::input_dropped;
+use crate::fs::lent_pages;
+use crate::loader::app_list;
+use crate::logging::{suppressed_messages, suppressed_sites};
+use crate::mm::{copy_to_user, tlb, MapPermission};
+use crate::percpu::online_harts;
+use crate::timer::{timer_interrupts, timer_interrupts_per_sec, timers_coalesced};
+use crate::task::{
+    bad_enqueues, current_user_token, early_wakes, kernel_stack_peaks, populate_user_buffer,
+    queue_duplicates, resched_ipis,
+};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub(super) const HANDLERS: &[super::SyscallHandler] = handlers! {
+    SYSINFO [NO_BATCH] => |args| sys_sysinfo(args[0] as *mut u8, args[1]),
+    LIST_APPS [NO_BATCH] => |args| sys_list_apps(args[0] as *mut u8, args[1]),
+};
+
+/// `(name, enabled)` of every cargo feature, checked at compile time, then
+/// of the parts of the kernel that are always built in
+const FEATURES: &[(&str, bool)] = &[
+    ("board_qemu", cfg!(feature = "board_qemu")),
+    ("board_k210", cfg!(feature = "board_k210")),
+    // W^X is off, user pages may be writable and executable at once
+    ("allow_wx", cfg!(feature = "allow_wx")),
+    ("trace_uart", cfg!(feature = "trace_uart")),
+    ("kernel_test", cfg!(feature = "kernel_test")),
+    ("debug_assertions", cfg!(debug_assertions)),
+    ("smp", MAX_HARTS > 1),
+    ("hotplug", MAX_HARTS > 1),
+    ("signals", true),
+    ("lazy_mmap", true),
+    ("vdso", true),
+    ("asid", true),
+    ("pipe_zerocopy", true),
+    ("profile", true),
+    ("audit", true),
+    ("syscall_filter", true),
+    ("sched_trace", true),
+    ("checkpoint", true),
+    ("kevent", true),
+];
+
+fn board() -> &'static str {
+    if cfg!(feature = "board_k210") {
+        "k210"
+    } else if cfg!(feature = "board_qemu") {
+        "qemu"
+    } else {
+        "none"
+    }
+}
+
+/// One `key=value` line for each fact
+fn sysinfo_text() -> String {
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    let [normal_peak, large_peak] = kernel_stack_peaks();
+    let (tlb_flushes, tlb_flushes_skipped) = tlb::flush_counts();
+    format!(
+        "version={}\ncommit={}\nprofile={}\nboard={}\nfeatures={}\n\
+         clock_freq={}\nmemory_end={:#x}\nkernel_heap_size={:#x}\nmax_harts={}\nmax_tasks={}\n\
+         harts_online={}\nscheduler=stride\nkstack_sizes={},{}\nkstack_peaks={},{}\n\
+         pipe_lent_pages={}\nconsole_rx_dropped={}\nlog_suppressed={}\nlog_suppressed_sites={}\n\
+         timer_interrupts={}\ntimer_interrupts_per_sec={}\ntimers_coalesced={}\n\
+         bad_enqueues={}\nqueue_duplicates={}\nearly_wakes={}\n\
+         asid_bits={}\ntlb_flushes={}\ntlb_flushes_skipped={}\nresched_ipis={}\n",
+        env!("CARGO_PKG_VERSION"),
+        env!("GIT_COMMIT"),
+        env!("BUILD_PROFILE"),
+        board(),
+        features.join(","),
+        CLOCK_FREQ,
+        MEMORY_END,
+        KERNEL_HEAP_SIZE,
+        MAX_HARTS,
+        MAX_TASKS,
+        online_harts(),
+        KERNEL_STACK_SIZE,
+        KERNEL_STACK_SIZE_LARGE,
+        normal_peak,
+        large_peak,
+        lent_pages(),
+        input_dropped(),
+        suppressed_messages(),
+        suppressed_sites(),
+        timer_interrupts(),
+        timer_interrupts_per_sec(),
+        timers_coalesced(),
+        bad_enqueues(),
+        queue_duplicates(),
+        early_wakes(),
+        tlb::asid_bits(),
+        tlb_flushes,
+        tlb_flushes_skipped,
+        resched_ipis(),
+    )
+}
+
+/// Copy the build information to `buf` and return its full length
+///
+/// If it is longer than `len` only the lines that fit whole are copied.
+pub fn sys_sysinfo(buf: *mut u8, len: usize) -> isize {
+    copy_lines(sysinfo_text(), buf, len)
+}
+
+/// Copy the apps built in to `buf` like [`sys_sysinfo`], a line each with
+/// ` (stale)` after the name if the image does not match its checksum
+pub fn sys_list_apps(buf: *mut u8, len: usize) -> isize {
+    copy_lines(app_list(), buf, len)
+}
+
+fn copy_lines(text: String, buf: *mut u8, len: usize) -> isize {
+    let token = current_or_esrch!(current_user_token());
+    let fits = if text.len() <= len {
+        text.len()
+    } else {
+        text[..len].rfind('\n').map_or(0, |end| end + 1)
+    };
+    if !populate_user_buffer(buf as usize, fits, MapPermission::W) {
+        return -EFAULT;
+    }
+    copy_to_user(token, buf, &text.as_bytes()[..fits]);
+    text.len() as isize
+}
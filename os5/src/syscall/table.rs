@@ -0,0 +1,98 @@
+//! Every syscall of the ABI, shared with the user library
+//!
+//! `syscall_table!(callback)` calls `callback!` with one
+//! `NAME = number, argument count;` entry per syscall, including the ones
+//! only later chapters implement. The kernel takes its numbers, dispatcher
+//! and counters from it, and the build script of the user library reads the
+//! same entries for its `SYSCALL_*` constants, so keep each entry on a line
+//! of its own.
+
+macro_rules! syscall_table {
+    ($callback:ident) => {
+        $callback! {
+            GETCWD = 17, 2;
+            DUP = 23, 1;
+            DUP3 = 24, 3;
+            FCNTL = 25, 3;
+            UNLINKAT = 35, 3;
+            LINKAT = 37, 5;
+            CHDIR = 49, 1;
+            OPENAT = 56, 4;
+            CLOSE = 57, 1;
+            PIPE = 59, 2;
+            LSEEK = 62, 3;
+            READ = 63, 3;
+            WRITE = 64, 3;
+            PPOLL = 73, 4;
+            FSTAT = 80, 2;
+            EXIT = 93, 1;
+            SLEEP = 101, 3;
+            SCHED_SETAFFINITY = 122, 2;
+            SCHED_GETAFFINITY = 123, 2;
+            YIELD = 124, 0;
+            KILL = 129, 2;
+            SIGSUSPEND = 133, 1;
+            SIGACTION = 134, 3;
+            SIGPROCMASK = 135, 3;
+            SIGRETURN = 139, 0;
+            SET_PRIORITY = 140, 1;
+            PRCTL = 167, 2;
+            GETCPU = 168, 0;
+            GETTIMEOFDAY = 169, 2;
+            GETPID = 172, 0;
+            GETPPID = 173, 0;
+            GETTID = 178, 0;
+            MUNMAP = 215, 2;
+            FORK = 220, 0;
+            EXEC = 221, 2;
+            MMAP = 222, 3;
+            MPROTECT = 226, 3;
+            MADVISE = 233, 3;
+            WAIT4 = 260, 4;
+            SPAWN = 400, 1;
+            MAIL_READ = 401, 2;
+            MAIL_WRITE = 402, 3;
+            TASK_INFO = 410, 2;
+            PROCESS_INFO = 411, 2;
+            LOADAVG = 412, 1;
+            SCHED_TRACE = 413, 3;
+            SYSINFO = 414, 2;
+            BATCH = 415, 3;
+            PAUSE = 416, 0;
+            SHUTDOWN = 417, 1;
+            FD_INFO = 418, 3;
+            CPU_UP = 419, 1;
+            CPU_DOWN = 420, 1;
+            SEM_OPEN = 421, 2;
+            SEM_UNLINK = 422, 1;
+            SEM_CLOSE = 423, 1;
+            MAP_SELF = 424, 1;
+            PROFILE_START = 425, 3;
+            PROFILE_STOP = 426, 2;
+            PAGEMAP = 427, 3;
+            REBOOT = 428, 2;
+            KEVENT_FILTER = 429, 2;
+            SPAWNV = 430, 5;
+            VMA_LIST = 431, 3;
+            AUDIT = 432, 2;
+            AUDIT_READ = 433, 3;
+            SET_SYSCALL_FILTER = 434, 3;
+            CHECKPOINT = 435, 1;
+            RESTORE = 436, 1;
+            MUTEX_CLOSE = 437, 1;
+            LIST_APPS = 438, 2;
+            THREAD_CREATE = 460, 2;
+            WAITTID = 462, 1;
+            MUTEX_CREATE = 463, 1;
+            MUTEX_LOCK = 464, 1;
+            MUTEX_UNLOCK = 466, 1;
+            SEMAPHORE_CREATE = 467, 1;
+            SEMAPHORE_UP = 468, 1;
+            ENABLE_DEADLOCK_DETECT = 469, 1;
+            SEMAPHORE_DOWN = 470, 1;
+            CONDVAR_CREATE = 471, 1;
+            CONDVAR_SIGNAL = 472, 1;
+            CONDVAR_WAIT = 473, 2;
+        }
+    };
+}
@@ -0,0 +1,204 @@
+//! Auditing the syscalls of a task with the buffers they were passed
+//!
+//! An audit started for a task keeps a ring of [`AuditRecord`]s, one for
+//! each syscall of it that takes a buffer or a path, with the first
+//! [`AUDIT_DATA`] bytes of it as they were when the syscall started. The
+//! ring is allocated up front and charged to the task that started the
+//! audit, which alone can read it, until the audited task is waited for.
+//! With no audit recording anywhere a syscall looks at one counter and goes
+//! on.
+
+use super::task::TaskControlBlockInner;
+use super::current_user_task;
+use crate::mm::{PTEFlags, PageTable, VirtAddr};
+use crate::timer::get_time_us;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes of a buffer a record keeps
+pub const AUDIT_DATA: usize = 256;
+/// Most records a ring holds
+pub const AUDIT_MAX_RECORDS: usize = 64;
+/// Most bytes of rings one task may have started at a time
+pub const AUDIT_MAX_BYTES: usize = 64 * 1024;
+
+/// Audits recording in the whole system
+static RECORDING: AtomicUsize = AtomicUsize::new(0);
+
+/// One audited syscall as handed to user space
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct AuditRecord {
+    /// Counts the records of the ring, a gap shows that older ones were
+    /// overwritten
+    pub seq: usize,
+    pub id: usize,
+    pub args: [usize; 6],
+    pub result: isize,
+    /// When the syscall started
+    pub time_us: usize,
+    /// Bytes of `data` captured, fewer than asked for where the buffer was
+    /// not mapped, and a path without its NUL
+    pub len: usize,
+    pub data: [u8; AUDIT_DATA],
+}
+
+/// What a syscall was passed that an audit captures
+#[derive(Copy, Clone)]
+pub enum AuditBuffer {
+    /// `len` bytes at `ptr`
+    Bytes(usize, usize),
+    /// A NUL-terminated string at `ptr`
+    Str(usize),
+}
+
+pub struct Audit {
+    /// Pid of the task that started it, the only one that may read it
+    pub owner: usize,
+    records: VecDeque<AuditRecord>,
+    capacity: usize,
+    seq: usize,
+    /// Still recording, until stopped or the task exits
+    recording: bool,
+    /// Bytes the owner has been charged for rings, this one included
+    charge: Arc<AtomicUsize>,
+}
+
+impl Audit {
+    fn bytes(&self) -> usize {
+        self.capacity * core::mem::size_of::<AuditRecord>()
+    }
+    /// Move up to `max` of the oldest records out of the ring
+    pub fn drain(&mut self, max: usize) -> VecDeque<AuditRecord> {
+        let rest = self.records.split_off(max.min(self.records.len()));
+        core::mem::replace(&mut self.records, rest)
+    }
+}
+
+impl Drop for Audit {
+    fn drop(&mut self) {
+        if self.recording {
+            RECORDING.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.charge.fetch_sub(self.bytes(), Ordering::Relaxed);
+    }
+}
+
+/// Why [`audit_start`] failed
+pub enum AuditError {
+    /// The task is being audited already
+    Busy,
+    /// The ring would take the owner over [`AUDIT_MAX_BYTES`]
+    NoMemory,
+}
+
+/// Start recording up to `records`, at most [`AUDIT_MAX_RECORDS`], of the
+/// syscalls of `inner` for `owner`, whose rings take `charge` bytes. A ring
+/// that stopped recording is dropped with what it still holds
+pub fn audit_start(
+    inner: &mut TaskControlBlockInner,
+    owner: usize,
+    charge: &Arc<AtomicUsize>,
+    records: usize,
+) -> Result<(), AuditError> {
+    if inner.audit.as_ref().map_or(false, |audit| audit.recording) {
+        return Err(AuditError::Busy);
+    }
+    inner.audit = None;
+    let capacity = records.min(AUDIT_MAX_RECORDS);
+    let bytes = capacity * core::mem::size_of::<AuditRecord>();
+    if charge.fetch_add(bytes, Ordering::Relaxed) + bytes > AUDIT_MAX_BYTES {
+        charge.fetch_sub(bytes, Ordering::Relaxed);
+        return Err(AuditError::NoMemory);
+    }
+    inner.audit = Some(Audit {
+        owner,
+        records: VecDeque::with_capacity(capacity),
+        capacity,
+        seq: 0,
+        recording: true,
+        charge: charge.clone(),
+    });
+    RECORDING.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stop recording the syscalls of `inner`, which keeps its ring for the
+/// owner, as the task exits or the owner asks
+pub fn audit_stop(inner: &mut TaskControlBlockInner) {
+    if let Some(audit) = inner.audit.as_mut() {
+        if audit.recording {
+            audit.recording = false;
+            RECORDING.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Whether any task is being audited, before a syscall looks further
+pub fn auditing() -> bool {
+    RECORDING.load(Ordering::Relaxed) != 0
+}
+
+/// Copy what the current task passes in `buffer` into a record of syscall
+/// `id`, `None` unless its syscalls are being recorded
+pub fn audit_begin(id: usize, args: [usize; 6], buffer: AuditBuffer) -> Option<AuditRecord> {
+    let task = current_user_task()?;
+    let mut inner = task.inner_exclusive_access();
+    let audit = inner.audit.as_mut().filter(|audit| audit.recording)?;
+    let mut record = AuditRecord {
+        seq: audit.seq,
+        id,
+        args,
+        result: 0,
+        time_us: get_time_us(),
+        len: 0,
+        data: [0; AUDIT_DATA],
+    };
+    audit.seq += 1;
+    drop(inner);
+    record.len = match buffer {
+        AuditBuffer::Bytes(ptr, len) => snapshot(task.get_user_token(), ptr, len, &mut record.data, false),
+        AuditBuffer::Str(ptr) => snapshot(task.get_user_token(), ptr, AUDIT_DATA, &mut record.data, true),
+    };
+    Some(record)
+}
+
+/// Put `record` in the ring of the current task with the `result` of its
+/// syscall, overwriting the oldest record if it is full
+pub fn audit_end(mut record: AuditRecord, result: isize) {
+    let task = match current_user_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let mut inner = task.inner_exclusive_access();
+    // gone if the owner started another one meanwhile
+    if let Some(audit) = inner.audit.as_mut().filter(|audit| audit.recording) {
+        if audit.records.len() == audit.capacity {
+            audit.records.pop_front();
+        }
+        record.result = result;
+        audit.records.push_back(record);
+    }
+}
+
+/// Copy up to `len` bytes at `ptr` in the space of `token` into `data`, up
+/// to the first byte not mapped readable for the user or, for a string, up
+/// to its NUL. Return how many were copied
+fn snapshot(token: usize, ptr: usize, len: usize, data: &mut [u8; AUDIT_DATA], string: bool) -> usize {
+    let page_table = PageTable::from_token(token);
+    for (i, byte) in data.iter_mut().take(len).enumerate() {
+        let va = VirtAddr::from(ptr.wrapping_add(i));
+        let readable = page_table.translate(va.floor()).map_or(false, |pte| {
+            pte.is_valid() && pte.readable() && pte.flags().contains(PTEFlags::U)
+        });
+        if !readable {
+            return i;
+        }
+        *byte = *page_table.translate_va(va).unwrap().get_ref::<u8>();
+        if string && *byte == 0 {
+            return i;
+        }
+    }
+    len.min(AUDIT_DATA)
+}
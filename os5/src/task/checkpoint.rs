@@ -0,0 +1,294 @@
+//! Saving a process to a ramfs file and starting it again from there
+//!
+//! A checkpoint is cooperative: the process saves itself, in the middle of
+//! the syscall, and only what is its alone, the registers, the private
+//! memory of its areas and its signal state. Anything shared with other
+//! tasks, files but stdio, semaphores, mutexes, shared mappings, makes
+//! the checkpoint fail rather than going missing. A restored process is a
+//! new child of the task restoring it, with fresh stdio, which goes on after
+//! the ecall of the checkpoint as if it returned 1 rather than 0.
+//!
+//! The image is a sequence of little-endian 64-bit words:
+//!
+//! ```text
+//! magic, version, name length, name bytes,
+//! sepc, x0..x31, signal mask, pending signals,
+//! handler, mask, flags of each signal 0..=MAX_SIG,
+//! area count, then for each area
+//!     start vpn, end vpn, permission, kind, lazy, page count,
+//!     then for each page its vpn and PAGE_SIZE bytes
+//! ```
+
+use super::signal::{SignalActions, SignalFlags};
+use super::task::stdio_fds;
+use super::TaskControlBlock;
+use crate::config::{PAGE_SIZE, RAMFS_FILE_MAX};
+use crate::fs::{read_ram_file, write_ram_file};
+use crate::mm::{
+    frame_allocator_free, reclaim, AreaImage, AreaKind, MapPermission, Memory, MemorySet,
+    VirtPageNum,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+/// "rCoreCkp"
+const CHECKPOINT_MAGIC: usize = 0x706b_4365_726f_4372;
+/// Bumped whenever the layout changes, older images are refused
+const CHECKPOINT_VERSION: usize = 1;
+
+/// Why a checkpoint or a restore failed
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CheckpointError {
+    /// The task holds something that cannot be saved, or is in a signal
+    /// handler
+    Busy,
+    /// The image does not fit in the ramfs
+    TooLarge,
+    /// No such file
+    NotFound,
+    /// The file is no image of this version, or one that does not make a
+    /// user space
+    BadImage,
+    /// Not enough frames for the restored space
+    NoMemory,
+    /// No pid left for the restored task
+    NoPid,
+}
+
+fn kind_code(kind: AreaKind) -> usize {
+    match kind {
+        AreaKind::Elf => 0,
+        AreaKind::Stack => 1,
+        AreaKind::Mmap => 2,
+        _ => unreachable!("{:?} area in a checkpoint", kind),
+    }
+}
+
+fn kind_from_code(code: usize) -> Option<AreaKind> {
+    match code {
+        0 => Some(AreaKind::Elf),
+        1 => Some(AreaKind::Stack),
+        2 => Some(AreaKind::Mmap),
+        _ => None,
+    }
+}
+
+/// The saved state of a process, see the module documentation
+struct Checkpoint<'a> {
+    name: &'a str,
+    sepc: usize,
+    regs: [usize; 32],
+    signal_mask: SignalFlags,
+    signals: SignalFlags,
+    signal_actions: SignalActions,
+    areas: Vec<AreaImage<'a>>,
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn word(&mut self, word: usize) {
+        self.0.extend_from_slice(&(word as u64).to_le_bytes());
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+    fn word(&mut self) -> Option<usize> {
+        let bytes = self.bytes(8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?) as usize)
+    }
+    fn signals(&mut self) -> Option<SignalFlags> {
+        SignalFlags::from_bits(self.word()?.try_into().ok()?)
+    }
+}
+
+impl<'a> Checkpoint<'a> {
+    fn write(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::new());
+        out.word(CHECKPOINT_MAGIC);
+        out.word(CHECKPOINT_VERSION);
+        out.word(self.name.len());
+        out.bytes(self.name.as_bytes());
+        out.word(self.sepc);
+        for &reg in self.regs.iter() {
+            out.word(reg);
+        }
+        out.word(self.signal_mask.bits() as usize);
+        out.word(self.signals.bits() as usize);
+        for action in self.signal_actions.table.iter() {
+            out.word(action.handler);
+            out.word(action.mask.bits() as usize);
+            out.word(action.flags as usize);
+        }
+        out.word(self.areas.len());
+        for area in self.areas.iter() {
+            out.word(area.start.0);
+            out.word(area.end.0);
+            out.word(area.perm.bits() as usize);
+            out.word(kind_code(area.kind));
+            out.word(area.lazy as usize);
+            out.word(area.pages.len());
+            for &(vpn, bytes) in area.pages.iter() {
+                out.word(vpn.0);
+                out.bytes(bytes);
+            }
+        }
+        out.0
+    }
+    /// The checkpoint in `data`, `None` if it is not one of this version.
+    /// Whether its areas make a user space is up to
+    /// [`MemorySet::from_images`]
+    fn read(data: &'a [u8]) -> Option<Self> {
+        let mut input = Reader { data, pos: 0 };
+        if input.word()? != CHECKPOINT_MAGIC || input.word()? != CHECKPOINT_VERSION {
+            return None;
+        }
+        let len = input.word()?;
+        let name = core::str::from_utf8(input.bytes(len)?).ok()?;
+        let sepc = input.word()?;
+        let mut regs = [0; 32];
+        for reg in regs.iter_mut() {
+            *reg = input.word()?;
+        }
+        // SIGKILL and SIGSTOP are neither blocked nor left pending
+        let signal_mask = input.signals()? - SignalFlags::unblockable();
+        let signals = input.signals()? - SignalFlags::unblockable();
+        let mut signal_actions = SignalActions::default();
+        for action in signal_actions.table.iter_mut() {
+            action.handler = input.word()?;
+            action.mask = input.signals()?;
+            action.flags = input.word()?.try_into().ok()?;
+        }
+        // each area takes six words at least, a bogus count runs out of data
+        let count = input.word()?;
+        let mut areas = Vec::new();
+        for _ in 0..count {
+            let start = VirtPageNum(input.word()?);
+            let end = VirtPageNum(input.word()?);
+            let perm = MapPermission::from_bits(input.word()?.try_into().ok()?)?;
+            let kind = kind_from_code(input.word()?)?;
+            let lazy = match input.word()? {
+                0 => false,
+                1 => true,
+                _ => return None,
+            };
+            let mut pages = Vec::new();
+            for _ in 0..input.word()? {
+                let vpn = VirtPageNum(input.word()?);
+                pages.push((vpn, input.bytes(PAGE_SIZE)?));
+            }
+            areas.push(AreaImage {
+                start,
+                end,
+                perm,
+                kind,
+                lazy,
+                pages,
+            });
+        }
+        if input.pos != data.len() {
+            return None;
+        }
+        Some(Self {
+            name,
+            sepc,
+            regs,
+            signal_mask,
+            signals,
+            signal_actions,
+            areas,
+        })
+    }
+}
+
+/// Save `task`, the current task in the middle of its checkpoint syscall,
+/// to the ramfs file `file`, replacing what it held. A restored copy goes
+/// on after that syscall with a0 1
+pub fn checkpoint(task: &Arc<TaskControlBlock>, file: &str) -> Result<(), CheckpointError> {
+    let inner = task.inner_exclusive_access();
+    let busy = inner.fd_table.iter().skip(3).any(Option::is_some)
+        || inner.semaphores.iter().any(Option::is_some)
+        || inner.mutexes.iter().any(Option::is_some)
+        || inner.handling_sig != -1
+        || Arc::strong_count(&task.memory_set) > 1;
+    if busy {
+        return Err(CheckpointError::Busy);
+    }
+    let trap_cx = inner.get_trap_cx();
+    let memory_set = task.memory_set.exclusive_access();
+    let areas = memory_set.area_images().ok_or(CheckpointError::Busy)?;
+    // no copy on the heap of an image that cannot be written anyway
+    let pages: usize = areas.iter().map(|area| area.pages.len()).sum();
+    if pages * (PAGE_SIZE + 8) > RAMFS_FILE_MAX {
+        return Err(CheckpointError::TooLarge);
+    }
+    let image = Checkpoint {
+        name: &inner.name,
+        sepc: trap_cx.syscall_sepc + 4,
+        regs: trap_cx.x,
+        signal_mask: inner.signal_mask,
+        signals: inner.signals - SignalFlags::unblockable(),
+        signal_actions: inner.signal_actions.clone(),
+        areas,
+    }
+    .write();
+    if !write_ram_file(file, &image) {
+        return Err(CheckpointError::TooLarge);
+    }
+    info!(
+        "[kernel] checkpoint of pid {} to {}: {} pages, {} bytes",
+        task.getpid(),
+        file,
+        pages,
+        image.len()
+    );
+    Ok(())
+}
+
+/// A child of `parent` running the process saved to the ramfs file `file`,
+/// not yet registered nor in the ready queue
+pub fn restore(
+    parent: &Arc<TaskControlBlock>,
+    file: &str,
+) -> Result<Arc<TaskControlBlock>, CheckpointError> {
+    let data = read_ram_file(file).ok_or(CheckpointError::NotFound)?;
+    let image = Checkpoint::read(&data).ok_or(CheckpointError::BadImage)?;
+    let needed = MemorySet::image_frames(&image.areas);
+    if frame_allocator_free() < needed {
+        reclaim(Memory::Frames);
+        if frame_allocator_free() < needed {
+            return Err(CheckpointError::NoMemory);
+        }
+    }
+    let memory_set = MemorySet::from_images(&image.areas).ok_or(CheckpointError::BadImage)?;
+    let child = parent
+        .spawn_from(image.name, memory_set, stdio_fds())
+        .ok_or(CheckpointError::NoPid)?;
+    let mut inner = child.inner_exclusive_access();
+    inner.signal_mask = image.signal_mask;
+    inner.signals = image.signals;
+    inner.signal_actions = image.signal_actions;
+    let trap_cx = inner.get_trap_cx();
+    trap_cx.x = image.regs;
+    trap_cx.x[0] = 0;
+    // what the checkpoint returns this time
+    trap_cx.x[10] = 1;
+    trap_cx.sepc = image.sepc;
+    drop(inner);
+    Ok(child)
+}
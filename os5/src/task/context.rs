@@ -15,13 +15,21 @@ pub struct TaskContext {
 }
 
 impl TaskContext {
-    pub fn zero_init() -> Self {
+    pub const fn zero_init() -> Self {
         Self {
             ra: 0,
             sp: 0,
             s: [0; 12],
         }
     }
+    /// Start running `entry` on the kernel stack at `kstack_ptr`
+    pub fn goto(entry: usize, kstack_ptr: usize) -> Self {
+        Self {
+            ra: entry,
+            sp: kstack_ptr,
+            s: [0; 12],
+        }
+    }
     pub fn goto_trap_return(kstack_ptr: usize) -> Self {
         Self {
             ra: trap_return as usize,
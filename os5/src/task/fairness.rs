@@ -0,0 +1,246 @@
+//! Repeatable workloads to judge the fairness of the scheduler by
+//!
+//! With the `kernel_test` feature [`fairness_test`] runs each [`SCRIPTS`]
+//! entry at boot. Its tasks are kernel-only TCBs like the idle tasks, which
+//! never really run: a virtual clock ticks [`TICKS`] times, and each tick
+//! goes to the task [`TaskManager::fetch`] picks, which computes or, at the
+//! end of its burst, goes to sleep. Nothing depends on the timer or the
+//! other harts, so a run always comes out the same.
+//!
+//! This kernel has neither a real-time class nor aging: the RT task is a
+//! stride task at the top priority, and the bound on starvation is how long
+//! a ready task may wait for its pass to come round.
+
+use super::manager::{stride, TaskManager};
+use super::TaskControlBlock;
+use crate::percpu::hart_id;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Ticks each script runs for
+const TICKS: usize = 1000;
+/// How far, in percent, the shares of the tasks that never sleep may be off
+/// what their priorities give them
+const SHARE_TOLERANCE: usize = 10;
+
+struct TaskScript {
+    name: &'static str,
+    prio: isize,
+    /// Ticks of computing before each sleep
+    burst: usize,
+    /// Ticks of each sleep, 0 for a task that always computes
+    sleep: usize,
+    /// Ticks it may be ready without running
+    max_wait: usize,
+}
+
+const fn batch(name: &'static str, prio: isize, max_wait: usize) -> TaskScript {
+    TaskScript {
+        name,
+        prio,
+        burst: TICKS,
+        sleep: 0,
+        max_wait,
+    }
+}
+
+struct Script {
+    name: &'static str,
+    tasks: &'static [TaskScript],
+    /// Context switches expected, in percent of [`TICKS`]
+    switches: (usize, usize),
+}
+
+const SCRIPTS: &[Script] = &[
+    Script {
+        name: "all equal",
+        tasks: &[batch("a", 16, 6), batch("b", 16, 6), batch("c", 16, 6), batch("d", 16, 6)],
+        switches: (90, 100),
+    },
+    Script {
+        name: "2:1 priorities",
+        tasks: &[batch("high", 4, 2), batch("low", 2, 4)],
+        switches: (55, 75),
+    },
+    Script {
+        name: "one RT task",
+        tasks: &[batch("rt", 127, 4), batch("a", 16, 12), batch("b", 16, 12), batch("c", 16, 12)],
+        switches: (40, 60),
+    },
+    Script {
+        name: "interactive vs batch",
+        tasks: &[
+            TaskScript {
+                name: "shell",
+                prio: 16,
+                burst: 1,
+                sleep: 4,
+                max_wait: 1,
+            },
+            batch("make", 16, 3),
+            batch("cc", 16, 3),
+        ],
+        switches: (90, 100),
+    },
+];
+
+/// A synthetic task and what it got so far
+struct Sim {
+    task: Arc<TaskControlBlock>,
+    script: &'static TaskScript,
+    /// Ticks left of the burst
+    left: usize,
+    ran: usize,
+    /// Tick it became ready, None while it runs or sleeps
+    ready_since: Option<usize>,
+    wake_at: Option<usize>,
+    longest_wait: usize,
+}
+
+fn never_runs() -> ! {
+    unreachable!("a synthetic task was switched to");
+}
+
+impl Sim {
+    fn new(script: &'static TaskScript, hart: usize) -> Self {
+        let task = Arc::new(TaskControlBlock::new_idle(hart, never_runs));
+        let mut inner = task.inner_exclusive_access();
+        inner.name = String::from(script.name);
+        inner.prio = script.prio;
+        inner.pass = 0;
+        drop(inner);
+        Self {
+            task,
+            script,
+            left: script.burst,
+            ran: 0,
+            ready_since: None,
+            wake_at: None,
+            longest_wait: 0,
+        }
+    }
+    fn enqueue(&mut self, manager: &mut TaskManager, now: usize) {
+        self.task.inner_exclusive_access().on_queue = true;
+        self.ready_since = Some(now);
+        manager.add(self.task.clone());
+    }
+    fn stride(&self) -> isize {
+        stride(self.script.prio)
+    }
+}
+
+/// Run `script` for [`TICKS`], return its tasks and the context switches
+fn run(script: &'static Script) -> (Vec<Sim>, usize) {
+    let hart = hart_id();
+    let mut manager = TaskManager::new();
+    let mut sims: Vec<Sim> = script.tasks.iter().map(|task| Sim::new(task, hart)).collect();
+    for sim in sims.iter_mut() {
+        sim.enqueue(&mut manager, 0);
+    }
+    let mut current: Option<usize> = None;
+    let mut last = None;
+    let mut switches = 0;
+    for now in 0..TICKS {
+        for sim in sims.iter_mut().filter(|sim| sim.wake_at == Some(now)) {
+            sim.wake_at = None;
+            sim.left = sim.script.burst;
+            sim.enqueue(&mut manager, now);
+        }
+        // the tick preempts the running task, as the timer does
+        if let Some(i) = current.take() {
+            sims[i].enqueue(&mut manager, now);
+        }
+        let i = match manager.fetch(hart) {
+            Some(task) => sims.iter().position(|sim| Arc::ptr_eq(&sim.task, &task)).unwrap(),
+            None => {
+                last = None;
+                continue;
+            }
+        };
+        if last != Some(i) {
+            switches += 1;
+        }
+        last = Some(i);
+        let sim = &mut sims[i];
+        let waited = now - sim.ready_since.take().unwrap();
+        sim.longest_wait = sim.longest_wait.max(waited);
+        sim.ran += 1;
+        sim.left -= 1;
+        if sim.left > 0 {
+            current = Some(i);
+        } else if sim.script.sleep > 0 {
+            sim.wake_at = Some(now + 1 + sim.script.sleep);
+        } else {
+            sim.left = sim.script.burst;
+            current = Some(i);
+        }
+    }
+    // a task still waiting at the end counts too
+    for sim in sims.iter_mut() {
+        if let Some(since) = sim.ready_since {
+            sim.longest_wait = sim.longest_wait.max(TICKS - since);
+        }
+    }
+    (sims, switches)
+}
+
+/// What is wrong with the outcome of `script`, if anything
+fn verdict(script: &Script, sims: &[Sim], switches: usize) -> Option<String> {
+    // a task that never sleeps gets ticks in inverse proportion to its
+    // stride, so ticks times stride is the same for all of them
+    let normalized = sims
+        .iter()
+        .filter(|sim| sim.script.sleep == 0)
+        .map(|sim| sim.ran * sim.stride() as usize);
+    let (min, max) = normalized.fold((usize::MAX, 0), |(min, max), n| (min.min(n), max.max(n)));
+    if max * 100 > min * (100 + SHARE_TOLERANCE) {
+        return Some(format!("shares off their priorities by more than {}%", SHARE_TOLERANCE));
+    }
+    if let Some(sim) = sims.iter().find(|sim| sim.longest_wait > sim.script.max_wait) {
+        return Some(format!(
+            "{} waited {} ticks, at most {} allowed",
+            sim.script.name, sim.longest_wait, sim.script.max_wait
+        ));
+    }
+    let (low, high) = script.switches;
+    if switches * 100 < low * TICKS || switches * 100 > high * TICKS {
+        return Some(format!(
+            "{} context switches, expected {}% to {}% of {} ticks",
+            switches, low, high, TICKS
+        ));
+    }
+    None
+}
+
+fn print_table(sims: &[Sim], switches: usize) {
+    println!("  task      prio  stride  ticks  share  longest wait");
+    for sim in sims {
+        println!(
+            "  {:<8} {:>5} {:>7} {:>6} {:>5}% {:>13}",
+            sim.script.name,
+            sim.script.prio,
+            sim.stride(),
+            sim.ran,
+            sim.ran * 100 / TICKS,
+            sim.longest_wait
+        );
+    }
+    println!("  {} context switches in {} ticks", switches, TICKS);
+}
+
+/// Run every script, printing the share table of a failing one before
+/// panicking
+pub fn fairness_test() {
+    for script in SCRIPTS {
+        let (sims, switches) = run(script);
+        if let Some(reason) = verdict(script, &sims, switches) {
+            println!("[kernel] fairness script \"{}\" failed: {}", script.name, reason);
+            print_table(&sims, switches);
+            panic!("fairness_test: {}", script.name);
+        }
+        info!("fairness script \"{}\" passed", script.name);
+    }
+    info!("fairness_test passed!");
+}
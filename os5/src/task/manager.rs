@@ -4,18 +4,32 @@
 //! Other CPU process monitoring functions are in Processor.
 
 
-use super::TaskControlBlock;
+use super::{TaskControlBlock, TaskControlBlockInner, TaskStatus};
 use crate::sync::UPSafeCell;
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 use alloc::collections::BinaryHeap;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use lazy_static::*;
 
-use crate::config::BIG_STRIDE;
+use crate::config::{BIG_STRIDE, MAX_HARTS};
+use crate::percpu::{hart_id, hart_state, online_mask};
+use crate::sbi::send_ipi;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fixed-point shift of the load average, as in Linux
+const FSHIFT: usize = 16;
+/// 1.0 in load average fixed point
+const FIXED_1: usize = 1 << FSHIFT;
+/// `FIXED_1 * exp(-1 / (TICKS_PER_SEC * period))` for periods of 1, 5 and 15
+/// seconds, with TICKS_PER_SEC = 100
+const LOAD_EXP: [usize; 3] = [64884, 65405, 65492];
 
 pub struct TaskManager {
     ready_queue: BinaryHeap<Arc<TaskControlBlock>>,
+    /// Load average over 1, 5 and 15 seconds, in FSHIFT fixed point
+    loadavg: [usize; 3],
 }
 
 // YOUR JOB: FIFO->Stride
@@ -24,25 +38,89 @@ impl TaskManager {
     pub fn new() -> Self {
         Self {
             ready_queue: BinaryHeap::new(),
+            loadavg: [0; 3],
         }
     }
     /// Add process back to ready queue
+    ///
+    /// Debug builds also look for the very same task in it, not only at the
+    /// `on_queue` flag [`add_task`] checks: it is counted in
+    /// [`queue_duplicates`] and not added again.
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        #[cfg(debug_assertions)]
+        {
+            let mut queued = self.ready_queue.iter();
+            if queued.any(|other| Arc::ptr_eq(other, &task)) {
+                QUEUE_DUPLICATES.fetch_add(1, Ordering::Relaxed);
+                error!("[kernel] pid {} is in the ready queue already", task.pid.0);
+                return;
+            }
+        }
         self.ready_queue.push(task);
-        let a = self.ready_queue.clone().into_iter().map(|x| x.clone().pid.0);
-        let b = a.collect::<Vec<usize>>();
-        //info!("after add: {:?}", b);
-    }
-    /// Take a process out of the ready queue
-    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        let a = self.ready_queue.pop();
-        let tcb = a.clone().unwrap();
-        let pid = tcb.pid.0;
+    }
+    /// Take a process allowed to run on `hart` out of the ready queue
+    ///
+    /// Tasks pinned elsewhere that are passed over go back to the queue with
+    /// their pass untouched, so they are still first in line for their harts.
+    pub fn fetch(&mut self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        let online = online_mask();
+        let mut skipped = Vec::new();
+        let a = loop {
+            match self.ready_queue.pop() {
+                Some(task) if !may_run_on(&task, hart, online) => skipped.push(task),
+                other => break other,
+            }
+        };
+        self.ready_queue.extend(skipped);
+        let tcb = a.clone()?;
         let mut inner = tcb.inner_exclusive_access();
+        inner.on_queue = false;
         //info!("fetch pid: {:?} and pass is {:?}", pid, inner.pass);
-        inner.pass += BIG_STRIDE / inner.prio;
+        inner.pass += stride(inner.effective_prio());
         a
     }
+    pub fn has_ready(&self) -> bool {
+        !self.ready_queue.is_empty()
+    }
+    /// Drop every task in the ready queue
+    pub fn clear(&mut self) {
+        self.ready_queue.clear();
+    }
+    /// Sample the number of runnable tasks into the load average, with
+    /// `running` tasks currently on a processor
+    pub fn sample_load(&mut self, running: usize) {
+        let n = self.ready_queue.len() + running;
+        for (load, exp) in self.loadavg.iter_mut().zip(LOAD_EXP) {
+            *load = (*load * exp + n * FIXED_1 * (FIXED_1 - exp)) >> FSHIFT;
+        }
+    }
+    /// Load average over 1, 5 and 15 seconds, in hundredths
+    pub fn loadavg(&self) -> [usize; 3] {
+        self.loadavg.map(|load| (load * 100 + FIXED_1 / 2) >> FSHIFT)
+    }
+}
+
+/// How far the pass of a task with priority `prio` moves each time it is
+/// picked
+pub fn stride(prio: isize) -> isize {
+    BIG_STRIDE / prio
+}
+
+/// Whether `task` may run on `hart`, with the harts in `online` up. A task
+/// pinned only to harts that are down runs anywhere rather than not at all
+fn may_run_on(task: &TaskControlBlock, hart: usize, online: usize) -> bool {
+    let cpu_mask = task.inner_exclusive_access().cpu_mask;
+    if cpu_mask & (1 << hart) != 0 {
+        return true;
+    }
+    if cpu_mask & online == 0 {
+        warn!(
+            "[kernel] pid {} may only run on harts {:#x}, which are down, running it on hart {}",
+            task.pid.0, cpu_mask, hart
+        );
+        return true;
+    }
+    false
 }
 
 lazy_static! {
@@ -51,10 +129,146 @@ lazy_static! {
         unsafe { UPSafeCell::new(TaskManager::new()) };
 }
 
+/// Tasks [`add_task`] refused as already queued, running or exited
+static BAD_ENQUEUES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn bad_enqueues() -> usize {
+    BAD_ENQUEUES.load(Ordering::Relaxed)
+}
+
+/// Tasks [`TaskManager::add`] found in the ready queue already, which
+/// [`add_task`] let through, always 0 in release builds
+static QUEUE_DUPLICATES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn queue_duplicates() -> usize {
+    QUEUE_DUPLICATES.load(Ordering::Relaxed)
+}
+
+/// IPIs [`kick_hart`] sent to make another hart reschedule
+static RESCHED_IPIS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn resched_ipis() -> usize {
+    RESCHED_IPIS.load(Ordering::Relaxed)
+}
+
+/// Put `task` in the ready queue
+///
+/// A task that is in it already, running or a zombie is a bug in a wake
+/// path: it would run twice at once. Debug builds panic, release builds log
+/// it and leave the queue alone.
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    add_task_if(task, |_| true);
+}
+
+/// [`add_task`] if `update`, run on the task under the lock of the ready
+/// queue, returns true, so that what it changes and the enqueue are one
+/// step for the other harts
+pub fn add_task_if(
+    task: Arc<TaskControlBlock>,
+    update: impl FnOnce(&mut TaskControlBlockInner) -> bool,
+) {
+    // the queue before the task, as fetch takes them
+    let mut manager = TASK_MANAGER.exclusive_access();
+    let mut inner = task.inner_exclusive_access();
+    if !update(&mut inner) {
+        return;
+    }
+    let status = inner.task_status;
+    if inner.on_queue || matches!(status, TaskStatus::Running | TaskStatus::Zombie) {
+        let on_queue = inner.on_queue;
+        drop(inner);
+        drop(manager);
+        BAD_ENQUEUES.fetch_add(1, Ordering::Relaxed);
+        if cfg!(debug_assertions) {
+            panic!("pid {} enqueued again ({:?}, on queue: {})", task.pid.0, status, on_queue);
+        }
+        error!(
+            "[kernel] pid {} enqueued again ({:?}, on queue: {}), skipped",
+            task.pid.0, status, on_queue
+        );
+        return;
+    }
+    inner.on_queue = true;
+    let (pass, cpu_mask) = (inner.pass, inner.cpu_mask);
+    // the heap compares passes, taking the lock of each task
+    drop(inner);
+    manager.add(task);
+    drop(manager);
+    kick_hart(pass, cpu_mask);
+}
+
+/// Make another hart reschedule if the task it runs should be preempted by a
+/// task with `pass` that just became ready
+///
+/// The hart in `cpu_mask` with the largest pass (an idle one if any) is
+/// picked.
+fn kick_hart(pass: isize, mut cpu_mask: usize) {
+    let online = online_mask();
+    if cpu_mask & online == 0 {
+        // it runs on any hart then, see may_run_on
+        cpu_mask = online;
+    }
+    let me = hart_id();
+    let target = (0..MAX_HARTS)
+        .filter(|&id| id != me && cpu_mask & online & (1 << id) != 0)
+        .max_by_key(|&id| hart_state(id).running_pass.load(Ordering::Relaxed));
+    if let Some(id) = target {
+        let state = hart_state(id);
+        if state.running_pass.load(Ordering::Relaxed) > pass {
+            state.need_resched.store(true, Ordering::Release);
+            RESCHED_IPIS.fetch_add(1, Ordering::Relaxed);
+            send_ipi(1 << id);
+        }
+    }
 }
 
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    TASK_MANAGER.exclusive_access().fetch(hart_id())
+}
+
+/// Called on every timer tick, `running` is 1 if the tick interrupted a
+/// task and 0 on an idle hart
+pub fn sample_load_average(running: usize) {
+    TASK_MANAGER.exclusive_access().sample_load(running);
+}
+
+/// Whether some task is waiting in the ready queue
+pub fn has_ready_task() -> bool {
+    TASK_MANAGER.exclusive_access().has_ready()
+}
+
+pub fn get_load_average() -> [usize; 3] {
+    TASK_MANAGER.exclusive_access().loadavg()
+}
+
+lazy_static! {
+    /// All live tasks indexed by pid
+    pub static ref PID2TCB: UPSafeCell<BTreeMap<usize, Arc<TaskControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    PID2TCB.exclusive_access().get(&pid).map(Arc::clone)
+}
+
+pub fn insert_into_pid2task(pid: usize, task: Arc<TaskControlBlock>) {
+    PID2TCB.exclusive_access().insert(pid, task);
+}
+
+/// Number of live tasks
+pub fn task_count() -> usize {
+    PID2TCB.exclusive_access().len()
+}
+
+/// Take every live task out of the table and the ready queue, for the
+/// caller to drop
+pub fn take_all_tasks() -> BTreeMap<usize, Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().clear();
+    core::mem::take(&mut *PID2TCB.exclusive_access())
+}
+
+pub fn remove_from_pid2task(pid: usize) {
+    if PID2TCB.exclusive_access().remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2task!", pid);
+    }
 }
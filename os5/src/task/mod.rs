@@ -4,56 +4,126 @@
 //! (such as syscall or clock interrupt).
 //! By suspending or exiting the current process, you can
 //! modify the process state, manage the process queue through TASK_MANAGER,
-//! and switch the control flow through PROCESSOR.
+//! and switch the control flow through the [`Processor`] of each hart.
 //!
 //! Be careful when you see [`__switch`]. Control flow around this function
 //! might not be what you expect.
 
+mod checkpoint;
 mod context;
+#[cfg(feature = "kernel_test")]
+mod fairness;
 mod manager;
 mod pid;
 mod processor;
+mod audit;
+mod profile;
+mod sched_trace;
+mod signal;
 mod switch;
+mod syscall_filter;
 #[allow(clippy::module_inception)]
 mod task;
 
 use crate::loader::get_app_data_by_name;
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
-use manager::fetch_task;
+use manager::add_task_if;
+use manager::{fetch_task, insert_into_pid2task, remove_from_pid2task, take_all_tasks, task_count};
 use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus};
+use task::TaskControlBlockInner;
+pub use signal::{
+    SigInfo, SignalAction, SignalFlags, MAX_SIG, SA_RESTART, SIG_BLOCK, SIG_DFL, SIG_SETMASK,
+    SIG_UNBLOCK,
+};
+pub use task::{
+    BlockReason, ExecError, Rusage, SyscallStat, TaskControlBlock, TaskStatus, Wait,
+    STATUS_TRANSITIONS, USAGE_SCALE,
+};
 
+pub use checkpoint::{checkpoint, restore, CheckpointError};
 pub use context::TaskContext;
-pub use manager::add_task;
-pub use pid::{pid_alloc, KernelStack, PidHandle};
+#[cfg(feature = "kernel_test")]
+pub use fairness::fairness_test;
+pub use manager::{
+    add_task, bad_enqueues, get_load_average, pid2task, queue_duplicates, resched_ipis,
+    sample_load_average,
+};
+pub use pid::{kernel_stack_peaks, pid_alloc, KernelStack, PidHandle, IDLE_PID};
+use pid::compact_ids;
+pub use sched_trace::{drain as drain_sched_trace, record as record_sched_event};
+pub use sched_trace::{SchedEvent, SchedEventKind};
+pub use audit::{
+    audit_begin, audit_end, audit_start, audit_stop, auditing, AuditBuffer, AuditError, AuditRecord,
+    AUDIT_MAX_RECORDS,
+};
+pub use syscall_filter::{
+    check_syscall_filter, may_set_syscall_filter, set_syscall_filter, FilterVerdict, SyscallFilter,
+    FILTER_ERRNO, FILTER_KILL,
+};
+pub use profile::{
+    profile_start, profile_stop, profile_tick, ProfileError, PROFILE_MAX_SAMPLES,
+    PROFILE_TOTAL_SAMPLES,
+};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    Processor, current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
         add_one_to_current_task, get_current_task_costed_time, get_current_task_status, get_current_task_syscall_times,
-        mmap, munmap
+        mmap, munmap, mprotect, fault_in, populate_user_buffer, try_populate_user_buffer, madvise_dontneed, lend_user_page,
+        read_user, write_user, read_user_str,
+        current_user_task, current_task_test, status_test, account_user_time, mark_user_entry, context_switches,
 };
 
-use crate::mm::VirtAddr;
-use crate::timer::get_time_ms;
+use crate::config::{ALLOW_WX, MAX_HARTS, SIGRETURN_TRAMPOLINE};
+use crate::sync::{
+    mutex_cancel_wait, mutex_close, release_mutexes, semaphore_cancel_wait, semaphore_close,
+};
+use crate::mm::{
+    frame_allocator_free, register_shrinker, copy_to_user, MapPermission, Memory, PTEFlags,
+    PageTable, VirtAddr,
+};
+use crate::percpu::online_mask;
+use crate::timer::{clear_timers, get_time_ms, get_time_us, remove_timer};
+use crate::shutdown::shutdown;
 
 
 
-/// Make current task suspended and switch to the next task
+/// Make current task suspended and switch to the next task, as it asked for
 pub fn suspend_current_and_run_next() {
+    requeue_current_and_run_next(true);
+}
+
+/// Preempt the current task and switch to the next task
+pub fn preempt_current_and_run_next() {
+    requeue_current_and_run_next(false);
+}
+
+/// Put the current task back to the ready queue, counting a voluntary or
+/// involuntary context switch
+fn requeue_current_and_run_next(voluntary: bool) {
     //info!("start of s c a r n");
     // There must be an application running.
     let task = take_current_task().unwrap();
 
     // ---- access current TCB exclusively
     let mut task_inner = task.inner_exclusive_access();
+    if voluntary {
+        task_inner.nvcsw += 1;
+    } else {
+        task_inner.nivcsw += 1;
+    }
     if  task_inner.dispatched == false {
         task_inner.first_time = get_time_ms();
         task_inner.dispatched = true;
-        info!("set task {} dispatched time: {}",task.pid.0, task_inner.first_time);
     }
+    kevent!(TaskSuspend { pid: task.pid.0, voluntary });
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // Change status to Ready
-    task_inner.task_status = TaskStatus::Ready;
+    task_inner.set_status(TaskStatus::Ready);
+    record_sched_event(SchedEventKind::SwitchOut, task.pid.0, task_inner.pass, task_inner.prio);
     drop(task_inner);
     // ---- release current PCB
 
@@ -64,93 +134,680 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// Make current task blocked and switch to the next task
+///
+/// Whoever blocks the task is responsible for putting it back to the ready
+/// queue, e.g. the sleep timers in [`crate::timer`]. A wake that came after
+/// the task published its [`Wait`] but before it got here is pending, and
+/// the task goes on without blocking.
+pub fn block_current_and_run_next() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    if core::mem::replace(&mut task_inner.wake_pending, false) {
+        return;
+    }
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.set_status(TaskStatus::Blocking);
+    task_inner.nvcsw += 1;
+    record_sched_event(SchedEventKind::Block, task.pid.0, task_inner.pass, task_inner.prio);
+    drop(task_inner);
+    // woken from here on, it waits in the ready queue for the switch below,
+    // see run_tasks
+    take_current_task();
+    schedule(task_cx_ptr);
+}
+
+/// Wakes [`wakeup_task`] left pending for a task not blocked yet
+static EARLY_WAKES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn early_wakes() -> usize {
+    EARLY_WAKES.load(Ordering::Relaxed)
+}
+
+/// Put `task`, blocked in a [`Wait`], back to the ready queue
+///
+/// Every wake path goes through here, so that [`add_task`] sees a task woken
+/// twice. The task becomes ready and is queued in one step under the lock of
+/// the queue; one still running on its way to blocking gets a pending wake
+/// instead.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let pid = task.pid.0;
+    add_task_if(task, |inner| {
+        inner.wait = None;
+        inner.block_reason = None;
+        record_sched_event(SchedEventKind::Wake, pid, inner.pass, inner.prio);
+        match inner.task_status {
+            TaskStatus::Running => {
+                inner.wake_pending = true;
+                EARLY_WAKES.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            TaskStatus::Blocking => {
+                inner.set_status(TaskStatus::Ready);
+                true
+            }
+            _ => true,
+        }
+    });
+}
+
+/// Kill the current task with `signal`, which its parent sees in the wait
+/// status
+///
+/// This is a normal exit apart from the status, whether the kill comes from
+/// a fault or from a signal found on the way back to user mode: children,
+/// zombie or not, go to the nearest subreaper or initproc, the parent gets
+/// SIGCHLD and the memory is recycled.
+pub fn kill_current_and_run_next(signal: SignalFlags) {
+    let signum = signal.bits().trailing_zeros() as usize;
+    if let Some(task) = current_task() {
+        task.inner_exclusive_access().term_signal = signum;
+    }
+    exit_current_and_run_next(-(signum as i32));
+}
+
 /// Exit current task, recycle process resources and switch to the next task
 pub fn exit_current_and_run_next(exit_code: i32) {
     // take from Processor
     let task = take_current_task().unwrap();
+    remove_from_pid2task(task.getpid());
+    release_mutexes(&task);
+    let mutexes = core::mem::take(&mut task.inner_exclusive_access().mutexes);
+    for id in mutexes.into_iter().flatten() {
+        mutex_close(id);
+    }
+    // may wake the tasks blocked on one it leaves dead
+    let semaphores = core::mem::take(&mut task.inner_exclusive_access().semaphores);
+    for id in semaphores.into_iter().flatten() {
+        semaphore_close(id);
+    }
     // **** access current TCB exclusively
+    let stats = task.memory_set.exclusive_access().stats();
     let mut inner = task.inner_exclusive_access();
+    kevent!(TaskExit {
+        pid: task.getpid(),
+        code: exit_code,
+        signal: inner.term_signal,
+        mapped: stats.mapped_pages,
+        resident: stats.resident_pages,
+        shared: stats.shared_pages,
+        peak: stats.peak_resident_pages,
+    });
+    // Close the files before becoming a zombie: one kept until the parent
+    // reaps us may be the write end of a pipe the parent is reading
+    let files = core::mem::take(&mut inner.fd_table);
+    // the samples stay for the owner to stop the profile
+    profile::disarm(&mut inner);
+    // and the records for the owner to read until we are reaped
+    audit::audit_stop(&mut inner);
     // Change status to Zombie
-    inner.task_status = TaskStatus::Zombie;
-    // Record exit code
-    inner.exit_code = exit_code;
-    // do not move to its parent but under initproc
-
-    // ++++++ access initproc TCB exclusively
-    {
-        let mut initproc_inner = INITPROC.inner_exclusive_access();
+    inner.set_status(TaskStatus::Zombie);
+    // Record the wait status: the code in bits 8..16 on a normal exit, the
+    // signal in the low 7 bits if killed
+    inner.exit_code = match inner.term_signal {
+        0 => (exit_code & 0xff) << 8,
+        signum => (signum & 0x7f) as i32,
+    };
+    // do not move to its parent but under the nearest subreaper or initproc
+    if !inner.children.is_empty() {
+        let reaper = find_reaper(&inner);
+        // ++++++ access reaper TCB exclusively
+        let mut reaper_inner = reaper.inner_exclusive_access();
         for child in inner.children.iter() {
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+            child.inner_exclusive_access().parent = Some(Arc::downgrade(&reaper));
+            reaper_inner.children.push(child.clone());
         }
+        // ++++++ release reaper PCB
     }
-    // ++++++ release parent PCB
 
     inner.children.clear();
+    let parent = inner.parent.as_ref().and_then(|parent| parent.upgrade());
     // deallocate user space
-    inner.memory_set.recycle_data_pages();
     drop(inner);
+    drop(files);
+    if let Some(parent) = parent {
+        send_signal(&parent, SignalFlags::SIGCHLD);
+    }
+    task.memory_set.exclusive_access().recycle_data_pages();
     // **** release current PCB
     // drop task manually to maintain rc correctly
     drop(task);
+    if task_count() == 0 {
+        // nothing left to run, nor to reap it
+        shutdown(exit_code);
+    }
     // we do not have to save task context
     let mut _unused = TaskContext::zero_init();
     schedule(&mut _unused as *mut _);
 }
 
+/// Where the children of an exiting task go: its nearest ancestor that is a
+/// subreaper and still alive, initproc if there is none
+fn find_reaper(inner: &TaskControlBlockInner) -> Arc<TaskControlBlock> {
+    let mut ancestor = inner.parent.as_ref().and_then(|parent| parent.upgrade());
+    while let Some(task) = ancestor {
+        // ++++++ temporarily access ancestor TCB exclusively
+        let task_inner = task.inner_exclusive_access();
+        if task_inner.child_subreaper && !task_inner.is_zombie() {
+            drop(task_inner);
+            return task;
+        }
+        let next = task_inner.parent.as_ref().and_then(|parent| parent.upgrade());
+        drop(task_inner);
+        ancestor = next;
+    }
+    INITPROC.clone()
+}
+
 lazy_static! {
     /// Creation of initial process
     ///
     /// the name "initproc" may be changed to any other app name like "usertests",
     /// but we have user_shell, so we don't need to change it.
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
+        "ch5b_initproc",
         get_app_data_by_name("ch5b_initproc").unwrap()
     ));
 }
 
+/// Pid of the shell, the first child of initproc
+pub const SHELL_PID: usize = 1;
+
+/// Whether `task` may use the syscalls that control or look into the whole
+/// system: initproc, or the shell it starts as [`SHELL_PID`]
+pub fn is_privileged(task: &TaskControlBlock) -> bool {
+    if core::ptr::eq(task, &**INITPROC) {
+        return true;
+    }
+    task.getpid() == SHELL_PID
+        && task
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(false, |parent| Arc::ptr_eq(&parent, &INITPROC))
+}
+
 pub fn add_initproc() {
+    insert_into_pid2task(INITPROC.getpid(), INITPROC.clone());
     add_task(INITPROC.clone());
+    // zombies are found from initproc down, so not before it exists
+    register_shrinker("zombie page tables", Memory::Frames, shrink_zombie_page_tables);
+}
+
+/// Drop every task but initproc and load initproc afresh in place, for a
+/// soft reboot, and return how many other tasks were alive
+///
+/// Pids and kernel stack slots are handed out as after boot again. initproc
+/// goes on running if it is the current task, else it is queued.
+pub fn respawn_initproc() -> usize {
+    let running = current_task().map_or(false, |task| Arc::ptr_eq(&task, &INITPROC));
+    let alive = reap_all_tasks() - usize::from(!running);
+    INITPROC.inner_exclusive_access().reset_for_respawn();
+    INITPROC
+        .exec("ch5b_initproc", get_app_data_by_name("ch5b_initproc").unwrap(), Vec::new())
+        .expect("initproc cannot be loaded again");
+    compact_ids();
+    insert_into_pid2task(INITPROC.getpid(), INITPROC.clone());
+    if !running {
+        add_task(INITPROC.clone());
+    }
+    alive
+}
+
+/// Free the page tables of zombies not reaped yet, a shrinker of frames
+fn shrink_zombie_page_tables(_level: usize) -> usize {
+    release_zombie_page_tables(&INITPROC)
+}
+
+/// [`shrink_zombie_page_tables`] for `root` and its descendants, walked with
+/// a stack of its own rather than recursion, however deep the tree. A task
+/// whose lock is held, e.g. by the allocation that ran out, is skipped with
+/// its children
+fn release_zombie_page_tables(root: &Arc<TaskControlBlock>) -> usize {
+    let mut freed = 0;
+    let mut pending = vec![root.clone()];
+    while let Some(task) = pending.pop() {
+        let inner = match task.try_inner_exclusive_access() {
+            Some(inner) => inner,
+            None => continue,
+        };
+        if inner.is_zombie() && Arc::strong_count(&task.memory_set) == 1 {
+            if let Some(mut memory_set) = task.memory_set.try_exclusive_access() {
+                freed += memory_set.release_page_tables();
+            }
+        }
+        pending.extend(inner.children.iter().cloned());
+    }
+    freed
+}
+
+/// Drop every task but the current one, once the other harts are parked,
+/// and return how many were alive
+///
+/// Tasks still held elsewhere, e.g. waiting for a mutex, stay allocated.
+pub fn reap_all_tasks() -> usize {
+    let current = current_task();
+    let tasks = take_all_tasks();
+    let alive = tasks
+        .values()
+        .filter(|task| !current.as_ref().map_or(false, |current| Arc::ptr_eq(task, current)))
+        .count();
+    clear_timers();
+    // the orphans initproc was left, zombie or not
+    let orphans = core::mem::take(&mut INITPROC.inner_exclusive_access().children);
+    drop(tasks);
+    drop(orphans);
+    alive
+}
+
+/// Register a newly created task so that it can be found by pid
+pub fn register_task(task: Arc<TaskControlBlock>) {
+    insert_into_pid2task(task.getpid(), task);
 }
 
 
+/// Arrange for a fault to be handled by the signal handler of the current
+/// task, return false if it has to be killed instead
+///
+/// That is the case when there is no handler, when the signal is blocked,
+/// when a handler is already running (handlers do not nest), and when the
+/// handler returned but the very same instruction faults on the very same
+/// address again.
+pub fn deliver_fault_signal(signal: SignalFlags, cause: usize, addr: usize) -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let signum = signal.bits().trailing_zeros() as usize;
+    if inner.signal_actions.table[signum].handler == SIG_DFL
+        || inner.signal_mask.contains(signal)
+        || inner.handling_sig != -1
+    {
+        return false;
+    }
+    let sepc = inner.get_trap_cx().sepc;
+    if inner.fault_retry.take() == Some((sepc, addr)) {
+        return false;
+    }
+    inner.signals |= signal;
+    inner.fault_info = Some(SigInfo {
+        signo: signum,
+        cause,
+        addr,
+    });
+    inner.fault_site = Some((sepc, addr));
+    true
+}
+
+/// Forget the fault a handler just returned from, called on every trap except
+/// the one that decides whether the fault repeats
+pub fn clear_fault_retry() {
+    current_task().unwrap().inner_exclusive_access().fault_retry = None;
+}
+
+/// Make `signal` pending for `task`, taking it out of the [`Wait`] it is
+/// blocked in if the signal is one it can act on. The syscall that blocked
+/// then finds it [`take_interrupted`]
+pub fn send_signal(task: &Arc<TaskControlBlock>, signal: SignalFlags) {
+    let mut inner = task.inner_exclusive_access();
+    inner.signals |= signal;
+    let wait = match inner.wait {
+        Some(wait) if inner.has_deliverable_signal() => wait,
+        _ => return,
+    };
+    inner.wait = None;
+    inner.interrupted = wait != Wait::Signal;
+    drop(inner);
+    match wait {
+        Wait::Signal => {}
+        Wait::Sleep => remove_timer(task),
+        Wait::Mutex(id) => mutex_cancel_wait(id, task),
+        Wait::Semaphore(id) => semaphore_cancel_wait(id, task),
+    }
+    wakeup_task(task.clone());
+}
+
+/// Whether a signal the current task can act on is pending, for the waits
+/// that poll instead of blocking, which then end like an interrupted one
+pub fn signal_pending() -> bool {
+    current_task().unwrap().inner_exclusive_access().has_deliverable_signal()
+}
+
+/// Note that a signal ended the wait of the current task, for a wait that
+/// polls with [`signal_pending`]
+pub fn mark_interrupted() {
+    current_task().unwrap().inner_exclusive_access().interrupted = true;
+}
+
+/// Whether a signal ended the last wait of the current task, clearing it
+pub fn take_interrupted() -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    core::mem::replace(&mut inner.interrupted, false)
+}
+
+/// Block the current task until a signal is pending that `handle_signals`
+/// will act on, which it does on the way back to user mode
+///
+/// The check and publishing the [`Wait`] happen under the lock of the task,
+/// as does [`send_signal`]. A signal sent from another hart after that but
+/// before the task blocks leaves its wake pending, and
+/// [`block_current_and_run_next`] returns at once for the check to find it.
+/// Inside a handler only the signals that kill wake it up, since handlers
+/// do not nest.
+pub fn wait_for_signal() {
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        if inner.has_deliverable_signal() {
+            return;
+        }
+        inner.wait = Some(Wait::Signal);
+        inner.block_reason = Some(BlockReason::Signal);
+        drop(inner);
+        drop(task);
+        block_current_and_run_next();
+    }
+}
+
+/// Act on the pending signals of the current task before it returns to user
+/// mode, lowest number first: start its handler for one of them, or kill it
+/// if the action is the default one. Blocked signals stay pending
+pub fn handle_signals() {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    for signum in 1..=MAX_SIG {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if !inner.signals.contains(signal) {
+            continue;
+        }
+        // the mask never holds the unblockable ones
+        if inner.signal_mask.contains(signal) {
+            continue;
+        }
+        if inner.ignores(signal) {
+            inner.signals.remove(signal);
+            continue;
+        }
+        let handler = inner.signal_actions.table[signum].handler;
+        if SignalFlags::unblockable().contains(signal) || handler == SIG_DFL {
+            drop(inner);
+            drop(task);
+            println_ratelimited!("[kernel] Killed by signal {}.", signum);
+            kill_current_and_run_next(signal);
+            return;
+        }
+        if inner.handling_sig != -1 {
+            // handlers do not nest, wait for sigreturn
+            continue;
+        }
+        inner.signals.remove(signal);
+        inner.handling_sig = signum as isize;
+        // blocked while the handler runs, until sigreturn
+        let handler_mask = inner.signal_actions.table[signum].mask | signal;
+        // after sigsuspend, the mask from before it comes back with sigreturn
+        inner.signal_mask_backup = inner.saved_signal_mask.take().unwrap_or(inner.signal_mask);
+        inner.signal_mask |= handler_mask - SignalFlags::unblockable();
+        let info = inner.fault_info.take().unwrap_or(SigInfo {
+            signo: signum,
+            cause: 0,
+            addr: 0,
+        });
+        let token = task.get_user_token();
+        let restart = inner.signal_actions.table[signum].flags & SA_RESTART != 0;
+        let trap_cx = inner.get_trap_cx();
+        if trap_cx.in_syscall() {
+            // an interrupted syscall, back to its ecall once the handler
+            // returns if it wants that
+            if restart {
+                trap_cx.restart_syscall();
+            } else {
+                trap_cx.complete_syscall(-EINTR);
+            }
+        }
+        let backup = *trap_cx;
+        // push the SigInfo to the user stack, 32-byte aligned so that it does
+        // not cross a page
+        let sp = trap_cx.x[2].wrapping_sub(core::mem::size_of::<SigInfo>()) & !0x1f;
+        // a page of the stack lent to a pipe becomes writable again
+        fault_in(sp, MapPermission::W);
+        let stack_ok = PageTable::from_token(token)
+            .translate(VirtAddr::from(sp).floor())
+            .map_or(false, |pte| {
+                pte.is_valid() && pte.writable() && pte.flags().contains(PTEFlags::U)
+            });
+        if !stack_ok {
+            drop(inner);
+            drop(task);
+            println_ratelimited!("[kernel] No stack for the handler of signal {}, core dumped.", signum);
+            kill_current_and_run_next(signal);
+            return;
+        }
+        copy_to_user(token, sp as *mut SigInfo, &[info]);
+        trap_cx.x[2] = sp;
+        trap_cx.sepc = handler;
+        trap_cx.x[10] = signum;
+        trap_cx.x[11] = sp;
+        // a handler that returns lands in sigreturn
+        trap_cx.x[1] = SIGRETURN_TRAMPOLINE;
+        inner.trap_ctx_backup = Some(backup);
+        return;
+    }
+    if let Some(mask) = inner.saved_signal_mask.take() {
+        inner.signal_mask = mask;
+    }
+    // no handler started, an interrupted syscall fails
+    let trap_cx = inner.get_trap_cx();
+    if trap_cx.in_syscall() {
+        trap_cx.complete_syscall(-EINTR);
+    }
+}
+
+/// Count syscall `id` for the current task, unless it is not in the table,
+/// and note it as the last one it made
 pub fn add_one_while_syscall(id: usize) {
-    add_one_to_current_task(id);
-}
-
-use super::syscall::TaskInfo;
-pub fn get_task_info_inner(t: *mut TaskInfo) {
-    let a = get_current_task_status();
-    let b = get_current_task_syscall_times();
-    let c = get_current_task_costed_time();
-    info!("get info okkkkkkkkkk");
-    info!("a : {:?}", a);
-    info!("b : {:?}", b.clone());
-    info!("c : {:?}", c);
-    unsafe {
-        *t = TaskInfo {
-
-            status : a,
-            syscall_times: b,
-            time: c,
+    let task = match current_user_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let mut inner = task.inner_exclusive_access();
+    inner.last_syscall = Some((id, get_time_ms()));
+    if let Some(index) = crate::syscall::counter_index(id) {
+        inner.syscall_times[index] += 1;
+    }
+}
+
+/// Count a call of syscall `id` by the current task that returned, failing
+/// with `errno` if it is not `None`
+pub fn count_syscall_outcome(id: usize, errno: Option<u16>) {
+    let task = match current_user_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let mut inner = task.inner_exclusive_access();
+    let stat = inner.syscall_stats.entry(id).or_default();
+    match errno {
+        Some(errno) => {
+            stat.err += 1;
+            stat.last_errno = errno;
         }
+        None => stat.ok += 1,
+    }
+}
+
+/// Note what the current task waits for in a wait that polls, `None` once
+/// it is done
+pub fn set_block_reason(reason: Option<BlockReason>) {
+    if let Some(task) = current_task() {
+        task.inner_exclusive_access().block_reason = reason;
     }
+}
+
+use super::syscall::{TaskInfo, TaskInfoV2, EINTR, EINVAL, EPERM, ESRCH, TASK_NAME_LEN};
+/// Version 1 of the task info of the current task, `None` without one
+pub fn task_info_v1() -> Option<TaskInfo> {
+    Some(TaskInfo {
+        status: get_current_task_status(),
+        syscall_times: get_current_task_syscall_times()?,
+        time: get_current_task_costed_time()?,
+    })
+}
 
-} 
+/// Version 2 of the task info of the current task, `None` without one
+pub fn task_info_v2() -> Option<Box<TaskInfoV2>> {
+    let time = get_current_task_costed_time()?;
+    let task = current_user_task()?;
+    let usage = task.rusage();
+    let stats = task.memory_set.exclusive_access().stats();
+    let mut info = TaskInfoV2::boxed();
+    info.struct_size = core::mem::size_of::<TaskInfoV2>();
+    info.status = get_current_task_status();
+    info.time = time;
+    info.utime_us = usage.utime_us;
+    info.stime_us = usage.stime_us;
+    info.nvcsw = usage.nvcsw;
+    info.nivcsw = usage.nivcsw;
+    info.mapped_pages = stats.mapped_pages;
+    info.resident_pages = stats.resident_pages;
+    info.shared_pages = stats.shared_pages;
+    info.peak_resident_pages = stats.peak_resident_pages;
+    let inner = task.inner_exclusive_access();
+    info.syscall_times.copy_from_slice(&inner.syscall_times);
+    // room for the NUL
+    let len = inner.name.len().min(TASK_NAME_LEN - 1);
+    info.name[..len].copy_from_slice(&inner.name.as_bytes()[..len]);
+    for (&id, stat) in inner.syscall_stats.iter() {
+        info.syscall_ok[id] = stat.ok;
+        info.syscall_err[id] = stat.err;
+        info.syscall_errno[id] = stat.last_errno;
+    }
+    Some(info)
+}
+
+/// `port` asks for pages both writable and executable, which W^X refuses
+fn writable_and_executable(port: usize) -> bool {
+    !ALLOW_WX && port & 0x6 == 0x6
+}
 
 pub fn sys_mmap_inner(start: usize, len: usize, port: usize) -> isize {
     let va = VirtAddr(start);
     if ! va.aligned() || port & !0x7 != 0  || port & 0x7 == 0 {
-        return -1;
+        return -EINVAL;
+    }
+    if writable_and_executable(port) {
+        return -EPERM;
+    }
+    mmap(start, len, port).unwrap_or(-ESRCH)
+}
+
+pub fn sys_mprotect_inner(start: usize, len: usize, port: usize) -> isize {
+    let va = VirtAddr(start);
+    if !va.aligned() || port & !0x7 != 0 {
+        return -EINVAL;
+    }
+    // a JIT writes its code, then makes it R+X
+    if writable_and_executable(port) {
+        return -EPERM;
+    }
+    mprotect(start, len, port).unwrap_or(-ESRCH)
+}
+
+/// The pages of the range are not needed, give their frames back
+const MADV_DONTNEED: usize = 4;
+
+pub fn sys_madvise_inner(start: usize, len: usize, advice: usize) -> isize {
+    let va = VirtAddr(start);
+    if !va.aligned() {
+        return -EINVAL;
+    }
+    match advice {
+        MADV_DONTNEED => madvise_dontneed(start, len).unwrap_or(-ESRCH),
+        // a hint we are free to ignore
+        _ => 0,
     }
-    mmap(start, len, port)
 }
 
 pub fn sys_munmap_inner(start: usize, len: usize ) -> isize {
     let va = VirtAddr(start);
     if ! va.aligned()  {
-        return -1;
+        return -EINVAL;
     }
-    munmap(start, len)
+    munmap(start, len).unwrap_or(-ESRCH)
+}
+
+use super::syscall::ProcessInfo;
+/// Fill in `info` for the task `pid`, return false if there is no such task
+pub fn get_process_info_inner(pid: usize, info: &mut ProcessInfo) -> bool {
+    let task = match pid2task(pid) {
+        Some(task) => task,
+        None => return false,
+    };
+    let now = get_time_us();
+    let stats = task.memory_set.exclusive_access().stats();
+    let inner = task.inner_exclusive_access();
+    let running = inner.task_status == TaskStatus::Running;
+    let (block_kind, mut block_arg) = BlockReason::encode(inner.block_reason);
+    if let Some(BlockReason::Mutex(id)) = inner.block_reason {
+        // the handle the task knows the mutex by
+        let handle = inner.mutexes.iter().position(|&slot| slot == Some(id));
+        block_arg = handle.map_or(-1, |handle| handle as isize);
+    }
+    let (last_syscall, last_syscall_ms) = inner
+        .last_syscall
+        .map_or((-1, 0), |(id, ms)| (id as isize, ms));
+    *info = ProcessInfo {
+        pid,
+        ppid: inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map(|parent| parent.getpid())
+            .unwrap_or(0),
+        status: inner.task_status,
+        prio: inner.prio as usize,
+        cpu_time_ms: inner.cpu_time_at(now) / 1000,
+        cpu_usage: inner.cpu_usage_at(now, running),
+        mapped_pages: stats.mapped_pages,
+        resident_pages: stats.resident_pages,
+        shared_pages: stats.shared_pages,
+        peak_resident_pages: stats.peak_resident_pages,
+        free_frames: frame_allocator_free(),
+        block_kind,
+        block_arg,
+        last_syscall,
+        last_syscall_ms,
+    };
+    true
+}
+
+/// The task `pid`, or the current one if `pid` is 0
+fn task_or_current(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    if pid == 0 {
+        current_task()
+    } else {
+        pid2task(pid)
+    }
+}
+
+/// Only allow `pid` on the harts in `mask`, it takes effect the next time
+/// the task is scheduled
+///
+/// A mask without any online hart is rejected, the task could never run.
+pub fn set_affinity_inner(pid: usize, mask: usize) -> isize {
+    let valid = usize::MAX >> (usize::BITS as usize - MAX_HARTS);
+    if mask & online_mask() == 0 {
+        return -EINVAL;
+    }
+    match task_or_current(pid) {
+        Some(task) => {
+            task.inner_exclusive_access().cpu_mask = mask & valid;
+            0
+        }
+        None => -ESRCH,
+    }
+}
+
+pub fn get_affinity_inner(pid: usize) -> Option<usize> {
+    task_or_current(pid).map(|task| task.inner_exclusive_access().cpu_mask)
 }
 
 pub fn set_priority_inner(prio: isize) -> isize {
@@ -1,90 +1,276 @@
 //! Task pid implementation.
 //!
-//! Assign PID to the process here. At the same time, the position of the application KernelStack
-//! is determined according to the PID.
+//! Assign PID to the process here. There are at most [`MAX_TASKS`] pids,
+//! each below it. Freed pids are handed out again oldest first and only
+//! once every pid has been used, so a pid a test just reaped does not come
+//! back right away.
+//!
+//! Kernel stacks sit in [`MAX_TASKS`] slots of their own below the
+//! trampoline, allocated apart from the pids and reused as soon as they
+//! are free. Kernel stacks come in two size classes. Every slot is big
+//! enough for a large one, a normal stack leaves the rest of it unmapped as
+//! a bigger guard. Stacks are painted when created, so that how deep each
+//! one ever got can be told when it is dropped.
 
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::config::{
+    KERNEL_STACK_SIZE, KERNEL_STACK_SIZE_LARGE, MAX_HARTS, MAX_TASKS, PAGE_SIZE, TRAMPOLINE,
+};
 use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::percpu::hart_id;
 use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::*;
 
-/// Process identifier allocator using stack allocation
-struct PidAllocator {
-    /// A new PID to be assigned
+/// Allocator of the ids below a limit, fresh ones first and then the freed
+/// ones either oldest or newest first
+struct IdAllocator {
+    /// The next id never handed out
     current: usize,
-    /// Recycled PID sequence
-    recycled: Vec<usize>,
+    limit: usize,
+    recycled: VecDeque<usize>,
+    /// Recycled ids go oldest first
+    fifo: bool,
 }
 
-impl PidAllocator {
-    pub fn new() -> Self {
-        PidAllocator {
+impl IdAllocator {
+    pub fn new(limit: usize, fifo: bool) -> Self {
+        IdAllocator {
             current: 0,
-            recycled: Vec::new(),
+            limit,
+            recycled: VecDeque::new(),
+            fifo,
         }
     }
-    pub fn alloc(&mut self) -> PidHandle {
-        if let Some(pid) = self.recycled.pop() {
-            PidHandle(pid)
-        } else {
+    pub fn alloc(&mut self) -> Option<usize> {
+        if self.fifo && self.current < self.limit {
+            self.current += 1;
+            return Some(self.current - 1);
+        }
+        if let Some(id) = self.recycled.pop_front() {
+            return Some(id);
+        }
+        if self.current < self.limit {
             self.current += 1;
-            PidHandle(self.current - 1)
+            return Some(self.current - 1);
         }
+        None
     }
-    pub fn dealloc(&mut self, pid: usize) {
-        assert!(pid < self.current);
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
         assert!(
-            !self.recycled.iter().any(|ppid| *ppid == pid),
-            "pid {} has been deallocated!",
-            pid
+            !self.recycled.iter().any(|&freed| freed == id),
+            "id {} has been deallocated!",
+            id
         );
-        self.recycled.push(pid);
+        if self.fifo {
+            self.recycled.push_back(id);
+        } else {
+            self.recycled.push_front(id);
+        }
+    }
+    /// Take the freed ids right below [`Self::current`] back, as if they
+    /// were never handed out, and order the others
+    pub fn compact(&mut self) {
+        let mut freed: Vec<usize> = self.recycled.drain(..).collect();
+        freed.sort_unstable();
+        while freed.last() == Some(&(self.current - 1)) {
+            freed.pop();
+            self.current -= 1;
+        }
+        self.recycled = freed.into();
     }
 }
 
 lazy_static! {
     /// Pid allocator instance through lazy_static!
-    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
-        unsafe { UPSafeCell::new(PidAllocator::new()) };
+    static ref PID_ALLOCATOR: UPSafeCell<IdAllocator> =
+        unsafe { UPSafeCell::new(IdAllocator::new(MAX_TASKS, true)) };
+    /// Kernel stack slots, the most recently freed first
+    static ref STACK_SLOTS: UPSafeCell<IdAllocator> =
+        unsafe { UPSafeCell::new(IdAllocator::new(MAX_TASKS, false)) };
 }
 
 /// Abstract structure of PID
 pub struct PidHandle(pub usize);
 
+/// Pid of the idle tasks, which is never allocated
+pub const IDLE_PID: usize = usize::MAX;
+
 impl Drop for PidHandle {
     fn drop(&mut self) {
         //println!("drop pid {}", self.0);
+        if self.0 == IDLE_PID {
+            return;
+        }
         PID_ALLOCATOR.exclusive_access().dealloc(self.0);
     }
 }
 
-pub fn pid_alloc() -> PidHandle {
-    PID_ALLOCATOR.exclusive_access().alloc()
+/// Hand out pids and kernel stack slots as if the freed ones at the top
+/// were never used, for a soft reboot
+pub fn compact_ids() {
+    PID_ALLOCATOR.exclusive_access().compact();
+    STACK_SLOTS.exclusive_access().compact();
+}
+
+/// A free pid, `None` if [`MAX_TASKS`] tasks hold one
+pub fn pid_alloc() -> Option<PidHandle> {
+    PID_ALLOCATOR.exclusive_access().alloc().map(PidHandle)
+}
+
+/// What unused stack words hold
+const STACK_PAINT: usize = 0x5a5a_5a5a_5a5a_5a5a;
+/// Deepest use seen of the kernel stacks of tasks, in bytes
+static TASK_STACK_PEAK: AtomicUsize = AtomicUsize::new(0);
+/// Deepest use seen of the scratch stacks, in bytes
+static SCRATCH_STACK_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Fill `[bottom, top)` with [`STACK_PAINT`]
+fn paint(bottom: usize, top: usize) {
+    let words = (top - bottom) / core::mem::size_of::<usize>();
+    unsafe { core::slice::from_raw_parts_mut(bottom as *mut usize, words) }.fill(STACK_PAINT);
+}
+
+/// Bytes of the painted `[bottom, top)` that were ever used
+fn high_watermark(bottom: usize, top: usize) -> usize {
+    let words = (top - bottom) / core::mem::size_of::<usize>();
+    let stack = unsafe { core::slice::from_raw_parts(bottom as *const usize, words) };
+    let untouched = stack.iter().take_while(|&&word| word == STACK_PAINT).count();
+    (words - untouched) * core::mem::size_of::<usize>()
+}
+
+/// Fold the use of a stack of `size` bytes into `peak`, true if it came
+/// close to overflowing
+fn record_watermark(peak: &AtomicUsize, size: usize, used: usize) -> bool {
+    peak.fetch_max(used, Ordering::Relaxed);
+    used > size / 4 * 3
+}
+
+/// Deepest use of any kernel stack of a task and of any scratch stack so
+/// far, in bytes. A stack counts once dropped, or, for a scratch stack,
+/// after each use
+pub fn kernel_stack_peaks() -> [usize; 2] {
+    [
+        TASK_STACK_PEAK.load(Ordering::Relaxed),
+        SCRATCH_STACK_PEAK.load(Ordering::Relaxed),
+    ]
+}
+
+/// A large stack for each hart, to run what is too deep for a normal
+/// kernel stack
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+struct ScratchStack([u8; KERNEL_STACK_SIZE_LARGE]);
+
+static mut SCRATCH_STACKS: [ScratchStack; MAX_HARTS] =
+    [ScratchStack([0; KERNEL_STACK_SIZE_LARGE]); MAX_HARTS];
+#[allow(clippy::declare_interior_mutable_const)]
+const SCRATCH_FREE: AtomicBool = AtomicBool::new(false);
+static SCRATCH_IN_USE: [AtomicBool; MAX_HARTS] = [SCRATCH_FREE; MAX_HARTS];
+
+/// Run `f` on the scratch stack of the current hart. `f` must not switch
+/// tasks, as the stack belongs to the hart rather than to the task
+fn on_scratch_stack<R>(f: impl FnOnce() -> R) -> R {
+    let hart = hart_id();
+    assert!(
+        !SCRATCH_IN_USE[hart].swap(true, Ordering::Acquire),
+        "scratch stack of hart {} is in use",
+        hart
+    );
+    let bottom = unsafe { SCRATCH_STACKS[hart].0.as_ptr() as usize };
+    let top = bottom + KERNEL_STACK_SIZE_LARGE;
+    paint(bottom, top);
+    let mut f = Some(f);
+    let mut ret = None;
+    let mut call = || ret = Some((f.take().unwrap())());
+    unsafe {
+        call_on_stack(top, &mut call);
+    }
+    let used = high_watermark(bottom, top);
+    if record_watermark(&SCRATCH_STACK_PEAK, KERNEL_STACK_SIZE_LARGE, used) {
+        warn!(
+            "[kernel] hart {} used {} of {} bytes of its scratch stack",
+            hart, used, KERNEL_STACK_SIZE_LARGE
+        );
+    }
+    SCRATCH_IN_USE[hart].store(false, Ordering::Release);
+    ret.unwrap()
+}
+
+/// Call `f` with `sp` at `top`, back on the current stack afterwards
+unsafe fn call_on_stack(top: usize, f: &mut dyn FnMut()) {
+    extern "C" fn enter(f: *mut &mut dyn FnMut()) {
+        unsafe { (*f)() }
+    }
+    let mut f = f;
+    // s2 is callee-saved, so it survives the call with the old sp
+    core::arch::asm!(
+        "mv s2, sp",
+        "mv sp, {top}",
+        "jalr {enter}",
+        "mv sp, s2",
+        top = in(reg) top,
+        enter = in(reg) enter as usize,
+        in("a0") &mut f as *mut &mut dyn FnMut(),
+        out("s2") _,
+        clobber_abi("C"),
+    );
 }
 
-/// Return (bottom, top) of a kernel stack in kernel space.
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+/// Return (bottom, top) of the kernel stack in `slot`, the slots are apart
+/// by a guard page
+pub fn kernel_stack_position(slot: usize) -> (usize, usize) {
+    assert!(slot < MAX_TASKS, "kernel stack slot {} out of range", slot);
+    let top = TRAMPOLINE - slot * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
 
-/// KernelStack corresponding to PID
+/// Kernel stack of a task, in a slot of its own
 pub struct KernelStack {
+    /// Pid of the task, for the messages
     pid: usize,
+    slot: usize,
+    /// The stack of an idle task, which has no pid and so no slot below the
+    /// trampoline
+    idle_stack: Option<Vec<u8>>,
 }
 
 impl KernelStack {
     pub fn new(pid_handle: &PidHandle) -> Self {
-        let pid = pid_handle.0;
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
+        // there are as many slots as pids
+        let slot = STACK_SLOTS
+            .exclusive_access()
+            .alloc()
+            .expect("more kernel stacks than pids");
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(slot);
         KERNEL_SPACE.exclusive_access().insert_framed_area(
             kernel_stack_bottom.into(),
             kernel_stack_top.into(),
             MapPermission::R | MapPermission::W,
         );
-        KernelStack { pid: pid_handle.0 }
+        paint(kernel_stack_bottom, kernel_stack_top);
+        KernelStack {
+            pid: pid_handle.0,
+            slot,
+            idle_stack: None,
+        }
+    }
+    /// A kernel stack on the kernel heap, for an idle task
+    pub fn new_idle() -> Self {
+        KernelStack {
+            pid: IDLE_PID,
+            slot: MAX_TASKS,
+            idle_stack: Some(vec![0; KERNEL_STACK_SIZE]),
+        }
+    }
+    /// Run `f` on the scratch stack of the hart, which is larger. This must
+    /// be the stack we are running on
+    pub fn with_large_stack<R>(&self, f: impl FnOnce() -> R) -> R {
+        on_scratch_stack(f)
     }
     #[allow(unused)]
     /// Push a variable of type T into the top of the KernelStack and return its raw pointer
@@ -100,17 +286,32 @@ impl KernelStack {
         ptr_mut
     }
     pub fn get_top(&self) -> usize {
-        let (_, kernel_stack_top) = kernel_stack_position(self.pid);
+        if let Some(stack) = &self.idle_stack {
+            // sp must stay 16-byte aligned
+            return (stack.as_ptr() as usize + stack.len()) & !0xf;
+        }
+        let (_, kernel_stack_top) = kernel_stack_position(self.slot);
         kernel_stack_top
     }
 }
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
+        if self.idle_stack.is_some() {
+            return;
+        }
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(self.slot);
+        let used = high_watermark(kernel_stack_bottom, kernel_stack_top);
+        if record_watermark(&TASK_STACK_PEAK, KERNEL_STACK_SIZE, used) {
+            warn!(
+                "[kernel] pid {} used {} of {} bytes of its kernel stack",
+                self.pid, used, KERNEL_STACK_SIZE
+            );
+        }
         let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
         KERNEL_SPACE
             .exclusive_access()
             .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        STACK_SLOTS.exclusive_access().dealloc(self.slot);
     }
 }
@@ -6,106 +6,255 @@
 
 
 use super::__switch;
-use super::{fetch_task, TaskStatus};
-use super::{TaskContext, TaskControlBlock};
-use crate::sync::UPSafeCell;
+use super::manager::has_ready_task;
+use super::{fetch_task, record_sched_event, SchedEventKind, TaskStatus, STATUS_TRANSITIONS};
+use super::{TaskContext, TaskControlBlock, IDLE_PID};
+use crate::kevent;
+use crate::percpu::{hart_id, this_hart};
+use crate::sync::InterruptGuard;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::config::PAGE_SIZE;
+use crate::mm::{copy_from_user, copy_to_user, tlb, user_chunks, FrameTracker, MapPermission, VirtAddr};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
-use lazy_static::*;
-use crate::timer::get_time_ms;
+use core::cell::{Cell, UnsafeCell};
+use crate::timer::{get_time_ms, get_time_us};
 use crate::config::MAX_SYSCALL_NUM;
+use crate::timer::{check_timer, set_idle_trigger, set_next_trigger};
+use crate::console::poll_output;
+use crate::trap::handle_idle_interrupts;
+use crate::syscall::{EFAULT, ENAMETOOLONG, ESRCH};
+use alloc::string::String;
+use core::mem::size_of;
+use crate::hotplug::stop_if_requested;
+use crate::shutdown::park_if_shutting_down;
 
 /// Processor management structure
+///
+/// It lives in the [`crate::percpu`] block of its hart, so it needs no lock.
 pub struct Processor {
-    /// The task currently executing on the current processor
-    current: Option<Arc<TaskControlBlock>>,
-    /// The basic control flow of each core, helping to select and switch process
-    idle_task_cx: TaskContext,
+    /// The task currently executing on the current processor, the idle task
+    /// if there is nothing else. `None` only while switching
+    current: Cell<Option<Arc<TaskControlBlock>>>,
+    /// The scheduling loop of [`run_tasks`], which every task switches back to
+    sched_cx: UnsafeCell<TaskContext>,
+    /// Runs when no task is ready, created on first use
+    idle_task: Cell<Option<Arc<TaskControlBlock>>>,
+    /// Pid of the task switched to last, for the switch events
+    last_pid: Cell<usize>,
 }
 
 impl Processor {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
-            current: None,
-            idle_task_cx: TaskContext::zero_init(),
+            current: Cell::new(None),
+            sched_cx: UnsafeCell::new(TaskContext::zero_init()),
+            idle_task: Cell::new(None),
+            last_pid: Cell::new(IDLE_PID),
         }
     }
-    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
-        &mut self.idle_task_cx as *mut _
+    fn get_sched_cx_ptr(&self) -> *mut TaskContext {
+        self.sched_cx.get()
+    }
+    fn idle_task(&self) -> Arc<TaskControlBlock> {
+        let idle = self
+            .idle_task
+            .take()
+            .unwrap_or_else(|| Arc::new(TaskControlBlock::new_idle(hart_id(), idle_loop)));
+        self.idle_task.set(Some(idle.clone()));
+        idle
     }
-    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.current.take()
+    /// Take the current task off this processor, charging it for the CPU
+    /// time it has used since it was switched in
+    pub fn take_current(&self) -> Option<Arc<TaskControlBlock>> {
+        let task = self.current.take()?;
+        let mut inner = task.inner_exclusive_access();
+        let now = get_time_us();
+        let used = now - inner.switch_in_us;
+        inner.cpu_time_us += used;
+        inner.update_cpu_usage(now, true);
+        drop(inner);
+        if task.is_idle() {
+            this_hart().idle_us.fetch_add(used, Ordering::Relaxed);
+        }
+        Some(task)
     }
     pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
-        self.current.as_ref().map(|task| Arc::clone(task))
+        let current = self.current.take();
+        let task = current.clone();
+        self.current.set(current);
+        task
     }
 }
 
-lazy_static! {
-    /// PROCESSOR instance through lazy_static!
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
-}
-
 /// The main part of process execution and scheduling
 ///
-/// Loop fetch_task to get the process that needs to run,
-/// and switch the process through __switch
+/// Loop fetch_task to get the process that needs to run, falling back to
+/// the idle task of the hart, and switch to it through __switch
+///
+/// A task is back in the ready queue, or woken, before the hart it ran on
+/// has saved its context in `__switch`. Until the switch returns here and
+/// clears [`TaskControlBlock::on_cpu`], another hart that fetched it waits.
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
-        if let Some(task) = fetch_task() {
-            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
-            // access coming task TCB exclusively
-            let mut task_inner = task.inner_exclusive_access();
-            let k = task.clone();
-            let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
-            task_inner.task_status = TaskStatus::Running;
-            drop(task_inner);
-            // release coming task TCB manually
-            processor.current = Some(task);
-            // release processor manually
-            drop(processor);
-            //info!("switch to {:?} in run tasks", k.pid.0);
-            unsafe {
-                __switch(idle_task_cx_ptr, next_task_cx_ptr);
-                //info!("switch ok....")
-            }
+        park_if_shutting_down();
+        stop_if_requested();
+        let guard = InterruptGuard::new();
+        let processor = &per_cpu!(&guard).processor;
+        let task = fetch_task().unwrap_or_else(|| processor.idle_task());
+        while task.on_cpu.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+        task.on_cpu.store(true, Ordering::Relaxed);
+        let running = task.clone();
+        let sched_cx_ptr = processor.get_sched_cx_ptr();
+        // access coming task TCB exclusively
+        let mut task_inner = task.inner_exclusive_access();
+        let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
+        task_inner.set_status(TaskStatus::Running);
+        // the time spent off the processor counts as idle
+        let now = get_time_us();
+        task_inner.update_cpu_usage(now, false);
+        task_inner.switch_in_us = now;
+        // the idle task has IDLE_PASS
+        this_hart().running_pass.store(task_inner.pass, Ordering::Relaxed);
+        let from = processor.last_pid.replace(task.pid.0);
+        if from != task.pid.0 {
+            kevent!(TaskSwitch { from: kevent::pid(from), to: kevent::pid(task.pid.0) });
+        }
+        if !task.is_idle() {
+            CONTEXT_SWITCHES.fetch_add(1, Ordering::Relaxed);
+            record_sched_event(SchedEventKind::SwitchIn, task.pid.0, task_inner.pass, task_inner.prio);
+        }
+        drop(task_inner);
+        // release coming task TCB manually
+        processor.current.set(Some(task));
+        // the kernel stack of the task may be new to this hart
+        tlb::sync();
+        // release processor manually
+        drop(guard);
+        unsafe {
+            __switch(sched_cx_ptr, next_task_cx_ptr);
+        }
+        // its context is saved, another hart may switch to it now
+        running.on_cpu.store(false, Ordering::Release);
+    }
+}
+
+/// Switches to a task other than the idle ones, on every hart
+static CONTEXT_SWITCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Switches to a task other than the idle ones since boot
+pub fn context_switches() -> usize {
+    CONTEXT_SWITCHES.load(Ordering::Relaxed)
+}
+
+/// Body of the idle tasks: sleep until an interrupt and go back to the
+/// scheduler once some task is ready
+fn idle_loop() -> ! {
+    loop {
+        handle_idle_interrupts();
+        // maybe some sleeping task is due
+        check_timer();
+        poll_output();
+        let kicked = this_hart().need_resched.swap(false, Ordering::Acquire);
+        if kicked || has_ready_task() {
+            // tasks are preempted by the tick
+            set_next_trigger();
+            yield_idle();
+        } else {
+            set_idle_trigger();
+            // returns at once if an interrupt came in since the checks above
+            unsafe { riscv::asm::wfi() };
         }
     }
 }
 
+/// Switch from the idle task back to the scheduler, without putting it on
+/// the ready queue
+fn yield_idle() {
+    let task = match take_current_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let mut inner = task.inner_exclusive_access();
+    inner.set_status(TaskStatus::Ready);
+    let task_cx_ptr = &mut inner.task_cx as *mut TaskContext;
+    drop(inner);
+    // the processor keeps the idle task alive
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    let guard = InterruptGuard::new();
+    per_cpu!(&guard).processor.take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    let guard = InterruptGuard::new();
+    per_cpu!(&guard).processor.current()
+}
+
+/// The current task if it has a user context, `None` on the idle task and
+/// in the window in which a hart switches tasks
+pub fn current_user_task() -> Option<Arc<TaskControlBlock>> {
+    current_task().filter(|task| !task.is_idle())
+}
+
+/// Run `f` on the current user task, `None` if there is none
+fn with_current_task<T>(f: impl FnOnce(&TaskControlBlock) -> T) -> Option<T> {
+    match current_user_task() {
+        Some(task) => Some(f(&task)),
+        None => {
+            warn!("[kernel] hart {} has no current user task", hart_id());
+            None
+        }
+    }
 }
 
 /// Get token of the address space of current task
-pub fn current_user_token() -> usize {
-    let task = current_task().unwrap();
-    let token = task.inner_exclusive_access().get_user_token();
-    token
+pub fn current_user_token() -> Option<usize> {
+    with_current_task(|task| task.get_user_token())
 }
 
 /// Get the mutable reference to trap context of current task
-pub fn current_trap_cx() -> &'static mut TrapContext {
-    current_task()
-        .unwrap()
-        .inner_exclusive_access()
-        .get_trap_cx()
+pub fn current_trap_cx() -> Option<&'static mut TrapContext> {
+    with_current_task(|task| task.inner_exclusive_access().get_trap_cx())
+}
+
+/// The current task trapped into the kernel, so the time since it last
+/// returned to user mode was user time
+pub fn account_user_time() {
+    with_current_task(|task| {
+        let mut inner = task.inner_exclusive_access();
+        inner.user_time_us += get_time_us() - inner.user_enter_us;
+    });
 }
 
-/// Return to idle control flow for new scheduling
+/// The current task is about to return to user mode
+pub fn mark_user_entry() {
+    with_current_task(|task| task.inner_exclusive_access().user_enter_us = get_time_us());
+}
+
+/// Return to the scheduling loop of the hart for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
-    let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
-    drop(processor);
+    assert!(
+        !this_hart().in_irq.load(Ordering::Relaxed),
+        "IRQ handlers must not block"
+    );
+    // a borrow kept across the switch fails in whatever runs next
+    #[cfg(debug_assertions)]
+    crate::sync::assert_none_held();
+    #[cfg(debug_assertions)]
+    crate::mm::assert_sum_clear();
+    let guard = InterruptGuard::new();
+    let sched_cx_ptr = per_cpu!(&guard).processor.get_sched_cx_ptr();
+    drop(guard);
     unsafe {
-        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+        __switch(switched_task_cx_ptr, sched_cx_ptr);
     }
 }
 
@@ -113,41 +262,202 @@ pub fn get_current_task_status() -> TaskStatus {
     TaskStatus::Running
 }
 
-pub fn get_current_task_costed_time() -> usize {
-    let task = current_task().unwrap();
-    let now = get_time_ms();
-    let first_time = task.inner_exclusive_access().first_time;
-    info!("task {:?} now time is {:?}", task.pid.0, now);
-    info!("task {:?} first time is {:?}", task.pid.0, first_time);
+pub fn get_current_task_costed_time() -> Option<usize> {
+    with_current_task(|task| {
+        let now = get_time_ms();
+        let first_time = task.inner_exclusive_access().first_time;
+        let costs = now - first_time ;
+        kevent!(TaskTime { pid: task.pid.0, first_ms: first_time, cost_ms: costs });
+        costs
+    })
+}
 
-    let costs = now - first_time ;
-    info!("task {:?} cost time {:?}",task.pid.0, costs);
-    costs
+pub fn add_one_to_current_task(call_id: usize) -> Option<()> {
+    with_current_task(|task| {
+        task.inner_exclusive_access().syscall_times[call_id] += 1;
+        //info!("add task {current} syscall {call_id} to {:?}", inner.tasks[current].syscall_times[call_id]);
+    })
+}
 
+pub fn get_current_task_syscall_times() -> Option<[u32; MAX_SYSCALL_NUM]> {
+    with_current_task(|task| task.inner_exclusive_access().syscall_times)
 }
 
-pub fn add_one_to_current_task(call_id: usize)  {
-    let task = current_task().unwrap();
-    
-    task.inner_exclusive_access().syscall_times[call_id] += 1;
-    //info!("add task {current} syscall {call_id} to {:?}", inner.tasks[current].syscall_times[call_id]);
+pub fn mmap(start: usize, len: usize, port: usize) -> Option<isize> {
+    with_current_task(|task| task.memory_set.exclusive_access().mmap(start, len, port))
 }
 
-pub fn get_current_task_syscall_times() -> [u32; MAX_SYSCALL_NUM] {
-    let task = current_task().unwrap();
-    let st = task.inner_exclusive_access().syscall_times.clone();
-    st
+/// Resolve a page fault of the current task on `va` by backing a lazy page,
+/// return false if the fault is a real one
+pub fn fault_in(va: usize, access: MapPermission) -> bool {
+    with_current_task(|task| {
+        task.memory_set
+            .exclusive_access()
+            .fault_in(VirtAddr::from(va).floor(), access)
+    })
+    .unwrap_or(false)
 }
 
-pub fn mmap( start: usize, len: usize, port: usize) -> isize {
-    let  task = current_task().unwrap();
-    let ret = task.inner_exclusive_access().memory_set.mmap(start, len, port);
-    ret
+/// Make the lazy pages of a user buffer resident before the kernel touches
+/// it, false if the buffer is not all user memory allowing `access`
+pub fn populate_user_buffer(start: usize, len: usize, access: MapPermission) -> bool {
+    try_populate_user_buffer(start, len, access).is_ok()
 }
 
-pub fn munmap( start: usize, len: usize ) -> isize {
-    let task = current_task().unwrap();
-    let ret = task.inner_exclusive_access().memory_set.munmap(start, len);
-    ret
-    
-}
\ No newline at end of file
+/// [`populate_user_buffer`] with the errno it fails with, `-ENOMEM` rather
+/// than `-EFAULT` if a page lent to a pipe cannot be copied to be written
+pub fn try_populate_user_buffer(
+    start: usize,
+    len: usize,
+    access: MapPermission,
+) -> Result<(), isize> {
+    with_current_task(|task| {
+        task.memory_set
+            .exclusive_access()
+            .populate(start, len, access)
+    })
+    .unwrap_or(Err(-EFAULT))
+}
+
+/// Read the `T` at the user pointer `ptr`, `-EFAULT` if it is not readable
+/// user memory
+pub fn read_user<T: Copy + Default>(ptr: *const T) -> Result<T, isize> {
+    let token = current_user_token().ok_or(-ESRCH)?;
+    if !populate_user_buffer(ptr as usize, size_of::<T>(), MapPermission::R) {
+        return Err(-EFAULT);
+    }
+    let mut value = [T::default()];
+    if copy_from_user(token, ptr, &mut value) {
+        Ok(value[0])
+    } else {
+        Err(-EFAULT)
+    }
+}
+
+/// Write `value` to the user pointer `ptr`, `-EFAULT` if it is not writable
+/// user memory
+pub fn write_user<T: Copy>(ptr: *mut T, value: T) -> Result<(), isize> {
+    let token = current_user_token().ok_or(-ESRCH)?;
+    try_populate_user_buffer(ptr as usize, size_of::<T>(), MapPermission::W)?;
+    if copy_to_user(token, ptr, &[value]) {
+        Ok(())
+    } else {
+        Err(-EFAULT)
+    }
+}
+
+/// The NUL-terminated string at the user pointer `ptr`, faulted in a page
+/// at a time so that nothing past its end needs to be mapped.
+/// `-ENAMETOOLONG` if it is longer than `max` bytes, `-EFAULT` if it runs
+/// into memory that is not readable
+pub fn read_user_str(ptr: *const u8, max: usize) -> Result<String, isize> {
+    let token = current_user_token().ok_or(-ESRCH)?;
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    loop {
+        let len = PAGE_SIZE - va % PAGE_SIZE;
+        if !populate_user_buffer(va, len, MapPermission::R) {
+            return Err(-EFAULT);
+        }
+        for chunk in user_chunks(token, va as *const u8, len) {
+            for &byte in chunk.iter() {
+                if byte == 0 {
+                    return Ok(string);
+                }
+                if string.len() == max {
+                    return Err(-ENAMETOOLONG);
+                }
+                string.push(byte as char);
+            }
+        }
+        va += len;
+    }
+}
+
+/// Lend the frame of the resident user page at `va`, e.g. to a pipe, the
+/// page stays read-only until the task writes it again
+pub fn lend_user_page(va: usize) -> Option<Arc<FrameTracker>> {
+    with_current_task(|task| {
+        task.memory_set
+            .exclusive_access()
+            .lend_page(VirtAddr::from(va).floor())
+    })
+    .flatten()
+}
+
+pub fn madvise_dontneed(start: usize, len: usize) -> Option<isize> {
+    with_current_task(|task| task.memory_set.exclusive_access().discard(start, len))
+}
+
+pub fn mprotect(start: usize, len: usize, port: usize) -> Option<isize> {
+    with_current_task(|task| task.memory_set.exclusive_access().mprotect(start, len, port))
+}
+
+pub fn munmap(start: usize, len: usize) -> Option<isize> {
+    with_current_task(|task| task.memory_set.exclusive_access().munmap(start, len))
+}
+
+/// The helpers above must fail gracefully where there is no user task: at
+/// boot, before the first switch, and on the idle task
+pub fn current_task_test() {
+    for context in ["boot", "idle"] {
+        let guard = InterruptGuard::new();
+        let processor = &per_cpu!(&guard).processor;
+        if context == "idle" {
+            processor.current.set(Some(processor.idle_task()));
+        }
+        assert!(current_user_task().is_none(), "current_task_test: {}", context);
+        assert!(current_user_token().is_none(), "current_task_test: {}", context);
+        assert!(current_trap_cx().is_none(), "current_task_test: {}", context);
+        assert!(add_one_to_current_task(0).is_none(), "current_task_test: {}", context);
+        assert!(get_current_task_costed_time().is_none(), "current_task_test: {}", context);
+        assert!(get_current_task_syscall_times().is_none(), "current_task_test: {}", context);
+        assert!(mmap(0x1000_0000, 0x1000, 0x3).is_none(), "current_task_test: {}", context);
+        assert!(munmap(0x1000_0000, 0x1000).is_none(), "current_task_test: {}", context);
+        assert!(!fault_in(0x1000_0000, MapPermission::R), "current_task_test: {}", context);
+        assert_eq!(
+            super::sys_mmap_inner(0x1000_0000, 0x1000, 0x3),
+            -ESRCH,
+            "current_task_test: {}",
+            context
+        );
+        processor.current.take();
+        drop(guard);
+    }
+    info!("current_task_test passed!");
+}
+
+/// Walk the idle task of the boot hart through every legal status
+/// transition, and check some illegal ones are refused
+pub fn status_test() {
+    use TaskStatus::*;
+    let guard = InterruptGuard::new();
+    let idle = per_cpu!(&guard).processor.idle_task();
+    drop(guard);
+    let mut inner = idle.inner_exclusive_access();
+    let saved = inner.task_status;
+    inner.task_status = UnInit;
+    let walk = [
+        Ready, Running, Ready, Running, Blocking, Ready, Running, Stopped, Ready, Running, Zombie,
+    ];
+    let mut edges = 0;
+    for &status in walk.iter() {
+        let old = inner.task_status;
+        assert_eq!(inner.set_status(status), old, "status_test: {:?} -> {:?}", old, status);
+        edges |= 1 << STATUS_TRANSITIONS.iter().position(|&edge| edge == (old, status)).unwrap();
+    }
+    assert_eq!(edges, (1 << STATUS_TRANSITIONS.len()) - 1, "status_test: edges left out");
+    inner.task_status = saved;
+    let illegal = [
+        (Zombie, Running),
+        (Zombie, Ready),
+        (Blocking, Running),
+        (Ready, Blocking),
+        (Running, Running),
+        (Stopped, Running),
+    ];
+    for &(from, to) in illegal.iter() {
+        assert!(!from.can_become(to), "status_test: {:?} -> {:?}", from, to);
+    }
+    info!("status_test passed!");
+}
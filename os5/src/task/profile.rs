@@ -0,0 +1,137 @@
+//! One-shot sampling profiler
+//!
+//! A profile armed for a task records the `sepc` of its trap context at each
+//! timer interrupt that finds it running in user mode, up to the number of
+//! samples asked for; later ones are only counted as dropped. With no
+//! profile armed anywhere the timer interrupt looks at one counter and goes
+//! on.
+//!
+//! The samples are allocated up front, so that the timer interrupt only
+//! stores. All profiles together may hold at most
+//! [`PROFILE_TOTAL_SAMPLES`], a few of them as large as they can be.
+
+use super::task::{TaskControlBlock, TaskControlBlockInner};
+use super::current_task;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Most samples a profile keeps
+pub const PROFILE_MAX_SAMPLES: usize = 8192;
+/// Most samples all profiles keep together, of the kernel heap
+pub const PROFILE_TOTAL_SAMPLES: usize = 2 * PROFILE_MAX_SAMPLES;
+
+/// Profiles armed in the whole system
+static ARMED: AtomicUsize = AtomicUsize::new(0);
+/// Samples the profiles that exist have room for
+static RESERVED: AtomicUsize = AtomicUsize::new(0);
+
+/// Why [`profile_start`] failed
+pub enum ProfileError {
+    /// The task has a profile already
+    Busy,
+    /// Other profiles hold too much of [`PROFILE_TOTAL_SAMPLES`]
+    NoRoom,
+}
+
+pub struct Profile {
+    /// Pid of the task that started it, the only one that may stop it
+    pub owner: usize,
+    /// Where the owner wants the samples, in its address space
+    pub buf: usize,
+    max_samples: usize,
+    pub samples: Vec<usize>,
+    /// Samples there was no room for
+    pub dropped: usize,
+    /// Still sampling, until stopped or the task exits
+    armed: bool,
+}
+
+impl Drop for Profile {
+    fn drop(&mut self) {
+        RESERVED.fetch_sub(self.max_samples, Ordering::Relaxed);
+    }
+}
+
+/// Arm a profile of up to `max_samples` for `task`, to be stopped by
+/// `owner` and copied to `buf`
+pub fn profile_start(
+    task: &TaskControlBlock,
+    owner: usize,
+    buf: usize,
+    max_samples: usize,
+) -> Result<(), ProfileError> {
+    let mut inner = task.inner_exclusive_access();
+    if inner.profile.is_some() {
+        return Err(ProfileError::Busy);
+    }
+    let max_samples = max_samples.min(PROFILE_MAX_SAMPLES);
+    RESERVED
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |reserved| {
+            Some(reserved + max_samples).filter(|&total| total <= PROFILE_TOTAL_SAMPLES)
+        })
+        .map_err(|_| ProfileError::NoRoom)?;
+    inner.profile = Some(Profile {
+        owner,
+        buf,
+        max_samples,
+        samples: Vec::with_capacity(max_samples),
+        dropped: 0,
+        armed: true,
+    });
+    ARMED.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Take the profile of `task` if `owner` started it, disarmed
+pub fn profile_stop(task: &TaskControlBlock, owner: usize) -> Option<Profile> {
+    let mut inner = task.inner_exclusive_access();
+    if inner.profile.as_ref()?.owner != owner {
+        return None;
+    }
+    disarm(&mut inner);
+    let profile = inner.profile.take()?;
+    #[cfg(feature = "trace_uart")]
+    crate::trace::emit_profile(task.getpid(), &profile.samples);
+    Some(profile)
+}
+
+/// Stop sampling `inner`, which keeps its samples for the owner, as the
+/// task exits
+pub fn disarm(inner: &mut TaskControlBlockInner) {
+    if let Some(profile) = inner.profile.as_mut() {
+        if profile.armed {
+            profile.armed = false;
+            ARMED.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Drop the profile of `inner` if `owner` started it, whose buffer is gone
+pub fn drop_profile(inner: &mut TaskControlBlockInner, owner: usize) {
+    let profile = inner.profile.as_ref();
+    if profile.map_or(false, |profile| profile.owner == owner) {
+        disarm(inner);
+        inner.profile = None;
+    }
+}
+
+/// Record where the current task is if it is being profiled, called by the
+/// timer interrupt from user mode
+pub fn profile_tick() {
+    if ARMED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let task = match current_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let mut inner = task.inner_exclusive_access();
+    let sepc = inner.get_trap_cx().sepc;
+    if let Some(profile) = inner.profile.as_mut().filter(|profile| profile.armed) {
+        if profile.samples.len() < profile.max_samples {
+            profile.samples.push(sepc);
+        } else {
+            profile.dropped += 1;
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Log of scheduling decisions, for analysing the scheduler without
+//! perturbing it with prints
+//!
+//! Every hart records into a ring of its own, so recording is a handful of
+//! relaxed stores and a release store of the ring head, with no lock. When a
+//! ring is full the oldest events are overwritten; the reader notices that it
+//! has been lapped and counts the lost events as dropped.
+
+use crate::config::MAX_HARTS;
+use crate::percpu::hart_id;
+use crate::sync::{InterruptGuard, UPSafeCell};
+use crate::timer::get_time;
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, AtomicIsize, AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// Events each hart keeps
+const RING_SIZE: usize = 256;
+
+#[derive(Copy, Clone)]
+pub enum SchedEventKind {
+    /// The task got the hart
+    SwitchIn = 0,
+    /// The task gave up the hart but is still ready
+    SwitchOut = 1,
+    /// The task gave up the hart to wait for something
+    Block = 2,
+    /// The task was made ready again after blocking
+    Wake = 3,
+}
+
+/// A recorded event as handed to user space
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SchedEvent {
+    /// `mtime` when it happened
+    pub tick: usize,
+    pub hart: usize,
+    /// A [`SchedEventKind`]
+    pub kind: usize,
+    pub pid: usize,
+    /// Stride pass and priority of the task at that point
+    pub pass: isize,
+    pub prio: isize,
+}
+
+struct Slot {
+    tick: AtomicUsize,
+    kind: AtomicUsize,
+    pid: AtomicUsize,
+    pass: AtomicIsize,
+    prio: AtomicIsize,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            tick: AtomicUsize::new(0),
+            kind: AtomicUsize::new(0),
+            pid: AtomicUsize::new(0),
+            pass: AtomicIsize::new(0),
+            prio: AtomicIsize::new(0),
+        }
+    }
+}
+
+struct Ring {
+    slots: [Slot; RING_SIZE],
+    /// Events ever recorded, only moved by the owning hart
+    head: AtomicUsize,
+}
+
+impl Ring {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const SLOT_INIT: Slot = Slot::new();
+
+    const fn new() -> Self {
+        Self {
+            slots: [Self::SLOT_INIT; RING_SIZE],
+            head: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const RING_INIT: Ring = Ring::new();
+static RINGS: [Ring; MAX_HARTS] = [RING_INIT; MAX_HARTS];
+
+/// Where the reader is in each ring
+struct Reader {
+    /// Events of each ring read or dropped so far
+    tails: [usize; MAX_HARTS],
+    /// Events lost since the last [`drain`]
+    dropped: usize,
+}
+
+lazy_static! {
+    static ref READER: UPSafeCell<Reader> = unsafe {
+        UPSafeCell::new(Reader {
+            tails: [0; MAX_HARTS],
+            dropped: 0,
+        })
+    };
+}
+
+/// Record an event of task `pid` on the current hart
+pub fn record(kind: SchedEventKind, pid: usize, pass: isize, prio: isize) {
+    // an interrupt on this hart must not record into the same slot
+    let _guard = InterruptGuard::new();
+    let ring = &RINGS[hart_id()];
+    let head = ring.head.load(Ordering::Relaxed);
+    let slot = &ring.slots[head % RING_SIZE];
+    let tick = get_time();
+    slot.tick.store(tick, Ordering::Relaxed);
+    slot.kind.store(kind as usize, Ordering::Relaxed);
+    slot.pid.store(pid, Ordering::Relaxed);
+    slot.pass.store(pass, Ordering::Relaxed);
+    slot.prio.store(prio, Ordering::Relaxed);
+    ring.head.store(head + 1, Ordering::Release);
+    #[cfg(feature = "trace_uart")]
+    crate::trace::emit(
+        crate::trace::Stream::Sched,
+        &[
+            tick as u64,
+            hart_id() as u64,
+            kind as u64,
+            pid as u64,
+            pass as u64,
+            prio as u64,
+        ],
+    );
+}
+
+/// Take up to `max` of the oldest recorded events, ring by ring, and the
+/// number of events dropped since the last call
+pub fn drain(max: usize) -> (Vec<SchedEvent>, usize) {
+    let mut reader = READER.exclusive_access();
+    let mut events = Vec::new();
+    for (hart, ring) in RINGS.iter().enumerate() {
+        let head = ring.head.load(Ordering::Acquire);
+        let mut tail = reader.tails[hart];
+        if head - tail > RING_SIZE {
+            reader.dropped += head - RING_SIZE - tail;
+            tail = head - RING_SIZE;
+        }
+        while tail < head && events.len() < max {
+            let slot = &ring.slots[tail % RING_SIZE];
+            let event = SchedEvent {
+                tick: slot.tick.load(Ordering::Relaxed),
+                hart,
+                kind: slot.kind.load(Ordering::Relaxed),
+                pid: slot.pid.load(Ordering::Relaxed),
+                pass: slot.pass.load(Ordering::Relaxed),
+                prio: slot.prio.load(Ordering::Relaxed),
+            };
+            fence(Ordering::Acquire);
+            // the slot may have been reused by the time we read it
+            if ring.head.load(Ordering::Relaxed) - tail >= RING_SIZE {
+                reader.dropped += 1;
+            } else {
+                events.push(event);
+            }
+            tail += 1;
+        }
+        reader.tails[hart] = tail;
+    }
+    let dropped = reader.dropped;
+    reader.dropped = 0;
+    (events, dropped)
+}
@@ -0,0 +1,120 @@
+//! Signal numbers, actions and the information delivered with them
+
+pub const MAX_SIG: usize = 31;
+/// Handler value for the default action
+pub const SIG_DFL: usize = 0;
+
+bitflags! {
+    /// One bit per signal number
+    pub struct SignalFlags: u32 {
+        const SIGDEF = 1;
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The flag of signal number `signum`, `None` if out of range
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            None
+        } else {
+            Self::from_bits(1 << signum)
+        }
+    }
+    /// Signals that can be neither blocked nor caught
+    pub fn unblockable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+    /// Signals dropped rather than killing when their action is the default
+    pub fn ignored_by_default() -> Self {
+        Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH
+    }
+}
+
+/// `how` of `sys_sigprocmask`: add `set` to the mask
+pub const SIG_BLOCK: usize = 0;
+/// Remove `set` from the mask
+pub const SIG_UNBLOCK: usize = 1;
+/// Replace the mask with `set`
+pub const SIG_SETMASK: usize = 2;
+
+/// Action taken on a signal
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    /// User handler entry, [`SIG_DFL`] for the default action
+    pub handler: usize,
+    /// Signals blocked while the handler runs
+    pub mask: SignalFlags,
+    /// `SA_*` bits
+    pub flags: u32,
+}
+
+/// Restart a blocking syscall the signal interrupted once the handler
+/// returns, instead of failing it with `-EINTR`
+pub const SA_RESTART: u32 = 0x1000_0000;
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::SIGQUIT | SignalFlags::SIGTRAP,
+            flags: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+/// Pushed to the user stack for the handler, its address is the second
+/// argument
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigInfo {
+    pub signo: usize,
+    /// `scause` of the fault, 0 if the signal was not caused by a fault
+    pub cause: usize,
+    /// Faulting address
+    pub addr: usize,
+}
@@ -0,0 +1,125 @@
+//! Restricting the syscalls a process may make
+//!
+//! A task with a [`SyscallFilter`] may make only the syscalls it allows,
+//! any other fails with `-EPERM` or kills the task with `SIGSYS`, before
+//! the handler looks at what it was passed. The filter goes on to the
+//! children of the task, forked or spawned, and through exec, and can only
+//! be narrowed afterwards. With no filter anywhere a syscall looks at one
+//! counter and goes on.
+
+use super::current_user_task;
+use super::task::TaskControlBlockInner;
+use crate::config::MAX_SYSCALL_NUM;
+use crate::syscall::nr;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A denied syscall fails with `-EPERM`
+pub const FILTER_ERRNO: usize = 0;
+/// A denied syscall kills the task with `SIGSYS`
+pub const FILTER_KILL: usize = 1;
+
+const FILTER_WORDS: usize = (MAX_SYSCALL_NUM + 63) / 64;
+
+/// Tasks with a filter in the whole system, zombies included
+static FILTERED: AtomicUsize = AtomicUsize::new(0);
+
+/// The syscalls a task may make, and what becomes of the others
+pub struct SyscallFilter {
+    allowed: [u64; FILTER_WORDS],
+    kill: bool,
+}
+
+impl SyscallFilter {
+    /// A filter allowing `ids`, whose denied syscalls get `action`, `None`
+    /// for an id at or above [`MAX_SYSCALL_NUM`] or an unknown action
+    pub fn new(ids: &[usize], action: usize) -> Option<Self> {
+        if action != FILTER_ERRNO && action != FILTER_KILL {
+            return None;
+        }
+        let mut allowed = [0; FILTER_WORDS];
+        for &id in ids {
+            if id >= MAX_SYSCALL_NUM {
+                return None;
+            }
+            allowed[id / 64] |= 1 << (id % 64);
+        }
+        Some(Self {
+            allowed,
+            kill: action == FILTER_KILL,
+        })
+    }
+    /// Whether syscall `id` may be made: exit and sigreturn always may, so
+    /// that a task can still end and leave a signal handler
+    pub fn allows(&self, id: usize) -> bool {
+        if id == nr::EXIT || id == nr::SIGRETURN {
+            return true;
+        }
+        id < MAX_SYSCALL_NUM && self.allowed[id / 64] & 1 << (id % 64) != 0
+    }
+    /// Whether `self` allows nothing `old` does not, and is as strict about
+    /// the rest
+    fn narrows(&self, old: &SyscallFilter) -> bool {
+        let subset = self.allowed.iter().zip(old.allowed.iter()).all(|(new, old)| new & !old == 0);
+        subset && (self.kill || !old.kill)
+    }
+}
+
+/// The filter of a task, shared with the tasks it went on to
+pub struct TaskFilter(Arc<SyscallFilter>);
+
+impl Clone for TaskFilter {
+    fn clone(&self) -> Self {
+        FILTERED.fetch_add(1, Ordering::Relaxed);
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for TaskFilter {
+    fn drop(&mut self) {
+        FILTERED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// What the filter of the current task makes of a syscall
+pub enum FilterVerdict {
+    Allow,
+    /// Fail it with `-EPERM`
+    Deny,
+    /// Kill the task with `SIGSYS`
+    Kill,
+}
+
+/// Whether `inner` may be given `filter`, which must allow nothing the one
+/// it has does not
+pub fn may_set_syscall_filter(inner: &TaskControlBlockInner, filter: &SyscallFilter) -> bool {
+    inner.syscall_filter.as_ref().map_or(true, |old| filter.narrows(&old.0))
+}
+
+/// Give `inner` `filter`, or narrow the one it has to it. `false`, and the
+/// filter is left as it was, if that would allow more than before
+pub fn set_syscall_filter(inner: &mut TaskControlBlockInner, filter: SyscallFilter) -> bool {
+    if !may_set_syscall_filter(inner, &filter) {
+        return false;
+    }
+    FILTERED.fetch_add(1, Ordering::Relaxed);
+    inner.syscall_filter = Some(TaskFilter(Arc::new(filter)));
+    true
+}
+
+/// Whether the current task may make syscall `id`
+pub fn check_syscall_filter(id: usize) -> FilterVerdict {
+    if FILTERED.load(Ordering::Relaxed) == 0 {
+        return FilterVerdict::Allow;
+    }
+    let task = match current_user_task() {
+        Some(task) => task,
+        None => return FilterVerdict::Allow,
+    };
+    let inner = task.inner_exclusive_access();
+    match inner.syscall_filter.as_ref() {
+        Some(filter) if !filter.0.allows(id) && filter.0.kill => FilterVerdict::Kill,
+        Some(filter) if !filter.0.allows(id) => FilterVerdict::Deny,
+        _ => FilterVerdict::Allow,
+    }
+}
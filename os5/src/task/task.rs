@@ -1,17 +1,28 @@
 //! Types related to task management & Functions for completely changing TCB
 
 use super::TaskContext;
-use super::{pid_alloc, KernelStack, PidHandle};
-use crate::config::TRAP_CONTEXT;
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
-use crate::timer::get_time_ms;
+use super::audit::Audit;
+use super::syscall_filter::TaskFilter;
+use super::profile::{disarm, drop_profile, Profile};
+use super::{pid_alloc, KernelStack, PidHandle, IDLE_PID};
+use super::signal::{SigInfo, SignalActions, SignalFlags, MAX_SIG, SIG_DFL};
+use crate::fs::{FileDescriptor, Stdin, Stdout, MAX_FD};
+use crate::mm::{
+    frame_allocator_free, reclaim, copy_to_user, Memory, MemorySet, PhysPageNum, KERNEL_SPACE,
+};
+use crate::percpu::IDLE_PASS;
+use crate::sync::{mutex_close, mutex_dup, semaphore_close, semaphore_dup, UPRefMut, UPSafeCell};
+use crate::timer::{get_time_ms, get_time_us};
 use crate::trap::{trap_handler, TrapContext};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use core::{cell::RefMut, char::MAX};
+use core::char::MAX;
 pub use crate::config::MAX_SYSCALL_NUM;
 use core::cmp::Ordering;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+use core::fmt;
 
 /// Task control block structure
 ///
@@ -23,6 +34,15 @@ pub struct TaskControlBlock {
     /// Kernel stack corresponding to PID
     pub kernel_stack: KernelStack,
     // mutable
+    /// Application address space, under a lock of its own so that faults
+    /// and user copies need not take `inner`.
+    ///
+    /// Lock it after `inner` when both are needed, and hold neither while
+    /// touching user memory, which may fault.
+    pub memory_set: Arc<UPSafeCell<MemorySet>>,
+    /// Some hart runs the task or has yet to save its context, see
+    /// [`super::processor::run_tasks`]
+    pub on_cpu: AtomicBool,
     inner: UPSafeCell<TaskControlBlockInner>,
 }
 
@@ -61,24 +81,157 @@ pub struct TaskControlBlockInner {
     pub base_size: usize,
     /// Save task context
     pub task_cx: TaskContext,
-    /// Maintain the execution status of the current process
+    /// Maintain the execution status of the current process, only changed
+    /// through [`Self::set_status`]
     pub task_status: TaskStatus,
-    /// Application address space
-    pub memory_set: MemorySet,
+    /// Name of the app the task runs
+    pub name: String,
     /// Parent process of the current process.
     /// Weak will not affect the reference count of the parent
     pub parent: Option<Weak<TaskControlBlock>>,
     /// A vector containing TCBs of all child processes of the current process
     pub children: Vec<Arc<TaskControlBlock>>,
-    /// It is set when active exit or execution error occurs
+    /// Orphaned descendants come to this process rather than to initproc,
+    /// set with `PR_SET_CHILD_SUBREAPER`
+    pub child_subreaper: bool,
+    /// Wait status, set when active exit or execution error occurs
     pub exit_code: i32,
+    /// Signal the task is being killed by, 0 if it exits by itself
+    pub term_signal: usize,
     pub first_time: usize,
     pub dispatched: bool, 
     pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// How the calls of each syscall ended, by number, counted as the
+    /// dispatcher returns; only syscalls that returned at least once
+    pub syscall_stats: BTreeMap<usize, SyscallStat>,
     pub pass: isize,
     pub prio: isize,
+    /// Total CPU time consumed, in microseconds
+    pub cpu_time_us: usize,
+    /// When the task was last switched in, in microseconds
+    pub switch_in_us: usize,
+    /// Moving average of CPU usage, in units of 1 / USAGE_SCALE
+    pub cpu_usage: usize,
+    /// When `cpu_usage` was last updated, in microseconds
+    pub usage_update_us: usize,
+    /// Part of `cpu_time_us` spent in user mode
+    pub user_time_us: usize,
+    /// When the task last returned to user mode, in microseconds
+    pub user_enter_us: usize,
+    /// Times the task gave up the CPU itself
+    pub nvcsw: usize,
+    /// Times the task was preempted
+    pub nivcsw: usize,
+    /// Usage of the reaped children, including what they reaped themselves
+    pub children_usage: Rusage,
+    /// Priority lent by the tasks waiting for a mutex this task holds, 0 if
+    /// none
+    pub inherited_prio: isize,
+    /// Ids of the mutexes the task holds
+    pub held_mutexes: Vec<usize>,
+    /// Id of the mutex the task is waiting for
+    pub blocked_on: Option<usize>,
+    /// In the ready queue, from [`super::add_task`] until it is fetched
+    pub on_queue: bool,
+    /// Woken while still running, between publishing its [`Wait`] and
+    /// blocking, which [`super::block_current_and_run_next`] then skips
+    pub wake_pending: bool,
+    /// Samples of where it runs, see [`super::profile`]
+    pub profile: Option<Profile>,
+    /// Record of its syscalls, see [`super::audit`]
+    pub audit: Option<Audit>,
+    /// Bytes of the audit rings this task started
+    pub audit_charge: Arc<AtomicUsize>,
+    /// Syscalls it is restricted to, see [`super::syscall_filter`]
+    pub syscall_filter: Option<TaskFilter>,
+    /// What the task waits for, cleared as it is woken up
+    pub block_reason: Option<BlockReason>,
+    /// Number of the last syscall the task made and when it entered it, in
+    /// milliseconds
+    pub last_syscall: Option<(usize, usize)>,
+    /// Bit `i` set if the task may run on hart `i`
+    pub cpu_mask: usize,
+    /// Pending signals, blocked ones stay here until unblocked
+    pub signals: SignalFlags,
+    /// Blocked signals, kept across fork and exec
+    pub signal_mask: SignalFlags,
+    pub signal_actions: SignalActions,
+    /// Signal number whose handler is running, -1 if none
+    pub handling_sig: isize,
+    /// User context interrupted by the running handler
+    pub trap_ctx_backup: Option<TrapContext>,
+    /// `signal_mask` to restore when the running handler returns
+    pub signal_mask_backup: SignalFlags,
+    /// The wait a blocked task is in that a signal can take it out of
+    pub wait: Option<Wait>,
+    /// The last wait ended because of a signal, until the syscall takes it
+    pub interrupted: bool,
+    /// Mask sigsuspend replaced, back once the signal it waited for is handled
+    pub saved_signal_mask: Option<SignalFlags>,
+    /// Information for a pending fault signal
+    pub fault_info: Option<SigInfo>,
+    /// `(sepc, addr)` of the fault whose handler is running
+    pub fault_site: Option<(usize, usize)>,
+    /// `(sepc, addr)` of the fault a handler has just returned from, only
+    /// valid until the next trap
+    pub fault_retry: Option<(usize, usize)>,
+    /// Open files, indexed by fd
+    pub fd_table: Vec<Option<FileDescriptor>>,
+    /// Global ids of the semaphores behind the task's semaphore handles,
+    /// indexed by handle
+    pub semaphores: Vec<Option<usize>>,
+    /// Global ids of the mutexes behind the task's mutex handles, indexed
+    /// by handle
+    pub mutexes: Vec<Option<usize>>,
+    /// Normalized absolute working directory that relative ramfs paths are
+    /// taken from, kept across exec
+    pub cwd: String,
+}
+
+/// Why [`TaskControlBlock::exec`] failed
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExecError {
+    /// Not an ELF that can be loaded, the old image is untouched
+    BadElf,
+    /// Not enough frames for the new image, the old one is untouched
+    NoMemory,
+    /// Not enough frames even after freeing the old image, so the task has
+    /// nothing left to run
+    ImageLost,
+}
+
+/// Resource usage of a task, as `wait4` reports it
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Rusage {
+    /// CPU time in user mode, in microseconds
+    pub utime_us: usize,
+    /// CPU time in the kernel, in microseconds
+    pub stime_us: usize,
+    /// Peak resident pages
+    pub maxrss_pages: usize,
+    /// Voluntary context switches
+    pub nvcsw: usize,
+    /// Involuntary context switches
+    pub nivcsw: usize,
 }
 
+impl Rusage {
+    /// Add the usage of another task, the peak is the larger one
+    pub fn add(&mut self, other: &Rusage) {
+        self.utime_us += other.utime_us;
+        self.stime_us += other.stime_us;
+        self.maxrss_pages = self.maxrss_pages.max(other.maxrss_pages);
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+}
+
+/// CPU usage of 100%
+pub const USAGE_SCALE: usize = 10000;
+/// Time constant of the CPU usage moving average, in microseconds
+const USAGE_PERIOD_US: usize = 1_000_000;
+
 /// Simple access to its internal fields
 impl TaskControlBlockInner {
     /*
@@ -89,8 +242,29 @@ impl TaskControlBlockInner {
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+    /// Priority the scheduler uses, possibly raised by priority inheritance
+    pub fn effective_prio(&self) -> isize {
+        self.prio.max(self.inherited_prio)
+    }
+    /// Whether `signal` is one whose default action is to drop it, and that
+    /// action is the one set
+    pub fn ignores(&self, signal: SignalFlags) -> bool {
+        SignalFlags::ignored_by_default().contains(signal)
+            && self.signal_actions.table[signal.bits().trailing_zeros() as usize].handler == SIG_DFL
+    }
+    /// Whether `handle_signals` would act on one of the pending signals,
+    /// by starting a handler or by killing the task
+    pub fn has_deliverable_signal(&self) -> bool {
+        let pending = self.signals - self.signal_mask;
+        (1..=MAX_SIG)
+            .filter_map(SignalFlags::from_signum)
+            .filter(|signal| pending.contains(*signal) && !self.ignores(*signal))
+            .any(|signal| {
+                self.handling_sig == -1
+                    || SignalFlags::unblockable().contains(signal)
+                    || self.signal_actions.table[signal.bits().trailing_zeros() as usize].handler
+                        == SIG_DFL
+            })
     }
     fn get_status(&self) -> TaskStatus {
         self.task_status
@@ -98,47 +272,245 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
+    /// The lowest free fd, the table grows if there is none. `None` if all
+    /// [`MAX_FD`] are open
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            Some(fd)
+        } else if self.fd_table.len() < MAX_FD {
+            self.fd_table.push(None);
+            Some(self.fd_table.len() - 1)
+        } else {
+            None
+        }
+    }
+    /// The file of `fd`, `None` if it is not open
+    pub fn file(&self, fd: usize) -> Option<&FileDescriptor> {
+        self.fd_table.get(fd)?.as_ref()
+    }
+    /// Put semaphore `id` behind the lowest free handle and return it
+    pub fn alloc_semaphore(&mut self, id: usize) -> usize {
+        alloc_handle(&mut self.semaphores, id)
+    }
+    /// The global id of the semaphore behind `handle`
+    pub fn semaphore(&self, handle: usize) -> Option<usize> {
+        *self.semaphores.get(handle)?
+    }
+    /// Put mutex `id` behind the lowest free handle and return it
+    pub fn alloc_mutex(&mut self, id: usize) -> usize {
+        alloc_handle(&mut self.mutexes, id)
+    }
+    /// The global id of the mutex behind `handle`
+    pub fn mutex(&self, handle: usize) -> Option<usize> {
+        *self.mutexes.get(handle)?
+    }
+    /// CPU usage as of `now_us`, counting the time since the last update as
+    /// running or not according to `running`.
+    ///
+    /// The average decays linearly over one `USAGE_PERIOD_US` window, which
+    /// keeps everything in integers.
+    pub fn cpu_usage_at(&self, now_us: usize, running: bool) -> usize {
+        let weight = (now_us - self.usage_update_us).min(USAGE_PERIOD_US);
+        let sample = if running { USAGE_SCALE } else { 0 };
+        (self.cpu_usage * (USAGE_PERIOD_US - weight) + sample * weight) / USAGE_PERIOD_US
+    }
+    /// Fold the time since the last update into the CPU usage average
+    pub fn update_cpu_usage(&mut self, now_us: usize, running: bool) {
+        self.cpu_usage = self.cpu_usage_at(now_us, running);
+        self.usage_update_us = now_us;
+    }
+    /// Total CPU time as of `now_us`, including the current time slice
+    pub fn cpu_time_at(&self, now_us: usize) -> usize {
+        if self.task_status == TaskStatus::Running {
+            self.cpu_time_us + (now_us - self.switch_in_us)
+        } else {
+            self.cpu_time_us
+        }
+    }
+    /// Move the task to `status` and return the one it had
+    ///
+    /// A transition [`TaskStatus::can_become`] does not allow is a bug in
+    /// the caller: debug builds panic, release builds log it and make it
+    /// anyway.
+    #[track_caller]
+    pub fn set_status(&mut self, status: TaskStatus) -> TaskStatus {
+        let old = self.task_status;
+        if !old.can_become(status) {
+            if cfg!(debug_assertions) {
+                panic!("task status {:?} -> {:?}", old, status);
+            }
+            error!(
+                "[kernel] task status {:?} -> {:?} at {}",
+                old,
+                status,
+                core::panic::Location::caller()
+            );
+        }
+        self.task_status = status;
+        old
+    }
+    /// Drop what belonged to the old image as task `pid` execs `name`
+    ///
+    /// Handlers go back to the default action and files marked close-on-exec
+    /// are closed, a profile stops sampling and is gone if the task profiled
+    /// itself, its buffer went with the old image, as are the profiles it
+    /// started of its children. Pending and blocked
+    /// signals, the other files, cwd, times and relatives stay. The address
+    /// space, comm page and trap context are up to the caller.
+    pub fn reset_for_exec(&mut self, pid: usize, name: &str) {
+        self.name = String::from(name);
+        self.syscall_times = [0; MAX_SYSCALL_NUM];
+        self.syscall_stats.clear();
+        self.first_time = get_time_ms();
+        self.signal_actions = SignalActions::default();
+        self.handling_sig = -1;
+        self.trap_ctx_backup = None;
+        self.saved_signal_mask = None;
+        self.fault_site = None;
+        self.fault_retry = None;
+        for fd in self.fd_table.iter_mut() {
+            if fd.as_ref().map_or(false, |fd| fd.cloexec) {
+                *fd = None;
+            }
+        }
+        for id in core::mem::take(&mut self.semaphores).into_iter().flatten() {
+            semaphore_close(id);
+        }
+        // the ones still held go away as sys_exec unlocks them
+        for id in core::mem::take(&mut self.mutexes).into_iter().flatten() {
+            mutex_close(id);
+        }
+        disarm(self);
+        drop_profile(self, pid);
+        for child in self.children.iter() {
+            drop_profile(&mut child.inner_exclusive_access(), pid);
+        }
+    }
+    /// Go back to the state of a task just created from an image, but for
+    /// what [`Self::reset_for_exec`] resets, as initproc does in a soft
+    /// reboot with every other task gone and the ready queue cleared
+    pub fn reset_for_respawn(&mut self) {
+        self.fd_table = stdio_fds();
+        self.cwd = String::from("/");
+        self.children.clear();
+        self.child_subreaper = false;
+        self.audit = None;
+        self.syscall_filter = None;
+        self.exit_code = 0;
+        self.term_signal = 0;
+        self.signals = SignalFlags::empty();
+        self.signal_mask = SignalFlags::empty();
+        self.wait = None;
+        self.interrupted = false;
+        self.blocked_on = None;
+        self.block_reason = None;
+        self.on_queue = false;
+        self.wake_pending = false;
+        self.prio = 16;
+        self.pass = 0;
+        self.cpu_mask = usize::MAX;
+        self.cpu_time_us = 0;
+        self.user_time_us = 0;
+        self.nvcsw = 0;
+        self.nivcsw = 0;
+        self.children_usage = Rusage::default();
+    }
 }
 
 impl TaskControlBlock {
     /// Get the mutex to get the RefMut TaskControlBlockInner
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn inner_exclusive_access(&self) -> UPRefMut<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
+    /// `None` if the inner part is borrowed, e.g. by the code that panicked
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_inner_exclusive_access(&self) -> Option<UPRefMut<'_, TaskControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
 
     /// Create a new process
     ///
     /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8]) -> Self {
+    pub fn new(name: &str, elf_data: &[u8]) -> Self {
         // memory_set with elf program headers/trampoline/trap context/user stack
         let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
+        let trap_cx_ppn = memory_set.trap_cx_ppn();
+        let user_satp = memory_set.token();
+        let user_generation = memory_set.generation();
         // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
+        let pid_handle = pid_alloc().expect("no pid for initproc");
+        memory_set.init_comm_page(pid_handle.0);
         let kernel_stack = KernelStack::new(&pid_handle);
         let kernel_stack_top = kernel_stack.get_top();
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
             pid: pid_handle,
             kernel_stack,
+            on_cpu: AtomicBool::new(false),
+            memory_set: Arc::new(unsafe { UPSafeCell::new(memory_set) }),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     trap_cx_ppn,
                     base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    name: String::from(name),
                     parent: None,
                     children: Vec::new(),
+                    child_subreaper: false,
                     exit_code: 0,
+                    term_signal: 0,
                     first_time: 0,
                     dispatched: false,
                     syscall_times: [0; MAX_SYSCALL_NUM],
+                    syscall_stats: BTreeMap::new(),
                     pass: 0,
                     prio: 16,
+                    cpu_time_us: 0,
+                    switch_in_us: 0,
+                    cpu_usage: 0,
+                    usage_update_us: get_time_us(),
+                    inherited_prio: 0,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
+                    on_queue: false,
+                    wake_pending: false,
+                    profile: None,
+                    audit: None,
+                    audit_charge: Arc::default(),
+                    syscall_filter: None,
+                    block_reason: None,
+                    last_syscall: None,
+                    user_time_us: 0,
+                    user_enter_us: 0,
+                    nvcsw: 0,
+                    nivcsw: 0,
+                    children_usage: Rusage::default(),
+                    cpu_mask: usize::MAX,
+                    signals: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    handling_sig: -1,
+                    signal_mask: SignalFlags::empty(),
+                    signal_mask_backup: SignalFlags::empty(),
+                    wait: None,
+                    interrupted: false,
+                    saved_signal_mask: None,
+                    trap_ctx_backup: None,
+                    fault_info: None,
+                    fault_site: None,
+                    fault_retry: None,
+                    cwd: String::from("/"),
+                    fd_table: alloc::vec![
+                        // 0 -> stdin
+                        Some(FileDescriptor::new(Arc::new(Stdin), false)),
+                        // 1 -> stdout
+                        Some(FileDescriptor::new(Arc::new(Stdout), false)),
+                        // 2 -> stderr
+                        Some(FileDescriptor::new(Arc::new(Stdout), false)),
+                    ],
+                    semaphores: Vec::new(),
+                    mutexes: Vec::new(),
                 })
             },
         };
@@ -147,71 +519,280 @@ impl TaskControlBlock {
         *trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
+            user_satp,
+            user_generation,
             KERNEL_SPACE.exclusive_access().token(),
             kernel_stack_top,
             trap_handler as usize,
         );
         task_control_block
     }
+    /// Create the idle task of hart `hart`, which runs `entry` in the kernel
+    ///
+    /// It has no user space and is never in the ready queue, the scheduler
+    /// switches to it when there is nothing else to run.
+    pub fn new_idle(hart: usize, entry: fn() -> !) -> Self {
+        let kernel_stack = KernelStack::new_idle();
+        let kernel_stack_top = kernel_stack.get_top();
+        Self {
+            pid: PidHandle(IDLE_PID),
+            kernel_stack,
+            on_cpu: AtomicBool::new(false),
+            memory_set: Arc::new(unsafe { UPSafeCell::new(MemorySet::new_bare()) }),
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    // never used, the task does not return to user mode
+                    trap_cx_ppn: PhysPageNum(0),
+                    base_size: 0,
+                    task_cx: TaskContext::goto(entry as usize, kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    name: String::from("idle"),
+                    parent: None,
+                    children: Vec::new(),
+                    child_subreaper: false,
+                    exit_code: 0,
+                    term_signal: 0,
+                    first_time: 0,
+                    dispatched: false,
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    syscall_stats: BTreeMap::new(),
+                    pass: IDLE_PASS,
+                    prio: 16,
+                    cpu_time_us: 0,
+                    switch_in_us: 0,
+                    cpu_usage: 0,
+                    usage_update_us: get_time_us(),
+                    inherited_prio: 0,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
+                    on_queue: false,
+                    wake_pending: false,
+                    profile: None,
+                    audit: None,
+                    audit_charge: Arc::default(),
+                    syscall_filter: None,
+                    block_reason: None,
+                    last_syscall: None,
+                    user_time_us: 0,
+                    user_enter_us: 0,
+                    nvcsw: 0,
+                    nivcsw: 0,
+                    children_usage: Rusage::default(),
+                    cpu_mask: 1 << hart,
+                    signals: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    handling_sig: -1,
+                    signal_mask: SignalFlags::empty(),
+                    signal_mask_backup: SignalFlags::empty(),
+                    wait: None,
+                    interrupted: false,
+                    saved_signal_mask: None,
+                    trap_ctx_backup: None,
+                    fault_info: None,
+                    fault_site: None,
+                    fault_retry: None,
+                    cwd: String::from("/"),
+                    fd_table: Vec::new(),
+                    semaphores: Vec::new(),
+                    mutexes: Vec::new(),
+                })
+            },
+        }
+    }
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.exclusive_access().token()
+    }
+    /// Usage of the task itself, not counting its children. Only complete
+    /// once it has stopped running
+    pub fn rusage(&self) -> Rusage {
+        let inner = self.inner_exclusive_access();
+        Rusage {
+            utime_us: inner.user_time_us,
+            stime_us: inner.cpu_time_us.saturating_sub(inner.user_time_us),
+            maxrss_pages: self.memory_set.exclusive_access().stats().peak_resident_pages,
+            nvcsw: inner.nvcsw,
+            nivcsw: inner.nivcsw,
+        }
+    }
+    /// Whether this is the idle task of a hart
+    pub fn is_idle(&self) -> bool {
+        self.pid.0 == IDLE_PID
+    }
     /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
+    ///
+    /// `args` are pushed to the user stack, `main` gets their count in a0
+    /// and the address of the null-terminated pointer array in a1.
+    ///
+    /// If nothing else uses the old address space it is freed before the new
+    /// one is built, so that both are never in memory at once.
+    pub fn exec(&self, name: &str, elf_data: &[u8], args: Vec<String>) -> Result<(), ExecError> {
+        // parsing the elf goes deeper than a normal kernel stack allows
+        self.kernel_stack
+            .with_large_stack(|| self.exec_image(name, elf_data, args))
+    }
+    fn exec_image(&self, name: &str, elf_data: &[u8], args: Vec<String>) -> Result<(), ExecError> {
+        let needed = MemorySet::elf_frames(elf_data).ok_or(ExecError::BadElf)?;
+        let before = frame_allocator_free();
+        let released = Arc::strong_count(&self.memory_set) == 1;
+        if released {
+            self.memory_set.exclusive_access().release_user_areas();
+        }
+        let mut free = frame_allocator_free();
+        if free < needed {
+            reclaim(Memory::Frames);
+            free = frame_allocator_free();
+        }
+        info!(
+            "[kernel] exec: {} frames free, {} without the old image, {} needed",
+            before, free, needed
+        );
+        if free < needed {
+            return Err(if released {
+                ExecError::ImageLost
+            } else {
+                ExecError::NoMemory
+            });
+        }
         // memory_set with elf program headers/trampoline/trap context/user stack
         let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
+        let trap_cx_ppn = memory_set.trap_cx_ppn();
+        let user_satp = memory_set.token();
+        let user_generation = memory_set.generation();
+        let (user_sp, argv_base) = push_args(&memory_set, user_sp, &args);
 
+        // substitute memory_set
+        memory_set.init_comm_page(self.getpid());
+        *self.memory_set.exclusive_access() = memory_set;
         // **** access inner exclusively
         let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
         // update trap_cx ppn
         inner.trap_cx_ppn = trap_cx_ppn;
-        inner.syscall_times = [0; MAX_SYSCALL_NUM];
-        inner.first_time = get_time_ms();
+        inner.reset_for_exec(self.getpid(), name);
         // initialize trap_cx
         let trap_cx = inner.get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
+            user_satp,
+            user_generation,
             KERNEL_SPACE.exclusive_access().token(),
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
         // **** release inner automatically
+        Ok(())
     }
 
-    pub fn spawn(self: &Arc<TaskControlBlock>, elf_data: &[u8]) -> Arc<TaskControlBlock> {
-        let mut parent_inner = self.inner_exclusive_access();
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
+    /// A child running `elf_data` with `args` and the fds of `fd_table` but
+    /// those with cloexec, `None` if there is no pid left
+    pub fn spawn(
+        self: &Arc<TaskControlBlock>,
+        name: &str,
+        elf_data: &[u8],
+        args: Vec<String>,
+        fd_table: Vec<Option<FileDescriptor>>,
+    ) -> Option<Arc<TaskControlBlock>> {
+        // on the stack of the parent, which is the current task
+        let (memory_set, user_sp, entry_point) = self
+            .kernel_stack
+            .with_large_stack(|| MemorySet::from_elf(elf_data));
+        let (user_sp, argv_base) = push_args(&memory_set, user_sp, &args);
+        let task_control_block = self.spawn_from(name, memory_set, fd_table)?;
+        let mut inner = task_control_block.inner_exclusive_access();
+        inner.base_size = user_sp;
+        let trap_cx = inner.get_trap_cx();
+        trap_cx.sepc = entry_point;
+        trap_cx.set_sp(user_sp);
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        drop(inner);
+        Some(task_control_block)
+    }
 
-        let pid_handle = pid_alloc();
+    /// A child running in `memory_set`, with the fds of `fd_table` but those
+    /// with cloexec and a trap context that is all zeros but for what trap
+    /// return needs, `None` if there is no pid left
+    pub fn spawn_from(
+        self: &Arc<TaskControlBlock>,
+        name: &str,
+        memory_set: MemorySet,
+        fd_table: Vec<Option<FileDescriptor>>,
+    ) -> Option<Arc<TaskControlBlock>> {
+        let pid_handle = pid_alloc()?;
+        let mut parent_inner = self.inner_exclusive_access();
+        let trap_cx_ppn = memory_set.trap_cx_ppn();
+        let user_satp = memory_set.token();
+        let user_generation = memory_set.generation();
+        memory_set.init_comm_page(pid_handle.0);
         let kernel_stack = KernelStack::new(&pid_handle);
         let kernel_stack_top = kernel_stack.get_top();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
+            on_cpu: AtomicBool::new(false),
+            memory_set: Arc::new(unsafe { UPSafeCell::new(memory_set) }),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     trap_cx_ppn,
-                    base_size: user_sp,
+                    base_size: 0,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    name: String::from(name),
                 
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
+                    child_subreaper: false,
                     exit_code: 0,
+                    term_signal: 0,
                     first_time: 0,
                     dispatched : false,
                     prio : 16,
                     syscall_times: [0; MAX_SYSCALL_NUM],
+                    syscall_stats: BTreeMap::new(),
                     pass: 0,
+                    cpu_time_us: 0,
+                    switch_in_us: 0,
+                    cpu_usage: 0,
+                    usage_update_us: get_time_us(),
+                    inherited_prio: 0,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
+                    on_queue: false,
+                    wake_pending: false,
+                    profile: None,
+                    audit: None,
+                    audit_charge: Arc::default(),
+                    syscall_filter: parent_inner.syscall_filter.clone(),
+                    block_reason: None,
+                    last_syscall: None,
+                    user_time_us: 0,
+                    user_enter_us: 0,
+                    nvcsw: 0,
+                    nivcsw: 0,
+                    children_usage: Rusage::default(),
+                    cpu_mask: parent_inner.cpu_mask,
+                    signals: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    handling_sig: -1,
+                    signal_mask: parent_inner.signal_mask,
+                    signal_mask_backup: SignalFlags::empty(),
+                    wait: None,
+                    interrupted: false,
+                    saved_signal_mask: None,
+                    trap_ctx_backup: None,
+                    fault_info: None,
+                    fault_site: None,
+                    fault_retry: None,
+                    // like fork + exec
+                    cwd: parent_inner.cwd.clone(),
+                    fd_table: fd_table
+                        .into_iter()
+                        .map(|fd| fd.filter(|fd| !fd.cloexec))
+                        .collect(),
+                    semaphores: Vec::new(),
+                    mutexes: Vec::new(),
                 })
             },
         });
@@ -219,58 +800,108 @@ impl TaskControlBlock {
 
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
+            0,
+            0,
+            user_satp,
+            user_generation,
             KERNEL_SPACE.exclusive_access().token(),
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        Some(task_control_block)
     }
 
-    /// Fork from parent to child
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    /// Fork from parent to child, `None` if there is no pid left
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
+        let pid_handle = pid_alloc()?;
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
         // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
+        let memory_set = MemorySet::from_existed_user(&self.memory_set.exclusive_access());
+        let trap_cx_ppn = memory_set.trap_cx_ppn();
+        let user_satp = memory_set.token();
+        let user_generation = memory_set.generation();
+        // alloc a kernel stack in kernel space
+        memory_set.init_comm_page(pid_handle.0);
         let kernel_stack = KernelStack::new(&pid_handle);
         let kernel_stack_top = kernel_stack.get_top();
+        let semaphores = parent_inner.semaphores.clone();
+        semaphore_dup(&semaphores);
+        let mutexes = parent_inner.mutexes.clone();
+        mutex_dup(&mutexes);
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
+            on_cpu: AtomicBool::new(false),
+            memory_set: Arc::new(unsafe { UPSafeCell::new(memory_set) }),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     trap_cx_ppn,
                     base_size: parent_inner.base_size,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    name: parent_inner.name.clone(),
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
+                    child_subreaper: false,
                     exit_code: 0,
+                    term_signal: 0,
                     first_time: parent_inner.first_time, 
                     dispatched: parent_inner.dispatched,
                     syscall_times: parent_inner.syscall_times.clone(),
+                    syscall_stats: parent_inner.syscall_stats.clone(),
                     pass: parent_inner.pass,
-                    prio: parent_inner.prio
+                    prio: parent_inner.prio,
+                    cpu_time_us: 0,
+                    switch_in_us: 0,
+                    cpu_usage: 0,
+                    usage_update_us: get_time_us(),
+                    inherited_prio: 0,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
+                    on_queue: false,
+                    wake_pending: false,
+                    profile: None,
+                    audit: None,
+                    audit_charge: Arc::default(),
+                    syscall_filter: parent_inner.syscall_filter.clone(),
+                    block_reason: None,
+                    last_syscall: None,
+                    user_time_us: 0,
+                    user_enter_us: 0,
+                    nvcsw: 0,
+                    nivcsw: 0,
+                    children_usage: Rusage::default(),
+                    cpu_mask: parent_inner.cpu_mask,
+                    signals: SignalFlags::empty(),
+                    signal_actions: parent_inner.signal_actions.clone(),
+                    handling_sig: -1,
+                    signal_mask: parent_inner.signal_mask,
+                    signal_mask_backup: SignalFlags::empty(),
+                    wait: None,
+                    interrupted: false,
+                    saved_signal_mask: None,
+                    trap_ctx_backup: None,
+                    fault_info: None,
+                    fault_site: None,
+                    fault_retry: None,
+                    cwd: parent_inner.cwd.clone(),
+                    fd_table: parent_inner.fd_table.clone(),
+                    semaphores,
+                    mutexes,
                 })
             },
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
-        // modify kernel_sp in trap_cx
+        // modify user_satp and kernel_sp in trap_cx
         // **** access children PCB exclusively
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        trap_cx.user_satp = user_satp;
+        trap_cx.user_generation = user_generation;
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Some(task_control_block)
         // ---- release parent PCB automatically
         // **** release children PCB automatically
     }
@@ -279,11 +910,155 @@ impl TaskControlBlock {
     }
 }
 
+/// A fresh fd table with stdin, stdout and stderr
+pub fn stdio_fds() -> Vec<Option<FileDescriptor>> {
+    alloc::vec![
+        Some(FileDescriptor::new(Arc::new(Stdin), false)),
+        Some(FileDescriptor::new(Arc::new(Stdout), false)),
+        Some(FileDescriptor::new(Arc::new(Stdout), false)),
+    ]
+}
+
+/// Put global id `id` behind the lowest free handle of `table` and return it
+fn alloc_handle(table: &mut Vec<Option<usize>>, id: usize) -> usize {
+    match table.iter().position(|slot| slot.is_none()) {
+        Some(handle) => {
+            table[handle] = Some(id);
+            handle
+        }
+        None => {
+            table.push(Some(id));
+            table.len() - 1
+        }
+    }
+}
+
+/// Push `args` and the argv array pointing to them on the user stack of
+/// `memory_set` below `user_sp`, return the new, aligned `user_sp` and where
+/// argv is
+///
+/// The eager part of the stack is a page at least, and the syscalls taking
+/// `args` keep them within [`crate::config::ARG_MAX`], so that they fit
+fn push_args(memory_set: &MemorySet, mut user_sp: usize, args: &[String]) -> (usize, usize) {
+    let token = memory_set.token();
+    user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+    let argv_base = user_sp;
+    let mut argv = Vec::with_capacity(args.len() + 1);
+    for arg in args.iter() {
+        user_sp -= arg.len() + 1;
+        argv.push(user_sp);
+        assert!(copy_to_user(token, user_sp as *mut u8, arg.as_bytes()));
+        assert!(copy_to_user(token, (user_sp + arg.len()) as *mut u8, &[0u8]));
+    }
+    argv.push(0);
+    assert!(copy_to_user(token, argv_base as *mut usize, &argv));
+    // keep user_sp aligned to 8 bytes
+    user_sp -= user_sp % core::mem::size_of::<usize>();
+    (user_sp, argv_base)
+}
+
+/// What a task blocks for where a signal may interrupt it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Wait {
+    /// pause or sigsuspend, only a signal ends it
+    Signal,
+    /// A timer of sys_sleep
+    Sleep,
+    /// The mutex with this global id
+    Mutex(usize),
+    /// The semaphore with this global id
+    Semaphore(usize),
+}
+
+/// What a task waits for, for diagnostics. Unlike [`Wait`] this covers the
+/// waits that poll too, the task is ready then rather than blocked
 #[derive(Copy, Clone, PartialEq, Debug)]
-/// task status: UnInit, Ready, Running, Exited
+pub enum BlockReason {
+    /// sys_sleep, until this time in milliseconds
+    SleepUntil(usize),
+    /// Data in the pipe with this id
+    PipeRead(usize),
+    /// The child with this pid to exit, -1 for any child
+    WaitChild(isize),
+    /// The mutex with this global id
+    Mutex(usize),
+    /// The semaphore with this global id
+    Semaphore(usize),
+    /// pause or sigsuspend
+    Signal,
+}
+
+impl BlockReason {
+    /// Kind and argument as [`crate::syscall::ProcessInfo`] reports them,
+    /// kind 0 is for no reason
+    pub fn encode(reason: Option<BlockReason>) -> (u32, isize) {
+        match reason {
+            None => (0, 0),
+            Some(BlockReason::SleepUntil(ms)) => (1, ms as isize),
+            Some(BlockReason::PipeRead(id)) => (2, id as isize),
+            Some(BlockReason::WaitChild(pid)) => (3, pid),
+            Some(BlockReason::Mutex(id)) => (4, id as isize),
+            Some(BlockReason::Semaphore(id)) => (5, id as isize),
+            Some(BlockReason::Signal) => (6, 0),
+        }
+    }
+}
+
+impl fmt::Display for BlockReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlockReason::SleepUntil(ms) => write!(f, "sleep until {} ms", ms),
+            BlockReason::PipeRead(id) => write!(f, "read of pipe {}", id),
+            BlockReason::WaitChild(-1) => write!(f, "any child"),
+            BlockReason::WaitChild(pid) => write!(f, "child {}", pid),
+            BlockReason::Mutex(id) => write!(f, "mutex {}", id),
+            BlockReason::Semaphore(id) => write!(f, "semaphore {}", id),
+            BlockReason::Signal => write!(f, "a signal"),
+        }
+    }
+}
+
+/// How the calls of one syscall by a task ended
+#[derive(Clone, Copy, Default)]
+pub struct SyscallStat {
+    /// Calls that returned 0 or more
+    pub ok: u32,
+    /// Calls that returned less than 0
+    pub err: u32,
+    /// errno of the last one that failed, 0 if none did
+    pub last_errno: u16,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// task status: UnInit, Ready, Running, Exited, Blocking, Stopped, a `u32`
+/// as user space sees it
+#[repr(u32)]
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
     Zombie,
+    /// Waiting for something, off the ready queue until woken up
+    Blocking,
+    /// Off the ready queue until continued; nothing stops tasks yet
+    Stopped,
+}
+
+/// Every transition [`TaskControlBlockInner::set_status`] allows
+pub const STATUS_TRANSITIONS: &[(TaskStatus, TaskStatus)] = &[
+    (TaskStatus::UnInit, TaskStatus::Ready),
+    (TaskStatus::Ready, TaskStatus::Running),
+    (TaskStatus::Running, TaskStatus::Ready),
+    (TaskStatus::Running, TaskStatus::Blocking),
+    (TaskStatus::Running, TaskStatus::Stopped),
+    (TaskStatus::Running, TaskStatus::Zombie),
+    (TaskStatus::Blocking, TaskStatus::Ready),
+    (TaskStatus::Stopped, TaskStatus::Ready),
+];
+
+impl TaskStatus {
+    /// Whether a task may go from `self` to `to`
+    pub fn can_become(self, to: TaskStatus) -> bool {
+        STATUS_TRANSITIONS.contains(&(self, to))
+    }
 }
@@ -2,19 +2,56 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use core::sync::atomic::{self, AtomicUsize};
+use lazy_static::*;
 use riscv::register::time;
 
 pub const TICKS_PER_SEC: usize = 100;
 const MICRO_PER_SEC: usize = 1_000_000;
+const MSEC_PER_SEC: usize = 1000;
+/// Longest an idle hart goes without a timer interrupt
+const IDLE_TRIGGER_MAX_MS: usize = 1000;
+/// `TM` of `scounteren`, which lets U-mode read `time`
+const SCOUNTEREN_TM: usize = 1 << 1;
+
+/// `time` when the kernel started, where [`get_time_us`] counts from
+static BOOT_TICKS: AtomicUsize = AtomicUsize::new(0);
+/// Timer interrupts taken by all harts since boot
+static TIMER_INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+/// Timers [`coalesce`] gave the deadline of one already waiting
+static TIMERS_COALESCED: AtomicUsize = AtomicUsize::new(0);
+
+/// Take the current `time` as the zero of [`get_time_us`]
+pub fn init() {
+    BOOT_TICKS.store(time::read(), atomic::Ordering::Relaxed);
+}
+
+pub fn boot_ticks() -> usize {
+    BOOT_TICKS.load(atomic::Ordering::Relaxed)
+}
+
+/// Let U-mode read `time` with `rdtime` on the current hart, for the vDSO
+/// clock. Nothing else writes `scounteren`, so it stays set across
+/// context switches
+pub fn enable_user_time() {
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) SCOUNTEREN_TM);
+    }
+}
 
 /// read the `mtime` register
 pub fn get_time() -> usize {
     time::read()
 }
 
-/// get current time in microseconds
+/// get current time in microseconds, since boot
 pub fn get_time_us() -> usize {
-    time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
+    (time::read() - boot_ticks()) / (CLOCK_FREQ / MICRO_PER_SEC)
 }
 
 /// set the next timer interrupt
@@ -22,6 +59,161 @@ pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
 
+/// Set the next timer interrupt of an idle hart for when the first sleeping
+/// task is due instead of the next tick, at most [`IDLE_TRIGGER_MAX_MS`]
+/// from now
+///
+/// The hart has to go back to [`set_next_trigger`] before it runs a task,
+/// which relies on the tick to be preempted.
+pub fn set_idle_trigger() {
+    let latest = get_time_ms() + IDLE_TRIGGER_MAX_MS;
+    let due_ms = TIMERS
+        .exclusive_access()
+        .peek()
+        .map_or(latest, |timer| timer.expire_ms.min(latest));
+    set_timer(boot_ticks() + due_ms * (CLOCK_FREQ / MSEC_PER_SEC));
+}
+
+/// Count a timer interrupt, for [`timer_interrupts_per_sec`]
+pub fn count_timer_interrupt() {
+    TIMER_INTERRUPTS.fetch_add(1, atomic::Ordering::Relaxed);
+    timer_interrupts_per_sec();
+}
+
+pub fn timer_interrupts() -> usize {
+    TIMER_INTERRUPTS.load(atomic::Ordering::Relaxed)
+}
+
+pub fn timers_coalesced() -> usize {
+    TIMERS_COALESCED.load(atomic::Ordering::Relaxed)
+}
+
+/// Where the window of [`timer_interrupts_per_sec`] opened, and the rate
+/// over the last one
+struct InterruptRate {
+    start_ms: usize,
+    start_count: usize,
+    per_sec: usize,
+}
+
+lazy_static! {
+    static ref INTERRUPT_RATE: UPSafeCell<InterruptRate> = unsafe {
+        UPSafeCell::new(InterruptRate {
+            start_ms: 0,
+            start_count: 0,
+            per_sec: 0,
+        })
+    };
+}
+
+/// Timer interrupts per second over the last window of a second or more
+pub fn timer_interrupts_per_sec() -> usize {
+    let now = get_time_ms();
+    let count = timer_interrupts();
+    let mut rate = INTERRUPT_RATE.exclusive_access();
+    let elapsed = now - rate.start_ms;
+    if elapsed >= MSEC_PER_SEC {
+        rate.per_sec = (count - rate.start_count) * MSEC_PER_SEC / elapsed;
+        rate.start_ms = now;
+        rate.start_count = count;
+    }
+    rate.per_sec
+}
+
+/// get current time in milliseconds
 pub fn get_time_ms() -> usize {
     get_time_us() / 1000
+}
+
+/// A task sleeping until `expire_ms`
+pub struct TimerCondVar {
+    pub expire_ms: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let a = -(self.expire_ms as isize);
+        let b = -(other.expire_ms as isize);
+        Some(a.cmp(&b))
+    }
+}
+
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap()
+    }
+}
+
+lazy_static! {
+    /// Sleeping tasks, the one that expires first on top
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+}
+
+/// Wake `task` up at `expire_ms`, or up to `slack_ms` later if that lets
+/// it share a timer interrupt with other sleepers
+pub fn add_timer(expire_ms: usize, slack_ms: usize, task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    let expire_ms = coalesce(&timers, expire_ms, slack_ms);
+    timers.push(TimerCondVar { expire_ms, task });
+}
+
+/// The time in `expire_ms..=expire_ms + slack_ms` to wake up at: the first
+/// one some timer already waits for, or else the one that is a multiple of
+/// the largest power of two, which overlapping ranges tend to pick alike
+fn coalesce(timers: &BinaryHeap<TimerCondVar>, expire_ms: usize, slack_ms: usize) -> usize {
+    if slack_ms == 0 {
+        return expire_ms;
+    }
+    let latest = expire_ms + slack_ms;
+    let shared = timers
+        .iter()
+        .map(|timer| timer.expire_ms)
+        .filter(|ms| (expire_ms..=latest).contains(ms))
+        .min();
+    if let Some(ms) = shared {
+        TIMERS_COALESCED.fetch_add(1, atomic::Ordering::Relaxed);
+        return ms;
+    }
+    let round_up = |grain: usize| (expire_ms + grain - 1) / grain * grain;
+    let mut grain = 1;
+    while round_up(grain * 2) <= latest {
+        grain *= 2;
+    }
+    round_up(grain)
+}
+
+/// Drop the timer of `task`, which a signal woke up before it expired
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    let kept = timers
+        .drain()
+        .filter(|timer| !Arc::ptr_eq(&timer.task, task))
+        .collect();
+    *timers = kept;
+}
+
+/// Drop every timer, with the tasks sleeping on them
+pub fn clear_timers() {
+    TIMERS.exclusive_access().clear();
+}
+
+/// Put every task whose timer has expired back to the ready queue
+pub fn check_timer() {
+    let current_ms = get_time_ms();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_ms <= current_ms {
+            wakeup_task(timers.pop().unwrap().task);
+        } else {
+            break;
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+//! Kernel traces in binary frames on the second serial port
+//!
+//! With the `trace_uart` feature the scheduler trace, profiler samples and
+//! the shutdown summary also go to [`crate::drivers::trace_uart`], where
+//! they do not mix with what user programs print. Each frame is
+//!
+//! | bytes | field                                                   |
+//! |-------|---------------------------------------------------------|
+//! | 2     | [`MAGIC`]                                               |
+//! | 1     | stream id, a [`Stream`]                                 |
+//! | 2     | payload length, at most [`MAX_PAYLOAD`]                 |
+//! | n     | payload, little-endian `u64` words                      |
+//! | 4     | CRC-32 (IEEE) of the stream id, length and payload      |
+//!
+//! with every number little-endian. Frames are queued whole in a ring and
+//! moved to the device as frames are queued, from the idle loop and at
+//! shutdown, as far as it takes them. Queueing never waits: a frame that
+//! does not fit, or that comes while the ring is in use, is dropped and
+//! counted, so it is safe from any context including interrupt handlers.
+
+use crate::drivers::trace_uart;
+use crate::sync::{InterruptGuard, UPSafeCell};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+pub const MAGIC: [u8; 2] = *b"TR";
+/// Longest payload, in bytes
+pub const MAX_PAYLOAD: usize = 512;
+/// Bytes queued for the device at most
+const RING_SIZE: usize = 16 * 1024;
+const HEADER_LEN: usize = 5;
+const CRC_LEN: usize = 4;
+
+/// What a frame holds, by the words of its payload
+#[derive(Copy, Clone)]
+pub enum Stream {
+    /// A [`crate::task::SchedEvent`]: tick, hart, kind, pid, pass, prio
+    Sched = 1,
+    /// Profiler samples of a task: pid, index of the first sample, then
+    /// `sepc`s, as many frames as it takes
+    Profile = 2,
+    /// Shutdown summary: exit code, tasks reaped, context switches, frames
+    /// peak and in use, heap bytes peak and in use, frames dropped
+    Summary = 3,
+    /// One syscall at shutdown: id, calls, ok, failed, last errno
+    Syscall = 4,
+}
+
+struct Ring {
+    buf: [u8; RING_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl Ring {
+    fn push(&mut self, byte: u8) {
+        self.buf[(self.head + self.len) % RING_SIZE] = byte;
+        self.len += 1;
+    }
+    /// Hand the front of the ring to the device as long as it takes bytes
+    fn drain(&mut self) {
+        while self.len > 0 {
+            let end = (self.head + self.len).min(RING_SIZE);
+            let sent = trace_uart::try_send(&self.buf[self.head..end]);
+            if sent == 0 {
+                break;
+            }
+            self.head = (self.head + sent) % RING_SIZE;
+            self.len -= sent;
+        }
+    }
+}
+
+lazy_static! {
+    static ref RING: UPSafeCell<Ring> = unsafe {
+        UPSafeCell::new(Ring {
+            buf: [0; RING_SIZE],
+            head: 0,
+            len: 0,
+        })
+    };
+}
+
+/// Frames dropped so far
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// CRC-32 (IEEE, reflected) of `bytes` continued from `crc`, start with
+/// `!0` and invert the result
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Queue a frame of `words` on `stream`, false if it was dropped
+pub fn emit(stream: Stream, words: &[u64]) -> bool {
+    let len = words.len() * 8;
+    assert!(len <= MAX_PAYLOAD);
+    let _guard = InterruptGuard::new();
+    let mut ring = match RING.try_exclusive_access() {
+        Some(ring) if RING_SIZE - ring.len >= HEADER_LEN + len + CRC_LEN => ring,
+        _ => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+    };
+    let header = [stream as u8, len as u8, (len >> 8) as u8];
+    let mut crc = crc32(!0, &header);
+    for &byte in MAGIC.iter().chain(header.iter()) {
+        ring.push(byte);
+    }
+    for word in words {
+        let bytes = word.to_le_bytes();
+        crc = crc32(crc, &bytes);
+        for byte in bytes {
+            ring.push(byte);
+        }
+    }
+    for byte in (!crc).to_le_bytes() {
+        ring.push(byte);
+    }
+    ring.drain();
+    true
+}
+
+/// Queue the samples of task `pid`, in as many frames as it takes
+pub fn emit_profile(pid: usize, samples: &[usize]) {
+    const PER_FRAME: usize = MAX_PAYLOAD / 8 - 2;
+    let mut words = [0u64; MAX_PAYLOAD / 8];
+    for (i, chunk) in samples.chunks(PER_FRAME).enumerate() {
+        words[0] = pid as u64;
+        words[1] = (i * PER_FRAME) as u64;
+        for (word, &sample) in words[2..].iter_mut().zip(chunk) {
+            *word = sample as u64;
+        }
+        emit(Stream::Profile, &words[..2 + chunk.len()]);
+    }
+}
+
+/// Frames dropped so far
+pub fn dropped() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Move queued bytes to the device as far as it takes them now
+pub fn poll() {
+    let _guard = InterruptGuard::new();
+    if let Some(mut ring) = RING.try_exclusive_access() {
+        ring.drain();
+    }
+}
+
+/// Wait until every queued byte is with the device, unless there is none
+pub fn flush() {
+    if !trace_uart::present() {
+        return;
+    }
+    let _guard = InterruptGuard::new();
+    if let Some(mut ring) = RING.try_exclusive_access() {
+        while ring.len > 0 {
+            ring.drain();
+        }
+    }
+}
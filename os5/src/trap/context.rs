@@ -1,8 +1,13 @@
 //! Implementation of [`TrapContext`]
 
+use crate::mm::tlb::GenerationPtr;
 use riscv::register::sstatus::{self, Sstatus, SPP};
 
+/// `syscall_sepc` outside of a syscall
+pub const NO_SYSCALL: usize = usize::MAX;
+
 #[repr(C)]
+#[derive(Clone, Copy)]
 /// trap context structure containing sstatus, sepc and registers
 pub struct TrapContext {
     /// General-Purpose Register x0-31
@@ -17,15 +22,55 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// Virtual address of trap handler entry point in kernel
     pub trap_handler: usize,
+    /// Hart id of the kernel, saved by `__restore` and loaded into `tp` by
+    /// `__alltraps`
+    pub kernel_tp: usize,
+    /// Address of the ecall being handled, [`NO_SYSCALL`] whenever the
+    /// context goes back to user mode; `sepc` only moves past the ecall once
+    /// the syscall is done
+    pub syscall_sepc: usize,
+    /// a0 at the ecall, what the syscall gets as its first argument if it
+    /// is restarted
+    pub orig_a0: usize,
+    /// Token of the user address space, which trap_return switches to
+    pub user_satp: usize,
+    /// Generation of its page table, which trap_return compares with the
+    /// one this hart last flushed the TLB against
+    pub user_generation: GenerationPtr,
 }
 
 impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
+    /// Whether this context made a syscall that is not done yet. Not true
+    /// of a context exec or sigreturn put in place of the one that did
+    pub fn in_syscall(&self) -> bool {
+        self.syscall_sepc != NO_SYSCALL
+    }
+    /// Record the ecall at `sepc` as the syscall being handled
+    pub fn enter_syscall(&mut self) {
+        self.syscall_sepc = self.sepc;
+        self.orig_a0 = self.x[10];
+    }
+    /// Finish the syscall being handled with `result`, going on after its ecall
+    pub fn complete_syscall(&mut self, result: isize) {
+        self.x[10] = result as usize;
+        self.sepc = self.syscall_sepc + 4;
+        self.syscall_sepc = NO_SYSCALL;
+    }
+    /// Finish the syscall being handled by going back to its ecall with
+    /// `orig_a0`, so that it runs again
+    pub fn restart_syscall(&mut self) {
+        self.x[10] = self.orig_a0;
+        self.sepc = self.syscall_sepc;
+        self.syscall_sepc = NO_SYSCALL;
+    }
     pub fn app_init_context(
         entry: usize,
         sp: usize,
+        user_satp: usize,
+        user_generation: GenerationPtr,
         kernel_satp: usize,
         kernel_sp: usize,
         trap_handler: usize,
@@ -40,6 +85,11 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            kernel_tp: 0,
+            syscall_sepc: NO_SYSCALL,
+            orig_a0: 0,
+            user_satp,
+            user_generation,
         };
         cx.set_sp(sp);
         cx
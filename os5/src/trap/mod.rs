@@ -10,27 +10,46 @@
 //!
 //! It then calls different functionality based on what exactly the exception
 //! was. For example, timer interrupts trigger task preemption, and syscalls go
-//! to [`syscall()`].
+//! to [`syscall()`], which tells whether the syscall is done and `sepc` moves
+//! past its ecall, or it is to run again.
 
 mod context;
 
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
-use crate::syscall::syscall;
+use crate::percpu::this_hart;
+use crate::syscall::{syscall, SyscallOutcome};
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
-    add_one_while_syscall
+    current_trap_cx, kill_current_and_run_next, preempt_current_and_run_next,
+    add_one_while_syscall, sample_load_average, clear_fault_retry, deliver_fault_signal,
+    handle_signals, SignalFlags, fault_in, account_user_time, mark_user_entry, profile_tick,
+    current_task, IDLE_PID,
 };
-use crate::timer::set_next_trigger;
+#[cfg(debug_assertions)]
+use crate::mm::assert_sum_clear;
+#[cfg(debug_assertions)]
+use crate::task::current_user_token;
+use crate::mm::tlb::{self, user_return_fence};
+use crate::mm::MapPermission;
+use crate::timer::{check_timer, count_timer_interrupt, set_next_trigger};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie, sip, sstatus, stval, stvec,
 };
+use core::sync::atomic::Ordering;
 
 core::arch::global_asm!(include_str!("trap.S"));
+core::arch::global_asm!(include_str!("sigreturn.S"));
+
+// sigreturn.S has the number built in
+const _: () = assert!(crate::syscall::nr::SIGRETURN == 139);
 
 pub fn init() {
     set_kernel_trap_entry();
+    // the kernel never touches user pages directly, see crate::mm::page_table
+    unsafe {
+        sstatus::clear_sum();
+    }
 }
 
 fn set_kernel_trap_entry() {
@@ -51,46 +70,134 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// Let the PLIC deliver device interrupts
+#[cfg(feature = "board_qemu")]
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// Let other harts interrupt us with IPIs
+pub fn enable_soft_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
+/// Handle what is pending on an idle hart, which waits for interrupts with
+/// them disabled since the kernel cannot take traps
+pub fn handle_idle_interrupts() {
+    let sip = sip::read();
+    if sip.stimer() {
+        count_timer_interrupt();
+        set_next_trigger();
+        sample_load_average(0);
+    }
+    if sip.ssoft() {
+        unsafe {
+            core::arch::asm!("csrc sip, {}", in(reg) 1usize << 1);
+        }
+    }
+    #[cfg(feature = "board_qemu")]
+    if sip.sext() {
+        crate::drivers::plic::handle_irq();
+    }
+}
+
+/// Trap context of the task that trapped from user mode, which is always there
+fn user_trap_cx() -> &'static mut TrapContext {
+    current_trap_cx().expect("trap from user mode without a current task")
+}
+
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    tlb::sync();
+    account_user_time();
     let scause = scause::read();
     let stval = stval::read();
-    match scause.cause() {
+    let cause = scause.cause();
+    let is_fault = matches!(
+        cause,
+        Trap::Exception(Exception::StoreFault)
+            | Trap::Exception(Exception::StorePageFault)
+            | Trap::Exception(Exception::InstructionFault)
+            | Trap::Exception(Exception::InstructionPageFault)
+            | Trap::Exception(Exception::LoadFault)
+            | Trap::Exception(Exception::LoadPageFault)
+            | Trap::Exception(Exception::IllegalInstruction)
+    );
+    if !is_fault {
+        // the instruction a signal handler returned to got past the fault
+        clear_fault_retry();
+    }
+    match cause {
         Trap::Exception(Exception::UserEnvCall) => {
-            // jump to next instruction anyway
-            let mut cx = current_trap_cx();
-            cx.sepc += 4;
+            let mut cx = user_trap_cx();
+            cx.enter_syscall();
             add_one_while_syscall(cx.x[17]);
-            // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let outcome = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
-            cx = current_trap_cx();
-            cx.x[10] = result as usize;
+            cx = user_trap_cx();
+            match outcome {
+                SyscallOutcome::Return(result) if cx.in_syscall() => cx.complete_syscall(result),
+                // exec and sigreturn put a context in place that goes on
+                // from its own sepc
+                SyscallOutcome::Return(result) => cx.x[10] = result as usize,
+                // handle_signals below decides whether it runs again
+                SyscallOutcome::Restart => {}
+            }
         }
+        Trap::Exception(Exception::LoadPageFault) if fault_in(stval, MapPermission::R) => {}
+        Trap::Exception(Exception::StorePageFault) if fault_in(stval, MapPermission::W) => {}
+        Trap::Exception(Exception::InstructionPageFault) if fault_in(stval, MapPermission::X) => {}
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            fault_event(scause.bits(), stval);
+            // the handler, if any, is started by handle_signals below
+            if !deliver_fault_signal(SignalFlags::SIGSEGV, scause.bits(), stval) {
+                println_ratelimited!(
+                    "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                    scause.cause(),
+                    stval,
+                    user_trap_cx().sepc,
+                );
+                kill_current_and_run_next(SignalFlags::SIGSEGV);
+            }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            let sepc = user_trap_cx().sepc;
+            fault_event(scause.bits(), stval);
+            if !deliver_fault_signal(SignalFlags::SIGILL, scause.bits(), sepc) {
+                println_ratelimited!("[kernel] IllegalInstruction in application, core dumped.");
+                kill_current_and_run_next(SignalFlags::SIGILL);
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            count_timer_interrupt();
+            profile_tick();
             set_next_trigger();
-            suspend_current_and_run_next();
+            check_timer();
+            sample_load_average(1);
+            preempt_current_and_run_next();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // an IPI from another hart, the reason is in need_resched
+            unsafe {
+                core::arch::asm!("csrc sip, {}", in(reg) 1usize << 1);
+            }
+        }
+        #[cfg(feature = "board_qemu")]
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            crate::drivers::plic::handle_irq();
         }
         _ => {
             panic!(
@@ -100,14 +207,36 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    if this_hart().need_resched.swap(false, Ordering::Acquire) {
+        preempt_current_and_run_next();
+    }
+    handle_signals();
     trap_return();
 }
 
+/// Log a fault of the current task that paging could not resolve
+fn fault_event(scause: usize, stval: usize) {
+    let pid = current_task().map_or(IDLE_PID, |task| task.getpid());
+    let sepc = user_trap_cx().sepc;
+    kevent!(Fault { pid, scause, stval, sepc });
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
     set_user_trap_entry();
+    mark_user_entry();
+    // __restore loads sstatus from the context, SUM there would still be
+    // set at the next trap
+    let cx = current_trap_cx().expect("returning to user mode without a current task");
+    #[cfg(debug_assertions)]
+    {
+        assert_sum_clear();
+        assert!(!cx.sstatus.sum(), "SUM set in the user sstatus");
+        assert_eq!(Some(cx.user_satp), current_user_token(), "stale user_satp in the trap context");
+    }
     let trap_cx_ptr = TRAP_CONTEXT;
-    let user_satp = current_user_token();
+    let user_satp = cx.user_satp;
+    let fence = user_return_fence(user_satp, cx.user_generation);
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -120,6 +249,7 @@ pub fn trap_return() -> ! {
             restore_va = in(reg) restore_va,
             in("a0") trap_cx_ptr,
             in("a1") user_satp,
+            in("a2") fence as usize,
             options(noreturn)
         );
     }
@@ -0,0 +1,33 @@
+//! Generate the `SYSCALL_*` numbers from the syscall table of the kernel
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+static TABLE: &str = "../os5/src/syscall/table.rs";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", TABLE);
+    let table = fs::read_to_string(TABLE).unwrap();
+    let mut numbers = String::new();
+    // entries are `NAME = number, argument count;`, one per line
+    for line in table.lines() {
+        let entry = match line.trim().strip_suffix(';') {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let (name, rest) = match entry.split_once(" = ") {
+            Some(split) => split,
+            None => continue,
+        };
+        let number = rest.split(',').next().unwrap().trim();
+        assert!(
+            name.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_'),
+            "bad syscall table entry: {}",
+            line
+        );
+        numbers += &format!("pub const SYSCALL_{}: usize = {};\n", name, number);
+    }
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("syscall_numbers.rs");
+    fs::write(out, numbers).unwrap();
+}
@@ -0,0 +1,29 @@
+"""Record the FNV-1a 64 checksum of every app, for the kernel to check the
+images it embeds against.
+
+Usage: checksums.py ELF_DIR OUTPUT
+"""
+import os
+import sys
+
+
+def fnv1a64(data):
+    h = 0xCBF29CE484222325
+    for byte in data:
+        h ^= byte
+        h = (h * 0x100000001B3) & 0xFFFFFFFFFFFFFFFF
+    return h
+
+
+def main():
+    elf_dir, output = sys.argv[1:3]
+    lines = []
+    for name in sorted(os.listdir(elf_dir)):
+        with open(os.path.join(elf_dir, name), "rb") as f:
+            lines.append("%s %016x\n" % (name[: name.find(".")], fnv1a64(f.read())))
+    with open(output, "w") as f:
+        f.writelines(lines)
+
+
+if __name__ == "__main__":
+    main()
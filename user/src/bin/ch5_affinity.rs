@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{fork, getcpu, getpid, sched_getaffinity, sched_setaffinity, waitpid};
+
+/// 正确输出：（无报错信息）
+/// Test affinity OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let hart = getcpu();
+    assert!(hart >= 0);
+    let mut mask = 0usize;
+    assert_eq!(sched_getaffinity(0, &mut mask), 0);
+    assert_ne!(mask & (1 << hart), 0);
+    // pin to the hart we are on, by pid this time
+    assert_eq!(sched_setaffinity(getpid() as usize, 1 << hart), 0);
+    assert_eq!(sched_getaffinity(0, &mut mask), 0);
+    assert_eq!(mask, 1 << hart);
+    assert_eq!(sched_setaffinity(0, 0), -1);
+    assert_eq!(sched_setaffinity(usize::MAX, 1 << hart), -1);
+    assert_eq!(sched_getaffinity(usize::MAX, &mut mask), -1);
+    let pid = fork();
+    if pid == 0 {
+        // the mask is inherited and we stay where it says
+        let mut mask = 0usize;
+        assert_eq!(sched_getaffinity(0, &mut mask), 0);
+        assert_eq!(mask, 1 << hart);
+        assert_eq!(getcpu(), hart);
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(getcpu(), hart);
+    println!("Test affinity OK!");
+    0
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    audit, audit_read, close, errno, exit, fork, getpid, open, pipe, read, waitpid, write,
+    AuditRecord, OpenFlags, AUDIT_DATA, EBADF, EINVAL, ENOENT, EPERM, ESRCH, SYSCALL_OPENAT,
+    SYSCALL_WRITE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test audit OK!
+
+/// Longer than what a record keeps
+const GARBLED_LEN: usize = 300;
+/// No such fd, the write fails after the buffer is captured
+const BAD_FD: usize = 1000;
+
+fn garbled() -> [u8; GARBLED_LEN] {
+    let mut buf = [0u8; GARBLED_LEN];
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = (i * 37 + 11) as u8 ^ 0xa5;
+    }
+    buf
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let (mut go, mut done) = ([0usize; 2], [0usize; 2]);
+    assert_eq!(pipe(&mut go), 0);
+    assert_eq!(pipe(&mut done), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(go[1]);
+        close(done[0]);
+        let mut byte = [0u8];
+        assert_eq!(read(go[0], &mut byte), 1);
+        assert_eq!(write(BAD_FD, &garbled()), -1);
+        assert_eq!(open("audit_missing\0", OpenFlags::RDONLY), -1);
+        // done[1] closes as we exit
+        exit(0);
+    }
+    close(go[0]);
+    close(done[1]);
+    assert_eq!(audit(pid as usize, 16), 0);
+    assert_eq!(write(go[1], &[1]), 1);
+    // end of file once the child exited
+    let mut byte = [0u8];
+    assert_eq!(read(done[0], &mut byte), 0);
+
+    // the child is a zombie, its records are still there
+    let mut records = [AuditRecord::default(); 4];
+    assert_eq!(audit_read(pid as usize, &mut records), 2);
+    let (write_record, open_record) = (&records[0], &records[1]);
+    assert_eq!((write_record.seq, open_record.seq), (0, 1));
+    assert_eq!(write_record.id, SYSCALL_WRITE);
+    assert_eq!(write_record.args[0], BAD_FD);
+    assert_eq!(write_record.args[2], GARBLED_LEN);
+    assert_eq!(write_record.result, -EBADF);
+    assert_eq!(write_record.data(), &garbled()[..AUDIT_DATA]);
+    assert_eq!(open_record.id, SYSCALL_OPENAT);
+    assert_eq!(open_record.data(), b"audit_missing");
+    assert_eq!(open_record.result, -ENOENT);
+    assert_eq!(audit_read(pid as usize, &mut records), 0);
+
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(audit_read(pid as usize, &mut records), -1);
+    // or the pid went to a task of another test
+    assert!(errno() == ESRCH || errno() == EPERM);
+    // not started by us
+    assert_eq!(audit_read(getpid() as usize, &mut records), -1);
+    assert_eq!(errno(), EINVAL);
+    close(done[0]);
+    close(go[1]);
+    println!("Test audit OK!");
+    0
+}
@@ -0,0 +1,106 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    batch, close, lseek, open, pipe, task_info, BatchEntry, OpenFlags, TaskInfo, TimeVal,
+    BATCH_STOP_ON_ERROR, EBADF, SEEK_CUR, SEEK_SET, SYSCALL_BATCH, SYSCALL_CLOSE, SYSCALL_FORK,
+    SYSCALL_GETTIMEOFDAY, SYSCALL_READ, SYSCALL_WRITE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test batch OK!
+
+const N: usize = 100;
+
+fn write_entry(fd: usize, buf: &[u8]) -> BatchEntry {
+    BatchEntry::new(SYSCALL_WRITE, [fd, buf.as_ptr() as usize, buf.len()])
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        "ch5_batch_out\0",
+        OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC,
+    );
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut data = [0u8; N];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = b'a' + (i % 26) as u8;
+    }
+
+    // a hundred writes cost one trap
+    let mut entries = [write_entry(fd, b""); N];
+    for (i, entry) in entries.iter_mut().enumerate() {
+        *entry = write_entry(fd, &data[i..i + 1]);
+    }
+    let before = TaskInfo::new();
+    let after = TaskInfo::new();
+    assert_eq!(task_info(&before), 0);
+    assert_eq!(batch(&mut entries, 0), N as isize);
+    assert_eq!(task_info(&after), 0);
+    assert_eq!(
+        after.syscall_times[SYSCALL_BATCH],
+        before.syscall_times[SYSCALL_BATCH] + 1
+    );
+    assert_eq!(
+        after.syscall_times[SYSCALL_WRITE],
+        before.syscall_times[SYSCALL_WRITE]
+    );
+    assert!(entries.iter().all(|entry| entry.ret == 1));
+
+    // reads of a file that is not a stream may be batched too
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut buf = [0u8; N];
+    let (head, tail) = buf.split_at_mut(N / 2);
+    let time = TimeVal::new();
+    let mut entries = [
+        BatchEntry::new(SYSCALL_READ, [fd, head.as_mut_ptr() as usize, head.len()]),
+        BatchEntry::new(SYSCALL_READ, [fd, tail.as_mut_ptr() as usize, tail.len()]),
+        BatchEntry::new(SYSCALL_GETTIMEOFDAY, [&time as *const _ as usize, 0, 0]),
+        BatchEntry::new(SYSCALL_CLOSE, [fd, 0, 0]),
+    ];
+    assert_eq!(batch(&mut entries, 0), 4);
+    assert_eq!(entries[0].ret, (N / 2) as isize);
+    assert_eq!(entries[1].ret, (N / 2) as isize);
+    assert_eq!(entries[2].ret, 0);
+    assert_eq!(entries[3].ret, 0);
+    assert_eq!(buf, data);
+    assert!(time.sec > 0 || time.usec > 0);
+
+    // the first failure ends the batch only if asked to, an entry keeps the
+    // errno, there is no wrapper in between
+    let fd = open("ch5_batch_out\0", OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let ok = b"ok";
+    let mut entries = [write_entry(fd, ok), write_entry(99, ok), write_entry(fd, ok)];
+    assert_eq!(batch(&mut entries, 0), 3);
+    assert_eq!([entries[0].ret, entries[1].ret, entries[2].ret], [2, -EBADF, 2]);
+    let mut entries = [write_entry(fd, ok), write_entry(99, ok), write_entry(fd, ok)];
+    assert_eq!(batch(&mut entries, BATCH_STOP_ON_ERROR), 2);
+    assert_eq!([entries[0].ret, entries[1].ret, entries[2].ret], [2, -EBADF, 0]);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 6);
+    assert_eq!(close(fd), 0);
+
+    // nothing runs if one entry could block or touch the trap context
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut byte = [0u8; 1];
+    for &bad in [
+        BatchEntry::new(SYSCALL_READ, [fds[0], byte.as_mut_ptr() as usize, 1]),
+        BatchEntry::new(SYSCALL_FORK, [0; 3]),
+    ]
+    .iter()
+    {
+        let mut entries = [BatchEntry::new(SYSCALL_CLOSE, [fds[1], 0, 0]), bad];
+        assert_eq!(batch(&mut entries, 0), -1);
+    }
+    // the close before each bad entry never ran
+    assert_eq!(close(fds[1]), 0);
+    assert_eq!(close(fds[0]), 0);
+    println!("Test batch OK!");
+    0
+}
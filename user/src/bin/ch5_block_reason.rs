@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    exit, fork, kill, mutex_blocking_create, mutex_lock, process_info, sleep_blocking, waitpid,
+    BlockKind, ProcessInfo, TaskStatus, SIGKILL, SYSCALL_MUTEX_LOCK,
+};
+
+/// 正确输出：（无报错信息）
+/// Test block reason OK!
+
+/// Lock `first`, give the other task time to lock its own, then `second`
+fn locker(first: usize, second: usize) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        mutex_lock(first);
+        sleep_blocking(50);
+        mutex_lock(second);
+        exit(1);
+    }
+    pid
+}
+
+fn assert_waits_for(pid: isize, mutex: usize) {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(pid as usize, &mut info), 0);
+    assert_eq!(info.status(), Ok(TaskStatus::Blocking));
+    assert_eq!(info.block_kind(), Ok(BlockKind::Mutex));
+    assert_eq!(info.block_arg, mutex as isize);
+    assert_eq!(info.last_syscall, SYSCALL_MUTEX_LOCK as isize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let a = mutex_blocking_create() as usize;
+    let b = mutex_blocking_create() as usize;
+    // two tasks deadlocked on the two mutexes
+    let first = locker(a, b);
+    let second = locker(b, a);
+    sleep_blocking(200);
+    assert_waits_for(first, b);
+    assert_waits_for(second, a);
+
+    // a sleeping task says until when
+    let sleeper = fork();
+    if sleeper == 0 {
+        sleep_blocking(1000);
+        exit(0);
+    }
+    sleep_blocking(50);
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(sleeper as usize, &mut info), 0);
+    assert_eq!(info.block_kind(), Ok(BlockKind::SleepUntil));
+    assert!(info.block_arg > info.last_syscall_ms as isize);
+
+    for pid in [first, second, sleeper] {
+        assert_eq!(kill(pid as usize, SIGKILL), 0);
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+    }
+    println!("Test block reason OK!");
+    0
+}
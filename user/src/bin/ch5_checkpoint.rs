@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    checkpoint, close, errno, exit, fork, map_self, mmap, open, restore, unlink, waitpid, write,
+    OpenFlags, EINVAL, ENOENT,
+};
+
+/// 正确输出：（无报错信息）
+/// Test checkpoint OK!
+
+const IMAGE: &str = "ch5_checkpoint_img\0";
+const NOT_AN_IMAGE: &str = "ch5_checkpoint_txt\0";
+/// Rounds of the loop, read through a pointer so that it is not folded
+static ROUNDS: u64 = 2_000_000;
+const SEED: u64 = 0x1234_5678;
+/// What the checkpointed child exits with, the restored one exits with 0
+const CHECKPOINTED: i32 = 7;
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+
+fn mix(acc: u64, i: u64) -> u64 {
+    (acc ^ i).wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(17)
+}
+
+fn run(mut acc: u64, from: u64, to: u64) -> u64 {
+    for i in from..to {
+        acc = mix(acc, i);
+    }
+    acc
+}
+
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+fn wait_exit(pid: isize) -> i32 {
+    assert!(pid > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// Check the page mapped at [`START`] still holds what was written there
+fn check_page() -> bool {
+    let page = unsafe { core::slice::from_raw_parts(START as *const u8, PAGE_SIZE) };
+    page.iter().enumerate().all(|(i, &byte)| byte == i as u8)
+}
+
+/// Half of the loop, a checkpoint, then the other half in the restored
+/// process only
+fn checkpointed_child(rounds: u64, expected: u64) -> ! {
+    assert_eq!(mmap(START, PAGE_SIZE, 0b011), 0);
+    let page = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, PAGE_SIZE) };
+    for (i, byte) in page.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let half = run(SEED, 0, rounds / 2);
+    match checkpoint(IMAGE) {
+        0 => exit(CHECKPOINTED),
+        1 => {}
+        ret => panic!("checkpoint returned {}", ret),
+    }
+    let acc = run(half, rounds / 2, rounds);
+    exit(if acc == expected && check_page() { 0 } else { 1 })
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let rounds = unsafe { core::ptr::read_volatile(&ROUNDS) };
+    let expected = run(SEED, 0, rounds);
+
+    let pid = fork();
+    if pid == 0 {
+        checkpointed_child(rounds, expected);
+    }
+    assert_eq!(wait_exit(pid), CHECKPOINTED);
+    // the image outlives the process, and can be restored more than once
+    for _ in 0..2 {
+        assert_eq!(wait_exit(restore(IMAGE)), 0);
+    }
+
+    // what cannot be saved
+    let fd = open(NOT_AN_IMAGE, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    fails_with(checkpoint(IMAGE), EINVAL);
+    assert_eq!(write(fd as usize, b"no image at all"), 15);
+    assert_eq!(close(fd as usize), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert!(map_self(0) > 0);
+        fails_with(checkpoint(IMAGE), EINVAL);
+        exit(0);
+    }
+    assert_eq!(wait_exit(pid), 0);
+
+    // nothing to restore
+    fails_with(restore("ch5_checkpoint_missing\0"), ENOENT);
+    fails_with(restore(NOT_AN_IMAGE), EINVAL);
+    assert_eq!(unlink(NOT_AN_IMAGE), 0);
+    assert_eq!(unlink(IMAGE), 0);
+    fails_with(restore(IMAGE), ENOENT);
+    println!("Test checkpoint OK!");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    comm_page, exec, getpid, in_child, kill, mprotect, segfaults, sigaction, SigInfo,
+    SignalAction, COMM_PAGE, SIGRETURN_TRAMPOLINE, SIGUSR1,
+};
+
+/// 正确输出：（无报错信息）
+/// Test comm page OK!
+
+static mut CAUGHT: usize = 0;
+
+/// Returns without calling sigreturn
+extern "C" fn count(_signum: usize, _info: *const SigInfo) {
+    unsafe {
+        CAUGHT += 1;
+    }
+}
+
+fn check_comm_page() {
+    let page = comm_page();
+    assert_eq!(page.pid, getpid() as usize);
+    assert_eq!(page.sigreturn, SIGRETURN_TRAMPOLINE);
+}
+
+fn write_segfaults(addr: usize) -> bool {
+    segfaults(|| unsafe { core::ptr::write_volatile(addr as *mut u8, 1) })
+}
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    check_comm_page();
+    if argc > 1 {
+        // a new image, from a fork, and its own fork again
+        let status = in_child(|| {
+            check_comm_page();
+            0
+        });
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+        return 0;
+    }
+
+    // a handler may simply return
+    let action = SignalAction {
+        handler: count as usize,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    assert_eq!(unsafe { core::ptr::read_volatile(&CAUGHT) }, 2);
+
+    let status = in_child(|| {
+        check_comm_page();
+        let args = ["ch5_comm_page\0".as_ptr(), "child\0".as_ptr(), 0 as *const u8];
+        exec("ch5_comm_page\0", &args);
+        -1
+    });
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+
+    // both pages are read-only for the process, and stay so
+    assert!(write_segfaults(COMM_PAGE));
+    assert!(write_segfaults(SIGRETURN_TRAMPOLINE));
+    assert_eq!(mprotect(COMM_PAGE, 4096, 0b011), -1);
+    assert_eq!(comm_page().pid, getpid() as usize);
+    println!("Test comm page OK!");
+    0
+}
@@ -0,0 +1,104 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    chdir, close, errno, exec, exit, fork, getcwd, open, read, waitpid, write, OpenFlags,
+    ERANGE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test cwd OK!
+
+const DATA: &[u8] = b"found through the cwd";
+
+fn cwd(buf: &mut [u8]) -> &str {
+    let len = getcwd(buf);
+    assert!(len > 0);
+    assert_eq!(buf[len as usize - 1], 0);
+    core::str::from_utf8(&buf[..len as usize - 1]).unwrap()
+}
+
+/// Whether `path` opens and holds `DATA`
+fn holds_data(path: &str) -> bool {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return false;
+    }
+    let mut buf = [0u8; 64];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    len == DATA.len() as isize && &buf[..DATA.len()] == DATA
+}
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    let mut buf = [0u8; 64];
+    if argc > 1 {
+        // the new image of the child keeps the cwd
+        assert_eq!(cwd(&mut buf), "/ch5_cwd/a");
+        assert!(holds_data("file\0"));
+        return 0;
+    }
+    assert_eq!(cwd(&mut buf), "/");
+    let fd = open(
+        "/ch5_cwd/a/file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, DATA), DATA.len() as isize);
+    close(fd as usize);
+
+    // only prefixes of files are directories
+    assert_eq!(chdir("ch5_cwd_missing\0"), -1);
+    assert_eq!(chdir("ch5_cwd/a/file\0"), -1);
+    assert_eq!(chdir("ch5_cw\0"), -1);
+    assert_eq!(cwd(&mut buf), "/");
+
+    // a trailing slash is the same directory
+    assert_eq!(chdir("ch5_cwd/a/\0"), 0);
+    assert_eq!(cwd(&mut buf), "/ch5_cwd/a");
+    assert!(holds_data("file\0"));
+    assert!(holds_data("./file\0"));
+    assert!(holds_data("../a/file\0"));
+    assert!(holds_data("../../ch5_cwd/./a/file\0"));
+    assert!(holds_data("/ch5_cwd/a/file\0"));
+    assert!(!holds_data("a/file\0"));
+
+    // a buffer one byte short takes nothing
+    let len = "/ch5_cwd/a\0".len();
+    let mut short = [0xffu8; 16];
+    assert_eq!(getcwd(&mut short[..len - 1]), -1);
+    assert_eq!(errno(), ERANGE);
+    assert!(short.iter().all(|&b| b == 0xff));
+    assert_eq!(getcwd(&mut short[..len]), len as isize);
+
+    // fork inherits it, exec keeps it, and the child changing it does not
+    // touch the parent
+    let pid = fork();
+    if pid == 0 {
+        let mut buf = [0u8; 64];
+        assert_eq!(cwd(&mut buf), "/ch5_cwd/a");
+        assert_eq!(chdir("/\0"), 0);
+        assert!(!holds_data("file\0"));
+        assert_eq!(chdir("ch5_cwd/a\0"), 0);
+        let args = ["ch5_cwd\0".as_ptr(), "child\0".as_ptr(), 0 as *const u8];
+        exec("ch5_cwd\0", &args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    assert_eq!(cwd(&mut buf), "/ch5_cwd/a");
+
+    // `..` goes up one level and stops at the root
+    assert_eq!(chdir("..\0"), 0);
+    assert_eq!(cwd(&mut buf), "/ch5_cwd");
+    assert!(holds_data("a/file\0"));
+    assert_eq!(chdir("../../..\0"), 0);
+    assert_eq!(cwd(&mut buf), "/");
+    println!("Test cwd OK!");
+    0
+}
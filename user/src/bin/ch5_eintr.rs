@@ -0,0 +1,143 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, errno, exit, fork, get_time, msleep, mutex_blocking_create, mutex_lock, mutex_unlock,
+    pipe, read, sigaction, sigreturn, sleep_blocking, waitpid, with_signaller, write, SigInfo,
+    SignalAction, Signaller, EINTR, SA_RESTART, SIGUSR1,
+};
+
+/// 正确输出：（无报错信息）
+/// Test eintr OK!
+
+static mut CAUGHT: usize = 0;
+
+fn caught() -> usize {
+    unsafe { core::ptr::read_volatile(&CAUGHT) }
+}
+
+extern "C" fn count(_signum: usize, _info: *const SigInfo) {
+    unsafe {
+        CAUGHT += 1;
+    }
+    sigreturn();
+}
+
+fn catch(flags: u32) {
+    let action = SignalAction {
+        handler: count as usize,
+        flags,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+}
+
+/// Run `f` in a child that exits with 0 after it
+fn child<F: FnOnce()>(f: F) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    assert!(pid > 0);
+    pid
+}
+
+fn reap(pid: isize) {
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+}
+
+/// `ret` with a failure as `-errno`, to compare with what [`check_all`]
+/// expects
+fn result(ret: isize) -> isize {
+    if ret == -1 {
+        -errno()
+    } else {
+        ret
+    }
+}
+
+/// Interrupt each blocking point once, with SIGUSR1 from the parent through
+/// `signals`, `expect` telling what it returns then, given the normal result
+fn check_all(restart: bool, signals: &Signaller) {
+    let before = caught();
+    let expect = |done: isize| if restart { done } else { -EINTR };
+
+    // sleep, a restart only sleeps what was left
+    let start = get_time();
+    signals.send(&[SIGUSR1], 150);
+    let mut rem = 0;
+    assert_eq!(result(msleep(300, Some(&mut rem))), expect(0));
+    let elapsed = (get_time() - start) as usize;
+    assert!(rem > 0 && rem < 300);
+    if restart {
+        assert!(elapsed >= 300 && elapsed < 400, "slept {} ms", elapsed);
+    } else {
+        assert!(elapsed < 300, "slept {} ms", elapsed);
+    }
+    signals.wait();
+
+    // an empty pipe
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let (read_end, write_end) = (fds[0], fds[1]);
+    signals.send(&[SIGUSR1], 50);
+    let writer = child(|| {
+        sleep_blocking(100);
+        assert_eq!(write(write_end, b"x"), 1);
+    });
+    let mut buf = [0u8; 1];
+    assert_eq!(result(read(read_end, &mut buf)), expect(1));
+    signals.wait();
+    reap(writer);
+    close(read_end);
+    close(write_end);
+
+    // a child still running
+    let sleeper = child(|| sleep_blocking(200));
+    signals.send(&[SIGUSR1], 50);
+    let mut status = 0;
+    assert_eq!(result(waitpid(sleeper as usize, &mut status)), expect(sleeper));
+    if !restart {
+        assert_eq!(waitpid(sleeper as usize, &mut status), sleeper);
+    }
+    signals.wait();
+
+    // a mutex another task holds
+    let mutex = mutex_blocking_create() as usize;
+    let holder = child(|| {
+        assert_eq!(mutex_lock(mutex), 0);
+        sleep_blocking(200);
+        mutex_unlock(mutex);
+    });
+    sleep_blocking(20);
+    signals.send(&[SIGUSR1], 50);
+    assert_eq!(result(mutex_lock(mutex)), expect(0));
+    if restart {
+        mutex_unlock(mutex);
+    }
+    signals.wait();
+    reap(holder);
+
+    assert_eq!(caught(), before + 4);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let status = with_signaller(|signals| {
+        catch(0);
+        check_all(false, signals);
+        catch(SA_RESTART);
+        check_all(true, signals);
+        0
+    });
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("Test eintr OK!");
+    0
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{errno, syscall, task_info, TaskInfo, ENOSYS, SYSCALL_WAITTID, SYSCALL_WRITE};
+
+/// 正确输出：（无报错信息）
+/// Test enosys OK!
+
+/// Beyond every table entry and syscall_times
+const UNKNOWN: usize = 100_000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // no syscall with that number at all, or one only later chapters have
+    for id in [UNKNOWN, 499, SYSCALL_WAITTID] {
+        assert_eq!(syscall(id, [0; 3]), -1);
+        assert_eq!(errno(), ENOSYS);
+    }
+    // the task goes on, and only table entries are counted
+    let info = TaskInfo::new();
+    assert_eq!(task_info(&info), 0);
+    assert_eq!(info.syscall_times[SYSCALL_WAITTID], 1);
+    assert_eq!(info.syscall_times[499], 0);
+    assert_eq!(info.syscall_times[SYSCALL_WRITE], 0);
+    println!("Test enosys OK!");
+    0
+}
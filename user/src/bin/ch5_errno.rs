@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, cpu_down, cpu_up, errno, exit, fork, getpid, kevent_filter, kill, madvise, mmap,
+    mprotect, munmap, pipe, read, sched_trace, shutdown, waitpid, write, SchedEvent, EBADF, ECHILD,
+    EEXIST, EFAULT, EINVAL, EPERM, ESRCH, KEVENT_FORK, MADV_DONTNEED, SIGUSR1,
+};
+
+/// 正确输出：（无报错信息）
+/// Test errno OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+/// Far beyond any pid the kernel hands out
+const NO_PID: usize = 1_000_000;
+/// The pid of initproc, which nothing may signal
+const INITPROC_PID: usize = 0;
+
+/// Assert that `ret` is a failure with `expected`
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // a mapping in the way, or no permission at all
+    assert_eq!(mmap(START, 2 * PAGE_SIZE, 3), 0);
+    fails_with(mmap(START + PAGE_SIZE, PAGE_SIZE, 3), EEXIST);
+    fails_with(mmap(START + 2 * PAGE_SIZE, PAGE_SIZE, 0), EINVAL);
+    // a length that wraps around
+    fails_with(munmap(START, usize::MAX), EINVAL);
+    fails_with(madvise(START, usize::MAX, MADV_DONTNEED), EINVAL);
+
+    // only children can be waited for, and only once
+    let mut status = 0;
+    fails_with(waitpid(getpid() as usize, &mut status), ECHILD);
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    fails_with(waitpid(pid as usize, &mut status), ECHILD);
+
+    // a task may signal only itself and its descendants
+    fails_with(kill(NO_PID, SIGUSR1), ESRCH);
+    fails_with(kill(INITPROC_PID, SIGUSR1), EPERM);
+    let parent = getpid() as usize;
+    let pid = fork();
+    if pid == 0 {
+        fails_with(kill(parent, SIGUSR1), EPERM);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+
+    // only the shell and initproc may look into the whole system
+    let mut events = [SchedEvent::default(); 1];
+    let mut dropped = 0;
+    fails_with(sched_trace(&mut events, &mut dropped), EPERM);
+    fails_with(shutdown(0), EPERM);
+    fails_with(cpu_up(1), EPERM);
+    fails_with(cpu_down(1), EPERM);
+    fails_with(kevent_filter(0, KEVENT_FORK), EPERM);
+
+    // buffers that are not mapped, not readable or not writable
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let unmapped = unsafe { core::slice::from_raw_parts((START - PAGE_SIZE) as *const u8, 8) };
+    fails_with(write(fds[1], unmapped), EFAULT);
+    assert_eq!(mprotect(START, PAGE_SIZE, 0), 0);
+    let hidden = unsafe { core::slice::from_raw_parts(START as *const u8, 8) };
+    fails_with(write(fds[1], hidden), EFAULT);
+    assert_eq!(write(fds[1], b"x"), 1);
+    assert_eq!(mprotect(START + PAGE_SIZE, PAGE_SIZE, 1), 0);
+    let read_only = unsafe { core::slice::from_raw_parts_mut((START + PAGE_SIZE) as *mut u8, 1) };
+    fails_with(read(fds[0], read_only), EFAULT);
+    assert_eq!(close(fds[1]), 0);
+    fails_with(write(fds[1], b"x"), EBADF);
+    assert_eq!(close(fds[0]), 0);
+
+    // a call that works leaves errno alone
+    assert!(getpid() > 0);
+    assert_eq!(errno(), EBADF);
+    println!("Test errno OK!");
+    0
+}
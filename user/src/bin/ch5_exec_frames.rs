@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, exec, exit, fork, getpid, mmap, munmap, pipe, process_info, read, waitpid, write, ProcessInfo,
+};
+
+/// 正确输出：（无报错信息）
+/// Test exec frames OK!
+
+const PAGE_SIZE: usize = 4096;
+/// Makes the image big enough for the test to tell one copy from two, in
+/// .data since BSS pages are only faulted in
+static mut BALLAST: [u8; 256 * 1024] = [1; 256 * 1024];
+/// Where the frames are used up
+const HOG: usize = 0x20000000;
+const HOG_CHUNK: usize = 256 * PAGE_SIZE;
+/// Frames left for the exec, well below the size of the image but enough
+/// for its page tables
+const LEFT: usize = 24;
+
+fn info() -> ProcessInfo {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(getpid() as usize, &mut info), 0);
+    info
+}
+
+/// Touch fresh pages until only `LEFT` frames are free, return the length
+/// of the mapping
+fn use_up_frames() -> usize {
+    let mut next = HOG;
+    loop {
+        assert_eq!(mmap(next, HOG_CHUNK, 0b011), 0);
+        for page in (next..next + HOG_CHUNK).step_by(PAGE_SIZE) {
+            if info().free_frames <= LEFT {
+                return next + HOG_CHUNK - HOG;
+            }
+            unsafe {
+                (page as *mut u8).write_volatile(1);
+            }
+        }
+        next += HOG_CHUNK;
+    }
+}
+
+/// A child execs this very program, with as many frames free as a fraction
+/// of its image. That only fits if exec lets go of the old image before
+/// building the new one
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    unsafe {
+        (&mut BALLAST[0] as *mut u8).write_volatile(1);
+    }
+    if argc > 1 {
+        // the new image of the child
+        return 0;
+    }
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        let image = info().resident_pages;
+        assert!(image > 2 * LEFT, "image of {} pages too small", image);
+        let mut go = [0u8; 1];
+        assert_eq!(read(fds[0], &mut go), 1);
+        let args = [
+            "ch5_exec_frames\0".as_ptr(),
+            "child\0".as_ptr(),
+            0 as *const u8,
+        ];
+        exec("ch5_exec_frames\0", &args);
+        exit(-1);
+    }
+    close(fds[0]);
+    let baseline = info().free_frames;
+    let hog = use_up_frames();
+    write(fds[1], &[1]);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0, "exec did not fit");
+    // the frames of the child and of both its images are back, only the
+    // page tables behind the hog stay
+    assert_eq!(munmap(HOG, hog), 0);
+    let tables = hog / PAGE_SIZE / 512 + 2;
+    assert!(info().free_frames + tables >= baseline, "frames lost");
+    println!("Test exec frames OK!");
+    0
+}
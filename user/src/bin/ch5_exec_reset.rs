@@ -0,0 +1,149 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    errno, exec, exit, fork, getpid, kill, profile_start, profile_stop, sigaction, sigprocmask,
+    task_info_v2, waitpid, yield_, SigInfo, SignalAction, SignalFlags, TaskInfoV2, EINVAL, SIGUSR1,
+    SIGUSR2, SIG_BLOCK, SIG_UNBLOCK, SYSCALL_YIELD,
+};
+
+// a TaskInfoV2 does not fit on the default stack
+stack_size!(64 * 1024);
+
+/// 正确输出：（无报错信息）
+/// Test exec reset OK!
+
+static mut CAUGHT: usize = 0;
+static mut SAMPLES: [usize; 16] = [0; 16];
+
+extern "C" fn count(_signum: usize, _info: *const SigInfo) {
+    unsafe {
+        CAUGHT += 1;
+    }
+}
+
+fn catch(signum: usize) {
+    let action = SignalAction {
+        handler: count as usize,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+/// Run `f` in a child, then exec this test again with `stage`, return the
+/// child's exit status
+fn exec_after<F: FnOnce()>(stage: &str, f: F) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        let args = ["ch5_exec_reset\0".as_ptr(), stage.as_ptr(), 0 as *const u8];
+        exec("ch5_exec_reset\0", &args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
+}
+
+fn exited_ok(status: i32) -> bool {
+    WIFEXITED!(status) && WEXITSTATUS!(status) == 0
+}
+
+/// The part of the test that runs in the new image, `argv[1]` its stage
+fn after_exec(argv: &[&str]) -> i32 {
+    match argv[1] {
+        // the handler is gone, the default action kills
+        "handler" => {
+            kill(getpid() as usize, SIGUSR1);
+            0
+        }
+        // still blocked and pending, delivered once unblocked
+        "pending" => {
+            let mut mask = SignalFlags::empty();
+            assert_eq!(sigprocmask(SIG_BLOCK, None, Some(&mut mask)), 0);
+            assert!(mask.contains(SignalFlags::SIGUSR2));
+            catch(SIGUSR2);
+            assert_eq!(unsafe { core::ptr::read_volatile(&CAUGHT) }, 0);
+            assert_eq!(sigprocmask(SIG_UNBLOCK, Some(SignalFlags::SIGUSR2), None), 0);
+            assert_eq!(unsafe { core::ptr::read_volatile(&CAUGHT) }, 1);
+            0
+        }
+        // the profile went with its buffer, and counts start over
+        "profile" => {
+            let pid = getpid() as usize;
+            assert_eq!(profile_stop(pid, None), -1);
+            let samples = unsafe { &mut *core::ptr::addr_of_mut!(SAMPLES) };
+            assert_eq!(profile_start(pid, samples), 0);
+            assert!(profile_stop(pid, None) >= 0);
+            let mut info = TaskInfoV2::new();
+            assert_eq!(task_info_v2(&mut info), 0);
+            assert_eq!(info.syscall_times[SYSCALL_YIELD], 0);
+            assert_eq!(info.name(), "ch5_exec_reset");
+            0
+        }
+        // so did the one of the child in `argv[2]`, which is not waited for
+        "child_profile" => {
+            let child = argv[2].parse().unwrap();
+            assert_eq!(profile_stop(child, None), -1);
+            assert_eq!(errno(), EINVAL);
+            let mut status = 0;
+            assert_eq!(waitpid(child, &mut status), child as isize);
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        return after_exec(argv);
+    }
+
+    let status = exec_after("handler\0", || catch(SIGUSR1));
+    assert!(WIFSIGNALED!(status) && WTERMSIG!(status) == SIGUSR1);
+
+    let status = exec_after("pending\0", || {
+        assert_eq!(sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR2), None), 0);
+        assert_eq!(kill(getpid() as usize, SIGUSR2), 0);
+    });
+    assert!(exited_ok(status));
+
+    let status = exec_after("profile\0", || {
+        let samples = unsafe { &mut *core::ptr::addr_of_mut!(SAMPLES) };
+        assert_eq!(profile_start(getpid() as usize, samples), 0);
+        for _ in 0..5 {
+            yield_();
+        }
+    });
+    assert!(exited_ok(status));
+
+    let pid = fork();
+    if pid == 0 {
+        let child = fork();
+        if child == 0 {
+            exit(0);
+        }
+        let samples = unsafe { &mut *core::ptr::addr_of_mut!(SAMPLES) };
+        assert_eq!(profile_start(child as usize, samples), 0);
+        let child = format!("{}\0", child);
+        let args = [
+            "ch5_exec_reset\0".as_ptr(),
+            "child_profile\0".as_ptr(),
+            child.as_ptr(),
+            0 as *const u8,
+        ];
+        exec("ch5_exec_reset\0", &args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(exited_ok(status));
+    println!("Test exec reset OK!");
+    0
+}
@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, errno, exit, fcntl, fd_info, fork, getpid, pipe, pipe2, read, sleep_blocking, waitpid,
+    write, FdInfo, FileKind, EAGAIN, EPERM, ESRCH, FD_INFO_CLOEXEC, FD_INFO_NONBLOCK,
+    FD_INFO_PEER_CLOSED, F_GETFL, F_SETFL, O_CLOEXEC, O_NONBLOCK,
+};
+
+/// 正确输出：（无报错信息）
+/// Test fd info OK!
+
+fn find(fds: &[FdInfo], fd: usize) -> FdInfo {
+    *fds.iter().find(|info| info.fd == fd).expect("fd not listed")
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let me = getpid() as usize;
+    let mut plain = [0usize; 2];
+    let mut cloexec = [0usize; 2];
+    assert_eq!(pipe(&mut plain), 0);
+    assert_eq!(pipe2(&mut cloexec, O_CLOEXEC), 0);
+
+    let mut fds = [FdInfo::new(); 16];
+    let n = fd_info(me, &mut fds) as usize;
+    let fds = &fds[..n];
+    assert_eq!(find(fds, 0).kind(), Ok(FileKind::Stdin));
+    assert_eq!(find(fds, 1).kind(), Ok(FileKind::Stdout));
+    let read_end = find(fds, plain[0]);
+    let write_end = find(fds, plain[1]);
+    assert_eq!(read_end.kind(), Ok(FileKind::PipeRead));
+    assert_eq!(write_end.kind(), Ok(FileKind::PipeWrite));
+    assert!(read_end.pipe_id != 0);
+    assert_eq!(read_end.pipe_id, write_end.pipe_id);
+    assert_eq!(read_end.flags, 0);
+    let other = find(fds, cloexec[0]);
+    assert!(other.pipe_id != read_end.pipe_id);
+    assert_eq!(other.flags & FD_INFO_CLOEXEC, FD_INFO_CLOEXEC);
+
+    // a short buffer still gets the count
+    let mut one = [FdInfo::new(); 1];
+    assert_eq!(fd_info(me, &mut one) as usize, n);
+
+    // O_NONBLOCK shows, and an empty pipe or a full one fails with EAGAIN
+    let mut nonblock = [0usize; 2];
+    assert_eq!(pipe2(&mut nonblock, O_NONBLOCK), 0);
+    let mut fds = [FdInfo::new(); 16];
+    let n = fd_info(me, &mut fds) as usize;
+    let read_end = find(&fds[..n], nonblock[0]);
+    assert_eq!(read_end.flags & FD_INFO_NONBLOCK, FD_INFO_NONBLOCK);
+    assert_eq!(find(&fds[..n], plain[0]).flags & FD_INFO_NONBLOCK, 0);
+    let mut byte = [0u8];
+    assert_eq!(read(nonblock[0], &mut byte), -1);
+    assert_eq!(errno(), EAGAIN);
+    let chunk = [0u8; 64];
+    let mut filled = 0;
+    loop {
+        let n = write(nonblock[1], &chunk);
+        if n < 0 {
+            break;
+        }
+        assert!(n > 0);
+        filled += n as usize;
+    }
+    assert_eq!(errno(), EAGAIN);
+    assert!(filled > 0);
+    assert_eq!(read(nonblock[0], &mut byte), 1);
+    assert_eq!(write(nonblock[1], &chunk), 1);
+    // set and cleared on the open file description
+    assert_eq!(fcntl(plain[0], F_GETFL, 0), 0);
+    assert_eq!(fcntl(plain[0], F_SETFL, O_NONBLOCK as usize), 0);
+    assert_eq!(fcntl(plain[0], F_GETFL, 0), O_NONBLOCK as isize);
+    assert_eq!(read(plain[0], &mut byte), -1);
+    assert_eq!(errno(), EAGAIN);
+    assert_eq!(fcntl(plain[0], F_SETFL, 0), 0);
+    assert_eq!(fcntl(plain[0], F_GETFL, 0), 0);
+    close(nonblock[0]);
+    close(nonblock[1]);
+
+    // the other end going away shows
+    close(plain[1]);
+    let mut fds = [FdInfo::new(); 16];
+    let n = fd_info(me, &mut fds) as usize;
+    let read_end = find(&fds[..n], plain[0]);
+    assert_eq!(read_end.flags & FD_INFO_PEER_CLOSED, FD_INFO_PEER_CLOSED);
+    assert!(fds[..n].iter().all(|info| info.fd != plain[1]));
+
+    // the parent may look at its child, not the other way round
+    let pid = fork();
+    if pid == 0 {
+        let mut fds = [FdInfo::new(); 16];
+        assert_eq!(fd_info(me, &mut fds), -1);
+        assert_eq!(errno(), EPERM);
+        sleep_blocking(50);
+        exit(0);
+    }
+    let mut fds = [FdInfo::new(); 16];
+    assert_eq!(fd_info(pid as usize, &mut fds) as usize, n);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    assert_eq!(fd_info(pid as usize, &mut fds), -1);
+    assert_eq!(errno(), ESRCH);
+    println!("Test fd info OK!");
+    0
+}
@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::getpid;
+use user_lib::{getpid, gettid};
 
 /*
 辅助测例 打印子进程 pid
@@ -13,6 +13,8 @@ use user_lib::getpid;
 #[no_mangle]
 pub fn main() -> i32 {
     let pid = getpid();
+    // one thread per process
+    assert_eq!(gettid(), pid);
     println!("Test getpid OK! pid = {}", pid);
     0
 }
@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{errno, kevent_filter, EPERM, KEVENT_ALL, KEVENT_EXEC, KEVENT_FORK};
+
+/// 正确输出：（无报错信息）
+/// Test kevent filter OK!
+
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+/// Anyone may read the filter, only the shell and initproc change it; how
+/// it changes is checked as the kernel boots
+#[no_mangle]
+pub fn main() -> i32 {
+    let filter = kevent_filter(0, 0);
+    assert!(filter >= 0);
+    assert_eq!(filter as usize & !KEVENT_ALL, 0);
+    fails_with(kevent_filter(0, KEVENT_FORK), EPERM);
+    fails_with(kevent_filter(KEVENT_EXEC, 0), EPERM);
+    assert_eq!(kevent_filter(0, 0), filter);
+    println!("Test kevent filter OK!");
+    0
+}
@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, exit, fork, get_time, getpid, kill, pipe, process_info, read, waitpid, write, yield_,
+    ProcessInfo, SIGKILL,
+};
+
+/// 正确输出：（无报错信息）
+/// Test kill zombies OK!
+
+const CHILDREN: usize = 8;
+const ROUNDS: usize = 4;
+const TIMEOUT_MS: isize = 3000;
+
+fn exists(pid: usize) -> bool {
+    process_info(pid, &mut ProcessInfo::new()) == 0
+}
+
+fn free_frames() -> usize {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(getpid() as usize, &mut info), 0);
+    info.free_frames
+}
+
+/// Fork a middle process with `CHILDREN` children that all exit at once and
+/// are never reaped, then kill it. The zombies must be handed to initproc,
+/// which reaps them, and all their frames must come back
+fn round() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let middle = fork();
+    if middle == 0 {
+        close(fds[0]);
+        for i in 0..CHILDREN {
+            let pid = fork();
+            if pid == 0 {
+                exit(i as i32);
+            }
+            write(fds[1], &(pid as usize).to_ne_bytes());
+        }
+        close(fds[1]);
+        loop {
+            yield_();
+        }
+    }
+    close(fds[1]);
+    let mut children = [0usize; CHILDREN];
+    for child in children.iter_mut() {
+        let mut bytes = [0u8; core::mem::size_of::<usize>()];
+        let mut got = 0;
+        while got < bytes.len() {
+            let n = read(fds[0], &mut bytes[got..]);
+            assert!(n > 0);
+            got += n as usize;
+        }
+        *child = usize::from_ne_bytes(bytes);
+    }
+    close(fds[0]);
+    // exited children are gone from the process table, zombie or not
+    let start = get_time();
+    while children.iter().any(|&pid| exists(pid)) {
+        assert!(get_time() - start < TIMEOUT_MS, "children did not exit");
+        yield_();
+    }
+    assert_eq!(kill(middle as usize, SIGKILL), 0);
+    let mut status = 0;
+    assert_eq!(waitpid(middle as usize, &mut status), middle);
+    assert!(WIFSIGNALED!(status));
+    assert_eq!(WTERMSIG!(status), SIGKILL);
+    assert!(!exists(middle as usize));
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let baseline = free_frames();
+    for _ in 0..ROUNDS {
+        round();
+    }
+    // initproc reaps the orphaned zombies whenever it gets to run
+    let start = get_time();
+    while free_frames() < baseline {
+        assert!(
+            get_time() - start < TIMEOUT_MS,
+            "{} frames lost",
+            baseline - free_frames()
+        );
+        yield_();
+    }
+    println!("Test kill zombies OK!");
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+use alloc::string::String;
+use user_lib::{exec, exit, fork, sysinfo_value, waitpid};
+
+/// 正确输出：（无报错信息）
+/// Test kstack OK!
+
+/// The two numbers of the `key=normal,large` line of sysinfo
+fn pair(key: &str) -> (usize, usize) {
+    let line: String = sysinfo_value(key).expect("no such sysinfo line");
+    let (normal, large) = line.split_once(',').unwrap();
+    (normal.parse().unwrap(), large.parse().unwrap())
+}
+
+/// An exec runs on the scratch stack and the kernel stack of a reaped
+/// child is measured, neither anywhere near its size
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let args = ["ch5_exit0\0".as_ptr(), 0 as *const u8];
+        exec("ch5_exit0\0", &args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    let (normal_size, large_size) = pair("kstack_sizes");
+    let (normal_peak, large_peak) = pair("kstack_peaks");
+    assert!(normal_size < large_size);
+    assert!(normal_peak > 0 && normal_peak < normal_size);
+    assert!(large_peak > 0 && large_peak < large_size);
+    println!(
+        "kernel stack peaks: {} of {}, {} of {} bytes",
+        normal_peak, normal_size, large_peak, large_size
+    );
+    println!("Test kstack OK!");
+    0
+}
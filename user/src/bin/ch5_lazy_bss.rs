@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    exec, exit, fork, get_time, getpid, page_flags, process_info, vma_line, waitpid,
+    ProcessInfo, PAGEMAP_PRESENT, PAGEMAP_WRITABLE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test lazy bss OK!
+
+const PAGE_SIZE: usize = 4096;
+const BIG_SIZE: usize = 4 << 20;
+const READ: usize = 16;
+const WRITTEN: usize = 16;
+
+static mut BIG: [u8; BIG_SIZE] = [0; BIG_SIZE];
+
+fn meminfo() -> ProcessInfo {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(getpid() as usize, &mut info), 0);
+    info
+}
+
+/// Address of the `i`th page wholly inside `BIG`
+fn big_page(i: usize) -> *mut u8 {
+    let start = unsafe { BIG.as_ptr() } as usize;
+    let first = (start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    assert!(first + (i + 1) * PAGE_SIZE <= start + BIG_SIZE);
+    (first + i * PAGE_SIZE) as *mut u8
+}
+
+/// Pages of the area of `BIG` mapped now, out of all of them, checking that
+/// it is a writable ELF area
+fn big_area_pages() -> (usize, usize) {
+    let mut buf = [0u8; 2048];
+    let line = vma_line(big_page(0) as usize, &mut buf).expect("BIG is in no area");
+    let mut fields = line.split(' ').skip(1);
+    assert_eq!(fields.next(), Some("rw-p"), "{}", line);
+    assert_eq!(fields.next(), Some("elf"), "{}", line);
+    let mut pages = fields.next().unwrap().split('/').map(|n| n.parse().unwrap());
+    (pages.next().unwrap(), pages.next().unwrap())
+}
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    let info = meminfo();
+    // an eager BSS alone would be over 1024 pages
+    assert!(info.mapped_pages > BIG_SIZE / PAGE_SIZE);
+    assert!(info.resident_pages < 128, "{} pages resident", info.resident_pages);
+    if argc > 1 {
+        return 0;
+    }
+
+    // reads see zeros, writes take one frame per page
+    let before = meminfo().resident_pages;
+    for i in 0..READ {
+        assert_eq!(unsafe { big_page(i * 8).add(100).read_volatile() }, 0);
+    }
+    assert!(meminfo().resident_pages <= before + READ);
+    let (mapped, total) = big_area_pages();
+    assert!(total >= BIG_SIZE / PAGE_SIZE - 1);
+    for i in 0..WRITTEN {
+        let page = big_page(i * 8 + 1) as usize;
+        assert_eq!(page_flags(page), 0, "page {} present before the write", i);
+        let before = meminfo().resident_pages;
+        unsafe {
+            big_page(i * 8 + 1).write_volatile(i as u8 + 1);
+        }
+        assert_eq!(meminfo().resident_pages, before + 1);
+        let flags = PAGEMAP_PRESENT | PAGEMAP_WRITABLE;
+        assert_eq!(page_flags(page) & flags, flags);
+    }
+    for i in 0..WRITTEN {
+        assert_eq!(unsafe { big_page(i * 8 + 1).read_volatile() }, i as u8 + 1);
+    }
+    assert_eq!(big_area_pages().0, mapped + WRITTEN);
+
+    let start = get_time();
+    let pid = fork();
+    if pid == 0 {
+        let args = ["ch5_lazy_bss\0".as_ptr(), "child\0".as_ptr(), 0 as *const u8];
+        exec("ch5_lazy_bss\0", &args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("fork, exec and exit with a 4 MiB BSS: {} ms", get_time() - start);
+    println!("Test lazy bss OK!");
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::list_apps;
+
+/// 正确输出：（无报错信息）
+/// Test list apps OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // only whole lines are copied
+    let mut buf = vec![0u8; 16];
+    let len = list_apps(&mut buf);
+    assert!(len as usize > buf.len());
+    assert!(buf.iter().all(|&byte| byte == 0) || buf.contains(&b'\n'));
+
+    buf.resize(len as usize, 0);
+    assert_eq!(list_apps(&mut buf), len);
+    let list = core::str::from_utf8(&buf).unwrap();
+    assert!(list.ends_with('\n'));
+    // every app is checked, and this build has matching images only
+    for app in ["ch5_list_apps", "ch5b_initproc", "ch5b_user_shell"] {
+        assert!(list.lines().any(|line| line == app), "{} not listed", app);
+    }
+    assert!(!list.contains("(stale)"));
+    println!("Test list apps OK!");
+    0
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+use alloc::string::String;
+use user_lib::{fork, get_time, sysinfo_value, waitpid, SIGSEGV};
+
+/// 正确输出：（无报错信息）
+/// Test log ratelimit OK!
+
+const CHILDREN: usize = 60;
+
+fn suppressed() -> usize {
+    sysinfo_value("log_suppressed").expect("no log_suppressed line")
+}
+
+/// Children dying of the same fault within a second or two have most of
+/// their core dump lines suppressed
+#[no_mangle]
+pub fn main() -> i32 {
+    let before = suppressed();
+    let start = get_time();
+    for _ in 0..CHILDREN {
+        let pid = fork();
+        if pid == 0 {
+            unsafe {
+                (0 as *mut u8).write_volatile(1);
+            }
+            unreachable!();
+        }
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFSIGNALED!(status));
+        assert_eq!(WTERMSIG!(status), SIGSEGV);
+    }
+    let elapsed = get_time() - start;
+    let dropped = suppressed() - before;
+    println!("{} faults in {} ms, {} lines suppressed", CHILDREN, elapsed, dropped);
+    // at most 10 lines a second get through
+    let windows = elapsed as usize / 1000 + 2;
+    assert!(dropped + 10 * windows >= CHILDREN, "only {} suppressed", dropped);
+    if dropped > 0 {
+        let sites: String = sysinfo_value("log_suppressed_sites").unwrap();
+        assert!(sites.contains("trap/mod.rs"));
+    }
+    println!("Test log ratelimit OK!");
+    0
+}
@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, dup, errno, lseek, open, pipe, read, write, OpenFlags, EINVAL, ENOSPC, ESPIPE, SEEK_CUR,
+    SEEK_END, SEEK_SET,
+};
+
+/// 正确输出：（无报错信息）
+/// Test lseek OK!
+
+/// RAMFS_FILE_MAX of the kernel
+const FILE_MAX: usize = 64 * 1024;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let name = "ch5_lseek_out\0";
+    let fd = open(name, OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello world"), 11);
+    let mut buf = [0u8; 32];
+
+    // the three origins, and no offset before the start
+    assert_eq!(lseek(fd, 6, SEEK_SET), 6);
+    assert_eq!(read(fd, &mut buf[..5]), 5);
+    assert_eq!(&buf[..5], b"world");
+    assert_eq!(lseek(fd, -5, SEEK_CUR), 6);
+    assert_eq!(lseek(fd, -11, SEEK_END), 0);
+    assert_eq!(lseek(fd, -1, SEEK_SET), -1);
+    assert_eq!(lseek(fd, 0, 3), -1);
+    // past isize::MAX fails instead of wrapping around
+    assert_eq!(lseek(fd, isize::MAX, SEEK_SET), isize::MAX);
+    assert_eq!(lseek(fd, 1, SEEK_CUR), -1);
+    assert_eq!(errno(), EINVAL);
+    assert_eq!(lseek(fd, isize::MAX, SEEK_END), -1);
+    assert_eq!(errno(), EINVAL);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 0);
+
+    // writes overwrite in place
+    assert_eq!(write(fd, b"HELLO"), 5);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(read(fd, &mut buf), 11);
+    assert_eq!(&buf[..11], b"HELLO world");
+
+    // past the end reads give 0, a write fills the gap with zeros
+    assert_eq!(lseek(fd, 16, SEEK_SET), 16);
+    assert_eq!(read(fd, &mut buf), 0);
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(lseek(fd, 0, SEEK_END), 17);
+    assert_eq!(lseek(fd, 11, SEEK_SET), 11);
+    assert_eq!(read(fd, &mut buf), 6);
+    assert_eq!(&buf[..6], b"\0\0\0\0\0!");
+
+    // a dup'd fd shares the offset, another open has its own
+    let copy = dup(fd);
+    assert!(copy >= 0);
+    let copy = copy as usize;
+    let other = open(name, OpenFlags::RDONLY);
+    assert!(other >= 0);
+    let other = other as usize;
+    assert_eq!(lseek(fd, 2, SEEK_SET), 2);
+    assert_eq!(lseek(copy, 0, SEEK_CUR), 2);
+    assert_eq!(read(copy, &mut buf[..3]), 3);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 5);
+    assert_eq!(lseek(other, 0, SEEK_CUR), 0);
+    assert_eq!(read(other, &mut buf[..5]), 5);
+    assert_eq!(&buf[..5], b"HELLO");
+    assert_eq!(close(copy), 0);
+    assert_eq!(close(other), 0);
+
+    // zero-filling stops at the cap
+    assert_eq!(lseek(fd, (FILE_MAX - 1) as isize, SEEK_SET), (FILE_MAX - 1) as isize);
+    assert_eq!(write(fd, b"ab"), 1);
+    assert_eq!(lseek(fd, 0, SEEK_END), FILE_MAX as isize);
+    assert_eq!(write(fd, b"c"), -1);
+    assert_eq!(errno(), ENOSPC);
+    assert_eq!(lseek(fd, (FILE_MAX + 10) as isize, SEEK_SET), (FILE_MAX + 10) as isize);
+    assert_eq!(write(fd, b"c"), -1);
+    assert_eq!(errno(), ENOSPC);
+    assert_eq!(close(fd), 0);
+    // give the space back
+    let fd = open(name, OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert_eq!(close(fd as usize), 0);
+
+    // streams cannot seek
+    assert_eq!(lseek(0, 0, SEEK_CUR), -1);
+    assert_eq!(errno(), ESPIPE);
+    assert_eq!(lseek(1, 0, SEEK_SET), -1);
+    assert_eq!(errno(), ESPIPE);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_CUR), -1);
+    assert_eq!(errno(), ESPIPE);
+    assert_eq!(lseek(pipe_fd[1], 0, SEEK_END), -1);
+    assert_eq!(errno(), ESPIPE);
+    assert_eq!(close(pipe_fd[0]), 0);
+    assert_eq!(close(pipe_fd[1]), 0);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_SET), -1);
+    println!("Test lseek OK!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{getpid, madvise, mmap, process_info, ProcessInfo, MADV_DONTNEED, MADV_NORMAL};
+
+/// 正确输出：（无报错信息）
+/// Test madvise OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 50;
+
+fn resident_pages() -> usize {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(getpid() as usize, &mut info), 0);
+    info.resident_pages
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let len = PAGES * PAGE_SIZE;
+    assert_eq!(mmap(START, len, 3), 0);
+    let before = resident_pages();
+    for i in 0..PAGES {
+        unsafe {
+            ((START + i * PAGE_SIZE) as *mut usize).write_volatile(i + 1);
+        }
+    }
+    assert_eq!(resident_pages(), before + PAGES);
+    // not entirely mmapped, nothing may be freed
+    assert_eq!(madvise(START, len + PAGE_SIZE, MADV_DONTNEED), -1);
+    assert_eq!(resident_pages(), before + PAGES);
+    assert_eq!(madvise(START, len, MADV_NORMAL), 0);
+    assert_eq!(resident_pages(), before + PAGES);
+    assert_eq!(madvise(START, len, MADV_DONTNEED), 0);
+    assert_eq!(resident_pages(), before);
+    for i in 0..PAGES {
+        unsafe {
+            assert_eq!(((START + i * PAGE_SIZE) as *const usize).read_volatile(), 0);
+        }
+    }
+    assert_eq!(resident_pages(), before + PAGES);
+    println!("Test madvise OK!");
+    0
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{_start, getpid, map_self, mmap, munmap, process_info, ProcessInfo};
+
+/// 正确输出：（无报错信息）
+/// Test map self OK!
+
+const PAGE_SIZE: usize = 4096;
+/// Where the image goes when asked for
+const HINT: usize = 0x10100000;
+/// Offsets in an ELF64 header
+const E_ENTRY: usize = 24;
+const E_SHOFF: usize = 40;
+const E_SHENTSIZE: usize = 58;
+const E_SHNUM: usize = 60;
+
+fn pages() -> (usize, usize) {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(getpid() as usize, &mut info), 0);
+    (info.mapped_pages, info.resident_pages)
+}
+
+fn image(start: usize, len: usize) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(start as *const u8, len) }
+}
+
+fn read(header: &[u8], offset: usize, len: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    bytes[..len].copy_from_slice(&header[offset..offset + len]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+/// Length of the image, up to its section headers which come last
+fn image_len(header: &[u8]) -> usize {
+    read(header, E_SHOFF, 8) + read(header, E_SHENTSIZE, 2) * read(header, E_SHNUM, 2)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let before = pages();
+    let start = map_self(HINT);
+    assert_eq!(start as usize, HINT);
+    let header = image(HINT, PAGE_SIZE);
+    assert_eq!(&header[..4], b"\x7fELF");
+    assert_eq!(read(header, E_ENTRY, 8), _start as usize);
+    let len = image_len(header);
+    // the kernel's own copy, no frames of ours
+    assert_eq!(pages(), before);
+
+    // taken, the next one goes elsewhere
+    let other = map_self(HINT) as usize;
+    assert!(other != HINT && other % PAGE_SIZE == 0);
+    assert_eq!(&image(other, 4)[..], b"\x7fELF");
+    assert_eq!(mmap(HINT, PAGE_SIZE, 0b011), -1);
+
+    // munmap takes it, the last byte of it too, and the address is free
+    // again
+    assert_eq!(image(other, len)[len - 1], image(HINT, len)[len - 1]);
+    assert_eq!(munmap(other, len), 0);
+    assert_eq!(munmap(HINT, len), 0);
+    assert_eq!(mmap(HINT, PAGE_SIZE, 0b011), 0);
+    assert_eq!(munmap(HINT, PAGE_SIZE), 0);
+    assert_eq!(pages(), before);
+    println!("Test map self OK!");
+    0
+}
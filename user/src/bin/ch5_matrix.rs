@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::get_time;
+
+const N: usize = 48;
+const ROUNDS: usize = 20;
+
+/// Multiply matrices for a while and report how long it took, the workload
+/// of `ch5b_rusage_bench`
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut a = [[0u32; N]; N];
+    let mut b = [[0u32; N]; N];
+    let mut c = [[0u32; N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            a[i][j] = (i * N + j) as u32;
+            b[i][j] = (i + j) as u32 % 7;
+        }
+    }
+    let start = get_time();
+    for _ in 0..ROUNDS {
+        for i in 0..N {
+            for j in 0..N {
+                let mut sum = 0u32;
+                for k in 0..N {
+                    sum = sum.wrapping_add(a[i][k].wrapping_mul(b[k][j]));
+                }
+                c[i][j] = sum;
+            }
+        }
+        // feed the result back so the rounds cannot be folded together
+        a[0][0] = c[N - 1][N - 1];
+    }
+    println!("matrix: {} ms by get_time, checksum {}", get_time() - start, c[0][0]);
+    0
+}
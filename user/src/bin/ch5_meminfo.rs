@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{fork, getpid, loadavg, mmap, munmap, process_info, waitpid, LoadAvg, ProcessInfo};
+
+/// 正确输出：（无报错信息）
+/// Test meminfo OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 100;
+const TOUCHED: usize = 10;
+
+fn meminfo() -> ProcessInfo {
+    let mut info = ProcessInfo::new();
+    assert_eq!(process_info(getpid() as usize, &mut info), 0);
+    info
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let before = meminfo();
+    assert_eq!(mmap(START, PAGES * PAGE_SIZE, 3), 0);
+    let mapped = meminfo();
+    assert_eq!(mapped.mapped_pages, before.mapped_pages + PAGES);
+    assert_eq!(mapped.resident_pages, before.resident_pages);
+    for i in 0..TOUCHED {
+        unsafe {
+            ((START + i * PAGE_SIZE * 7) as *mut u8).write_volatile(i as u8);
+        }
+    }
+    let touched = meminfo();
+    assert_eq!(touched.mapped_pages, before.mapped_pages + PAGES);
+    assert_eq!(touched.resident_pages, before.resident_pages + TOUCHED);
+    assert!(touched.peak_resident_pages >= touched.resident_pages);
+
+    // the child gets exactly the pages we touched
+    let pid = fork();
+    if pid == 0 {
+        let child = meminfo();
+        assert_eq!(child.mapped_pages, touched.mapped_pages);
+        assert_eq!(child.resident_pages, touched.resident_pages);
+        for i in 0..TOUCHED {
+            unsafe {
+                assert_eq!(((START + i * PAGE_SIZE * 7) as *const u8).read_volatile(), i as u8);
+            }
+        }
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // unmapping the first half gives back the 8 frames touched there
+    assert_eq!(munmap(START, PAGES / 2 * PAGE_SIZE), 0);
+    let unmapped = meminfo();
+    assert_eq!(unmapped.mapped_pages, before.mapped_pages + PAGES / 2);
+    assert_eq!(unmapped.resident_pages, before.resident_pages + 2);
+    assert_eq!(unmapped.peak_resident_pages, touched.peak_resident_pages);
+
+    // buffers across two pages that were never touched yet
+    let straddling = START + 60 * PAGE_SIZE - 16;
+    let info = unsafe { &mut *(straddling as *mut ProcessInfo) };
+    assert_eq!(process_info(getpid() as usize, info), 0);
+    assert_eq!(info.pid, getpid() as usize);
+    assert_eq!(info.peak_resident_pages, unmapped.peak_resident_pages);
+    let straddling = START + 70 * PAGE_SIZE - 8;
+    let avg = unsafe { &mut *(straddling as *mut LoadAvg) };
+    assert_eq!(loadavg(avg), 0);
+    assert!(avg.load.iter().all(|&load| load < 100 * 1000));
+    println!("Test meminfo OK!");
+    0
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, mmap, vma_line, waitpid};
+
+/// 正确输出：（无报错信息）
+/// Test mmap fork OK!
+
+const PAGE_SIZE: usize = 4096;
+/// Every page touched before the fork
+const FULL: usize = 0x10000000;
+/// No page touched before the fork
+const UNTOUCHED: usize = 0x10010000;
+/// Only the even pages touched before the fork
+const PARTIAL: usize = 0x10020000;
+const FULL_PAGES: usize = 2;
+const UNTOUCHED_PAGES: usize = 2;
+const PARTIAL_PAGES: usize = 4;
+
+fn page(start: usize, i: usize) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut((start + i * PAGE_SIZE) as *mut u8, PAGE_SIZE) }
+}
+
+fn fill(start: usize, i: usize, byte: u8) {
+    page(start, i).fill(byte);
+}
+
+fn check(start: usize, i: usize, byte: u8) {
+    assert!(
+        page(start, i).iter().all(|&b| b == byte),
+        "page {} of {:#x} is not {:#x}",
+        i,
+        start,
+        byte
+    );
+}
+
+/// Pattern of a page of the area at `start` as seen by one side
+fn pattern(side: u8, start: usize, i: usize) -> u8 {
+    side ^ ((start >> 16) as u8) ^ i as u8
+}
+
+/// Write and check this side's pattern in every page, the other side wrote
+/// its own pattern over the same pages
+fn write_all(side: u8) {
+    for (start, pages) in [
+        (FULL, FULL_PAGES),
+        (UNTOUCHED, UNTOUCHED_PAGES),
+        (PARTIAL, PARTIAL_PAGES),
+    ] {
+        for i in 0..pages {
+            fill(start, i, pattern(side, start, i));
+        }
+    }
+}
+
+fn check_all(side: u8) {
+    for (start, pages) in [
+        (FULL, FULL_PAGES),
+        (UNTOUCHED, UNTOUCHED_PAGES),
+        (PARTIAL, PARTIAL_PAGES),
+    ] {
+        for i in 0..pages {
+            check(start, i, pattern(side, start, i));
+        }
+    }
+}
+
+/// Check that the areas are mapped as far as they were touched before the
+/// fork, on either side
+fn check_areas() {
+    let mut buf = [0u8; 2048];
+    assert_eq!(vma_line(FULL, &mut buf), Some("0000000010000000-0000000010002000 rw-p mmap 2/2"));
+    assert_eq!(vma_line(UNTOUCHED, &mut buf), Some("0000000010010000-0000000010012000 rw-p mmap 0/2"));
+    assert_eq!(vma_line(PARTIAL, &mut buf), Some("0000000010020000-0000000010024000 rw-p mmap 2/4"));
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mmap(FULL, FULL_PAGES * PAGE_SIZE, 0b011), 0);
+    assert_eq!(mmap(UNTOUCHED, UNTOUCHED_PAGES * PAGE_SIZE, 0b011), 0);
+    assert_eq!(mmap(PARTIAL, PARTIAL_PAGES * PAGE_SIZE, 0b011), 0);
+    for i in 0..FULL_PAGES {
+        fill(FULL, i, 0x11);
+    }
+    for i in (0..PARTIAL_PAGES).step_by(2) {
+        fill(PARTIAL, i, 0x22);
+    }
+
+    check_areas();
+
+    let pid = fork();
+    if pid == 0 {
+        check_areas();
+        // what the parent had at the fork
+        for i in 0..FULL_PAGES {
+            check(FULL, i, 0x11);
+        }
+        for i in 0..UNTOUCHED_PAGES {
+            check(UNTOUCHED, i, 0);
+        }
+        for i in 0..PARTIAL_PAGES {
+            check(PARTIAL, i, if i % 2 == 0 { 0x22 } else { 0 });
+        }
+        write_all(0xc0);
+        check_all(0xc0);
+        exit(0);
+    }
+    write_all(0xa0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+    // none of the child's writes show through
+    check_all(0xa0);
+    println!("Test mmap fork OK!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, exit, fork, mutex_blocking_create, mutex_close, mutex_lock, mutex_unlock, waitpid,
+    EAGAIN, EBUSY, EINVAL,
+};
+
+/// 正确输出：（无报错信息）
+/// Test mutex handles OK!
+
+/// As in the kernel config
+const MUTEX_MAX: usize = 256;
+
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+fn wait_exit(pid: isize) -> i32 {
+    assert!(pid > 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    WEXITSTATUS!(status)
+}
+
+/// Create mutexes in a child until there are no more, return how many
+/// others hold
+fn create_all() -> usize {
+    let pid = fork();
+    if pid == 0 {
+        let mut count = 0;
+        while mutex_blocking_create() >= 0 {
+            count += 1;
+        }
+        assert_eq!(errno(), EAGAIN);
+        assert!(count <= MUTEX_MAX);
+        // exit closes them all, none held
+        exit((MUTEX_MAX - count) as i32);
+    }
+    wait_exit(pid) as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let shared = mutex_blocking_create();
+    assert!(shared >= 0);
+    let shared = shared as usize;
+    fails_with(mutex_lock(shared + 1), EINVAL);
+
+    // a child shares what it was forked with and has its own from then on
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(mutex_lock(shared), 0);
+        mutex_unlock(shared);
+        let own = mutex_blocking_create();
+        assert_eq!(own as usize, shared + 1);
+        assert_eq!(mutex_lock(own as usize), 0);
+        exit(0);
+    }
+    assert_eq!(wait_exit(pid), 0);
+    // the child's mutex is no handle here, and went away as it exited held
+    fails_with(mutex_lock(shared + 1), EINVAL);
+
+    // a held mutex stays open, a closed handle is free for the next one
+    assert_eq!(mutex_lock(shared), 0);
+    fails_with(mutex_close(shared), EBUSY);
+    mutex_unlock(shared);
+    assert_eq!(mutex_close(shared), 0);
+    fails_with(mutex_lock(shared), EINVAL);
+    fails_with(mutex_close(shared), EINVAL);
+    assert_eq!(mutex_blocking_create() as usize, shared);
+    assert_eq!(mutex_close(shared), 0);
+
+    // the table is capped, and what a process leaves is freed as it exits
+    let others = create_all();
+    assert!(others < MUTEX_MAX);
+    assert_eq!(create_all(), others);
+    println!("Test mutex handles OK!");
+    0
+}
@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    mmap, mprotect, munmap, page_flags, pagemap, PAGEMAP_EXECUTABLE, PAGEMAP_PRESENT,
+    PAGEMAP_USER, PAGEMAP_WRITABLE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test pagemap OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+
+const RWU: u64 = PAGEMAP_PRESENT | PAGEMAP_WRITABLE | PAGEMAP_USER;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let text = page_flags(main as usize);
+    assert_eq!(
+        text & (RWU | PAGEMAP_EXECUTABLE),
+        PAGEMAP_PRESENT | PAGEMAP_USER | PAGEMAP_EXECUTABLE
+    );
+
+    assert_eq!(mmap(START, 3 * PAGE_SIZE, 3), 0);
+    for page in 0..3 {
+        unsafe { ((START + page * PAGE_SIZE) as *mut u8).write_volatile(1) };
+        assert_eq!(page_flags(START + page * PAGE_SIZE) & RWU, RWU);
+    }
+    assert_eq!(mprotect(START + PAGE_SIZE, PAGE_SIZE, 1), 0);
+    assert_eq!(mprotect(START + 2 * PAGE_SIZE, PAGE_SIZE, 0), 0);
+    let mut entries = [0u64; 4];
+    assert_eq!(pagemap(START + 100, &mut entries), 0);
+    assert_eq!(entries[0] & RWU, RWU);
+    assert_eq!(entries[1] & RWU, PAGEMAP_PRESENT | PAGEMAP_USER);
+    // still resident, just out of reach
+    assert_eq!(entries[2] & RWU, PAGEMAP_PRESENT);
+    assert_eq!(entries[3], 0);
+
+    assert_eq!(munmap(START, 3 * PAGE_SIZE), 0);
+    assert_eq!(page_flags(START), 0);
+    // a range past the end of the address space
+    assert_eq!(pagemap(usize::MAX - PAGE_SIZE + 1, &mut entries), -1);
+    println!("Test pagemap OK!");
+    0
+}
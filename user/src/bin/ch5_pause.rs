@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, get_time, pause, sigaction, sigprocmask, sigreturn, sigsuspend, with_signaller, SigInfo,
+    SignalAction, SignalFlags, Signaller, EINTR, SIGUSR1, SIGUSR2, SIG_BLOCK, SIG_SETMASK,
+};
+
+/// 正确输出：（无报错信息）
+/// Test pause OK!
+
+static mut USR1: usize = 0;
+static mut USR2: usize = 0;
+
+fn count(counter: &usize) -> usize {
+    unsafe { core::ptr::read_volatile(counter) }
+}
+
+fn mask() -> SignalFlags {
+    let mut old = SignalFlags::all();
+    assert_eq!(sigprocmask(SIG_BLOCK, None, Some(&mut old)), 0);
+    old
+}
+
+extern "C" fn count_usr1(_signum: usize, _info: *const SigInfo) {
+    unsafe {
+        USR1 += 1;
+    }
+    sigreturn();
+}
+
+extern "C" fn count_usr2(_signum: usize, _info: *const SigInfo) {
+    unsafe {
+        USR2 += 1;
+    }
+    sigreturn();
+}
+
+fn catch(signum: usize, handler: usize) {
+    let action = SignalAction {
+        handler,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+/// The checks, signalled by the parent through `signals`
+fn run(signals: &Signaller) -> i32 {
+    catch(SIGUSR1, count_usr1 as usize);
+    catch(SIGUSR2, count_usr2 as usize);
+
+    // the handler has run by the time pause returns
+    let start = get_time();
+    signals.send(&[SIGUSR1], 100);
+    assert_eq!(pause(), -1);
+    assert_eq!(errno(), EINTR);
+    assert!(get_time() - start >= 100);
+    assert_eq!(count(unsafe { &USR1 }), 1);
+    signals.wait();
+
+    // a signal already pending when sigsuspend unblocks it is not lost
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR1), None), 0);
+    signals.send(&[SIGUSR1], 0);
+    signals.wait();
+    assert_eq!(count(unsafe { &USR1 }), 1);
+    assert_eq!(sigsuspend(SignalFlags::empty()), -1);
+    assert_eq!(errno(), EINTR);
+    assert_eq!(count(unsafe { &USR1 }), 2);
+    assert_eq!(mask(), SignalFlags::SIGUSR1);
+
+    // signals in the temporary mask do not wake it up, and are delivered
+    // once the old mask, which does not block them, is back
+    signals.send(&[SIGUSR2, SIGUSR1], 50);
+    assert_eq!(sigsuspend(SignalFlags::SIGUSR2), -1);
+    assert_eq!(errno(), EINTR);
+    assert_eq!(count(unsafe { &USR1 }), 3);
+    assert_eq!(count(unsafe { &USR2 }), 1);
+    assert_eq!(mask(), SignalFlags::SIGUSR1);
+    signals.wait();
+    assert_eq!(sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None), 0);
+    0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let status = with_signaller(run);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("Test pause OK!");
+    0
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, sysinfo_value, waitpid};
+
+/// 正确输出：（无报错信息）
+/// Test pid reuse OK!
+
+const ROUNDS: usize = 20;
+
+fn max_tasks() -> usize {
+    sysinfo_value("max_tasks").expect("no max_tasks line")
+}
+
+/// Fork a child that exits at once, reap it and return its pid
+fn fork_and_reap() -> usize {
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert!(pid > 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    pid as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let max = max_tasks();
+    let mut last = fork_and_reap();
+    for _ in 0..ROUNDS {
+        // a pid just reaped is not handed out again right away
+        let pid = fork_and_reap();
+        assert!(pid < max, "pid {} beyond {}", pid, max);
+        assert_ne!(pid, last);
+        last = pid;
+    }
+    println!("Test pid reuse OK!");
+    0
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, dup, dup3, errno, exec, exit, fcntl, fork, pipe2, waitpid, EMFILE, FD_CLOEXEC, F_GETFD,
+    F_SETFD, O_CLOEXEC, SYSCALL_DUP, SYSCALL_DUP3,
+};
+
+/// 正确输出：（无报错信息）
+/// Test pipe cloexec OK!
+
+const STDIN: usize = 0;
+const STDOUT: usize = 1;
+/// `MAX_FD` of the kernel
+const MAX_FD: usize = 1024;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe2(&mut fds, O_CLOEXEC), 0);
+    assert_eq!(fcntl(fds[0], F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(fds[1], F_GETFD, 0), FD_CLOEXEC as isize);
+    // dup does not copy the flag
+    let copy = dup(fds[1]) as usize;
+    assert_eq!(fcntl(copy, F_GETFD, 0), 0);
+    assert_eq!(fcntl(copy, F_SETFD, FD_CLOEXEC), 0);
+    assert_eq!(fcntl(copy, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(close(copy), 0);
+    assert_eq!(close(copy), -1);
+    assert_eq!(fcntl(copy, F_GETFD, 0), -1);
+    assert_eq!(close(999), -1);
+    // the numbers of Linux on RISC-V
+    assert_eq!((SYSCALL_DUP, SYSCALL_DUP3), (23, 24));
+
+    // the fd table is full at MAX_FD, in a child that drops it all on exit
+    let pid = fork();
+    if pid == 0 {
+        while dup(STDOUT) >= 0 {}
+        assert_eq!(errno(), EMFILE);
+        assert_eq!(fcntl(MAX_FD - 1, F_GETFD, 0), 0);
+        // room for one end only, which is not kept
+        assert_eq!(close(MAX_FD - 1), 0);
+        let mut more = [0usize; 2];
+        assert_eq!(pipe2(&mut more, 0), -1);
+        assert_eq!(errno(), EMFILE);
+        assert_eq!(dup(STDOUT), (MAX_FD - 1) as isize);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // producer | consumer, both children also inherit both ends of the pipe
+    // but lose them on exec
+    let consumer = fork();
+    if consumer == 0 {
+        assert_eq!(dup3(fds[0], STDIN, 0), STDIN as isize);
+        exec("ch5_pipe_consumer\0", &[0 as *const u8]);
+        exit(-1);
+    }
+    let producer = fork();
+    if producer == 0 {
+        assert_eq!(dup3(fds[1], STDOUT, 0), STDOUT as isize);
+        exec("ch5_pipe_producer\0", &[0 as *const u8]);
+        exit(-1);
+    }
+    assert_eq!(close(fds[0]), 0);
+    assert_eq!(close(fds[1]), 0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(producer as usize, &mut exit_code), producer);
+    assert_eq!(exit_code, 0);
+    // only returns if the consumer saw EOF
+    assert_eq!(waitpid(consumer as usize, &mut exit_code), consumer);
+    assert!(WIFEXITED!(exit_code));
+    assert_eq!(WEXITSTATUS!(exit_code), 0);
+    println!("Test pipe cloexec OK!");
+    0
+}
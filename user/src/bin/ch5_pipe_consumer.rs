@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+use user_lib::read;
+
+const STDIN: usize = 0;
+/// What ch5_pipe_producer writes
+const BYTES: usize = 5000;
+
+/// Read stdin up to EOF and exit with 0 if all of ch5_pipe_producer's bytes
+/// came through, for ch5_pipe_cloexec
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 64];
+    let mut total = 0;
+    loop {
+        let n = read(STDIN, &mut buf);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    if total as usize == BYTES {
+        0
+    } else {
+        1
+    }
+}
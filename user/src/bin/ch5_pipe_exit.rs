@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, dup, errno, exit, fork, kill, pipe, read, sigprocmask, sleep_blocking, sys_wait4,
+    waitpid, write, SignalFlags, EPIPE, SIGKILL, SIGPIPE, SIG_BLOCK, WNOHANG,
+};
+
+/// 正确输出：（无报错信息）
+/// Test pipe exit OK!
+
+/// Long enough for the reads below, a hang is a failure it turns into
+const WATCHDOG_MS: usize = 2000;
+/// How often the watchdog checks whether the checks are done
+const POLL_MS: usize = 10;
+
+/// Start a child writing to a pipe until that fails, stopped by SIGPIPE
+/// unless `block_sigpipe`, and close the read end once it is blocked on the
+/// full pipe. Return its wait status
+fn write_until_closed(block_sigpipe: bool) -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let child = fork();
+    if child == 0 {
+        close(fds[0]);
+        if block_sigpipe {
+            assert_eq!(sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGPIPE), None), 0);
+        }
+        let buf = [0u8; 100];
+        while write(fds[1], &buf) > 0 {}
+        assert_eq!(errno(), EPIPE);
+        // nothing is taken once the reader is gone
+        assert_eq!(write(fds[1], &buf), -1);
+        exit(0);
+    }
+    close(fds[1]);
+    sleep_blocking(50);
+    close(fds[0]);
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    status
+}
+
+fn run() -> i32 {
+    // the child exits holding two fds of the write end, the reader sees the
+    // end of the pipe before reaping it
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let child = fork();
+    if child == 0 {
+        close(fds[0]);
+        assert!(dup(fds[1]) > 0);
+        assert_eq!(write(fds[1], b"bye"), 3);
+        exit(7);
+    }
+    close(fds[1]);
+    let mut buf = [0u8; 8];
+    let mut got = 0;
+    loop {
+        let n = read(fds[0], &mut buf[got..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        got += n as usize;
+    }
+    assert_eq!(&buf[..got], b"bye");
+    close(fds[0]);
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 7);
+
+    // a writer waiting for room fails once the reader is gone
+    let writer = write_until_closed(true);
+    assert!(WIFEXITED!(writer));
+    assert_eq!(WEXITSTATUS!(writer), 0);
+    let writer = write_until_closed(false);
+    assert!(WIFSIGNALED!(writer));
+    assert_eq!(WTERMSIG!(writer), SIGPIPE);
+    0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the checks run in a child for us to kill if they hang, a child may not
+    // kill its parent
+    let checks = fork();
+    if checks == 0 {
+        exit(run());
+    }
+    let mut status = 0;
+    let mut waited = 0;
+    loop {
+        let ret = sys_wait4(checks, &mut status, WNOHANG, core::ptr::null_mut());
+        if ret != 0 {
+            assert_eq!(ret, checks);
+            break;
+        }
+        if waited == WATCHDOG_MS {
+            println!("pipe exit: reader still blocked, killing it");
+            assert_eq!(kill(checks as usize, SIGKILL), 0);
+        }
+        sleep_blocking(POLL_MS);
+        waited += POLL_MS;
+    }
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("Test pipe exit OK!");
+    0
+}
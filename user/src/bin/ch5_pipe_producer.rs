@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+use user_lib::write;
+
+const STDOUT: usize = 1;
+const BYTES: usize = 5000;
+
+/// Write `BYTES` bytes to stdout, for ch5_pipe_cloexec
+#[no_mangle]
+pub fn main() -> i32 {
+    let buf = [b'x'; 100];
+    for _ in 0..BYTES / buf.len() {
+        assert_eq!(write(STDOUT, &buf), buf.len() as isize);
+    }
+    0
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, exit, fork, mmap, page_flags, pipe, read, sleep_blocking, sysinfo_value, waitpid, write,
+    PAGEMAP_COW, PAGEMAP_WRITABLE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test pipe zerocopy OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+const BLOCK: usize = 16 * PAGE_SIZE;
+
+fn lent_pages() -> usize {
+    sysinfo_value("pipe_lent_pages").expect("no pipe_lent_pages line")
+}
+
+fn pattern(round: usize, i: usize) -> u8 {
+    (i * 7 + i / PAGE_SIZE + round) as u8
+}
+
+fn fill(buf: &mut [u8], round: usize) {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = pattern(round, i);
+    }
+}
+
+/// Read `rounds` blocks less than a page at a time, each must be the one
+/// written before the writer changed its buffer
+fn reader(read_end: usize, rounds: &[usize]) -> ! {
+    let mut chunk = [0u8; 1000];
+    for &round in rounds {
+        let mut got = 0;
+        while got < BLOCK {
+            let want = chunk.len().min(BLOCK - got);
+            let n = read(read_end, &mut chunk[..want]);
+            assert!(n > 0);
+            for (i, &byte) in chunk[..n as usize].iter().enumerate() {
+                assert_eq!(byte, pattern(round, got + i), "round {} byte {}", round, got + i);
+            }
+            got += n as usize;
+        }
+    }
+    let mut rest = [0u8; 1];
+    assert_eq!(read(read_end, &mut rest), 0);
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // one more page for the unaligned block
+    assert_eq!(mmap(START, BLOCK + PAGE_SIZE, 3), 0);
+    let buf = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, BLOCK + PAGE_SIZE) };
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        reader(fds[0], &[0, 1, 2, 3]);
+    }
+    close(fds[0]);
+
+    // the reader is blocked by now, the writer takes its pages back by
+    // writing to them and the reader never sees it
+    sleep_blocking(20);
+    let before = lent_pages();
+    fill(&mut buf[..BLOCK], 0);
+    assert_eq!(write(fds[1], &buf[..BLOCK]), BLOCK as isize);
+    assert_eq!(lent_pages(), before + 16);
+    for page in (START..START + BLOCK).step_by(PAGE_SIZE) {
+        assert_eq!(page_flags(page) & (PAGEMAP_COW | PAGEMAP_WRITABLE), PAGEMAP_COW);
+    }
+    fill(&mut buf[..BLOCK], 1);
+    for page in (START..START + BLOCK).step_by(PAGE_SIZE) {
+        assert_eq!(page_flags(page) & (PAGEMAP_COW | PAGEMAP_WRITABLE), PAGEMAP_WRITABLE);
+    }
+    assert_eq!(buf[5], pattern(1, 5));
+    assert_eq!(write(fds[1], &buf[..BLOCK]), BLOCK as isize);
+
+    // unaligned and short writes are copied
+    let before = lent_pages();
+    fill(&mut buf[1..BLOCK + 1], 2);
+    assert_eq!(write(fds[1], &buf[1..BLOCK + 1]), BLOCK as isize);
+    fill(&mut buf[..BLOCK], 3);
+    for i in (0..BLOCK).step_by(100) {
+        let end = (i + 100).min(BLOCK);
+        assert_eq!(write(fds[1], &buf[i..end]), (end - i) as isize);
+    }
+    assert_eq!(lent_pages(), before);
+    close(fds[1]);
+
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("Test pipe zerocopy OK!");
+    0
+}
@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, errno, get_time, getpid, kill, pipe, ppoll, sigaction, sigprocmask, sigreturn,
+    with_signaller, write, PollFd, SigInfo, SignalAction, SignalFlags, EINTR, POLLERR, POLLHUP,
+    POLLIN, POLLNVAL, POLLOUT, SIGUSR1, SIG_BLOCK, SIG_SETMASK,
+};
+
+/// 正确输出：（无报错信息）
+/// Test ppoll OK!
+
+static mut CAUGHT: usize = 0;
+
+fn caught() -> usize {
+    unsafe { core::ptr::read_volatile(&CAUGHT) }
+}
+
+extern "C" fn count(_signum: usize, _info: *const SigInfo) {
+    unsafe {
+        CAUGHT += 1;
+    }
+    sigreturn();
+}
+
+fn blocked() -> SignalFlags {
+    let mut mask = SignalFlags::empty();
+    assert_eq!(sigprocmask(SIG_BLOCK, None, Some(&mut mask)), 0);
+    mask
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let (read_end, write_end) = (fds[0] as i32, fds[1] as i32);
+
+    // an empty pipe is only writable
+    let mut polls = [
+        PollFd::new(read_end, POLLIN),
+        PollFd::new(write_end, POLLOUT),
+        PollFd::new(-1, POLLIN),
+    ];
+    assert_eq!(ppoll(&mut polls[..1], 0, None), 0);
+    assert_eq!(polls[0].revents, 0);
+    assert_eq!(ppoll(&mut polls, 0, None), 1);
+    assert_eq!(polls[1].revents, POLLOUT);
+    assert_eq!(polls[2].revents, 0);
+    let start = get_time();
+    assert_eq!(ppoll(&mut polls[..1], 50, None), 0);
+    assert!(get_time() - start >= 50);
+
+    // data, and then no writer
+    assert_eq!(write(write_end as usize, b"x"), 1);
+    assert_eq!(ppoll(&mut polls, -1, None), 2);
+    assert_eq!(polls[0].revents, POLLIN);
+    close(write_end as usize);
+    assert_eq!(ppoll(&mut polls, 0, None), 2);
+    assert_eq!(polls[0].revents, POLLIN | POLLHUP);
+    assert_eq!(polls[1].revents, POLLNVAL);
+
+    // no reader
+    assert_eq!(pipe(&mut fds), 0);
+    close(fds[0]);
+    let mut polls = [PollFd::new(fds[1] as i32, POLLOUT)];
+    assert_eq!(ppoll(&mut polls, 0, None), 1);
+    assert_eq!(polls[0].revents, POLLERR);
+    close(fds[1]);
+    close(read_end as usize);
+
+    // a signal blocked outside is let through while ppoll waits, and
+    // blocked again after its handler
+    // from the parent, which runs the rest in a child
+    let status = with_signaller(|signals| {
+        let action = SignalAction {
+            handler: count as usize,
+            ..Default::default()
+        };
+        assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+        assert_eq!(
+            sigprocmask(SIG_SETMASK, Some(SignalFlags::SIGUSR1), None),
+            0
+        );
+        assert_eq!(pipe(&mut fds), 0);
+        let mut polls = [PollFd::new(fds[0] as i32, POLLIN)];
+        signals.send(&[SIGUSR1], 50);
+        assert_eq!(ppoll(&mut polls, 1000, Some(SignalFlags::empty())), -1);
+        assert_eq!(errno(), EINTR);
+        assert_eq!(caught(), 1);
+        assert_eq!(blocked(), SignalFlags::SIGUSR1);
+        signals.wait();
+
+        // one already pending only interrupts a ppoll that lets it through
+        assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+        assert_eq!(ppoll(&mut polls, 20, Some(SignalFlags::SIGUSR1)), 0);
+        assert_eq!(caught(), 1);
+        assert_eq!(ppoll(&mut polls, 1000, Some(SignalFlags::empty())), -1);
+        assert_eq!(errno(), EINTR);
+        assert_eq!(caught(), 2);
+        assert_eq!(blocked(), SignalFlags::SIGUSR1);
+        close(fds[0]);
+        close(fds[1]);
+        0
+    });
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("Test ppoll OK!");
+    0
+}
@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, exit, fork, get_time, mutex_blocking_create, mutex_lock, mutex_unlock, pipe, read,
+    set_priority, sleep_blocking, waitpid, write,
+};
+
+/// 正确输出：（无报错信息）
+/// Test priority inheritance OK!
+
+const LOW: isize = 2;
+const MID: isize = 64;
+const HIGH: isize = 127;
+/// Work the low task does while holding the lock
+const ROUNDS: usize = 1 << 20;
+
+static mut SINK: usize = 0;
+
+fn work(rounds: usize) {
+    for i in 0..rounds {
+        unsafe {
+            let x = (&SINK as *const usize).read_volatile();
+            (&mut SINK as *mut usize).write_volatile(x.wrapping_mul(31).wrapping_add(i));
+        }
+    }
+}
+
+/// Lock a mutex, then let a child of each of `prios` block on it in turn
+/// and return the order in which they got it once it is unlocked
+fn handoff_order(prios: &[isize]) -> [u8; 8] {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mutex = mutex_blocking_create() as usize;
+    assert_eq!(mutex_lock(mutex), 0);
+    let mut pids = [0isize; 8];
+    for (i, &prio) in prios.iter().enumerate() {
+        pids[i] = fork();
+        if pids[i] == 0 {
+            close(fds[0]);
+            set_priority(prio);
+            assert_eq!(mutex_lock(mutex), 0);
+            assert_eq!(write(fds[1], &[i as u8]), 1);
+            mutex_unlock(mutex);
+            exit(0);
+        }
+        // blocked on the mutex before the next one is
+        sleep_blocking(20);
+    }
+    close(fds[1]);
+    mutex_unlock(mutex);
+    let mut order = [u8::MAX; 8];
+    for slot in order[..prios.len()].iter_mut() {
+        let mut tag = [0u8];
+        assert_eq!(read(fds[0], &mut tag), 1);
+        *slot = tag[0];
+    }
+    close(fds[0]);
+    for &pid in &pids[..prios.len()] {
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    }
+    order
+}
+
+/// The low task holds a lock the high task wants while the middle one spins.
+/// Without inheritance the low task gets 1/64 of the CPU next to the middle
+/// one and the high task waits until the spinning is over, with it the low
+/// task runs at the high task's priority and the wait is a few times the
+/// work it does under the lock
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = get_time();
+    work(ROUNDS);
+    let alone = (get_time() - start).max(1);
+
+    let mutex = mutex_blocking_create() as usize;
+    assert_eq!(mutex_lock(mutex), 0);
+    let mid = fork();
+    if mid == 0 {
+        set_priority(MID);
+        let end = get_time() + 16 * alone;
+        while get_time() < end {}
+        exit(0);
+    }
+    let high = fork();
+    if high == 0 {
+        set_priority(HIGH);
+        let start = get_time();
+        assert_eq!(mutex_lock(mutex), 0);
+        let waited = get_time() - start;
+        mutex_unlock(mutex);
+        println!(
+            "high task waited {} ms for a lock held for {} ms of work",
+            waited, alone
+        );
+        exit((waited >= 8 * alone) as i32);
+    }
+    set_priority(LOW);
+    work(ROUNDS);
+    mutex_unlock(mutex);
+
+    let mut status = 0;
+    assert_eq!(waitpid(high as usize, &mut status), high);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0, "the high task was starved");
+    assert_eq!(waitpid(mid as usize, &mut status), mid);
+
+    // the waiter of the highest priority gets the lock first, the one that
+    // has waited longest of equal ones
+    let order = handoff_order(&[LOW, MID, HIGH, MID]);
+    assert_eq!(order[..4], [2, 1, 3, 0]);
+    println!("Test priority inheritance OK!");
+    0
+}
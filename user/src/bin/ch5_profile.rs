@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, errno, exit, fork, get_time, getpid, mmap, pipe, profile_start, profile_stop, read,
+    waitpid, ENOMEM, EPERM, ESRCH, PROFILE_MAX_SAMPLES, PROFILE_TOTAL_SAMPLES,
+};
+
+/// 正确输出：（无报错信息）
+/// Test profile OK!
+
+/// How long the hot loop runs
+const SPIN_MS: isize = 300;
+/// Bytes of code of `spin` a sample may fall in, more than it takes
+const SPIN_LEN: usize = 256;
+/// No task has this pid
+const NO_PID: usize = 100_000;
+/// Where the buffers of the largest profiles go, lazily mapped
+const START: usize = 0x10000000;
+
+static mut COUNTER: usize = 0;
+
+#[inline(never)]
+fn spin() {
+    for _ in 0..10_000 {
+        unsafe { core::ptr::write_volatile(&mut COUNTER, core::ptr::read_volatile(&COUNTER) + 1) };
+    }
+}
+
+fn spin_for(ms: isize) {
+    let end = get_time() + ms;
+    while get_time() < end {
+        spin();
+    }
+}
+
+/// Profiles as large as they can be, of this process and blocked children,
+/// until the kernel has no room for another one
+fn largest_profiles() {
+    const COUNT: usize = PROFILE_TOTAL_SAMPLES / PROFILE_MAX_SAMPLES + 1;
+    let len = PROFILE_MAX_SAMPLES * core::mem::size_of::<usize>();
+    assert_eq!(mmap(START, COUNT * len, 3), 0);
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut pids = [0isize; COUNT];
+    pids[0] = getpid();
+    for pid in pids[1..].iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            close(fds[1]);
+            assert_eq!(read(fds[0], &mut [0u8; 1]), 0);
+            exit(0);
+        }
+    }
+    close(fds[0]);
+    for (i, &pid) in pids.iter().enumerate() {
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut((START + i * len) as *mut usize, PROFILE_MAX_SAMPLES)
+        };
+        if i < COUNT - 1 {
+            assert_eq!(profile_start(pid as usize, buf), 0);
+        } else {
+            assert_eq!(profile_start(pid as usize, buf), -1);
+            assert_eq!(errno(), ENOMEM);
+        }
+    }
+    // a stopped one makes room
+    assert!(profile_stop(pids[0] as usize, None) >= 0);
+    let last = pids[COUNT - 1] as usize;
+    let buf = unsafe { core::slice::from_raw_parts_mut(START as *mut usize, PROFILE_MAX_SAMPLES) };
+    assert_eq!(profile_start(last, buf), 0);
+    close(fds[1]);
+    for &pid in &pids[1..] {
+        let mut status = 0;
+        assert!(profile_stop(pid as usize, None) >= 0);
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let me = getpid() as usize;
+    let mut samples = [0usize; 256];
+    assert_eq!(profile_start(me, &mut samples), 0);
+    let mut other = [0usize; 1];
+    assert_eq!(profile_start(me, &mut other), -1);
+    spin_for(SPIN_MS);
+    let mut dropped = usize::MAX;
+    let n = profile_stop(me, Some(&mut dropped));
+    assert!(n > 0, "no samples");
+    let n = n as usize;
+    assert_eq!(dropped, 0);
+    // most of the time goes to spin
+    let start = spin as usize;
+    let hot = samples[..n]
+        .iter()
+        .filter(|&&pc| pc >= start && pc < start + SPIN_LEN)
+        .count();
+    assert!(hot * 2 > n, "{} of {} samples in spin", hot, n);
+    // stopped, there is nothing left to stop
+    assert_eq!(profile_stop(me, None), -1);
+
+    // a child, stopped after it exited, with room for one sample
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[0]);
+        let mut buf = [0usize; 1];
+        assert_eq!(profile_start(me, &mut buf), -1);
+        assert_eq!(errno(), EPERM);
+        spin_for(SPIN_MS);
+        exit(0);
+    }
+    close(fds[1]);
+    let mut one = [0usize; 1];
+    assert_eq!(profile_start(pid as usize, &mut one), 0);
+    // the write end closes as it exits
+    assert_eq!(read(fds[0], &mut [0u8; 1]), 0);
+    close(fds[0]);
+    let mut dropped = 0;
+    assert_eq!(profile_stop(pid as usize, Some(&mut dropped)), 1);
+    assert!(dropped > 0, "the child was sampled once only");
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    assert_eq!(profile_stop(pid as usize, None), -1);
+    assert_eq!(errno(), ESRCH);
+    assert_eq!(profile_start(NO_PID, &mut one), -1);
+    assert_eq!(errno(), ESRCH);
+    largest_profiles();
+    println!("Test profile OK!");
+    0
+}
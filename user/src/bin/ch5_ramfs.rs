@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{close, errno, exit, fork, open, read, waitpid, write, OpenFlags, ENOSPC};
+
+/// 正确输出：（无报错信息）
+/// Test ramfs OK!
+
+/// RAMFS_FILE_MAX of the kernel
+const FILE_MAX: usize = 64 * 1024;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let name = "ch5_ramfs_out\0";
+    let data = b"written by the child";
+    assert_eq!(open("ch5_ramfs_missing\0", OpenFlags::RDONLY), -1);
+
+    // the file outlives the process that wrote it
+    let pid = fork();
+    if pid == 0 {
+        let fd = open(name, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+        assert!(fd >= 0);
+        assert_eq!(write(fd as usize, data), data.len() as isize);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    let fd = open(name, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut buf = [0u8; 64];
+    assert_eq!(read(fd, &mut buf), data.len() as isize);
+    assert_eq!(&buf[..data.len()], data);
+    assert_eq!(read(fd, &mut buf), 0);
+    assert_eq!(write(fd, data), -1);
+    assert_eq!(close(fd), 0);
+
+    // O_TRUNC empties it
+    let fd = open(name, OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    assert_eq!(close(fd as usize), 0);
+    let fd = open(name, OpenFlags::RDONLY) as usize;
+    assert_eq!(read(fd, &mut buf), 0);
+    assert_eq!(close(fd), 0);
+
+    // a file stops growing at the cap
+    let fd = open(name, OpenFlags::CREATE | OpenFlags::WRONLY) as usize;
+    let chunk = [b'x'; 1000];
+    let mut written = 0;
+    loop {
+        let n = write(fd, &chunk);
+        if n == -1 && errno() == ENOSPC {
+            break;
+        }
+        assert!(n > 0);
+        written += n as usize;
+    }
+    assert_eq!(written, FILE_MAX);
+    assert_eq!(close(fd), 0);
+    // give the space back
+    let fd = open(name, OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert_eq!(close(fd as usize), 0);
+    println!("Test ramfs OK!");
+    0
+}
@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{errno, reboot, EINVAL, EPERM, REBOOT_COLD, REBOOT_MAGIC, REBOOT_SOFT};
+
+/// 正确输出：（无报错信息）
+/// Test reboot OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // only initproc may reboot, and only with the magic number
+    for cmd in [REBOOT_COLD, REBOOT_SOFT] {
+        assert_eq!(reboot(REBOOT_MAGIC, cmd), -1);
+        assert_eq!(errno(), EPERM);
+        assert_eq!(reboot(0, cmd), -1);
+        assert_eq!(errno(), EINVAL);
+    }
+    println!("Test reboot OK!");
+    0
+}
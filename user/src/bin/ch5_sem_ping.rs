@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, exit, fork, kill, sem_close, sem_open, sem_unlink, semaphore_down, semaphore_up,
+    sleep_blocking, spawn, waitpid, EIDRM, SIGKILL,
+};
+
+/// 正确输出：（无报错信息）
+/// Test sem ping OK!
+
+/// As many as ch5_sem_pong answers
+const ROUNDS: usize = 100;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // what an earlier run that failed may have left
+    sem_unlink("ch5_sem_ping\0");
+    sem_unlink("ch5_sem_pong\0");
+    let ping = sem_open("ch5_sem_ping\0", 0);
+    let pong = sem_open("ch5_sem_pong\0", 0);
+    assert!(ping >= 0 && pong >= 0 && ping != pong);
+    assert_eq!(sem_open("a name longer than thirty-two bytes\0", 0), -1);
+
+    // an unrelated program finds them by name
+    let pid = spawn("ch5_sem_pong\0");
+    assert!(pid > 0);
+    for _ in 0..ROUNDS {
+        semaphore_up(ping as usize);
+        assert_eq!(semaphore_down(pong as usize), 0);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    assert_eq!(sem_unlink("ch5_sem_ping\0"), 0);
+    assert_eq!(sem_unlink("ch5_sem_ping\0"), -1);
+    assert_eq!(sem_unlink("ch5_sem_pong\0"), 0);
+    // the handles stay valid without a name
+    semaphore_up(ping as usize);
+    assert_eq!(semaphore_down(ping as usize), 0);
+    assert_eq!(sem_close(ping as usize), 0);
+    assert_eq!(sem_close(ping as usize), -1);
+    assert_eq!(semaphore_down(ping as usize), -1);
+    assert_eq!(sem_close(pong as usize), 0);
+
+    // the one that could raise it is killed, the waiter does not hang
+    let sem = sem_open("ch5_sem_last\0", 0) as usize;
+    let waiter = fork();
+    if waiter == 0 {
+        let dead = semaphore_down(sem) == -1 && errno() == EIDRM;
+        exit(if dead { 0 } else { 1 });
+    }
+    let poster = fork();
+    if poster == 0 {
+        loop {
+            sleep_blocking(10);
+        }
+    }
+    assert_eq!(sem_close(sem), 0);
+    sleep_blocking(50);
+    assert_eq!(kill(poster as usize, SIGKILL), 0);
+    assert_eq!(waitpid(waiter as usize, &mut status), waiter);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    assert_eq!(waitpid(poster as usize, &mut status), poster);
+    // the name went with it
+    assert_eq!(sem_unlink("ch5_sem_last\0"), -1);
+    println!("Test sem ping OK!");
+    0
+}
@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+use user_lib::{sem_open, semaphore_down, semaphore_up};
+
+/// The other half of ch5_sem_ping, which spawns it: answer each ping with a
+/// pong. Exits with the rounds it did not get through.
+
+const ROUNDS: i32 = 100;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let ping = sem_open("ch5_sem_ping\0", 0);
+    let pong = sem_open("ch5_sem_pong\0", 0);
+    if ping < 0 || pong < 0 {
+        return ROUNDS;
+    }
+    for round in 0..ROUNDS {
+        if semaphore_down(ping as usize) != 0 {
+            return ROUNDS - round;
+        }
+        semaphore_up(pong as usize);
+    }
+    0
+}
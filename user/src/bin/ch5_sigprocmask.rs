@@ -0,0 +1,138 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    exec, exit, fork, getpid, kill, sigaction, sigprocmask, sigreturn, waitpid, yield_, SigInfo,
+    SignalAction, SignalFlags, SIGKILL, SIGUSR1, SIGUSR2, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
+};
+
+/// 正确输出：（无报错信息）
+/// Test sigprocmask OK!
+
+static mut USR1: usize = 0;
+static mut USR2: usize = 0;
+/// The mask the SIGUSR2 handler ran with
+static mut HANDLER_MASK: u32 = 0;
+
+fn usr1_count() -> usize {
+    unsafe { core::ptr::read_volatile(&USR1) }
+}
+
+fn mask() -> SignalFlags {
+    let mut old = SignalFlags::all();
+    assert_eq!(sigprocmask(SIG_BLOCK, None, Some(&mut old)), 0);
+    old
+}
+
+extern "C" fn count_usr1(signum: usize, _info: *const SigInfo) {
+    assert_eq!(signum, SIGUSR1);
+    unsafe {
+        USR1 += 1;
+    }
+    sigreturn();
+}
+
+/// SIGUSR1 sent from here waits for the sigreturn
+extern "C" fn send_usr1(signum: usize, _info: *const SigInfo) {
+    assert_eq!(signum, SIGUSR2);
+    unsafe {
+        USR2 += 1;
+        HANDLER_MASK = mask().bits();
+    }
+    let before = usr1_count();
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    yield_();
+    assert_eq!(usr1_count(), before);
+    sigreturn();
+}
+
+fn catch(signum: usize, handler: usize, mask: SignalFlags) {
+    let action = SignalAction {
+        handler,
+        mask,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    if argc > 1 {
+        // the new image of the child keeps the mask
+        assert_eq!(mask(), SignalFlags::SIGUSR1);
+        return 0;
+    }
+    let pid = getpid() as usize;
+    catch(SIGUSR1, count_usr1 as usize, SignalFlags::empty());
+    assert_eq!(mask(), SignalFlags::empty());
+
+    // a blocked signal stays pending, sending it twice delivers it once
+    let mut old = SignalFlags::all();
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR1), Some(&mut old)), 0);
+    assert_eq!(old, SignalFlags::empty());
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    for _ in 0..5 {
+        yield_();
+    }
+    assert_eq!(usr1_count(), 0);
+    // and it is delivered before the unblocking call returns
+    assert_eq!(sigprocmask(SIG_UNBLOCK, Some(SignalFlags::SIGUSR1), Some(&mut old)), 0);
+    assert_eq!(old, SignalFlags::SIGUSR1);
+    assert_eq!(usr1_count(), 1);
+    yield_();
+    assert_eq!(usr1_count(), 1);
+
+    // SIGKILL cannot be blocked, a bad `how` changes nothing
+    let set = SignalFlags::SIGKILL | SignalFlags::SIGUSR2;
+    assert_eq!(sigprocmask(SIG_SETMASK, Some(set), None), 0);
+    assert_eq!(mask(), SignalFlags::SIGUSR2);
+    assert_eq!(sigprocmask(3, Some(SignalFlags::SIGUSR1), None), -1);
+    assert_eq!(mask(), SignalFlags::SIGUSR2);
+    assert_eq!(sigprocmask(SIG_SETMASK, Some(SignalFlags::empty()), None), 0);
+
+    // a handler runs with its own signal and its action's mask blocked, the
+    // mask before it comes back with sigreturn
+    catch(SIGUSR2, send_usr1 as usize, SignalFlags::SIGUSR1);
+    assert_eq!(kill(pid, SIGUSR2), 0);
+    assert_eq!(unsafe { core::ptr::read_volatile(&USR2) }, 1);
+    assert_eq!(
+        SignalFlags::from_bits_truncate(unsafe { core::ptr::read_volatile(&HANDLER_MASK) }),
+        SignalFlags::SIGUSR1 | SignalFlags::SIGUSR2
+    );
+    assert_eq!(mask(), SignalFlags::empty());
+    assert_eq!(usr1_count(), 2);
+
+    // fork inherits the mask and exec keeps it
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR1), None), 0);
+    let child = fork();
+    if child == 0 {
+        assert_eq!(mask(), SignalFlags::SIGUSR1);
+        let args = ["ch5_sigprocmask\0".as_ptr(), "child\0".as_ptr(), 0 as *const u8];
+        exec("ch5_sigprocmask\0", &args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+
+    // a child blocking everything still dies of SIGKILL
+    let child = fork();
+    if child == 0 {
+        assert_eq!(sigprocmask(SIG_SETMASK, Some(SignalFlags::all()), None), 0);
+        assert!(!mask().contains(SignalFlags::SIGKILL));
+        loop {
+            yield_();
+        }
+    }
+    yield_();
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert!(WIFSIGNALED!(status));
+    assert_eq!(WTERMSIG!(status), SIGKILL);
+    println!("Test sigprocmask OK!");
+    0
+}
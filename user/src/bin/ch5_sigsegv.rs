@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    exit, fork, mmap, mprotect, sigaction, sigreturn, waitpid, SigInfo, SignalAction, SIGSEGV,
+};
+
+/// 正确输出：（无报错信息）
+/// Test sigsegv OK!
+
+const PAGE: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+
+static mut FAULTS: usize = 0;
+
+/// Make the page accessible again and retry
+extern "C" fn fix_page(signum: usize, info: *const SigInfo) {
+    let info = unsafe { &*info };
+    assert_eq!(signum, SIGSEGV);
+    assert_eq!(info.signo, SIGSEGV);
+    assert_eq!(info.addr, PAGE);
+    unsafe {
+        FAULTS += 1;
+    }
+    assert_eq!(mprotect(PAGE, PAGE_SIZE, 0b011), 0);
+    sigreturn();
+}
+
+/// Return without fixing anything, the fault repeats and we get killed
+extern "C" fn ignore_fault(_signum: usize, _info: *const SigInfo) {
+    sigreturn();
+}
+
+fn catch(handler: usize) {
+    let action = SignalAction {
+        handler,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGSEGV, Some(&action), None), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mmap(PAGE, PAGE_SIZE, 0b011), 0);
+    let p = PAGE as *mut u8;
+    unsafe {
+        p.write_volatile(42);
+    }
+    assert_eq!(mprotect(PAGE, PAGE_SIZE, 0), 0);
+    assert_eq!(mprotect(PAGE + PAGE_SIZE, PAGE_SIZE, 0), -1);
+    catch(fix_page as usize);
+    unsafe {
+        p.write_volatile(43);
+        assert_eq!(p.read_volatile(), 43);
+        assert_eq!(FAULTS, 1);
+    }
+
+    let pid = fork();
+    if pid == 0 {
+        catch(ignore_fault as usize);
+        assert_eq!(mprotect(PAGE, PAGE_SIZE, 0), 0);
+        unsafe {
+            p.write_volatile(44);
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    // killed like any other page fault
+    assert!(WIFSIGNALED!(exit_code));
+    assert_eq!(WTERMSIG!(exit_code), SIGSEGV);
+    println!("Test sigsegv OK!");
+    0
+}
@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, exit, fork, get_time, pipe, read, sleep_slack, sysinfo_value, waitpid, write,
+};
+
+/// 正确输出：（无报错信息）
+/// Test sleep slack OK!
+
+const SLEEP_MS: usize = 50;
+const SLACK_MS: usize = 8;
+/// What a busy hart may add on top, a few ticks
+const LATE_MS: isize = 30;
+/// Sleepers started together, and a slack they are sure to fall within
+const SLEEPERS: usize = 4;
+const SHARED_SLACK_MS: usize = 32;
+
+fn timers_coalesced() -> usize {
+    sysinfo_value("timers_coalesced").expect("no timers_coalesced line")
+}
+
+/// Start `SLEEPERS` children at once that sleep with `slack_ms`, return how
+/// many of their timers the kernel put on the deadline of another
+fn sleep_together(slack_ms: usize) -> usize {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut pids = [0isize; SLEEPERS];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            close(fds[1]);
+            let mut byte = [0u8];
+            assert_eq!(read(fds[0], &mut byte), 1);
+            sleep_slack(SLEEP_MS, slack_ms);
+            exit(0);
+        }
+    }
+    close(fds[0]);
+    let before = timers_coalesced();
+    assert_eq!(write(fds[1], &[0u8; SLEEPERS]), SLEEPERS as isize);
+    close(fds[1]);
+    for pid in pids {
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    }
+    timers_coalesced() - before
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // no sooner than asked, and late only by the slack and the scheduler
+    for slack in [0, SLACK_MS] {
+        for _ in 0..5 {
+            let start = get_time();
+            sleep_slack(SLEEP_MS, slack);
+            let slept = get_time() - start;
+            assert!(slept >= SLEEP_MS as isize, "woke after {} ms", slept);
+            assert!(
+                slept <= (SLEEP_MS + slack) as isize + LATE_MS,
+                "woke after {} ms with {} ms slack",
+                slept,
+                slack
+            );
+        }
+    }
+
+    // sleepers that start together share deadlines, at most one falls
+    // past the one the first picked and starts another
+    assert_eq!(sleep_together(0), 0);
+    let shared = sleep_together(SHARED_SLACK_MS);
+    assert!(shared + 2 >= SLEEPERS, "only {} timers coalesced", shared);
+    println!("Test sleep slack OK!");
+    0
+}
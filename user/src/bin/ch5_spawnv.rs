@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, dup3, errno, pipe, read, spawnv, waitpid, SpawnAction, EBADF, EINVAL, ENOENT,
+    O_CLOEXEC,
+};
+
+/// 正确输出：（无报错信息）
+/// Test spawnv OK!
+
+/// An fd of the parent with `O_CLOEXEC`, which the child must not have
+const CLOEXEC_FD: usize = 9;
+const GREETING: &str = "spawnv child OK\n";
+
+/// Assert that `ret` is a failure with `expected`
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        assert_eq!(argc, 3);
+        assert_eq!(argv[1], "child");
+        assert_eq!(argv[2], "two words");
+        assert_eq!(close(CLOEXEC_FD), -1);
+        // fd 1 is the pipe now
+        print!("{}", GREETING);
+        return 0;
+    }
+    let args = [
+        "ch5_spawnv\0".as_ptr(),
+        "child\0".as_ptr(),
+        "two words\0".as_ptr(),
+        0 as *const u8,
+    ];
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(dup3(fds[1], CLOEXEC_FD, O_CLOEXEC), CLOEXEC_FD as isize);
+    let actions = [SpawnAction::dup2(fds[1], 1), SpawnAction::close(fds[0])];
+    let pid = spawnv("ch5_spawnv\0", &args, &actions);
+    assert!(pid > 0);
+    // the actions changed the fds of the child only
+    assert_eq!(close(fds[1]), 0);
+    assert_eq!(close(CLOEXEC_FD), 0);
+    let mut buf = [0u8; 64];
+    let mut len = 0;
+    loop {
+        match read(fds[0], &mut buf[len..]) {
+            0 => break,
+            n if n > 0 => len += n as usize,
+            _ => panic!("read from the pipe failed"),
+        }
+    }
+    assert_eq!(&buf[..len], GREETING.as_bytes());
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+
+    // a bad action starts nothing
+    fails_with(spawnv("ch5_spawnv\0", &args, &[SpawnAction::close(200)]), EBADF);
+    fails_with(spawnv("ch5_spawnv\0", &args, &[SpawnAction::dup2(fds[0], 1024)]), EBADF);
+    let bad_op = SpawnAction { op: 7, fd: 0, new_fd: 0 };
+    fails_with(spawnv("ch5_spawnv\0", &args, &[bad_op]), EINVAL);
+    fails_with(spawnv("ch5_spawnv\0", &args, &[SpawnAction::close(fds[0]); 17]), EINVAL);
+    fails_with(spawnv("ch5_no_such_app\0", &args, &[]), ENOENT);
+    assert_eq!(close(fds[0]), 0);
+    println!("Test spawnv OK!");
+    0
+}
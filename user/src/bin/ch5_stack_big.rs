@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+/// 正确输出：（无报错信息）
+/// Test stack big OK!
+
+/// Stack each level of the recursion takes, at least
+const FRAME: usize = 1024;
+/// Levels, about 3/4 of the stack
+const DEPTH: usize = 768;
+
+stack_size!(1 << 20);
+
+/// Fill a frame of `FRAME` bytes at each of `depth` levels and sum it up on
+/// the way back, so that none of them is optimized away
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; FRAME];
+    for (i, byte) in frame.iter_mut().enumerate() {
+        unsafe { core::ptr::write_volatile(byte, (depth + i) as u8) };
+    }
+    let below = if depth == 0 { 0 } else { recurse(depth - 1) };
+    below + frame.iter().map(|&byte| byte as usize).sum::<usize>()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let expected: usize = (0..=DEPTH)
+        .map(|depth| (0..FRAME).map(|i| (depth + i) as u8 as usize).sum::<usize>())
+        .sum();
+    assert_eq!(recurse(DEPTH), expected);
+    println!("Test stack big OK!");
+    0
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{segfaults, USER_STACK_TOP};
+
+/// 正确输出：（无报错信息）
+/// Test stack small OK!
+
+const STACK_SIZE: usize = 16 * 1024;
+
+stack_size!(STACK_SIZE);
+
+fn touch(addr: usize) {
+    unsafe { core::ptr::write_volatile(addr as *mut u8, 1) };
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let bottom = USER_STACK_TOP - STACK_SIZE;
+    // all of the stack is there, in the child as the fork copies it
+    touch(bottom);
+    assert!(!segfaults(|| touch(bottom)));
+    // and nothing below it
+    assert!(segfaults(|| touch(bottom - 1)));
+    println!("Test stack small OK!");
+    0
+}
@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, errno, exit, fork, getpid, getppid, pipe, prctl, read, set_child_subreaper, sigaction,
+    sigreturn, waitpid, write, yield_, SigInfo, SignalAction, SignalFlags, ECHILD,
+    PR_GET_CHILD_SUBREAPER, SA_RESTART, SIGCHLD,
+};
+
+/// 正确输出：（无报错信息）
+/// Test subreaper OK!
+
+static mut CHLD: usize = 0;
+
+fn chld_count() -> usize {
+    unsafe { core::ptr::read_volatile(&CHLD) }
+}
+
+extern "C" fn count_chld(signum: usize, _info: *const SigInfo) {
+    assert_eq!(signum, SIGCHLD);
+    unsafe {
+        CHLD += 1;
+    }
+    sigreturn();
+}
+
+/// Fork A, which forks B, sends us its pid and exits, while B waits for A
+/// to be gone and exits with 0 only if its parent is then
+/// `expected_parent`, or initproc if that is `None`. Return B's pid once A
+/// is reaped
+fn orphan_grandchild(expected_parent: Option<isize>) -> usize {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let a = fork();
+    if a == 0 {
+        let a = getpid();
+        let b = fork();
+        if b == 0 {
+            while getppid() == a {
+                yield_();
+            }
+            exit(if getppid() == expected_parent.unwrap_or(0) { 0 } else { 1 });
+        }
+        write(fds[1], &b.to_ne_bytes());
+        exit(0);
+    }
+    close(fds[1]);
+    let mut b = [0u8; core::mem::size_of::<isize>()];
+    assert_eq!(read(fds[0], &mut b), b.len() as isize);
+    close(fds[0]);
+    let mut status = 0;
+    assert_eq!(waitpid(a as usize, &mut status), a);
+    assert!(WIFEXITED!(status));
+    isize::from_ne_bytes(b) as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let me = getpid();
+    let mut subreaper = -1i32;
+    assert_eq!(prctl(PR_GET_CHILD_SUBREAPER, &mut subreaper as *mut i32 as usize), 0);
+    assert_eq!(subreaper, 0);
+
+    // SIGCHLD is dropped by default, B goes to initproc
+    let b = orphan_grandchild(None);
+    let mut status = 0;
+    assert_eq!(waitpid(b, &mut status), -1);
+    assert_eq!(errno(), ECHILD);
+
+    let action = SignalAction {
+        handler: count_chld as usize,
+        mask: SignalFlags::empty(),
+        // the waits below go on through the signal
+        flags: SA_RESTART,
+    };
+    assert_eq!(sigaction(SIGCHLD, Some(&action), None), 0);
+    assert_eq!(set_child_subreaper(true), 0);
+    assert_eq!(prctl(PR_GET_CHILD_SUBREAPER, &mut subreaper as *mut i32 as usize), 0);
+    assert_eq!(subreaper, 1);
+
+    // now B comes to us
+    let b = orphan_grandchild(Some(me));
+    assert_eq!(waitpid(b, &mut status), b as isize);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0, "grandchild saw the wrong parent");
+    // one for A, one for B, which may still be on its way out
+    for _ in 0..100 {
+        if chld_count() == 2 {
+            break;
+        }
+        yield_();
+    }
+    assert_eq!(chld_count(), 2);
+
+    assert_eq!(prctl(usize::MAX, 0), -1);
+    println!("Test subreaper OK!");
+    0
+}
@@ -0,0 +1,136 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, getpid, getppid, gettid, in_child, set_syscall_filter, spawnv_filtered, syscall,
+    waitpid, SyscallFilter, EFAULT, EINVAL, EPERM, FILTER_ERRNO, FILTER_KILL, SIGSYS,
+    SYSCALL_GETPID, SYSCALL_GETPPID, SYSCALL_GETTID, SYSCALL_PIPE, SYSCALL_SET_SYSCALL_FILTER,
+    SYSCALL_WRITE,
+};
+
+/// 正确输出：（无报错信息）
+/// Test syscall filter OK!
+
+/// What a filtered child is left with: printing, its ids, and narrowing
+/// the filter further
+const ALLOWED: &[usize] = &[
+    SYSCALL_WRITE,
+    SYSCALL_GETPID,
+    SYSCALL_GETTID,
+    SYSCALL_SET_SYSCALL_FILTER,
+];
+/// Never mapped, a pipe made there fails with `EFAULT`
+const BAD_ADDR: usize = 8;
+
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+fn wait_status(pid: isize) -> i32 {
+    assert!(pid > 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
+}
+
+fn killed_by_sigsys(status: i32) -> bool {
+    WIFSIGNALED!(status) && WTERMSIG!(status) == SIGSYS
+}
+
+/// Run as the spawned child, with the filter of the parent in place
+fn spawned_child() -> i32 {
+    assert_eq!(gettid(), getpid());
+    fails_with(getppid(), EPERM);
+    0
+}
+
+fn errno_action() {
+    let status = in_child(|| {
+        let filter = SyscallFilter {
+            ids: ALLOWED,
+            action: FILTER_ERRNO,
+        };
+        assert_eq!(set_syscall_filter(&filter), 0);
+        assert!(getpid() > 0);
+        fails_with(getppid(), EPERM);
+        // the pointer is not even looked at
+        fails_with(syscall(SYSCALL_PIPE, [BAD_ADDR, 0, 0]), EPERM);
+        let wider = [SYSCALL_GETPPID, SYSCALL_WRITE];
+        let filter = SyscallFilter {
+            ids: &wider,
+            action: FILTER_ERRNO,
+        };
+        fails_with(set_syscall_filter(&filter), EPERM);
+        fails_with(getppid(), EPERM);
+        // narrowing is fine
+        let filter = SyscallFilter {
+            ids: &[SYSCALL_WRITE],
+            action: FILTER_ERRNO,
+        };
+        assert_eq!(set_syscall_filter(&filter), 0);
+        fails_with(getpid(), EPERM);
+        0
+    });
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+}
+
+fn kill_action() {
+    let status = in_child(|| {
+        let filter = SyscallFilter {
+            ids: ALLOWED,
+            action: FILTER_KILL,
+        };
+        assert_eq!(set_syscall_filter(&filter), 0);
+        assert!(getpid() > 0);
+        // a kill does not become an error
+        let filter = SyscallFilter {
+            ids: ALLOWED,
+            action: FILTER_ERRNO,
+        };
+        fails_with(set_syscall_filter(&filter), EPERM);
+        getppid();
+        1
+    });
+    assert!(killed_by_sigsys(status));
+}
+
+fn spawned() {
+    let args = ["ch5_syscall_filter\0".as_ptr(), "child\0".as_ptr(), 0 as *const u8];
+    let filter = SyscallFilter {
+        ids: ALLOWED,
+        action: FILTER_ERRNO,
+    };
+    let status = wait_status(spawnv_filtered("ch5_syscall_filter\0", &args, &[], &filter));
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    // from its first instruction on
+    let filter = SyscallFilter {
+        ids: &[SYSCALL_WRITE],
+        action: FILTER_KILL,
+    };
+    let status = wait_status(spawnv_filtered("ch5_syscall_filter\0", &args, &[], &filter));
+    assert!(killed_by_sigsys(status));
+    let filter = SyscallFilter {
+        ids: &[usize::MAX],
+        action: FILTER_ERRNO,
+    };
+    fails_with(spawnv_filtered("ch5_syscall_filter\0", &args, &[], &filter), EINVAL);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        assert_eq!(argv[1], "child");
+        return spawned_child();
+    }
+    errno_action();
+    kill_action();
+    spawned();
+    // none of it applies here
+    assert!(getppid() >= 0);
+    fails_with(syscall(SYSCALL_PIPE, [BAD_ADDR, 0, 0]), EFAULT);
+    println!("Test syscall filter OK!");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, getpid, mmap, syscall, task_info_v2, TaskInfoV2, EINVAL, ENOSYS, SYSCALL_GETPID,
+    SYSCALL_MMAP, SYSCALL_TASK_INFO, SYSCALL_WAITTID,
+};
+
+/// 正确输出：（无报错信息）
+/// Test syscall outcomes OK!
+
+const HINT: usize = 0x10200000;
+const PAGE_SIZE: usize = 4096;
+
+// two TaskInfoV2 do not fit on the default stack
+stack_size!(64 * 1024);
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut before = TaskInfoV2::new();
+    let mut after = TaskInfoV2::new();
+    assert_eq!(task_info_v2(&mut before), 0);
+    // a retry loop that gets nowhere, with no permission bits, and some
+    // calls that work
+    for _ in 0..3 {
+        assert_eq!(mmap(HINT, PAGE_SIZE, 0), -1);
+        assert_eq!(errno(), EINVAL);
+    }
+    for _ in 0..2 {
+        assert!(getpid() > 0);
+    }
+    assert_eq!(syscall(SYSCALL_WAITTID, [0; 3]), -1);
+    assert_eq!(errno(), ENOSYS);
+    assert_eq!(task_info_v2(&mut after), 0);
+
+    assert_eq!(after.syscall_ok[SYSCALL_MMAP], before.syscall_ok[SYSCALL_MMAP]);
+    assert_eq!(after.syscall_err[SYSCALL_MMAP] - before.syscall_err[SYSCALL_MMAP], 3);
+    assert_eq!(after.syscall_errno[SYSCALL_MMAP], EINVAL as u16);
+    assert_eq!(after.syscall_ok[SYSCALL_GETPID] - before.syscall_ok[SYSCALL_GETPID], 2);
+    assert_eq!(after.syscall_err[SYSCALL_GETPID], 0);
+    assert_eq!(after.syscall_err[SYSCALL_WAITTID] - before.syscall_err[SYSCALL_WAITTID], 1);
+    assert_eq!(after.syscall_errno[SYSCALL_WAITTID], ENOSYS as u16);
+    // the invocations count the call in flight, the outcomes do not
+    for id in [SYSCALL_MMAP, SYSCALL_GETPID, SYSCALL_WAITTID] {
+        assert_eq!(after.syscall_times[id], after.syscall_ok[id] + after.syscall_err[id]);
+    }
+    assert_eq!(
+        after.syscall_times[SYSCALL_TASK_INFO],
+        after.syscall_ok[SYSCALL_TASK_INFO] + after.syscall_err[SYSCALL_TASK_INFO] + 1
+    );
+    println!("Test syscall outcomes OK!");
+    0
+}
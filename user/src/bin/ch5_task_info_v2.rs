@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    sys_task_info, task_info, task_info_v2, TaskInfo, TaskInfoV2, TaskStatus, SYSCALL_TASK_INFO,
+    TASK_INFO_V2,
+};
+
+// a TaskInfoV2 does not fit on the default stack
+stack_size!(64 * 1024);
+
+/// 正确输出：（无报错信息）
+/// Test task info v2 OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // back to back, v2 sees the v1 call and nothing else
+    let v1 = TaskInfo::new();
+    let mut v2 = TaskInfoV2::new();
+    assert_eq!(task_info(&v1), 0);
+    assert_eq!(task_info_v2(&mut v2), 0);
+    assert_eq!(v2.struct_size, core::mem::size_of::<TaskInfoV2>());
+    assert_eq!(v1.status(), Ok(TaskStatus::Running));
+    assert_eq!(v2.status(), v1.status());
+    for (id, (&old, &new)) in v1.syscall_times.iter().zip(v2.syscall_times.iter()).enumerate() {
+        let expected = if id == SYSCALL_TASK_INFO { old + 1 } else { old };
+        assert_eq!(new, expected, "syscall {}", id);
+    }
+    assert!(v2.time >= v1.time && v2.time - v1.time < 50);
+    assert_eq!(v2.name(), "ch5_task_info_v2");
+    assert!(v2.resident_pages > 0 && v2.mapped_pages >= v2.resident_pages);
+    assert!(v2.peak_resident_pages >= v2.resident_pages);
+
+    // a caller knowing less gets that much, and the size the kernel knows
+    let mut short = [usize::MAX; 4];
+    short[0] = 2 * core::mem::size_of::<usize>();
+    assert_eq!(sys_task_info(TASK_INFO_V2, short.as_mut_ptr() as usize), 0);
+    assert_eq!(short[0], core::mem::size_of::<TaskInfoV2>());
+    // the status is a u32, the rest of the word padding
+    assert_eq!(short[1] as u32, TaskStatus::Running as u32);
+    assert_eq!(short[2..], [usize::MAX; 2]);
+
+    // too short for the size, or a version there is none of
+    let mut tiny = [0usize; 1];
+    assert_eq!(sys_task_info(TASK_INFO_V2, tiny.as_mut_ptr() as usize), -1);
+    assert_eq!(sys_task_info(3, tiny.as_mut_ptr() as usize), -1);
+    assert_eq!(sys_task_info(0, tiny.as_mut_ptr() as usize), -1);
+    println!("Test task info v2 OK!");
+    0
+}
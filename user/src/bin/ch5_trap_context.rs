@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{mmap, mprotect, munmap, segfaults, TRAP_CONTEXT};
+
+/// 正确输出：（无报错信息）
+/// Test trap context OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the registers of this very process are there, neither readable nor
+    // writable by it
+    assert!(segfaults(|| unsafe {
+        core::ptr::read_volatile(TRAP_CONTEXT as *const usize);
+    }));
+    assert!(segfaults(|| unsafe {
+        core::ptr::write_volatile((TRAP_CONTEXT + 8) as *mut usize, 0);
+    }));
+    // nor can it be opened up or replaced
+    assert_eq!(mprotect(TRAP_CONTEXT, 4096, 0b011), -1);
+    assert_eq!(munmap(TRAP_CONTEXT, 4096), -1);
+    assert_eq!(mmap(TRAP_CONTEXT, 4096, 0b011), -1);
+    // and the process goes on trapping as before
+    assert!(segfaults(|| unsafe {
+        core::ptr::read_volatile(TRAP_CONTEXT as *const usize);
+    }));
+    println!("Test trap context OK!");
+    0
+}
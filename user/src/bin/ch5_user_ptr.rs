@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, mmap, syscall, TimeVal, E2BIG, EFAULT, ENAMETOOLONG, SYSCALL_EXEC,
+    SYSCALL_GETTIMEOFDAY, SYSCALL_OPENAT, SYSCALL_SCHED_GETAFFINITY, SYSCALL_SIGACTION,
+    SYSCALL_SIGPROCMASK,
+};
+
+/// 正确输出：（无报错信息）
+/// Test user pointers OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+/// Below the mapping, never mapped
+const UNMAPPED: usize = START - PAGE_SIZE;
+const SIGUSR1: usize = 10;
+
+fn fails_with(ret: isize, expected: isize) {
+    assert_eq!(ret, -1);
+    assert_eq!(errno(), expected);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the pages are lazy, the kernel faults them in rather than failing
+    assert_eq!(mmap(START, 4 * PAGE_SIZE, 3), 0);
+    let ts = (START + PAGE_SIZE - 8) as *const TimeVal;
+    assert_eq!(syscall(SYSCALL_GETTIMEOFDAY, [ts as usize, 0, 0]), 0);
+    assert!(unsafe { (*ts).sec } > 0 || unsafe { (*ts).usec } > 0);
+
+    // pointers to no memory at all fail instead of taking the kernel down
+    fails_with(syscall(SYSCALL_GETTIMEOFDAY, [UNMAPPED, 0, 0]), EFAULT);
+    fails_with(syscall(SYSCALL_SCHED_GETAFFINITY, [0, UNMAPPED, 0]), EFAULT);
+    fails_with(syscall(SYSCALL_SIGACTION, [SIGUSR1, UNMAPPED, 0]), EFAULT);
+    fails_with(syscall(SYSCALL_SIGPROCMASK, [0, UNMAPPED, 0]), EFAULT);
+    fails_with(syscall(SYSCALL_OPENAT, [0, UNMAPPED, 0]), EFAULT);
+    fails_with(syscall(SYSCALL_EXEC, [UNMAPPED, 0, 0]), EFAULT);
+
+    // a string that runs off the end of the mapping
+    let end = START + 4 * PAGE_SIZE;
+    let tail = unsafe { core::slice::from_raw_parts_mut((end - 3) as *mut u8, 3) };
+    tail.copy_from_slice(b"abc");
+    fails_with(syscall(SYSCALL_OPENAT, [0, end - 3, 0]), EFAULT);
+    // one longer than PATH_MAX, across a page boundary
+    let long = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, 4098) };
+    long[..4097].fill(b'a');
+    long[4097] = 0;
+    fails_with(syscall(SYSCALL_OPENAT, [0, START, 0]), ENAMETOOLONG);
+
+    // the arguments of exec, as an array in no memory or too long together
+    let path = b"ch5_user_ptr\0";
+    fails_with(syscall(SYSCALL_EXEC, [path.as_ptr() as usize, UNMAPPED, 0]), EFAULT);
+    long[2000] = 0;
+    let args = [START as *const u8, START as *const u8, core::ptr::null()];
+    fails_with(
+        syscall(SYSCALL_EXEC, [path.as_ptr() as usize, args.as_ptr() as usize, 0]),
+        E2BIG,
+    );
+    println!("Test user pointers OK!");
+    0
+}
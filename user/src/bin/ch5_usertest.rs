@@ -3,6 +3,7 @@
 
 #[macro_use]
 extern crate user_lib;
+extern crate alloc;
 
 static TESTS: &[&str] = &[
     "ch2b_hello_world\0",
@@ -23,20 +24,79 @@ static TESTS: &[&str] = &[
     "ch5_spawn0\0",
     "ch5_spawn1\0",
     "ch5_setprio\0",
+    "ch5_affinity\0",
+    "ch5_sigsegv\0",
+    "ch5_meminfo\0",
+    "ch5_madvise\0",
+    "ch5_pipe_cloexec\0",
+    "ch5_ramfs\0",
+    "ch5_lseek\0",
+    "ch5_wait_status\0",
+    "ch5_prio_inherit\0",
+    "ch5_mutex_handles\0",
+    "ch5_mmap_fork\0",
+    "ch5_cwd\0",
+    "ch5_batch\0",
+    "ch5_vdso\0",
+    "ch5_kstack\0",
+    "ch5_sigprocmask\0",
+    "ch5_pause\0",
+    "ch5_lazy_bss\0",
+    "ch5_enosys\0",
+    "ch5_wx\0",
+    "ch5_pipe_exit\0",
+    "ch5_task_info_v2\0",
+    "ch5_fd_info\0",
+    "ch5_sem_ping\0",
+    "ch5_map_self\0",
+    "ch5_stack_big\0",
+    "ch5_stack_small\0",
+    "ch5_syscall_outcomes\0",
+    "ch5_comm_page\0",
+    "ch5_wake_race\0",
+    "ch5_pid_reuse\0",
+    "ch5_pagemap\0",
+    "ch5_exec_reset\0",
+    "ch5_errno\0",
+    "ch5_user_ptr\0",
+    "ch5_reboot\0",
+    "ch5_kevent\0",
+    "ch5_trap_context\0",
+    "ch5_spawnv\0",
+    "ch5_vma_list\0",
+    "ch5_subreaper\0",
+    "ch5_audit\0",
+    "ch5_syscall_filter\0",
+    "ch5_checkpoint\0",
     // "ch5_stride\0",
 ];
+/// Tests that compare system-wide counters or time their children, run one
+/// at a time
+static SERIAL_TESTS: &[&str] = &[
+    "ch5_kill_zombies\0",
+    "ch5_exec_frames\0",
+    "ch5_eintr\0",
+    "ch5_ppoll\0",
+    "ch5_pipe_zerocopy\0",
+    "ch5_list_apps\0",
+    "ch5_log_ratelimit\0",
+    "ch5_sleep_slack\0",
+    "ch5_block_reason\0",
+    "ch5_profile\0",
+];
 static STEST: &str = "ch5_stride\0";
 
+use alloc::vec::Vec;
 use user_lib::{spawn, waitpid};
 
 /// 辅助测例，运行所有其他测例。
 
 #[no_mangle]
 pub fn main() -> i32 {
-    let mut pid = [0; 20];
-    for (i, &test) in TESTS.iter().enumerate() {
+    let mut pid = Vec::new();
+    for &test in TESTS.iter() {
         println!("Usertests: Running {}", test);
-        pid[i] = spawn(test);
+        pid.push(spawn(test));
     }
     let mut xstate: i32 = Default::default();
     for (i, &test) in TESTS.iter().enumerate() {
@@ -47,6 +107,17 @@ pub fn main() -> i32 {
             test, pid[i], xstate
         );
     }
+    for &test in SERIAL_TESTS.iter() {
+        println!("Usertests: Running {}", test);
+        let spid = spawn(test);
+        xstate = Default::default();
+        let wait_pid = waitpid(spid as usize, &mut xstate);
+        assert_eq!(spid, wait_pid);
+        println!(
+            "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
+            test, spid, xstate
+        );
+    }
     println!("Usertests: Running {}", STEST);
     let spid = spawn(STEST);
     xstate = Default::default();
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, get_time_vdso, sleep, sys_get_time, waitpid, yield_, TimeVal};
+
+/// 正确输出：（无报错信息）
+/// Test vdso OK!
+
+const ROUNDS: usize = 100;
+/// What a get_time syscall may take, at best, in microseconds
+const SLACK_US: usize = 50;
+
+fn vdso_us() -> usize {
+    let mut time = TimeVal::new();
+    assert_eq!(get_time_vdso(&mut time), 0);
+    time.sec * 1_000_000 + time.usec
+}
+
+fn syscall_us() -> usize {
+    let time = TimeVal::new();
+    assert_eq!(sys_get_time(&time, 0), 0);
+    time.sec * 1_000_000 + time.usec
+}
+
+/// Both clocks agree and the fast one still works, whatever ran before
+fn check_agree() {
+    let mut best = usize::MAX;
+    for _ in 0..ROUNDS {
+        let before = vdso_us();
+        let kernel = syscall_us();
+        let after = vdso_us();
+        assert!(before <= kernel && kernel <= after);
+        best = best.min(after - before);
+    }
+    assert!(best <= SLACK_US, "clocks apart by {} us", best);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    check_agree();
+    // scounteren is per hart, not per task
+    for _ in 0..10 {
+        yield_();
+        sleep(2);
+        check_agree();
+    }
+    let pid = fork();
+    if pid == 0 {
+        check_agree();
+        exit(0);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0);
+    println!("Test vdso OK!");
+    0
+}
@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    errno, exit, fork, getpid, mmap, mprotect, vma_line, vma_list, waitpid, COMM_PAGE, EPERM,
+    ESRCH, TRAP_CONTEXT,
+};
+
+/// 正确输出：（无报错信息）
+/// Test vma list OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+const INITPROC_PID: usize = 0;
+/// The shell, which anyone may look at
+const SHELL_PID: usize = 1;
+/// Far beyond any pid the kernel hands out
+const NO_PID: usize = 1_000_000;
+
+/// The kind and permissions of the area holding `addr`
+fn kind_of(addr: usize, buf: &mut [u8]) -> (&str, &str) {
+    let line = vma_line(addr, buf).expect("address in no area");
+    let mut fields = line.split(' ').skip(1);
+    let perm = fields.next().unwrap();
+    (fields.next().unwrap(), perm)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 2048];
+    assert_eq!(kind_of(main as usize, &mut buf), ("elf", "r-xp"));
+    let local = 0usize;
+    assert_eq!(kind_of(&local as *const usize as usize, &mut buf), ("stack", "rw-p"));
+    assert_eq!(
+        vma_line(COMM_PAGE, &mut buf),
+        Some("ffffffffffffc000-ffffffffffffd000 r--p comm 1/1")
+    );
+    // listed, though out of reach of the process
+    assert_eq!(
+        vma_line(TRAP_CONTEXT, &mut buf),
+        Some("ffffffffffffe000-fffffffffffff000 rw-p trap 1/1")
+    );
+
+    // mprotect splits an area, the lines follow
+    assert_eq!(mmap(START, 3 * PAGE_SIZE, 0b011), 0);
+    unsafe { (START as *mut u8).write_volatile(1) };
+    assert_eq!(mprotect(START + PAGE_SIZE, PAGE_SIZE, 0b001), 0);
+    assert_eq!(vma_line(START, &mut buf), Some("0000000010000000-0000000010001000 rw-p mmap 1/1"));
+    assert_eq!(
+        vma_line(START + PAGE_SIZE, &mut buf),
+        Some("0000000010001000-0000000010002000 r--p mmap 0/1")
+    );
+    assert_eq!(
+        vma_line(START + 2 * PAGE_SIZE, &mut buf),
+        Some("0000000010002000-0000000010003000 rw-p mmap 0/1")
+    );
+
+    // whole lines only, and the length of all of them
+    let len = vma_list(getpid() as usize, &mut buf);
+    assert!(len > 0 && (len as usize) < buf.len());
+    let line_len = buf.iter().position(|&b| b == b'\n').unwrap() + 1;
+    let mut short = [0u8; 2048];
+    assert_eq!(vma_list(getpid() as usize, &mut short[..line_len + 5]), len);
+    assert_eq!(short[..line_len], buf[..line_len]);
+    assert!(short[line_len..].iter().all(|&b| b == 0));
+
+    // the parent and pid 1 may be looked at, not a grandparent or a child
+    let parent = getpid() as usize;
+    let pid = fork();
+    if pid == 0 {
+        let grandchild = fork();
+        if grandchild == 0 {
+            let mut buf = [0u8; 2048];
+            assert_eq!(vma_list(parent, &mut buf), -1);
+            assert_eq!(errno(), EPERM);
+            exit(0);
+        }
+        let mut buf = [0u8; 2048];
+        assert!(vma_list(parent, &mut buf) > 0);
+        let mut status = 0;
+        assert_eq!(waitpid(grandchild as usize, &mut status), grandchild);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+        exit(0);
+    }
+    assert_eq!(vma_list(pid as usize, &mut buf), -1);
+    assert_eq!(errno(), EPERM);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    assert!(vma_list(SHELL_PID, &mut buf) > 0);
+    assert_eq!(vma_list(INITPROC_PID, &mut buf), -1);
+    assert_eq!(errno(), EPERM);
+    assert_eq!(vma_list(NO_PID, &mut buf), -1);
+    assert_eq!(errno(), ESRCH);
+    println!("Test vma list OK!");
+    0
+}
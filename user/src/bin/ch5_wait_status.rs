@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, kill, waitpid, yield_, SIGILL, SIGKILL, SIGSEGV};
+
+/// 正确输出：（无报错信息）
+/// Test wait status OK!
+
+/// Fork a child running `f` and return its wait status
+fn status_of(f: fn() -> !) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
+}
+
+fn exit_3() -> ! {
+    exit(3);
+}
+
+fn exit_minus_1() -> ! {
+    exit(-1);
+}
+
+fn segfault() -> ! {
+    unsafe {
+        (0 as *mut u8).write_volatile(1);
+    }
+    exit(0);
+}
+
+fn illegal_instruction() -> ! {
+    unsafe {
+        core::arch::asm!("sret");
+    }
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let status = status_of(exit_3);
+    assert!(WIFEXITED!(status) && !WIFSIGNALED!(status));
+    assert_eq!(WEXITSTATUS!(status), 3);
+
+    let status = status_of(exit_minus_1);
+    assert!(WIFEXITED!(status));
+    assert_eq!(WEXITSTATUS!(status), 0xff);
+
+    let status = status_of(segfault);
+    assert!(WIFSIGNALED!(status) && !WIFEXITED!(status));
+    assert_eq!(WTERMSIG!(status), SIGSEGV);
+
+    let status = status_of(illegal_instruction);
+    assert!(WIFSIGNALED!(status));
+    assert_eq!(WTERMSIG!(status), SIGILL);
+
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    assert_eq!(kill(pid as usize, SIGKILL), 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFSIGNALED!(status));
+    assert_eq!(WTERMSIG!(status), SIGKILL);
+    println!("Test wait status OK!");
+    0
+}
@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    exit, fork, kill, msleep, sigaction, sigreturn, sleep_blocking, sysinfo_value, waitpid,
+    SigInfo, SignalAction, SIGUSR1,
+};
+
+/// 正确输出：（无报错信息）
+/// Test wake race OK!
+
+/// Sleeps of the child, each ended by its timer or a signal, whichever
+/// comes first
+const ROUNDS: usize = 100;
+const SLEEP_MS: usize = 5;
+
+extern "C" fn ignore(_signum: usize, _info: *const SigInfo) {
+    sigreturn();
+}
+
+fn bad_enqueues() -> usize {
+    sysinfo_value("bad_enqueues").expect("no bad_enqueues line")
+}
+
+/// Times the kernel found the very same task in the ready queue as it was
+/// added, whatever the flag that should prevent it said
+fn queue_duplicates() -> usize {
+    sysinfo_value("queue_duplicates").expect("no queue_duplicates line")
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let (before, duplicates) = (bad_enqueues(), queue_duplicates());
+    let action = SignalAction {
+        handler: ignore as usize,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    let child = fork();
+    if child == 0 {
+        // due at once, another hart may wake it before it has blocked
+        for _ in 0..ROUNDS {
+            msleep(0, None);
+        }
+        for _ in 0..ROUNDS {
+            msleep(SLEEP_MS, None);
+        }
+        exit(0);
+    }
+    // kill it about as its timer runs out, a little before or after
+    for i in 0..ROUNDS {
+        sleep_blocking(SLEEP_MS - 1 + i % 3);
+        kill(child as usize, SIGUSR1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    assert_eq!(bad_enqueues(), before);
+    assert_eq!(queue_duplicates(), duplicates);
+    println!("Test wake race OK!");
+    0
+}
@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+use alloc::string::String;
+use user_lib::{errno, mmap, mprotect, segfaults, sysinfo_value, EPERM};
+
+/// 正确输出：（无报错信息）
+/// Test wx OK!
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+/// `li a0, 42; ret`
+const CODE: [u32; 2] = [0x02a0_0513, 0x0000_8067];
+
+// a page the ELF asks to be writable and executable
+core::arch::global_asm!(
+    ".pushsection .wxdata, \"awx\", @progbits",
+    ".balign 4096",
+    ".globl wx_code",
+    "wx_code:",
+    "li a0, 42",
+    "ret",
+    ".balign 4096",
+    ".popsection",
+);
+
+extern "C" {
+    fn wx_code() -> usize;
+}
+
+/// Built with the `allow_wx` feature, which turns W^X off
+fn wx_allowed() -> bool {
+    let features: String = sysinfo_value("features").unwrap();
+    features.split(',').any(|feature| feature == "allow_wx")
+}
+
+fn call(addr: usize) -> usize {
+    unsafe {
+        core::arch::asm!("fence.i");
+        let code: extern "C" fn() -> usize = core::mem::transmute(addr);
+        code()
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let allowed = wx_allowed();
+    let refused = |ret: isize| if allowed { ret == 0 } else { ret == -1 && errno() == EPERM };
+
+    // no writable and executable mapping, from mmap or mprotect
+    assert!(refused(mmap(START, PAGE_SIZE, 0b111)));
+    assert!(refused(mmap(START + PAGE_SIZE, PAGE_SIZE, 0b110)));
+    let page = START + 2 * PAGE_SIZE;
+    assert_eq!(mmap(page, PAGE_SIZE, 0b011), 0);
+    assert!(refused(mprotect(page, PAGE_SIZE, 0b111)));
+
+    // but code can be written and then made executable
+    assert_eq!(mprotect(page, PAGE_SIZE, 0b011), 0);
+    for (i, &word) in CODE.iter().enumerate() {
+        unsafe {
+            (page as *mut u32).add(i).write_volatile(word);
+        }
+    }
+    assert_eq!(mprotect(page, PAGE_SIZE, 0b101), 0);
+    assert_eq!(call(page), 42);
+    assert!(segfaults(|| unsafe { (page as *mut u32).write_volatile(0) }));
+
+    // the W+X segment of the ELF stays writable but not executable
+    let wx = wx_code as usize;
+    unsafe {
+        let word = (wx as *const u32).read_volatile();
+        (wx as *mut u32).write_volatile(word);
+    }
+    assert_eq!(
+        segfaults(|| {
+            call(wx);
+        }),
+        !allowed
+    );
+    println!("Test wx OK!");
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, write, OpenFlags};
+
+const STDOUT: usize = 1;
+
+/// Copy the ramfs files named on the command line to stdout
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    for path in argv.iter().take(argc).skip(1) {
+        // the kernel put a NUL after every argument
+        let fd = open(path, OpenFlags::RDONLY);
+        if fd == -1 {
+            println!("cat: cannot open {}", path);
+            return -1;
+        }
+        let fd = fd as usize;
+        let mut buf = [0u8; 256];
+        loop {
+            let size = read(fd, &mut buf);
+            if size <= 0 {
+                break;
+            }
+            write(STDOUT, &buf[..size as usize]);
+        }
+        close(fd);
+    }
+    0
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, get_time, open, read, unlink, write, OpenFlags};
+
+/// One write, as big as a ramfs file gets
+const LEN: usize = 64 * 1024;
+const ROUNDS: usize = 200;
+
+static mut BUF: [u8; LEN] = [0; LEN];
+
+fn rate(elapsed: isize) -> isize {
+    (LEN * ROUNDS / 1024) as isize * 1000 / elapsed.max(1)
+}
+
+/// How fast the kernel copies a 64 KiB buffer in and out of user space,
+/// through sys_write and sys_read of a ramfs file
+#[no_mangle]
+pub fn main() -> i32 {
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(BUF) };
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let (mut write_ms, mut read_ms) = (0, 0);
+    for _ in 0..ROUNDS {
+        let fd = open("copy_bench\0", OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+        assert!(fd >= 0);
+        let start = get_time();
+        assert_eq!(write(fd as usize, buf), LEN as isize);
+        write_ms += get_time() - start;
+        close(fd as usize);
+
+        let fd = open("copy_bench\0", OpenFlags::RDONLY);
+        let start = get_time();
+        assert_eq!(read(fd as usize, buf), LEN as isize);
+        read_ms += get_time() - start;
+        close(fd as usize);
+    }
+    assert_eq!(buf[LEN - 1], (LEN - 1) as u8);
+    unlink("copy_bench\0");
+    println!(
+        "{} KiB buffers: {} KiB/s written, {} KiB/s read",
+        LEN / 1024,
+        rate(write_ms),
+        rate(read_ms)
+    );
+    0
+}
@@ -21,8 +21,9 @@ pub fn main() -> i32 {
         let mut exit_code: i32 = 0;
         println!("ready waiting on parent process!");
         assert_eq!(pid, wait(&mut exit_code));
-        assert_eq!(exit_code, 100);
-        println!("child process pid = {}, exit code = {}", pid, exit_code);
+        assert!(WIFEXITED!(exit_code));
+        assert_eq!(WEXITSTATUS!(exit_code), 100);
+        println!("child process pid = {}, exit code = {}", pid, WEXITSTATUS!(exit_code));
         0
     }
 }
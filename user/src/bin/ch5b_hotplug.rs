@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    cpu_down, cpu_up, errno, exec, exit, fork, get_time, getcpu, loadavg, waitpid, LoadAvg, EPERM,
+};
+
+/// 正确输出：（无报错信息）
+/// Test hotplug up OK!
+/// Test hotplug down OK!
+///
+/// Needs a second hart, `make run SMP=2`. Only the shell takes harts up and
+/// down, so this is run from it in steps:
+///
+/// ```text
+/// cpu_up 1
+/// ch5b_hotplug up
+/// cpu_down 1
+/// ch5b_hotplug down
+/// cpu_up 1
+/// ch5b_hotplug up
+/// ```
+
+const HART: usize = 1;
+/// Busy tasks, one per hart
+const WORKERS: usize = 2;
+/// How long the workers spin
+const WINDOW_MS: isize = 300;
+
+fn idle_ms() -> [usize; 2] {
+    let mut avg = LoadAvg::default();
+    assert_eq!(loadavg(&mut avg), 0);
+    [avg.idle_ms[0], avg.idle_ms[HART]]
+}
+
+/// Keep `WORKERS` tasks busy for `WINDOW_MS`, return the harts they ran on
+/// and how long each of hart 0 and `HART` was idle meanwhile
+fn busy() -> (usize, [usize; 2]) {
+    let before = idle_ms();
+    let mut pids = [0isize; WORKERS];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            let end = get_time() + WINDOW_MS;
+            let mut harts: i32 = 0;
+            while get_time() < end {
+                harts |= 1 << getcpu();
+            }
+            exit(harts);
+        }
+    }
+    let mut harts = 0;
+    for pid in pids {
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFEXITED!(status));
+        harts |= WEXITSTATUS!(status);
+    }
+    let after = idle_ms();
+    (harts as usize, [after[0] - before[0], after[1] - before[1]])
+}
+
+/// Run every ch5 test with `HART` down
+fn usertests() {
+    let pid = fork();
+    if pid == 0 {
+        exec("ch5_usertest\0", &[0 as *const u8]);
+        panic!("no ch5_usertest");
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+}
+
+/// With `HART` up the work spreads over both harts
+fn up() {
+    let (harts, idle) = busy();
+    if harts & 1 << HART == 0 {
+        println!("hotplug: hart {} takes no work, is it up?", HART);
+        exit(-1);
+    }
+    assert_eq!(harts, 1 | 1 << HART);
+    for (hart, ms) in [0, HART].iter().zip(idle) {
+        assert!(ms < WINDOW_MS as usize / 2, "hart {} idle {} ms of {}", hart, ms, WINDOW_MS);
+    }
+    println!("Test hotplug up OK!");
+}
+
+/// With `HART` down everything runs on hart 0, which does not count idle
+/// time on `HART`
+fn down() {
+    let (harts, idle) = busy();
+    assert_eq!(harts, 1, "a task ran on a hart that is down");
+    assert_eq!(idle[1], 0, "hart {} is down and counts idle time", HART);
+    usertests();
+    println!("Test hotplug down OK!");
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // a child of the shell may not do it itself
+    for ret in [cpu_up(HART), cpu_down(HART)] {
+        assert_eq!(ret, -1);
+        assert_eq!(errno(), EPERM);
+    }
+    match argv.get(1).filter(|_| argc == 2) {
+        Some(&"up") => up(),
+        Some(&"down") => down(),
+        _ => {
+            println!("usage: ch5b_hotplug up|down");
+            return -1;
+        }
+    }
+    0
+}
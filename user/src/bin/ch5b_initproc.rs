@@ -18,10 +18,19 @@ fn main() -> i32 {
                 yield_();
                 continue;
             }
-            println!(
-                "[initproc] Released a zombie process, pid={}, exit_code={}",
-                pid, exit_code,
-            );
+            if WIFSIGNALED!(exit_code) {
+                println!(
+                    "[initproc] Released a zombie process, pid={}, killed by signal {}",
+                    pid,
+                    WTERMSIG!(exit_code),
+                );
+            } else {
+                println!(
+                    "[initproc] Released a zombie process, pid={}, exit_code={}",
+                    pid,
+                    WEXITSTATUS!(exit_code),
+                );
+            }
         }
     }
     0
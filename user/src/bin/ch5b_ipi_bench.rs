@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, get_time_vdso, getcpu, pipe, read, sched_setaffinity, sysinfo_value,
+    waitpid, write, TimeVal,
+};
+
+const HART: usize = 1;
+const ROUNDS: usize = 2000;
+
+fn now_us() -> usize {
+    let mut ts = TimeVal::new();
+    assert_eq!(get_time_vdso(&mut ts), 0);
+    ts.sec * 1_000_000 + ts.usec
+}
+
+fn resched_ipis() -> usize {
+    sysinfo_value("resched_ipis").expect("no resched_ipis line")
+}
+
+/// Bounce a byte `ROUNDS` times between the caller on hart 0 and a child on
+/// `child_hart`, return the mean round trip in us and the IPIs sent
+/// meanwhile, `None` if the child could not run there
+fn ping_pong(child_hart: usize) -> Option<(usize, usize)> {
+    assert_eq!(sched_setaffinity(0, 1), 0);
+    let (mut ping, mut pong) = ([0usize; 2], [0usize; 2]);
+    assert_eq!(pipe(&mut ping), 0);
+    assert_eq!(pipe(&mut pong), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(ping[1]);
+        close(pong[0]);
+        if sched_setaffinity(0, 1 << child_hart) != 0 || getcpu() as usize != child_hart {
+            exit(1);
+        }
+        let mut byte = [0u8];
+        while read(ping[0], &mut byte) == 1 {
+            assert_eq!(write(pong[1], &byte), 1);
+        }
+        exit(0);
+    }
+    close(ping[0]);
+    close(pong[1]);
+    let mut byte = [0u8];
+    // the child answers the first byte once it runs where it should
+    let ok = write(ping[1], &byte) == 1 && read(pong[0], &mut byte) == 1;
+    let ipis = resched_ipis();
+    let start = now_us();
+    for _ in 0..ROUNDS {
+        if !ok {
+            break;
+        }
+        assert_eq!(write(ping[1], &byte), 1);
+        assert_eq!(read(pong[0], &mut byte), 1);
+    }
+    let elapsed = now_us() - start;
+    let ipis = resched_ipis() - ipis;
+    close(ping[1]);
+    close(pong[0]);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    let exited = WIFEXITED!(status) && WEXITSTATUS!(status) == 0;
+    if !ok || !exited {
+        return None;
+    }
+    Some((elapsed / ROUNDS, ipis))
+}
+
+/// Wakeup latency of a pipe ping-pong, with both ends on hart 0 and with the
+/// child on `HART`, where each wakeup needs an IPI rather than a timer tick.
+/// Needs `HART` up, `make run SMP=2` and `cpu_up 1` in the shell first
+#[no_mangle]
+pub fn main() -> i32 {
+    let (same_us, _) = ping_pong(0).unwrap();
+    let (cross_us, ipis) = match ping_pong(HART) {
+        Some(result) => result,
+        None => {
+            println!("ipi_bench: nothing runs on hart {}, is it up?", HART);
+            return -1;
+        }
+    };
+    println!(
+        "pipe round trip: {} us on one hart, {} us across harts with {} IPIs in {} round trips",
+        same_us, cross_us, ipis, ROUNDS
+    );
+    0
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock,
+    sysinfo_value, wait,
+};
+
+/// Tasks taking the lock at once
+const WORKERS: usize = 4;
+/// Lock and unlock pairs of each worker
+const ROUNDS: usize = 20000;
+/// Work under the lock, a few hundred cycles
+const HOLD: usize = 50;
+
+static mut SINK: usize = 0;
+
+fn harts_online() -> usize {
+    sysinfo_value("harts_online").expect("no harts_online line")
+}
+
+/// Run `WORKERS` tasks through short critical sections of `mutex`, return
+/// the lock and unlock pairs per ms
+fn run(mutex: usize) -> usize {
+    let start = get_time();
+    for _ in 0..WORKERS {
+        if fork() == 0 {
+            for i in 0..ROUNDS {
+                assert_eq!(mutex_lock(mutex), 0);
+                for j in 0..HOLD {
+                    unsafe {
+                        let x = (&SINK as *const usize).read_volatile();
+                        (&mut SINK as *mut usize).write_volatile(x ^ (i + j));
+                    }
+                }
+                mutex_unlock(mutex);
+            }
+            exit(0);
+        }
+    }
+    for _ in 0..WORKERS {
+        let mut status = 0;
+        assert!(wait(&mut status) > 0);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    }
+    let elapsed = (get_time() - start).max(1) as usize;
+    WORKERS * ROUNDS / elapsed
+}
+
+/// Throughput of short critical sections under a mutex that always blocks,
+/// as every mutex did before, and under an adaptive one; the two only
+/// differ with more than one hart online
+#[no_mangle]
+pub fn main() -> i32 {
+    let blocking = run(mutex_blocking_create() as usize);
+    let adaptive = run(mutex_create() as usize);
+    println!(
+        "harts {}: blocking {} locks/ms, adaptive {} locks/ms",
+        harts_online(),
+        blocking,
+        adaptive
+    );
+    0
+}
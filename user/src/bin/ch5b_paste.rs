@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, read, sysinfo_value, waitpid};
+
+/// Pasted lines, each `LINE_LEN` bytes with its newline, then two `end`
+/// lines (see os5/scripts/paste_test.py)
+const LINES: usize = 32;
+const LINE_LEN: usize = 64;
+const FD_STDIN: usize = 0;
+
+fn input_dropped() -> usize {
+    sysinfo_value("console_rx_dropped").expect("no console_rx_dropped line")
+}
+
+/// The number of a whole, correct line
+fn check_line(line: &[u8]) -> usize {
+    assert_eq!(line.len(), LINE_LEN, "split line");
+    let number: usize = core::str::from_utf8(&line[..4]).unwrap().parse().unwrap();
+    assert_eq!(line[4], b' ');
+    for (j, &byte) in line[5..LINE_LEN - 1].iter().enumerate() {
+        assert_eq!(byte, b'a' + ((number + j) % 26) as u8, "line {}", number);
+    }
+    assert!(matches!(line[LINE_LEN - 1], b'\n' | b'\r'));
+    number
+}
+
+/// Read lines until `end`, each one after the last. Return how many
+fn read_lines() -> usize {
+    let mut buf = [0u8; 128];
+    let mut count = 0;
+    let mut last = None;
+    loop {
+        let n = read(FD_STDIN, &mut buf);
+        assert!(n > 0);
+        let line = &buf[..n as usize];
+        if line.starts_with(b"end") {
+            return count;
+        }
+        let number = check_line(line);
+        assert!(last.map_or(true, |last| number > last), "line {} out of order", number);
+        last = Some(number);
+        count += 1;
+    }
+}
+
+/// Two readers take the pasted lines in turns, neither gets a line split or
+/// out of order and no byte is dropped
+#[no_mangle]
+pub fn main() -> i32 {
+    let dropped = input_dropped();
+    println!("paste {} lines now", LINES);
+    let pid = fork();
+    if pid == 0 {
+        exit(read_lines() as i32);
+    }
+    let ours = read_lines();
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert!(WIFEXITED!(status));
+    let theirs = WEXITSTATUS!(status) as usize;
+    assert_eq!(ours + theirs, LINES);
+    assert_eq!(input_dropped(), dropped);
+    println!("got {} + {} lines, paste OK!", ours, theirs);
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, sysinfo_value, waitpid};
+
+/// 正确输出：（无报错信息）
+/// Test pid stress OK!
+///
+/// Takes a while, it is not part of ch5_usertest.
+
+const PROCESSES: usize = 100_000;
+
+fn max_tasks() -> usize {
+    sysinfo_value("max_tasks").expect("no max_tasks line")
+}
+
+/// Create and reap many more processes than there are pids and kernel
+/// stack slots. The kernel panics if a stack would go outside its window
+#[no_mangle]
+pub fn main() -> i32 {
+    let max = max_tasks();
+    for i in 0..PROCESSES {
+        let pid = fork();
+        if pid == 0 {
+            exit((i % 256) as i32);
+        }
+        assert!(pid > 0, "fork {} failed with {}", i, pid);
+        assert!((pid as usize) < max, "pid {} beyond {}", pid, max);
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == (i % 256) as i32);
+        if i % 10_000 == 0 {
+            println!("{} processes", i);
+        }
+    }
+    println!("Test pid stress OK!");
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, get_time, mmap, pipe, read, waitpid, write};
+
+const START: usize = 0x10000000;
+const PAGE_SIZE: usize = 4096;
+const BLOCK: usize = 64 * 1024;
+const TOTAL: usize = 16 << 20;
+
+/// Push `TOTAL` bytes through a pipe in 64 KiB blocks from `offset` into the
+/// mapped buffer, return the KiB/s
+fn run(offset: usize) -> isize {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        let dst = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, BLOCK) };
+        let mut got = 0;
+        while got < TOTAL {
+            let n = read(fds[0], dst);
+            assert!(n > 0);
+            got += n as usize;
+        }
+        exit(0);
+    }
+    close(fds[0]);
+    let src = unsafe { core::slice::from_raw_parts((START + offset) as *const u8, BLOCK) };
+    let start = get_time();
+    for _ in 0..TOTAL / BLOCK {
+        assert_eq!(write(fds[1], src), BLOCK as isize);
+    }
+    close(fds[1]);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    let elapsed = get_time() - start;
+    TOTAL as isize / 1024 * 1000 / elapsed.max(1)
+}
+
+/// Pipe throughput of 64 KiB blocks, page aligned ones are lent and
+/// unaligned ones copied
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mmap(START, BLOCK + PAGE_SIZE, 3), 0);
+    unsafe { core::slice::from_raw_parts_mut(START as *mut u8, BLOCK + PAGE_SIZE).fill(b'x') };
+    let aligned = run(0);
+    let unaligned = run(1);
+    println!(
+        "{} KiB in 64 KiB blocks: aligned {} KiB/s, unaligned {} KiB/s",
+        TOTAL / 1024,
+        aligned,
+        unaligned
+    );
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, spawn, wait4, Rusage};
+
+/// Spawn `ch5_matrix` and print the CPU time the kernel accounted to it, to
+/// compare with what it reports itself
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = get_time();
+    let pid = spawn("ch5_matrix\0");
+    if pid < 0 {
+        println!("rusage_bench: cannot spawn ch5_matrix");
+        return -1;
+    }
+    let mut exit_code = 0;
+    let mut usage = Rusage::default();
+    assert_eq!(wait4(pid, &mut exit_code, &mut usage), pid);
+    println!(
+        "rusage_bench: wall {} ms, user {} ms, system {} ms",
+        get_time() - start,
+        usage.utime_us / 1000,
+        usage.stime_us / 1000
+    );
+    println!(
+        "rusage_bench: maxrss {} pages, {} voluntary / {} involuntary switches",
+        usage.maxrss_pages, usage.nvcsw, usage.nivcsw
+    );
+    exit_code
+}
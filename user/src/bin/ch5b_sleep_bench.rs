@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, sleep_slack, sysinfo_value, wait};
+
+/// Sleeping tasks
+const TASKS: usize = 50;
+/// Sleeps of each task, about 100 ms each
+const ROUNDS: usize = 20;
+const PERIOD_MS: usize = 100;
+/// Slack of the coalescing run
+const SLACK_MS: usize = 2;
+
+fn timer_interrupts() -> usize {
+    sysinfo_value("timer_interrupts").expect("no timer_interrupts line")
+}
+
+/// Let `TASKS` tasks sleep with periods a few ms apart and `slack_ms`, return
+/// the timer interrupts per second meanwhile
+fn run(slack_ms: usize) -> usize {
+    let before = timer_interrupts();
+    let start = get_time();
+    for i in 0..TASKS {
+        if fork() == 0 {
+            for _ in 0..ROUNDS {
+                sleep_slack(PERIOD_MS + i % 5, slack_ms);
+            }
+            exit(0);
+        }
+    }
+    for _ in 0..TASKS {
+        let mut status = 0;
+        assert!(wait(&mut status) > 0);
+    }
+    let elapsed = (get_time() - start).max(1) as usize;
+    (timer_interrupts() - before) * 1000 / elapsed
+}
+
+/// Timer interrupts per second with many sleepers, waking each at its exact
+/// deadline and with a little slack to share wakeups
+#[no_mangle]
+pub fn main() -> i32 {
+    let exact = run(0);
+    let coalesced = run(SLACK_MS);
+    println!(
+        "{} sleepers: {} timer interrupts/s exact, {} with {} ms slack",
+        TASKS, exact, coalesced, SLACK_MS
+    );
+    0
+}
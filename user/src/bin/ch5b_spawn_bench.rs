@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, exit, fork, get_time, spawnv, task_info, waitpid, TaskInfo, SYSCALL_FORK};
+
+/// Launches timed of each kind
+const LAUNCHES: usize = 50;
+
+const ARGS: [*const u8; 3] = [
+    "ch5b_spawn_bench\0".as_ptr(),
+    "child\0".as_ptr(),
+    core::ptr::null(),
+];
+
+fn launch_fork_exec() -> isize {
+    let pid = fork();
+    if pid == 0 {
+        exec("ch5b_spawn_bench\0", &ARGS);
+        exit(-1);
+    }
+    pid
+}
+
+fn launch_spawnv() -> isize {
+    spawnv("ch5b_spawn_bench\0", &ARGS, &[])
+}
+
+/// The forks the bench has made so far, from its own syscall histogram
+fn forks() -> u32 {
+    let info = TaskInfo::new();
+    assert_eq!(task_info(&info), 0);
+    info.syscall_times[SYSCALL_FORK]
+}
+
+/// Launch a child that exits at once [`LAUNCHES`] times and wait for each,
+/// return the average time a launch takes, in microseconds, and the forks
+/// made meanwhile
+fn time(launch: fn() -> isize) -> (usize, u32) {
+    let forks_before = forks();
+    let start = get_time();
+    for _ in 0..LAUNCHES {
+        let pid = launch();
+        assert!(pid > 0);
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert!(WIFEXITED!(status) && WEXITSTATUS!(status) == 0);
+    }
+    let elapsed = (get_time() - start) as usize;
+    (elapsed * 1000 / LAUNCHES, forks() - forks_before)
+}
+
+/// Compare how long a shell command takes to start and finish with fork and
+/// exec, as the shell used to, and with spawnv, which forks nothing
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    if argc > 1 {
+        return 0;
+    }
+    let (fork_exec, forked) = time(launch_fork_exec);
+    let (spawn, spawn_forked) = time(launch_spawnv);
+    assert_eq!(forked as usize, LAUNCHES);
+    assert_eq!(spawn_forked, 0);
+    println!(
+        "spawn_bench: fork + exec {} us per launch, {} forks",
+        fork_exec, forked
+    );
+    println!(
+        "spawn_bench: spawnv      {} us per launch, {} forks",
+        spawn, spawn_forked
+    );
+    0
+}
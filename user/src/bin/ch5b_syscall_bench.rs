@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, get_time, getpid, kill, mmap, munmap, sysinfo_value, waitpid, yield_, SIGKILL,
+};
+
+const CALLS: usize = 20000;
+/// Where the busy child maps and unmaps its page
+const BUSY_PAGE: usize = 0x10000000;
+
+/// `tlb_flushes` and `tlb_flushes_skipped` of sysinfo
+fn flush_counts() -> (usize, usize) {
+    let count =
+        |key: &str| -> usize { sysinfo_value(key).unwrap_or_else(|| panic!("no {} line", key)) };
+    (count("tlb_flushes"), count("tlb_flushes_skipped"))
+}
+
+/// Time `CALLS` calls of `call`, print ns per call and TLB flushes per
+/// call, where one trap makes two decisions, at entry and at return
+fn run(name: &str, call: fn() -> isize) {
+    let (flushes, skipped) = flush_counts();
+    let start = get_time();
+    for _ in 0..CALLS {
+        call();
+    }
+    let elapsed_ms = get_time() - start;
+    let (flushes_after, skipped_after) = flush_counts();
+    println!(
+        "{}: {} ns/call, {}/1000 calls flushed the TLB, {} flushes skipped",
+        name,
+        elapsed_ms as usize * 1_000_000 / CALLS,
+        (flushes_after - flushes) * 1000 / CALLS,
+        skipped_after - skipped
+    );
+}
+
+/// A child that changes its page table all the time, killed by the caller
+fn busy_child() -> isize {
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            assert_eq!(mmap(BUSY_PAGE, 4096, 0b011), 0);
+            unsafe { (BUSY_PAGE as *mut u8).write_volatile(1) };
+            assert_eq!(munmap(BUSY_PAGE, 4096), 0);
+        }
+    }
+    assert!(pid > 0);
+    pid
+}
+
+/// The cost of a round trip through the kernel, with sys_yield switching
+/// back to the same task when nothing else is ready. Run it again on a
+/// kernel built with `make run NO_ASID=1`, which flushes on every trap, for
+/// what skipping the flushes saves. The last run has a child changing its
+/// own page table on another hart, which should not make this one flush;
+/// its flushes count along with the child's
+#[no_mangle]
+pub fn main() -> i32 {
+    for key in ["asid_bits", "harts_online"] {
+        if let Some(value) = sysinfo_value::<usize>(key) {
+            println!("{}={}", key, value);
+        }
+    }
+    run("getpid", getpid);
+    run("yield", yield_);
+    let child = busy_child();
+    run("getpid beside a busy child", getpid);
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    0
+}
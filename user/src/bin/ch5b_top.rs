@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{
+    fork, get_time, loadavg, process_info, sleep_blocking, waitpid, BlockKind, LoadAvg,
+    ProcessInfo, TaskStatus, USAGE_SCALE,
+};
+
+const MAX_PID: usize = 64;
+const ROUNDS: usize = 5;
+const INTERVAL_MS: usize = 1000;
+
+fn status_name(status: Result<TaskStatus, u32>) -> &'static str {
+    let status = match status {
+        Ok(status) => status,
+        Err(_) => return "?",
+    };
+    match status {
+        TaskStatus::UnInit => "UnInit",
+        TaskStatus::Ready => "Ready",
+        TaskStatus::Running => "Running",
+        TaskStatus::Exited => "Exited",
+        TaskStatus::Blocking => "Blocking",
+        TaskStatus::Stopped => "Stopped",
+    }
+}
+
+/// What `info` waits for
+fn wait_name(info: &ProcessInfo) -> String {
+    let kind = match info.block_kind() {
+        Ok(kind) => kind,
+        Err(kind) => return format!("kind {}", kind),
+    };
+    match kind {
+        BlockKind::None => String::from("-"),
+        BlockKind::Signal => String::from("signal"),
+        BlockKind::WaitChild if info.block_arg == -1 => String::from("any child"),
+        BlockKind::SleepUntil => format!("sleep {} ms", (info.block_arg - get_time()).max(0)),
+        BlockKind::PipeRead => format!("pipe {}", info.block_arg),
+        BlockKind::WaitChild => format!("child {}", info.block_arg),
+        BlockKind::Mutex => format!("mutex {}", info.block_arg),
+        BlockKind::Semaphore => format!("semaphore {}", info.block_arg),
+    }
+}
+
+/// Print the load average and then one line per live task
+fn show() {
+    let mut avg = LoadAvg::default();
+    assert_eq!(loadavg(&mut avg), 0);
+    println!(
+        "load average: {}.{:02} {}.{:02} {}.{:02}",
+        avg.load[0] / 100,
+        avg.load[0] % 100,
+        avg.load[1] / 100,
+        avg.load[1] % 100,
+        avg.load[2] / 100,
+        avg.load[2] % 100,
+    );
+    print!("idle(ms):");
+    for ms in avg.idle_ms {
+        print!(" {}", ms);
+    }
+    println!("");
+    println!("  PID  PPID  PRIO  STATUS      TIME(ms)   CPU%  SYSCALL  WAIT");
+    let mut info = ProcessInfo::new();
+    for pid in 0..MAX_PID {
+        if process_info(pid, &mut info) != 0 {
+            continue;
+        }
+        let percent = info.cpu_usage * 100 / USAGE_SCALE;
+        let fraction = info.cpu_usage * 1000 / USAGE_SCALE % 10;
+        println!(
+            "{:>5} {:>5} {:>5}  {:<10} {:>9} {:>4}.{}  {:>7}  {}",
+            info.pid,
+            info.ppid,
+            info.prio,
+            status_name(info.status()),
+            info.cpu_time_ms,
+            percent,
+            fraction,
+            info.last_syscall,
+            wait_name(&info),
+        );
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let spin_ms = (ROUNDS * INTERVAL_MS) as isize;
+    let pid = fork();
+    if pid == 0 {
+        // keep the CPU busy so that there is something to watch
+        let start = get_time();
+        while get_time() < start + spin_ms {}
+        return 0;
+    }
+    for _ in 0..ROUNDS {
+        sleep_blocking(INTERVAL_MS);
+        show();
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    0
+}
@@ -12,40 +12,349 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{exec, flush, fork, waitpid};
+use user_lib::{
+    chdir, close, cpu_down, cpu_up, errno, fd_info, flush, getcwd, kevent_filter, list_apps, open,
+    sched_trace, shutdown, spawnv, sysinfo, vma_list, waitpid, FdInfo, FileKind, OpenFlags,
+    SchedEvent, SpawnAction, EPERM, FD_INFO_CLOEXEC, FD_INFO_NONBLOCK, FD_INFO_PEER_CLOSED,
+    SCHED_BLOCK, SCHED_SWITCH_IN, SCHED_SWITCH_OUT, SCHED_WAKE,
+};
+
+/// Open `path` with `flags` for the child to have as `fd`, adding the
+/// actions for that to `actions`. Return the fd the shell has to close once
+/// the child is started, None if the file cannot be opened
+fn redirect(path: &str, flags: OpenFlags, fd: usize, actions: &mut Vec<SpawnAction>) -> Option<usize> {
+    let file = open(path, flags);
+    if file == -1 {
+        println!("Error when opening file {}", path.trim_end_matches('\0'));
+        return None;
+    }
+    let file = file as usize;
+    if file != fd {
+        actions.push(SpawnAction::dup2(file, fd));
+        actions.push(SpawnAction::close(file));
+    }
+    Some(file)
+}
+
+/// Take `op file` out of `args`, returning the file
+fn take_redirection(args: &mut Vec<String>, op: &str) -> Option<String> {
+    let idx = args.iter().position(|arg| arg.as_str() == op)?;
+    if idx + 1 >= args.len() {
+        return None;
+    }
+    let file = args[idx + 1].clone();
+    args.drain(idx..=idx + 1);
+    Some(file)
+}
+
+/// The `version` builtin, print what kernel build is running
+fn print_version() {
+    let mut buf = vec![0u8; 256];
+    let len = sysinfo(&mut buf) as usize;
+    if len > buf.len() {
+        buf.resize(len, 0);
+        sysinfo(&mut buf);
+    }
+    print!("{}", core::str::from_utf8(&buf[..len]).unwrap_or("bad sysinfo\n"));
+}
+
+/// The `apps` builtin, print the apps there are to run, the stale ones
+/// marked
+fn print_apps() {
+    let mut buf = vec![0u8; 1024];
+    let len = list_apps(&mut buf) as usize;
+    if len > buf.len() {
+        buf.resize(len, 0);
+        list_apps(&mut buf);
+    }
+    print!("{}", core::str::from_utf8(&buf[..len]).unwrap_or("bad list_apps\n"));
+}
+
+/// The `maps <pid>` builtin, print the areas of the shell or its parent
+fn print_maps(pid: &str) {
+    let pid = match pid.trim_end_matches('\0').parse() {
+        Ok(pid) => pid,
+        Err(_) => {
+            println!("maps: bad pid {}", pid.trim_end_matches('\0'));
+            return;
+        }
+    };
+    let mut buf = vec![0u8; 1024];
+    let mut len = vma_list(pid, &mut buf);
+    if len > buf.len() as isize {
+        buf.resize(len as usize, 0);
+        len = vma_list(pid, &mut buf);
+    }
+    if len < 0 {
+        println!("maps: cannot list the areas of {}, errno {}", pid, errno());
+        return;
+    }
+    print!("{}", core::str::from_utf8(&buf[..len as usize]).unwrap_or("bad vma_list\n"));
+}
+
+/// The `cpu_up <hart>` and `cpu_down <hart>` builtins, only the shell itself
+/// may take harts up and down
+fn hotplug(cmd: &str, hart: &str) {
+    let (cmd, hart) = (cmd.trim_end_matches('\0'), hart.trim_end_matches('\0'));
+    let hart = match hart.parse() {
+        Ok(hart) => hart,
+        Err(_) => {
+            println!("{}: bad hart {}", cmd, hart);
+            return;
+        }
+    };
+    let ret = if cmd == "cpu_up" {
+        cpu_up(hart)
+    } else {
+        cpu_down(hart)
+    };
+    if ret != 0 {
+        println!("{}: failed on hart {}, errno {}", cmd, hart, errno());
+    }
+}
+
+/// A bit mask in decimal or, with `0x`, in hex
+fn parse_mask(arg: &str) -> Option<usize> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+/// The `kevent_filter [enable [disable]]` builtin, switch the kernel events
+/// of the kinds in the masks on, then off, and print the kinds on. Only the
+/// shell itself may change them
+fn set_kevent_filter(masks: &[String]) {
+    let mut parsed = [0usize; 2];
+    for (mask, arg) in parsed.iter_mut().zip(masks) {
+        let arg = arg.trim_end_matches('\0');
+        *mask = match parse_mask(arg) {
+            Some(mask) => mask,
+            None => {
+                println!("kevent_filter: bad mask {}", arg);
+                return;
+            }
+        };
+    }
+    let filter = kevent_filter(parsed[0], parsed[1]);
+    if filter < 0 {
+        println!("kevent_filter: cannot change the filter, errno {}", errno());
+        return;
+    }
+    println!("kevent filter {:#x}", filter);
+}
+
+/// Pids `lsof` looks at
+const LSOF_MAX_PID: usize = 64;
+/// fds of one task `lsof` prints at most
+const LSOF_MAX_FDS: usize = 64;
+
+/// The `lsof` builtin, print the open fds of every task, pipe ends whose
+/// other end is closed marked. Pipe 0 is no pipe. A builtin as only the
+/// shell, not a child of it, may look at every task
+fn print_lsof() {
+    let mut fds = [FdInfo::new(); LSOF_MAX_FDS];
+    println!("  PID   FD  KIND        PIPE  FLAGS");
+    for pid in 0..LSOF_MAX_PID {
+        let n = fd_info(pid, &mut fds);
+        if n == -1 && errno() == EPERM {
+            println!("{:>5}  (not permitted)", pid);
+            continue;
+        }
+        if n < 0 {
+            continue;
+        }
+        for fd in &fds[..(n as usize).min(LSOF_MAX_FDS)] {
+            let kind = match fd.kind() {
+                Ok(FileKind::Stdin) => "stdin",
+                Ok(FileKind::Stdout) => "stdout",
+                Ok(FileKind::PipeRead) => "pipe-read",
+                Ok(FileKind::PipeWrite) => "pipe-write",
+                Ok(FileKind::RamFile) => "ramfs",
+                Err(_) => "?",
+            };
+            println!(
+                "{:>5} {:>4}  {:<10} {:>5}  {}{}{}",
+                pid,
+                fd.fd,
+                kind,
+                fd.pipe_id,
+                if fd.flags & FD_INFO_CLOEXEC != 0 { "cloexec " } else { "" },
+                if fd.flags & FD_INFO_NONBLOCK != 0 { "nonblock " } else { "" },
+                if fd.flags & FD_INFO_PEER_CLOSED != 0 { "peer-closed" } else { "" },
+            );
+        }
+        if n as usize > LSOF_MAX_FDS {
+            println!("{:>5}  ({} more)", pid, n as usize - LSOF_MAX_FDS);
+        }
+    }
+}
+
+/// The `sched_trace [cmd]` builtin, print the scheduler event log as CSV,
+/// after running `cmd` if there is one. Only the shell may read the log
+fn print_sched_trace() {
+    let mut buf = [SchedEvent::default(); 32];
+    let mut dropped = 0;
+    let mut total_dropped = 0;
+    println!("tick,hart,event,pid,pass,prio");
+    loop {
+        let n = sched_trace(&mut buf, &mut dropped);
+        if n < 0 {
+            println!("sched_trace: cannot read the log, errno {}", errno());
+            return;
+        }
+        total_dropped += dropped;
+        if n == 0 {
+            break;
+        }
+        for event in &buf[..n as usize] {
+            let kind = match event.kind {
+                SCHED_SWITCH_IN => "switch_in",
+                SCHED_SWITCH_OUT => "switch_out",
+                SCHED_BLOCK => "block",
+                SCHED_WAKE => "wake",
+                _ => "unknown",
+            };
+            println!(
+                "{},{},{},{},{},{}",
+                event.tick, event.hart, kind, event.pid, event.pass, event.prio
+            );
+        }
+    }
+    println!("# dropped {}", total_dropped);
+}
+
+/// Print the prompt with the working directory
+fn prompt() {
+    let mut buf = [0u8; 128];
+    let cwd = match getcwd(&mut buf) {
+        len if len > 0 => core::str::from_utf8(&buf[..len as usize - 1]).unwrap_or("?"),
+        _ => "?",
+    };
+    print!("{} >> ", cwd);
+    flush();
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
     let mut line: String = String::new();
-    print!(">> ");
-    flush();
+    prompt();
     loop {
         let c = getchar();
         match c {
             LF | CR => {
                 print!("\n");
-                if !line.is_empty() {
-                    line.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        // child process
-                        if exec(line.as_str(), &[0 as *const u8]) == -1 {
-                            println!("Error when executing!");
-                            return -4;
+                let mut args: Vec<String> = line
+                    .split(' ')
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| {
+                        let mut string = String::from(arg);
+                        string.push('\0');
+                        string
+                    })
+                    .collect();
+                // `cmd < in` and `cmd > out` with ramfs files
+                let input = take_redirection(&mut args, "<\0");
+                let output = take_redirection(&mut args, ">\0");
+                let trace = args.first().map_or(false, |arg| arg.as_str() == "sched_trace\0");
+                if trace {
+                    args.remove(0);
+                }
+                if args.len() == 1 && args[0].as_str() == "version\0" {
+                    print_version();
+                } else if args.len() == 1 && args[0].as_str() == "apps\0" {
+                    print_apps();
+                } else if args.len() == 1 && args[0].as_str() == "lsof\0" {
+                    print_lsof();
+                } else if !args.is_empty() && args[0].as_str() == "shutdown\0" {
+                    // `shutdown [code]`, only the shell itself may
+                    let code = args
+                        .get(1)
+                        .map_or(Some(0), |code| code.trim_end_matches('\0').parse().ok());
+                    match code {
+                        Some(code) if args.len() <= 2 => {
+                            shutdown(code);
+                            println!("shutdown: errno {}", errno());
+                        }
+                        _ => println!("shutdown: bad exit code"),
+                    }
+                } else if args.len() == 2
+                    && (args[0].as_str() == "cpu_up\0" || args[0].as_str() == "cpu_down\0")
+                {
+                    hotplug(args[0].as_str(), args[1].as_str());
+                } else if (1..=3).contains(&args.len()) && args[0].as_str() == "kevent_filter\0" {
+                    set_kevent_filter(&args[1..]);
+                } else if args.len() == 2 && args[0].as_str() == "maps\0" {
+                    print_maps(args[1].as_str());
+                } else if !args.is_empty() && args[0].as_str() == "cd\0" {
+                    // a bare `cd` goes back to the root
+                    let path = args.get(1).map_or("/\0", |path| path.as_str());
+                    if args.len() > 2 || chdir(path) != 0 {
+                        println!("cd: cannot change to {}", path.trim_end_matches('\0'));
+                    }
+                } else if !args.is_empty() {
+                    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+                    args_addr.push(0 as *const u8);
+                    // the child is started right from the program, the shell
+                    // is never forked
+                    let mut actions = Vec::new();
+                    let mut opened = Vec::new();
+                    let mut redirected = true;
+                    if let Some(input) = input {
+                        match redirect(input.as_str(), OpenFlags::RDONLY, 0, &mut actions) {
+                            Some(file) => opened.push(file),
+                            None => redirected = false,
+                        }
+                    }
+                    if let Some(output) = output.filter(|_| redirected) {
+                        let flags = OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC;
+                        match redirect(output.as_str(), flags, 1, &mut actions) {
+                            Some(file) => opened.push(file),
+                            None => redirected = false,
+                        }
+                    }
+                    let pid = if redirected {
+                        spawnv(args[0].as_str(), args_addr.as_slice(), &actions)
+                    } else {
+                        -1
+                    };
+                    for file in opened {
+                        close(file);
+                    }
+                    if pid == -1 {
+                        // a redirection the kernel could not set up fails
+                        // the spawn too, with EBADF
+                        if redirected {
+                            println!("Error when executing! errno {}", errno());
                         }
-                        unreachable!();
                     } else {
                         let mut exit_code: i32 = 0;
                         let exit_pid = waitpid(pid as usize, &mut exit_code);
                         assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        if WIFSIGNALED!(exit_code) {
+                            println!(
+                                "Shell: Process {} killed by signal {}",
+                                pid,
+                                WTERMSIG!(exit_code)
+                            );
+                        } else {
+                            println!(
+                                "Shell: Process {} exited with code {}",
+                                pid,
+                                WEXITSTATUS!(exit_code)
+                            );
+                        }
                     }
-                    line.clear();
                 }
-                print!(">> ");
-                flush();
+                if trace {
+                    print_sched_trace();
+                }
+                line.clear();
+                prompt();
             }
             BS | DL => {
                 if !line.is_empty() {
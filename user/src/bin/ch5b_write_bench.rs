@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, write};
+
+const TOTAL: usize = 1 << 20;
+const CHUNK: usize = 4096;
+const FD_STDOUT: usize = 1;
+
+/// Write 1 MiB to stdout and report how long it took
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [b'.'; CHUNK];
+    for i in (63..CHUNK).step_by(64) {
+        buf[i] = b'\n';
+    }
+    let start = get_time();
+    let mut written = 0;
+    while written < TOTAL {
+        let n = write(FD_STDOUT, &buf);
+        assert_eq!(n, CHUNK as isize);
+        written += CHUNK;
+    }
+    let elapsed = get_time() - start;
+    println!(
+        "wrote {} KiB in {} ms, {} KiB/s",
+        TOTAL / 1024,
+        elapsed,
+        TOTAL as isize / 1024 * 1000 / elapsed.max(1)
+    );
+    0
+}
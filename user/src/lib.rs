@@ -5,6 +5,9 @@
 
 #[macro_use]
 pub mod console;
+// the numbers are the kernel's own
+#[path = "../../os5/src/syscall/errno.rs"]
+mod errno;
 mod lang_items;
 mod syscall;
 
@@ -16,6 +19,9 @@ extern crate bitflags;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
 pub use console::{flush, STDIN, STDOUT};
+use core::convert::TryFrom;
+use core::str::FromStr;
+pub use errno::*;
 pub use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
@@ -30,24 +36,11 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
-fn clear_bss() {
-    extern "C" {
-        fn start_bss();
-        fn end_bss();
-    }
-    unsafe {
-        core::slice::from_raw_parts_mut(
-            start_bss as usize as *mut u8,
-            end_bss as usize - start_bss as usize,
-        )
-        .fill(0);
-    }
-}
-
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
-    clear_bss();
+    // the kernel hands out the BSS zeroed, clearing it again would fault in
+    // every page of it
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
@@ -98,12 +91,33 @@ impl TimeVal {
     }
 }
 
+/// What the `status` of [`TaskInfo`] and the like holds, as a `u32` since
+/// a kernel may have more; see [`TaskInfo::status`]
+#[repr(u32)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
-    UnInit,
-    Ready,
-    Running,
-    Exited,
+    UnInit = 0,
+    Ready = 1,
+    Running = 2,
+    Exited = 3,
+    Blocking = 4,
+    Stopped = 5,
+}
+
+impl TryFrom<u32> for TaskStatus {
+    /// A status this library does not know
+    type Error = u32;
+    fn try_from(status: u32) -> Result<Self, u32> {
+        Ok(match status {
+            0 => TaskStatus::UnInit,
+            1 => TaskStatus::Ready,
+            2 => TaskStatus::Running,
+            3 => TaskStatus::Exited,
+            4 => TaskStatus::Blocking,
+            5 => TaskStatus::Stopped,
+            _ => return Err(status),
+        })
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -114,9 +128,11 @@ pub struct SyscallInfo {
 
 const MAX_SYSCALL_NUM: usize = 500;
 
+#[repr(C)]
 #[derive(Debug)]
 pub struct TaskInfo {
-    pub status: TaskStatus,
+    /// A [`TaskStatus`], decoded by [`Self::status`]
+    pub status: u32,
     pub syscall_times: [u32; MAX_SYSCALL_NUM],
     pub time: usize,
 }
@@ -124,11 +140,396 @@ pub struct TaskInfo {
 impl TaskInfo {
     pub fn new() -> Self {
         TaskInfo {
-            status: TaskStatus::UnInit,
+            status: TaskStatus::UnInit as u32,
             syscall_times: [0; MAX_SYSCALL_NUM],
             time: 0,
         }
     }
+    pub fn status(&self) -> Result<TaskStatus, u32> {
+        TaskStatus::try_from(self.status)
+    }
+}
+
+/// The layout `task_info` gets
+pub const TASK_INFO_V1: usize = 1;
+/// [`TaskInfoV2`]
+pub const TASK_INFO_V2: usize = 2;
+pub const TASK_NAME_LEN: usize = 32;
+
+/// Version 2 of the task info, `struct_size` first: the size of the struct
+/// on the way in, the one the kernel knows on the way out
+#[repr(C)]
+#[derive(Debug)]
+pub struct TaskInfoV2 {
+    pub struct_size: usize,
+    /// See [`TaskInfo::status`]
+    pub status: u32,
+    pub time: usize,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub utime_us: usize,
+    pub stime_us: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+    pub mapped_pages: usize,
+    pub resident_pages: usize,
+    pub shared_pages: usize,
+    pub peak_resident_pages: usize,
+    pub name: [u8; TASK_NAME_LEN],
+    /// Calls of each syscall that returned 0 or more and less than 0,
+    /// counted as they return: neither has the call in flight
+    pub syscall_ok: [u32; MAX_SYSCALL_NUM],
+    pub syscall_err: [u32; MAX_SYSCALL_NUM],
+    /// errno of the last call of each syscall that failed, 0 if none did
+    pub syscall_errno: [u16; MAX_SYSCALL_NUM],
+}
+
+impl TaskInfoV2 {
+    pub fn new() -> Self {
+        TaskInfoV2 {
+            struct_size: core::mem::size_of::<Self>(),
+            status: TaskStatus::UnInit as u32,
+            time: 0,
+            syscall_times: [0; MAX_SYSCALL_NUM],
+            utime_us: 0,
+            stime_us: 0,
+            nvcsw: 0,
+            nivcsw: 0,
+            mapped_pages: 0,
+            resident_pages: 0,
+            shared_pages: 0,
+            peak_resident_pages: 0,
+            name: [0; TASK_NAME_LEN],
+            syscall_ok: [0; MAX_SYSCALL_NUM],
+            syscall_err: [0; MAX_SYSCALL_NUM],
+            syscall_errno: [0; MAX_SYSCALL_NUM],
+        }
+    }
+    /// The name up to its NUL
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(TASK_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+    pub fn status(&self) -> Result<TaskStatus, u32> {
+        TaskStatus::try_from(self.status)
+    }
+}
+
+/// CPU usage of 100% in [`ProcessInfo::cpu_usage`]
+pub const USAGE_SCALE: usize = 10000;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct ProcessInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    /// See [`TaskInfo::status`]
+    pub status: u32,
+    pub prio: usize,
+    /// Total CPU time used, in milliseconds
+    pub cpu_time_ms: usize,
+    /// Recent CPU usage, in units of 1 / USAGE_SCALE
+    pub cpu_usage: usize,
+    /// Pages the address space covers
+    pub mapped_pages: usize,
+    /// Pages of those backed by a frame
+    pub resident_pages: usize,
+    /// Resident pages shared with another process
+    pub shared_pages: usize,
+    /// Highest `resident_pages` so far
+    pub peak_resident_pages: usize,
+    /// Frames free in the whole system
+    pub free_frames: usize,
+    /// A [`BlockKind`], what the task waits for, with the argument it
+    /// describes. Decoded by [`Self::block_kind`]
+    pub block_kind: u32,
+    pub block_arg: isize,
+    /// Number of the last syscall the task made, -1 if none, and when it
+    /// entered it in milliseconds, like [`get_time`]
+    pub last_syscall: isize,
+    pub last_syscall_ms: usize,
+}
+
+/// What a task waits for, see [`ProcessInfo::block_kind`]
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BlockKind {
+    None = 0,
+    /// Sleeping until `block_arg` ms
+    SleepUntil = 1,
+    /// Reading the pipe with id `block_arg`, see [`FdInfo::pipe_id`]
+    PipeRead = 2,
+    /// Waiting for the child `block_arg` to exit, -1 for any
+    WaitChild = 3,
+    /// Locking the mutex behind the handle `block_arg`
+    Mutex = 4,
+    /// Taking a semaphore, `block_arg` is its id in the kernel rather than
+    /// a handle
+    Semaphore = 5,
+    /// In [`pause`] or [`sigsuspend`]
+    Signal = 6,
+}
+
+impl TryFrom<u32> for BlockKind {
+    /// A kind this library does not know
+    type Error = u32;
+    fn try_from(kind: u32) -> Result<Self, u32> {
+        Ok(match kind {
+            0 => BlockKind::None,
+            1 => BlockKind::SleepUntil,
+            2 => BlockKind::PipeRead,
+            3 => BlockKind::WaitChild,
+            4 => BlockKind::Mutex,
+            5 => BlockKind::Semaphore,
+            6 => BlockKind::Signal,
+            _ => return Err(kind),
+        })
+    }
+}
+
+impl ProcessInfo {
+    pub fn new() -> Self {
+        ProcessInfo {
+            pid: 0,
+            ppid: 0,
+            status: TaskStatus::UnInit as u32,
+            prio: 0,
+            cpu_time_ms: 0,
+            cpu_usage: 0,
+            mapped_pages: 0,
+            resident_pages: 0,
+            shared_pages: 0,
+            peak_resident_pages: 0,
+            free_frames: 0,
+            block_kind: BlockKind::None as u32,
+            block_arg: 0,
+            last_syscall: -1,
+            last_syscall_ms: 0,
+        }
+    }
+    pub fn status(&self) -> Result<TaskStatus, u32> {
+        TaskStatus::try_from(self.status)
+    }
+    pub fn block_kind(&self) -> Result<BlockKind, u32> {
+        BlockKind::try_from(self.block_kind)
+    }
+}
+
+/// What an fd refers to
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FileKind {
+    Stdin = 0,
+    Stdout = 1,
+    PipeRead = 2,
+    PipeWrite = 3,
+    RamFile = 4,
+}
+
+impl TryFrom<u32> for FileKind {
+    /// A kind this library does not know
+    type Error = u32;
+    fn try_from(kind: u32) -> Result<Self, u32> {
+        Ok(match kind {
+            0 => FileKind::Stdin,
+            1 => FileKind::Stdout,
+            2 => FileKind::PipeRead,
+            3 => FileKind::PipeWrite,
+            4 => FileKind::RamFile,
+            _ => return Err(kind),
+        })
+    }
+}
+
+/// [`FdInfo::flags`] of an fd closed on exec
+pub const FD_INFO_CLOEXEC: u32 = 1;
+/// [`FdInfo::flags`] of a pipe end whose other end is closed
+pub const FD_INFO_PEER_CLOSED: u32 = 2;
+/// [`FdInfo::flags`] of an fd with [`O_NONBLOCK`]
+pub const FD_INFO_NONBLOCK: u32 = 4;
+
+/// One open fd of a task, see [`fd_info`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FdInfo {
+    pub fd: usize,
+    /// See [`Self::kind`]
+    pub kind: u32,
+    pub flags: u32,
+    /// The same for both ends of a pipe, 0 for any other file
+    pub pipe_id: usize,
+}
+
+impl FdInfo {
+    pub fn new() -> Self {
+        FdInfo {
+            fd: 0,
+            kind: FileKind::Stdin as u32,
+            flags: 0,
+            pipe_id: 0,
+        }
+    }
+    pub fn kind(&self) -> Result<FileKind, u32> {
+        FileKind::try_from(self.kind)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct LoadAvg {
+    /// Load average over 1, 5 and 15 seconds, in hundredths
+    pub load: [usize; 3],
+    /// Time each hart has spent idle, in milliseconds
+    pub idle_ms: [usize; MAX_HARTS],
+}
+
+/// Harts the kernel can schedule on
+pub const MAX_HARTS: usize = 4;
+
+/// `kind` of a [`SchedEvent`]
+pub const SCHED_SWITCH_IN: usize = 0;
+pub const SCHED_SWITCH_OUT: usize = 1;
+pub const SCHED_BLOCK: usize = 2;
+pub const SCHED_WAKE: usize = 3;
+
+/// A scheduling decision recorded by the kernel, see [`sched_trace`]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SchedEvent {
+    /// `mtime` when it happened
+    pub tick: usize,
+    pub hart: usize,
+    pub kind: usize,
+    pub pid: usize,
+    /// Stride pass and priority of the task at that point
+    pub pass: isize,
+    pub prio: isize,
+}
+
+/// Bytes of a buffer an [`AuditRecord`] keeps
+pub const AUDIT_DATA: usize = 256;
+
+/// A syscall that took a buffer or a path, recorded by [`audit`]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AuditRecord {
+    /// Counts the records of the audit, a gap shows that older ones were
+    /// overwritten
+    pub seq: usize,
+    pub id: usize,
+    pub args: [usize; 6],
+    /// As the kernel returned it, `-errno` for a failure
+    pub result: isize,
+    /// When the syscall started
+    pub time_us: usize,
+    /// Bytes of `data` captured, a path without its NUL
+    pub len: usize,
+    pub data: [u8; AUDIT_DATA],
+}
+
+impl Default for AuditRecord {
+    fn default() -> Self {
+        Self {
+            seq: 0,
+            id: 0,
+            args: [0; 6],
+            result: 0,
+            time_us: 0,
+            len: 0,
+            data: [0; AUDIT_DATA],
+        }
+    }
+}
+
+impl AuditRecord {
+    /// The captured start of the buffer, as it was when the syscall started
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len.min(AUDIT_DATA)]
+    }
+}
+
+/// CPU time and context switches of a child, see [`wait4`]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rusage {
+    pub utime_us: usize,
+    pub stime_us: usize,
+    /// Peak resident pages
+    pub maxrss_pages: usize,
+    /// Voluntary and involuntary context switches
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+/// Option of [`sys_wait4`]: return 0 instead of waiting
+pub const WNOHANG: usize = 1;
+
+/// Whether a wait status is that of a child that exited by itself
+#[macro_export]
+macro_rules! WIFEXITED {
+    ($status:expr) => {
+        ($status & 0x7f) == 0
+    };
+}
+
+/// The code a child passed to `exit`, truncated to 8 bits
+#[macro_export]
+macro_rules! WEXITSTATUS {
+    ($status:expr) => {
+        ($status >> 8) & 0xff
+    };
+}
+
+/// Whether a wait status is that of a child killed by a signal
+#[macro_export]
+macro_rules! WIFSIGNALED {
+    ($status:expr) => {
+        ($status & 0x7f) != 0
+    };
+}
+
+/// The signal that killed a child
+#[macro_export]
+macro_rules! WTERMSIG {
+    ($status:expr) => {
+        ($status & 0x7f) as usize
+    };
+}
+
+/// Where the user stack starts, it grows down from here
+pub const USER_STACK_TOP: usize = 1 << 38;
+
+/// An ELF note asking for a user stack size, see [`stack_size!`]
+#[repr(C, align(4))]
+pub struct StackSizeNote {
+    name_size: u32,
+    desc_size: u32,
+    kind: u32,
+    name: [u8; 8],
+    size: [u8; 8],
+}
+
+impl StackSizeNote {
+    pub const fn new(size: usize) -> Self {
+        StackSizeNote {
+            name_size: 6,
+            desc_size: 8,
+            kind: 1,
+            name: *b"rcore\0\0\0",
+            size: (size as u64).to_le_bytes(),
+        }
+    }
+}
+
+/// Give the program a user stack of `size` bytes rather than the default of
+/// two pages, rounded up to pages and capped by the kernel at 8 MiB. Used
+/// once, at the top level of the program
+#[macro_export]
+macro_rules! stack_size {
+    ($size:expr) => {
+        #[link_section = ".note.rcore.stacksize"]
+        #[used]
+        static STACK_SIZE_NOTE: $crate::StackSizeNote = $crate::StackSizeNote::new($size);
+    };
 }
 
 #[repr(C)]
@@ -174,12 +575,104 @@ bitflags! {
     }
 }
 
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGSYS: usize = 31;
+
+bitflags! {
+    /// One bit per signal number
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << SIGHUP;
+        const SIGINT = 1 << SIGINT;
+        const SIGQUIT = 1 << SIGQUIT;
+        const SIGILL = 1 << SIGILL;
+        const SIGTRAP = 1 << SIGTRAP;
+        const SIGABRT = 1 << SIGABRT;
+        const SIGBUS = 1 << SIGBUS;
+        const SIGFPE = 1 << SIGFPE;
+        const SIGKILL = 1 << SIGKILL;
+        const SIGUSR1 = 1 << SIGUSR1;
+        const SIGSEGV = 1 << SIGSEGV;
+        const SIGUSR2 = 1 << SIGUSR2;
+        const SIGPIPE = 1 << SIGPIPE;
+        const SIGALRM = 1 << SIGALRM;
+        const SIGTERM = 1 << SIGTERM;
+        const SIGCHLD = 1 << SIGCHLD;
+        const SIGCONT = 1 << SIGCONT;
+        const SIGSTOP = 1 << SIGSTOP;
+        const SIGSYS = 1 << SIGSYS;
+    }
+}
+
+/// Action taken on a signal, `handler` 0 for the default one
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    /// Signals blocked while the handler runs
+    pub mask: SignalFlags,
+    /// `SA_*` bits
+    pub flags: u32,
+}
+
+/// Restart a blocking call the signal interrupted, instead of it failing
+/// with `EINTR`
+pub const SA_RESTART: u32 = 0x1000_0000;
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: 0,
+            mask: SignalFlags::empty(),
+            flags: 0,
+        }
+    }
+}
+
+/// Second argument of a signal handler
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: usize,
+    /// `scause` of the fault, 0 if the signal was not caused by a fault
+    pub cause: usize,
+    /// Faulting address
+    pub addr: usize,
+}
+
 const AT_FDCWD: isize = -100;
 
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_openat(AT_FDCWD as usize, path, flags.bits, OpenFlags::RDWR.bits)
 }
 
+/// `path` must end with a NUL, like for [`open`]
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+
+/// The working directory and a NUL in `buf`, returns the length with the
+/// NUL, fails with `ERANGE` if it does not fit
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
+
 pub fn close(fd: usize) -> isize {
     if fd == STDOUT {
         console::flush();
@@ -232,14 +725,110 @@ pub fn get_time() -> isize {
     }
 }
 
+/// Where the kernel maps its read-only vDSO data page, below the trap
+/// context
+const VDSO_DATA: usize = usize::MAX - 3 * 4096 + 1;
+
+/// Layout of the vDSO data page
+#[repr(C)]
+struct VdsoData {
+    clock_freq: usize,
+    boot_ticks: usize,
+}
+
+/// The time since boot, as [`sys_get_time`] would give it, from the `time`
+/// CSR and the vDSO page without a syscall
+pub fn get_time_vdso(ts: &mut TimeVal) -> isize {
+    let data = unsafe { (VDSO_DATA as *const VdsoData).read_volatile() };
+    let ticks: usize;
+    unsafe {
+        core::arch::asm!("rdtime {}", out(reg) ticks);
+    }
+    // with the same rounding as the kernel
+    let us = (ticks - data.boot_ticks) / (data.clock_freq / 1_000_000);
+    ts.sec = us / 1_000_000;
+    ts.usec = us % 1_000_000;
+    0
+}
+
+/// Where the kernel keeps the registers of a trapped process, no access
+/// for the process itself
+pub const TRAP_CONTEXT: usize = usize::MAX - 2 * 4096 + 1;
+/// Where the kernel maps the read-only comm page, below the vDSO data
+pub const COMM_PAGE: usize = usize::MAX - 4 * 4096 + 1;
+/// The code a signal handler returns to, calling [`sigreturn`]
+pub const SIGRETURN_TRAMPOLINE: usize = usize::MAX - 5 * 4096 + 1;
+
+/// Layout of the comm page, filled in by the kernel at fork and exec
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CommPage {
+    pub pid: usize,
+    /// [`SIGRETURN_TRAMPOLINE`]
+    pub sigreturn: usize,
+}
+
+/// The comm page of this process, read without a syscall
+pub fn comm_page() -> CommPage {
+    unsafe { (COMM_PAGE as *const CommPage).read_volatile() }
+}
+
 pub fn getpid() -> isize {
     sys_getpid()
 }
 
+/// The pid of the parent, which becomes the nearest subreaper or initproc
+/// once the parent exits
+pub fn getppid() -> isize {
+    sys_getppid()
+}
+
+/// `option` of [`prctl`]: orphaned descendants come to the caller instead
+/// of initproc if `arg` is not 0
+pub const PR_SET_CHILD_SUBREAPER: usize = 36;
+/// `option` of [`prctl`]: write whether the caller is a subreaper to the
+/// `i32` at `arg`
+pub const PR_GET_CHILD_SUBREAPER: usize = 37;
+
+pub fn prctl(option: usize, arg: usize) -> isize {
+    sys_prctl(option, arg)
+}
+
+/// Make the caller a subreaper or not, see [`PR_SET_CHILD_SUBREAPER`]
+pub fn set_child_subreaper(on: bool) -> isize {
+    prctl(PR_SET_CHILD_SUBREAPER, on as usize)
+}
+
+pub fn sched_setaffinity(pid: usize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, mask)
+}
+
+pub fn sched_getaffinity(pid: usize, mask: &mut usize) -> isize {
+    sys_sched_getaffinity(pid, mask)
+}
+
+pub fn getcpu() -> isize {
+    sys_getcpu()
+}
+
+/// Start hart `hart`, 0 once it takes tasks. Only initproc and the shell
+/// may, anyone else gets `EPERM`
+pub fn cpu_up(hart: usize) -> isize {
+    sys_cpu_up(hart)
+}
+
+/// Take hart `hart` down, its tasks move to the other harts. The boot hart
+/// stays up. Only initproc and the shell may, like [`cpu_up`]
+pub fn cpu_down(hart: usize) -> isize {
+    sys_cpu_down(hart)
+}
+
 pub fn fork() -> isize {
     sys_fork()
 }
 
+/// Replace the program with `path`, which gets `args`, a null-terminated
+/// array of NUL-terminated strings, as the `argc` and `argv` of its `main`
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
@@ -249,33 +838,148 @@ pub fn set_priority(prio: isize) -> isize {
 }
 
 pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                sys_yield();
-            }
-            n => {
-                return n;
-            }
+    sys_waitpid(-1, exit_code as *mut _)
+}
+
+/// Wait for child `pid` to exit, fails with `ECHILD` if there is no such
+/// child
+pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _)
+}
+
+/// Like [`waitpid`], also filling `rusage` with what the child and its
+/// reaped descendants used
+pub fn wait4(pid: isize, exit_code: &mut i32, rusage: &mut Rusage) -> isize {
+    sys_wait4(pid, exit_code as *mut _, 0, rusage as *mut _)
+}
+
+/// Run `f` in a forked child that exits with what it returns, and return
+/// the wait status of the child
+pub fn in_child<F: FnOnce() -> i32>(f: F) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    assert!(pid > 0, "fork failed");
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
+}
+
+/// Run `f` in a forked child, true if it died of SIGSEGV and false if `f`
+/// returned. Panics if the child died otherwise
+pub fn segfaults<F: FnOnce()>(f: F) -> bool {
+    let status = in_child(|| {
+        f();
+        0
+    });
+    if WIFSIGNALED!(status) {
+        assert_eq!(WTERMSIG!(status), SIGSEGV);
+        return true;
+    }
+    assert_eq!(WEXITSTATUS!(status), 0);
+    false
+}
+
+/// Signals a [`Signaller`] sends at most per request
+const SIGNALLER_MAX: usize = 2;
+
+/// The child end of a parent that signals it on request, see
+/// [`with_signaller`]
+pub struct Signaller {
+    request: usize,
+    done: usize,
+}
+
+impl Signaller {
+    /// Have the parent send `signals`, sleeping for `delay_ms` before each.
+    /// Each request needs a [`Signaller::wait`]
+    pub fn send(&self, signals: &[usize], delay_ms: usize) {
+        assert!(signals.len() <= SIGNALLER_MAX);
+        let mut request = [0usize; SIGNALLER_MAX + 1];
+        request[0] = delay_ms;
+        request[1..=signals.len()].copy_from_slice(signals);
+        let len = core::mem::size_of_val(&request);
+        let bytes = unsafe { core::slice::from_raw_parts(request.as_ptr() as *const u8, len) };
+        assert_eq!(write(self.request, bytes), len as isize);
+    }
+
+    /// Wait until the parent has sent the signals of the oldest request
+    pub fn wait(&self) {
+        let mut done = [0u8; 1];
+        while read(self.done, &mut done) != 1 {
+            assert_eq!(errno(), EINTR);
         }
     }
 }
 
-pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
-                sys_yield();
-            }
-            n => {
-                return n;
-            }
+/// Fill `buf` from `fd`, false if the write ends closed first
+fn read_exact(fd: usize, buf: &mut [usize]) -> bool {
+    let len = core::mem::size_of_val(buf);
+    let bytes = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, len) };
+    let mut got = 0;
+    while got < len {
+        let n = read(fd, &mut bytes[got..]);
+        assert!(n >= 0);
+        if n == 0 {
+            return false;
         }
+        got += n as usize;
     }
+    true
+}
+
+/// Run `f` in a forked child like [`in_child`], the parent sending it the
+/// signals it asks for. A task may only signal itself and its descendants,
+/// so this is how a task gets signalled while it blocks
+pub fn with_signaller<F: FnOnce(&Signaller) -> i32>(f: F) -> i32 {
+    let mut request = [0usize; 2];
+    let mut done = [0usize; 2];
+    assert_eq!(pipe(&mut request), 0);
+    assert_eq!(pipe(&mut done), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(request[0]);
+        close(done[1]);
+        exit(f(&Signaller {
+            request: request[1],
+            done: done[0],
+        }));
+    }
+    assert!(pid > 0, "fork failed");
+    close(request[1]);
+    close(done[0]);
+    let mut buf = [0usize; SIGNALLER_MAX + 1];
+    // the child exiting closes the last write end
+    while read_exact(request[0], &mut buf) {
+        let delay_ms = buf[0];
+        for &signum in buf[1..].iter().take_while(|&&signum| signum != 0) {
+            sleep_blocking(delay_ms);
+            assert_eq!(kill(pid as usize, signum), 0);
+        }
+        assert_eq!(write(done[1], b"x"), 1);
+    }
+    close(request[0]);
+    close(done[1]);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
 }
 
 pub fn sleep_blocking(sleep_ms: usize) {
-    sys_sleep(sleep_ms);
+    sys_sleep(sleep_ms, core::ptr::null_mut(), 0);
+}
+
+/// Sleep for `sleep_ms`, and up to `slack_ms` more if that lets the kernel
+/// wake this task along with others
+pub fn sleep_slack(sleep_ms: usize, slack_ms: usize) {
+    sys_sleep(sleep_ms, core::ptr::null_mut(), slack_ms);
+}
+
+/// Sleep for `period_ms`, failing with `EINTR` if a signal cuts it short,
+/// with the milliseconds left in `rem`
+pub fn msleep(period_ms: usize, rem: Option<&mut usize>) -> isize {
+    sys_sleep(period_ms, rem.map_or(core::ptr::null_mut(), |rem| rem), 0)
 }
 
 pub fn sleep(period_ms: usize) {
@@ -292,10 +996,302 @@ pub fn munmap(start: usize, len: usize) -> isize {
     sys_munmap(start, len)
 }
 
+/// Map the ELF image this program was started from read-only, at `hint` if
+/// that is a free page boundary, and return where; 0 lets the kernel choose.
+/// [`munmap`] takes it away again
+pub fn map_self(hint: usize) -> isize {
+    sys_map_self(hint)
+}
+
+/// Most samples the kernel keeps for a profile
+pub const PROFILE_MAX_SAMPLES: usize = 8192;
+/// Most samples the kernel keeps for all profiles together
+pub const PROFILE_TOTAL_SAMPLES: usize = 2 * PROFILE_MAX_SAMPLES;
+
+/// Sample the pc of `pid`, this process or a child of it, at each timer
+/// interrupt that finds it running, into a kernel buffer as large as `buf`.
+/// [`profile_stop`] copies them to `buf`, which must stay around until then.
+/// Fails with `ENOMEM` if other profiles leave no room for that buffer
+pub fn profile_start(pid: usize, buf: &mut [usize]) -> isize {
+    sys_profile_start(pid, buf.as_mut_ptr(), buf.len())
+}
+
+/// Stop the profile of `pid`, possibly exited but not waited for, and
+/// return how many samples it copied; the ones there was no room for go to
+/// `dropped`
+pub fn profile_stop(pid: usize, dropped: Option<&mut usize>) -> isize {
+    sys_profile_stop(pid, dropped.map_or(core::ptr::null_mut(), |dropped| dropped))
+}
+
+/// Bits of a [`pagemap`] entry: the page is mapped
+pub const PAGEMAP_PRESENT: u64 = 1 << 0;
+pub const PAGEMAP_WRITABLE: u64 = 1 << 1;
+pub const PAGEMAP_EXECUTABLE: u64 = 1 << 2;
+/// Reachable from user mode, not so after an [`mprotect`] to no access
+pub const PAGEMAP_USER: u64 = 1 << 3;
+/// Read-only until the next write copies it or takes it back, as a page
+/// written to a pipe
+pub const PAGEMAP_COW: u64 = 1 << 4;
+/// The frame is shared with another process or a pipe
+pub const PAGEMAP_SHARED: u64 = 1 << 5;
+/// The frame number is above this, in a debug kernel only
+pub const PAGEMAP_PFN_SHIFT: u64 = 12;
+
+/// One entry for each page from the one holding `addr` on, for as many
+/// pages as `buf` has room for
+pub fn pagemap(addr: usize, buf: &mut [u64]) -> isize {
+    sys_pagemap(addr, buf)
+}
+
+/// The [`pagemap`] entry of the page holding `addr`, without the frame
+/// number
+pub fn page_flags(addr: usize) -> u64 {
+    let mut entry = [0u64];
+    assert_eq!(pagemap(addr, &mut entry), 0);
+    entry[0] & ((1 << PAGEMAP_PFN_SHIFT) - 1)
+}
+
+/// Fill `buf` with one line for each area of process `pid`, the caller, its
+/// parent or pid 1, as many whole lines as fit, and return the length of
+/// all of them. A line is the range, `rwx` and `p` or `s` for shared on
+/// fork, the kind of area and the pages mapped now out of all of them:
+///
+/// ```text
+/// 0000000010000000-0000000010004000 rw-p mmap 2/4
+/// ```
+pub fn vma_list(pid: usize, buf: &mut [u8]) -> isize {
+    sys_vma_list(pid, buf)
+}
+
+/// Record the syscalls of `pid`, the caller or a child of it, that take a
+/// buffer or a path, up to `records` of them with the start of what they
+/// were passed. `records` 0 stops recording; the records stay readable
+/// with [`audit_read`] until `pid` is waited for
+pub fn audit(pid: usize, records: usize) -> isize {
+    sys_audit(pid, records)
+}
+
+/// Move the oldest records of the audit of `pid` started by the caller to
+/// `buf` and return how many
+pub fn audit_read(pid: usize, buf: &mut [AuditRecord]) -> isize {
+    sys_audit_read(pid, buf)
+}
+
+/// The [`vma_list`] line of the area of the caller holding `addr`, read into
+/// `buf`, without the newline
+pub fn vma_line(addr: usize, buf: &mut [u8]) -> Option<&str> {
+    let len = vma_list(getpid() as usize, buf);
+    assert!(len >= 0 && len as usize <= buf.len(), "vma_list does not fit");
+    let text = core::str::from_utf8(&buf[..len as usize]).ok()?;
+    text.lines().find(|line| {
+        let range = line.split(' ').next().unwrap_or("");
+        let mut ends = range.split('-').map(|end| usize::from_str_radix(end, 16));
+        match (ends.next(), ends.next()) {
+            (Some(Ok(start)), Some(Ok(end))) => start <= addr && addr < end,
+            _ => false,
+        }
+    })
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub const MADV_NORMAL: usize = 0;
+/// Drop the frames of the range, it reads back as zeros
+pub const MADV_DONTNEED: usize = 4;
+
+pub fn madvise(start: usize, len: usize, advice: usize) -> isize {
+    sys_madvise(start, len, advice)
+}
+
+/// Send `signum` to `pid`, the caller or one of its descendants. Fails with
+/// `EPERM` for another task unless the caller is the shell, and always for
+/// initproc
+pub fn kill(pid: usize, signum: usize) -> isize {
+    sys_kill(pid, signum)
+}
+
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |a| a),
+        old_action.map_or(core::ptr::null_mut(), |a| a),
+    )
+}
+
+/// `how` of [`sigprocmask`]: block the signals of `set` too
+pub const SIG_BLOCK: usize = 0;
+/// Unblock the signals of `set`
+pub const SIG_UNBLOCK: usize = 1;
+/// Block exactly the signals of `set`
+pub const SIG_SETMASK: usize = 2;
+
+/// Change the blocked signals unless `set` is `None`, the old mask goes to
+/// `old_set`. SIGKILL and SIGSTOP cannot be blocked
+pub fn sigprocmask(how: usize, set: Option<SignalFlags>, old_set: Option<&mut SignalFlags>) -> isize {
+    let set = set.map(|set| set.bits());
+    let mut old = 0u32;
+    let ret = sys_sigprocmask(
+        how,
+        set.as_ref().map_or(core::ptr::null(), |set| set),
+        &mut old,
+    );
+    if let Some(old_set) = old_set {
+        *old_set = SignalFlags::from_bits_truncate(old);
+    }
+    ret
+}
+
+/// The errno of the last syscall that failed, one of the `E*` constants.
+/// Syscalls that succeed leave it alone
+pub fn errno() -> isize {
+    syscall::ERRNO.load(core::sync::atomic::Ordering::Relaxed)
+}
+
+/// Wait for a signal, fails with `EINTR` once its handler has run
+pub fn pause() -> isize {
+    sys_pause()
+}
+
+/// Power off after the kernel has printed its summary, a nonzero
+/// `exit_code` tells the platform the run failed. Only initproc and the
+/// shell may, anyone else gets `EPERM`
+pub fn shutdown(exit_code: i32) -> isize {
+    console::flush();
+    sys_shutdown(exit_code)
+}
+
+/// What [`reboot`] needs as `magic`, so that a stray call does not reboot
+pub const REBOOT_MAGIC: usize = 0xfee1_dead;
+/// Reset the machine, or power off if the platform cannot reset
+pub const REBOOT_COLD: usize = 1;
+/// Drop every task and start initproc again without resetting the machine
+pub const REBOOT_SOFT: usize = 2;
+
+/// Reboot as `cmd` says, only initproc may. A soft reboot returns 0 in
+/// initproc loaded afresh
+pub fn reboot(magic: usize, cmd: usize) -> isize {
+    console::flush();
+    sys_reboot(magic, cmd)
+}
+
+/// Kinds of the kernel's lifecycle events, as bits for [`kevent_filter`]
+pub const KEVENT_TASK_SWITCH: usize = 1 << 0;
+pub const KEVENT_TASK_SUSPEND: usize = 1 << 1;
+pub const KEVENT_TASK_EXIT: usize = 1 << 2;
+pub const KEVENT_TASK_REAP: usize = 1 << 3;
+pub const KEVENT_TASK_TIME: usize = 1 << 4;
+pub const KEVENT_FAULT: usize = 1 << 5;
+pub const KEVENT_FORK: usize = 1 << 6;
+pub const KEVENT_SPAWN: usize = 1 << 7;
+pub const KEVENT_EXEC: usize = 1 << 8;
+pub const KEVENT_ALL: usize = (1 << 9) - 1;
+
+/// Switch the kernel's events of the kinds in `enable` on, then those in
+/// `disable` off, and return the kinds on afterwards. They only print with
+/// the kernel log at `DEBUG` or above. Anyone may read the kinds with both
+/// masks 0, only initproc and the shell may change them, anyone else gets
+/// `EPERM`
+pub fn kevent_filter(enable: usize, disable: usize) -> isize {
+    sys_kevent_filter(enable, disable)
+}
+
+/// Wait for a signal with `mask` blocked instead of the current mask, which
+/// is back after the handler has run. Fails with `EINTR` like pause
+pub fn sigsuspend(mask: SignalFlags) -> isize {
+    let mask = mask.bits();
+    sys_sigsuspend(&mask)
+}
+
+/// Return from a signal handler, which a handler that simply returns does
+/// through [`SIGRETURN_TRAMPOLINE`]
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
 pub fn spawn(path: &str) -> isize {
     sys_spawn(path)
 }
 
+/// A change [`spawnv`] makes to the fds the child starts with
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SpawnAction {
+    pub op: usize,
+    pub fd: usize,
+    pub new_fd: usize,
+}
+
+impl SpawnAction {
+    /// `new_fd` becomes another fd for `fd`, without `O_CLOEXEC`
+    pub fn dup2(fd: usize, new_fd: usize) -> Self {
+        Self { op: 0, fd, new_fd }
+    }
+    /// `fd` is closed
+    pub fn close(fd: usize) -> Self {
+        Self { op: 1, fd, new_fd: 0 }
+    }
+}
+
+/// Start `path` with `args`, null-terminated as for [`exec`], as a child
+/// with the fds of the caller changed by `actions` in order, at most 16 of
+/// them. The caller is not forked, so this is much cheaper than fork and
+/// exec. Fds with `O_CLOEXEC` are not passed on
+pub fn spawnv(path: &str, args: &[*const u8], actions: &[SpawnAction]) -> isize {
+    sys_spawnv(path, args, actions, None)
+}
+
+/// [`spawnv`] with the child restricted to `filter` from its first
+/// instruction on, as if it had called [`set_syscall_filter`]
+pub fn spawnv_filtered(
+    path: &str,
+    args: &[*const u8],
+    actions: &[SpawnAction],
+    filter: &SyscallFilter,
+) -> isize {
+    sys_spawnv(path, args, actions, Some(filter))
+}
+
+/// [`SyscallFilter::action`]: a denied syscall fails with `EPERM`
+pub const FILTER_ERRNO: usize = 0;
+/// [`SyscallFilter::action`]: a denied syscall kills the caller with
+/// [`SIGSYS`]
+pub const FILTER_KILL: usize = 1;
+
+/// The syscalls a process may make, by their `SYSCALL_*` numbers, and what
+/// becomes of the others
+pub struct SyscallFilter<'a> {
+    pub ids: &'a [usize],
+    pub action: usize,
+}
+
+/// Restrict the caller, and the processes it starts from now on, to
+/// `filter`. Exit and sigreturn are always allowed. Once set it can only be
+/// narrowed: a filter allowing anything more, or an error where a kill was,
+/// fails with `EPERM`
+pub fn set_syscall_filter(filter: &SyscallFilter) -> isize {
+    sys_set_syscall_filter(filter)
+}
+
+/// Save the caller to the ramfs file `path` and return 0. The process
+/// [`restore`] starts from the file returns 1 here instead. Fails with
+/// `EINVAL` if any file but stdio, a semaphore or a mutex is open or
+/// the caller is in a signal handler, `ENOSPC` if the image does not fit
+pub fn checkpoint(path: &str) -> isize {
+    sys_checkpoint(path)
+}
+
+/// Start the process saved to `path` by [`checkpoint`] as a child with
+/// fresh stdio and return its pid
+pub fn restore(path: &str) -> isize {
+    sys_restore(path)
+}
+
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
@@ -303,8 +1299,166 @@ pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }
 
+/// Flag of [`pipe2`] and [`dup3`] for fds that are closed on exec
+pub const O_CLOEXEC: u32 = 1 << 19;
+/// Flag of [`pipe2`] and [`F_SETFL`] for streams that fail with [`EAGAIN`]
+/// rather than block
+pub const O_NONBLOCK: u32 = 1 << 11;
+pub const F_GETFD: usize = 1;
+pub const F_SETFD: usize = 2;
+/// The only file status flag is [`O_NONBLOCK`], shared by dup'd fds
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+/// The only fd flag, see [`fcntl`]
+pub const FD_CLOEXEC: usize = 1;
+
+pub fn pipe2(pipe_fd: &mut [usize], flags: u32) -> isize {
+    sys_pipe2(pipe_fd, flags)
+}
+pub fn dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    sys_dup3(old_fd, new_fd, flags)
+}
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+/// [`PollFd::events`] for data to read
+pub const POLLIN: u16 = 0x1;
+/// [`PollFd::events`] for room to write
+pub const POLLOUT: u16 = 0x4;
+/// [`PollFd::revents`] of a pipe write end with no reader
+pub const POLLERR: u16 = 0x8;
+/// [`PollFd::revents`] of a pipe read end with no writer
+pub const POLLHUP: u16 = 0x10;
+/// [`PollFd::revents`] of an fd that is not open
+pub const POLLNVAL: u16 = 0x20;
+
+/// One fd of [`ppoll`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PollFd {
+    /// Skipped if negative
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub fn new(fd: i32, events: u16) -> Self {
+        PollFd {
+            fd,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// Wait up to `timeout_ms` milliseconds, for ever if negative, until one of
+/// `fds` has one of its events, and return how many have any. With
+/// `sigmask` those signals are blocked instead while it waits
+pub fn ppoll(fds: &mut [PollFd], timeout_ms: isize, sigmask: Option<SignalFlags>) -> isize {
+    match sigmask {
+        Some(mask) => {
+            let mask = mask.bits();
+            sys_ppoll(fds, timeout_ms, &mask)
+        }
+        None => sys_ppoll(fds, timeout_ms, core::ptr::null()),
+    }
+}
+
+/// `whence` of [`lseek`]
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
 pub fn task_info(info: &TaskInfo) -> isize {
-    sys_task_info(info)
+    sys_task_info(TASK_INFO_V1, info as *const _ as usize)
+}
+
+/// Task info in version 2, `info.struct_size` must say how big it is
+pub fn task_info_v2(info: &mut TaskInfoV2) -> isize {
+    sys_task_info(TASK_INFO_V2, info as *mut _ as usize)
+}
+
+pub fn process_info(pid: usize, info: &mut ProcessInfo) -> isize {
+    sys_process_info(pid, info)
+}
+
+/// Describe the open fds of task `pid` in `buf` and return how many it has,
+/// which may be more than `buf` holds. Only the task itself, its parent and
+/// pid 1 may ask
+pub fn fd_info(pid: usize, buf: &mut [FdInfo]) -> isize {
+    sys_fd_info(pid, buf)
+}
+
+pub fn loadavg(avg: &mut LoadAvg) -> isize {
+    sys_loadavg(avg)
+}
+
+/// Fill `buf` with `key=value` lines describing the kernel build, as many
+/// whole lines as fit, and return the length of all of them
+pub fn sysinfo(buf: &mut [u8]) -> isize {
+    sys_sysinfo(buf)
+}
+
+/// Fill `buf` with a line for each app built in like [`sysinfo`], the name
+/// and ` (stale)` after it if the image does not match its checksum
+pub fn list_apps(buf: &mut [u8]) -> isize {
+    sys_list_apps(buf)
+}
+
+/// The value of the `key=value` line of [`sysinfo`], `None` if there is no
+/// such line or the value is no `T`
+pub fn sysinfo_value<T: FromStr>(key: &str) -> Option<T> {
+    let mut buf = alloc::vec![0u8; 1024];
+    let mut len = usize::try_from(sysinfo(&mut buf)).ok()?;
+    if len > buf.len() {
+        buf.resize(len, 0);
+        len = usize::try_from(sysinfo(&mut buf)).ok()?.min(buf.len());
+    }
+    core::str::from_utf8(&buf[..len])
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))?
+        .parse()
+        .ok()
+}
+
+/// One syscall of a [`batch`], `ret` is filled in once it has run, as the
+/// kernel returns it: `-errno` for a failure
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BatchEntry {
+    pub id: usize,
+    pub args: [usize; 3],
+    pub ret: isize,
+}
+
+impl BatchEntry {
+    pub fn new(id: usize, args: [usize; 3]) -> Self {
+        Self { id, args, ret: 0 }
+    }
+}
+
+/// Flag of [`batch`] to stop at the first entry that fails
+pub const BATCH_STOP_ON_ERROR: usize = 1;
+
+/// Run `entries` in order with one trap and return how many ran. Fails with
+/// `EPERM` without running any if one is not a write, close, yield, get_time
+/// or a read of a file that is not a stream
+pub fn batch(entries: &mut [BatchEntry], flags: usize) -> isize {
+    sys_batch(entries, flags)
+}
+
+/// Move scheduler events to `buf` and the count of those lost since the last
+/// call to `dropped`, return how many were moved. Fails with `EPERM` but
+/// for initproc and the shell, whose `sched_trace` builtin prints the log
+pub fn sched_trace(buf: &mut [SchedEvent], dropped: &mut usize) -> isize {
+    sys_sched_trace(buf, dropped)
 }
 
 pub fn thread_create(entry: usize, arg: usize) -> isize {
@@ -314,14 +1468,7 @@ pub fn gettid() -> isize {
     sys_gettid()
 }
 pub fn waittid(tid: usize) -> isize {
-    loop {
-        match sys_waittid(tid) {
-            -2 => {
-                yield_();
-            }
-            exit_code => return exit_code,
-        }
-    }
+    sys_waittid(tid)
 }
 
 pub fn mutex_create() -> isize {
@@ -336,12 +1483,32 @@ pub fn mutex_lock(mutex_id: usize) -> isize {
 pub fn mutex_unlock(mutex_id: usize) {
     sys_mutex_unlock(mutex_id);
 }
+/// Close the handle `mutex_id`, the mutex goes away with its last handle.
+/// Fails with `EBUSY` while the caller holds it
+pub fn mutex_close(mutex_id: usize) -> isize {
+    sys_mutex_close(mutex_id)
+}
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
 }
 pub fn semaphore_up(sem_id: usize) {
     sys_semaphore_up(sem_id);
 }
+/// Open the semaphore `name`, NUL-terminated and at most 32 bytes, creating
+/// it with `count` if there is none. Any process opening the same name gets
+/// a handle to the same semaphore, for [`semaphore_up`] and
+/// [`semaphore_down`]
+pub fn sem_open(name: &str, count: usize) -> isize {
+    sys_sem_open(name, count)
+}
+/// Remove the name `name`, the semaphore stays until its last handle is
+/// closed
+pub fn sem_unlink(name: &str) -> isize {
+    sys_sem_unlink(name)
+}
+pub fn sem_close(sem_id: usize) -> isize {
+    sys_sem_close(sem_id)
+}
 pub fn enable_deadlock_detect(enabled: bool) -> isize {
     sys_enable_deadlock_detect(enabled as usize)
 }
@@ -1,44 +1,26 @@
-use crate::TaskInfo;
-
-use super::{Stat, TimeVal};
-
-pub const SYSCALL_OPENAT: usize = 56;
-pub const SYSCALL_CLOSE: usize = 57;
-pub const SYSCALL_READ: usize = 63;
-pub const SYSCALL_WRITE: usize = 64;
-pub const SYSCALL_UNLINKAT: usize = 35;
-pub const SYSCALL_LINKAT: usize = 37;
-pub const SYSCALL_FSTAT: usize = 80;
-pub const SYSCALL_EXIT: usize = 93;
-pub const SYSCALL_SLEEP: usize = 101;
-pub const SYSCALL_YIELD: usize = 124;
-pub const SYSCALL_GETTIMEOFDAY: usize = 169;
-pub const SYSCALL_GETPID: usize = 172;
-pub const SYSCALL_GETTID: usize = 178;
-pub const SYSCALL_FORK: usize = 220;
-pub const SYSCALL_EXEC: usize = 221;
-pub const SYSCALL_WAITPID: usize = 260;
-pub const SYSCALL_SET_PRIORITY: usize = 140;
-pub const SYSCALL_MUNMAP: usize = 215;
-pub const SYSCALL_MMAP: usize = 222;
-pub const SYSCALL_SPAWN: usize = 400;
-pub const SYSCALL_MAIL_READ: usize = 401;
-pub const SYSCALL_MAIL_WRITE: usize = 402;
-pub const SYSCALL_DUP: usize = 24;
-pub const SYSCALL_PIPE: usize = 59;
-pub const SYSCALL_TASK_INFO: usize = 410;
-pub const SYSCALL_THREAD_CREATE: usize = 460;
-pub const SYSCALL_WAITTID: usize = 462;
-pub const SYSCALL_MUTEX_CREATE: usize = 463;
-pub const SYSCALL_MUTEX_LOCK: usize = 464;
-pub const SYSCALL_MUTEX_UNLOCK: usize = 466;
-pub const SYSCALL_SEMAPHORE_CREATE: usize = 467;
-pub const SYSCALL_SEMAPHORE_UP: usize = 468;
-pub const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
-pub const SYSCALL_SEMAPHORE_DOWN: usize = 470;
-pub const SYSCALL_CONDVAR_CREATE: usize = 471;
-pub const SYSCALL_CONDVAR_SIGNAL: usize = 472;
-pub const SYSCALL_CONDVAR_WAIT: usize = 473;
+
+use super::{
+    AuditRecord, BatchEntry, FdInfo, LoadAvg, PollFd, ProcessInfo, Rusage, SchedEvent,
+    SignalAction, SpawnAction, Stat, SyscallFilter, TimeVal,
+};
+use core::sync::atomic::{AtomicIsize, Ordering};
+
+// `SYSCALL_*`, generated by build.rs from the syscall table of the kernel
+include!(concat!(env!("OUT_DIR"), "/syscall_numbers.rs"));
+
+/// errno of the last syscall that failed, a task has a single thread
+pub(crate) static ERRNO: AtomicIsize = AtomicIsize::new(0);
+
+/// The kernel returns `-errno` for a failure, a wrapper -1 with the errno
+/// kept for [`crate::errno`]
+fn check(ret: isize) -> isize {
+    if ret < 0 {
+        ERRNO.store(-ret, Ordering::Relaxed);
+        -1
+    } else {
+        ret
+    }
+}
 
 pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -51,7 +33,7 @@ pub fn syscall(id: usize, args: [usize; 3]) -> isize {
             in("x17") id
         );
     }
-    ret
+    check(ret)
 }
 
 pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
@@ -67,7 +49,7 @@ pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
             in("x17") id
         );
     }
-    ret
+    check(ret)
 }
 
 pub fn sys_openat(dirfd: usize, path: &str, flags: u32, mode: u32) -> isize {
@@ -146,8 +128,8 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
-pub fn sys_sleep(sleep_ms: usize) -> isize {
-    syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
+pub fn sys_sleep(sleep_ms: usize, rem: *mut usize, slack_ms: usize) -> isize {
+    syscall(SYSCALL_SLEEP, [sleep_ms, rem as usize, slack_ms])
 }
 
 pub fn sys_yield() -> isize {
@@ -162,6 +144,34 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
+pub fn sys_prctl(option: usize, arg: usize) -> isize {
+    syscall(SYSCALL_PRCTL, [option, arg, 0])
+}
+
+pub fn sys_sched_setaffinity(pid: usize, mask: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETAFFINITY, [pid, mask, 0])
+}
+
+pub fn sys_sched_getaffinity(pid: usize, mask: &mut usize) -> isize {
+    syscall(SYSCALL_SCHED_GETAFFINITY, [pid, mask as *mut _ as usize, 0])
+}
+
+pub fn sys_getcpu() -> isize {
+    syscall(SYSCALL_GETCPU, [0, 0, 0])
+}
+
+pub fn sys_cpu_up(hart: usize) -> isize {
+    syscall(SYSCALL_CPU_UP, [hart, 0, 0])
+}
+
+pub fn sys_cpu_down(hart: usize) -> isize {
+    syscall(SYSCALL_CPU_DOWN, [hart, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -174,7 +184,21 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
 }
 
 pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
+    sys_wait4(pid, xstatus, 0, core::ptr::null_mut())
+}
+
+pub fn sys_wait4(pid: isize, xstatus: *mut i32, options: usize, rusage: *mut Rusage) -> isize {
+    syscall6(
+        SYSCALL_WAIT4,
+        [
+            pid as usize,
+            xstatus as usize,
+            options,
+            rusage as usize,
+            0,
+            0,
+        ],
+    )
 }
 
 pub fn sys_set_priority(prio: isize) -> isize {
@@ -185,14 +209,151 @@ pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
     syscall(SYSCALL_MMAP, [start, len, prot])
 }
 
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_madvise(start: usize, len: usize, advice: usize) -> isize {
+    syscall(SYSCALL_MADVISE, [start, len, advice])
+}
+
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid, signum, 0])
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    syscall(
+        SYSCALL_SIGPROCMASK,
+        [how, set as usize, old_set as usize],
+    )
+}
+
+pub fn sys_sigsuspend(mask: *const u32) -> isize {
+    syscall(SYSCALL_SIGSUSPEND, [mask as usize, 0, 0])
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout_ms: isize, sigmask: *const u32) -> isize {
+    syscall6(
+        SYSCALL_PPOLL,
+        [
+            fds.as_mut_ptr() as usize,
+            fds.len(),
+            timeout_ms as usize,
+            sigmask as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_pause() -> isize {
+    syscall(SYSCALL_PAUSE, [0, 0, 0])
+}
+
+pub fn sys_shutdown(exit_code: i32) -> isize {
+    syscall(SYSCALL_SHUTDOWN, [exit_code as usize, 0, 0])
+}
+
+pub fn sys_reboot(magic: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_REBOOT, [magic, cmd, 0])
+}
+
+pub fn sys_kevent_filter(enable: usize, disable: usize) -> isize {
+    syscall(SYSCALL_KEVENT_FILTER, [enable, disable, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_map_self(hint: usize) -> isize {
+    syscall(SYSCALL_MAP_SELF, [hint, 0, 0])
+}
+
+pub fn sys_profile_start(pid: usize, buf: *mut usize, max_samples: usize) -> isize {
+    syscall(SYSCALL_PROFILE_START, [pid, buf as usize, max_samples])
+}
+
+pub fn sys_profile_stop(pid: usize, dropped: *mut usize) -> isize {
+    syscall(SYSCALL_PROFILE_STOP, [pid, dropped as usize, 0])
+}
+
+pub fn sys_pagemap(addr: usize, buf: &mut [u64]) -> isize {
+    syscall(SYSCALL_PAGEMAP, [addr, buf.as_mut_ptr() as usize, buf.len()])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
 
+/// A filter as the kernel takes it from [`sys_spawnv`]
+#[repr(C)]
+struct FilterArgs {
+    ids: usize,
+    count: usize,
+    action: usize,
+}
+
+impl FilterArgs {
+    fn new(filter: &SyscallFilter) -> Self {
+        Self {
+            ids: filter.ids.as_ptr() as usize,
+            count: filter.ids.len(),
+            action: filter.action,
+        }
+    }
+}
+
+pub fn sys_spawnv(
+    path: &str,
+    args: &[*const u8],
+    actions: &[SpawnAction],
+    filter: Option<&SyscallFilter>,
+) -> isize {
+    let filter = filter.map(FilterArgs::new);
+    syscall6(
+        SYSCALL_SPAWNV,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            actions.as_ptr() as usize,
+            actions.len(),
+            filter.as_ref().map_or(0, |filter| filter as *const FilterArgs as usize),
+            0,
+        ],
+    )
+}
+
+pub fn sys_set_syscall_filter(filter: &SyscallFilter) -> isize {
+    syscall(
+        SYSCALL_SET_SYSCALL_FILTER,
+        [filter.ids.as_ptr() as usize, filter.ids.len(), filter.action],
+    )
+}
+
+pub fn sys_checkpoint(path: &str) -> isize {
+    syscall(SYSCALL_CHECKPOINT, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_restore(path: &str) -> isize {
+    syscall(SYSCALL_RESTORE, [path.as_ptr() as usize, 0, 0])
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
@@ -201,8 +362,81 @@ pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
 
-pub fn sys_task_info(info: &TaskInfo) -> isize {
-    syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
+pub fn sys_pipe2(pipe: &mut [usize], flags: u32) -> isize {
+    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYSCALL_DUP3, [old_fd, new_fd, flags as usize])
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
+pub fn sys_task_info(version: usize, info: usize) -> isize {
+    syscall(SYSCALL_TASK_INFO, [version, info, 0])
+}
+
+pub fn sys_process_info(pid: usize, info: &mut ProcessInfo) -> isize {
+    syscall(SYSCALL_PROCESS_INFO, [pid, info as *mut _ as usize, 0])
+}
+
+pub fn sys_loadavg(avg: &mut LoadAvg) -> isize {
+    syscall(SYSCALL_LOADAVG, [avg as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_batch(entries: &mut [BatchEntry], flags: usize) -> isize {
+    syscall(
+        SYSCALL_BATCH,
+        [entries.as_mut_ptr() as usize, entries.len(), flags],
+    )
+}
+
+pub fn sys_getcwd(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_fd_info(pid: usize, buf: &mut [FdInfo]) -> isize {
+    syscall(SYSCALL_FD_INFO, [pid, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+pub fn sys_vma_list(pid: usize, buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_VMA_LIST, [pid, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+pub fn sys_audit(pid: usize, records: usize) -> isize {
+    syscall(SYSCALL_AUDIT, [pid, records, 0])
+}
+
+pub fn sys_audit_read(pid: usize, buf: &mut [AuditRecord]) -> isize {
+    syscall(
+        SYSCALL_AUDIT_READ,
+        [pid, buf.as_mut_ptr() as usize, core::mem::size_of_val(buf)],
+    )
+}
+
+pub fn sys_sysinfo(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_SYSINFO, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_list_apps(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_LIST_APPS, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_sched_trace(buf: &mut [SchedEvent], dropped: &mut usize) -> isize {
+    syscall(
+        SYSCALL_SCHED_TRACE,
+        [buf.as_mut_ptr() as usize, buf.len(), dropped as *mut _ as usize],
+    )
 }
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
@@ -229,6 +463,10 @@ pub fn sys_mutex_unlock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_close(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_CLOSE, [id, 0, 0])
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
 }
@@ -237,6 +475,18 @@ pub fn sys_semaphore_up(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_UP, [sem_id, 0, 0])
 }
 
+pub fn sys_sem_open(name: &str, count: usize) -> isize {
+    syscall(SYSCALL_SEM_OPEN, [name.as_ptr() as usize, count, 0])
+}
+
+pub fn sys_sem_unlink(name: &str) -> isize {
+    syscall(SYSCALL_SEM_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_sem_close(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEM_CLOSE, [sem_id, 0, 0])
+}
+
 pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
     syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
 }